@@ -0,0 +1,255 @@
+//! Packages the Rust lib as a standalone AAR, for apps whose Gradle projects
+//! aren't generated by us. The AAR's assembled by Gradle (using the generated
+//! project's wrapper) from a throwaway library project, so the Kotlin bindings
+//! get compiled the same way they would be in an app.
+
+use super::{config::Config, env::Env, jnilibs, target::Target};
+use crate::{
+    env::ExplicitEnv as _,
+    opts::Profile,
+    util::{
+        self,
+        cli::{Report, Reportable},
+        repro::Recorded as _,
+    },
+};
+use heck::CamelCase as _;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The Kotlin version the bindings are compiled with, which consumers also
+/// need the stdlib for.
+static KOTLIN_VERSION: &str = "1.3.72";
+
+#[derive(Debug)]
+pub enum Error {
+    StageFailed { path: PathBuf, cause: io::Error },
+    LibCopyFailed { path: PathBuf, cause: io::Error },
+    AssembleFailed(bossy::Error),
+    OutputFailed { path: PathBuf, cause: io::Error },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::StageFailed { path, cause } => Report::error(
+                format!("Failed to write AAR project file {:?}", path),
+                cause,
+            ),
+            Self::LibCopyFailed { path, cause } => {
+                Report::error(format!("Failed to copy lib {:?} into AAR", path), cause)
+            }
+            Self::AssembleFailed(err) => Report::error("Failed to assemble AAR", err),
+            Self::OutputFailed { path, cause } => {
+                Report::error(format!("Failed to write {:?}", path), cause)
+            }
+        }
+    }
+}
+
+fn package(config: &Config) -> String {
+    format!(
+        "{}.{}",
+        config.app().reverse_domain(),
+        config.app().name_snake()
+    )
+}
+
+fn bindings_name(config: &Config) -> String {
+    config.app().name().to_camel_case()
+}
+
+fn build_gradle(config: &Config) -> String {
+    format!(
+        r#"// Generated by `cargo android build --aar`; changes will be overwritten.
+
+buildscript {{
+    repositories {{
+        google()
+        jcenter()
+    }}
+    dependencies {{
+        classpath 'com.android.tools.build:gradle:3.4.1'
+        classpath 'org.jetbrains.kotlin:kotlin-gradle-plugin:{kotlin}'
+    }}
+}}
+
+apply plugin: 'com.android.library'
+apply plugin: 'kotlin-android'
+
+repositories {{
+    google()
+    jcenter()
+}}
+
+android {{
+    compileSdkVersion 28
+    defaultConfig {{
+        minSdkVersion {min_sdk}
+        targetSdkVersion 28
+    }}
+    sourceSets {{
+        main {{
+            java.srcDirs = ['src/main/kotlin']
+            jniLibs.srcDirs = ['jniLibs']
+        }}
+    }}
+}}
+
+dependencies {{
+    implementation 'org.jetbrains.kotlin:kotlin-stdlib:{kotlin}'
+}}
+"#,
+        kotlin = KOTLIN_VERSION,
+        min_sdk = config.min_sdk_version(),
+    )
+}
+
+fn bindings(config: &Config) -> String {
+    format!(
+        r#"// Generated by `cargo android build --aar`; changes will be overwritten.
+
+package {package}
+
+// Loads the Rust library the first time it's used. Its JNI functions can be
+// declared here as `external fun`s, which the Rust side exports as
+// `Java_<package>_{name}_<function>`.
+object {name} {{
+    init {{
+        System.loadLibrary("{lib_name}")
+    }}
+
+    // Forces the library to load, i.e. ahead of time on a background thread.
+    @JvmStatic
+    fun load() {{}}
+}}
+"#,
+        package = package(config),
+        name = bindings_name(config),
+        lib_name = config.app().lib_name(),
+    )
+}
+
+fn pom(config: &Config) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<project xmlns="http://maven.apache.org/POM/4.0.0" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://maven.apache.org/POM/4.0.0 http://maven.apache.org/xsd/maven-4.0.0.xsd">
+  <modelVersion>4.0.0</modelVersion>
+  <groupId>{group}</groupId>
+  <artifactId>{artifact}</artifactId>
+  <version>{version}</version>
+  <packaging>aar</packaging>
+  <dependencies>
+    <dependency>
+      <groupId>org.jetbrains.kotlin</groupId>
+      <artifactId>kotlin-stdlib</artifactId>
+      <version>{kotlin}</version>
+      <scope>runtime</scope>
+    </dependency>
+  </dependencies>
+</project>
+"#,
+        group = config.app().reverse_domain(),
+        artifact = config.app().name(),
+        version = config.app().version(),
+        kotlin = KOTLIN_VERSION,
+    )
+}
+
+fn write(path: PathBuf, contents: String) -> Result<(), Error> {
+    path.parent()
+        .map(fs::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|()| fs::write(&path, contents))
+        .map_err(|cause| Error::StageFailed { path, cause })
+}
+
+// The jniLibs dirs hold symlinks, which we replace with copies so the stage
+// dir doesn't depend on anything outside of it.
+fn copy_libs(config: &Config, target: Target<'_>, dest: &Path) -> Result<(), Error> {
+    let src = jnilibs::path(config, target);
+    let lib_copy_failed = |path: &Path| {
+        let path = path.to_owned();
+        move |cause: io::Error| Error::LibCopyFailed { path, cause }
+    };
+    fs::create_dir_all(dest).map_err(lib_copy_failed(dest))?;
+    for entry in fs::read_dir(&src).map_err(lib_copy_failed(&src))? {
+        let path = entry.map_err(lib_copy_failed(&src))?.path();
+        if let Some(file_name) = path.file_name() {
+            fs::copy(&path, dest.join(file_name)).map_err(lib_copy_failed(&path))?;
+        }
+    }
+    Ok(())
+}
+
+/// Assembles an AAR containing the libs already built for `targets`, and
+/// writes it alongside a POM for publishing to a Maven repository. Returns the
+/// AAR's path.
+pub fn build(
+    config: &Config,
+    env: &Env,
+    profile: Profile,
+    targets: &[Target<'_>],
+) -> Result<PathBuf, Error> {
+    let app = config.app();
+    let _lock = util::lock::ProjectLock::acquire(app.gen_dir());
+    let stage = config.project_dir().join("build/aar-project");
+    // Anything left over from a previous build would end up in the AAR.
+    let jni_libs = stage.join("jniLibs");
+    if jni_libs.exists() {
+        fs::remove_dir_all(&jni_libs).map_err(|cause| Error::StageFailed {
+            path: jni_libs.clone(),
+            cause,
+        })?;
+    }
+    write(
+        stage.join("settings.gradle"),
+        format!("rootProject.name = '{}'\n", app.name()),
+    )?;
+    write(stage.join("build.gradle"), build_gradle(config))?;
+    write(
+        stage.join("src/main/AndroidManifest.xml"),
+        format!("<manifest package=\"{}\" />\n", package(config)),
+    )?;
+    write(
+        stage.join(format!("src/main/kotlin/{}.kt", bindings_name(config))),
+        bindings(config),
+    )?;
+    for target in targets {
+        copy_libs(config, *target, &jni_libs.join(target.abi))?;
+    }
+
+    bossy::Command::pure(config.project_dir().join("gradlew"))
+        .with_env_vars(env.explicit_env())
+        .with_arg("--project-dir")
+        .with_arg(&stage)
+        .with_args(util::proxy::gradle_args())
+        .with_arg(format!("assemble{}", profile.as_str().to_camel_case()))
+        .recorded(env, Default::default())
+        .run_and_wait()
+        .map_err(Error::AssembleFailed)?;
+
+    let out_dir = config.project_dir().join("build/outputs/aar");
+    let output_failed = |path: &Path| {
+        let path = path.to_owned();
+        move |cause: io::Error| Error::OutputFailed { path, cause }
+    };
+    fs::create_dir_all(&out_dir).map_err(output_failed(&out_dir))?;
+    // Named the way Maven repositories lay them out.
+    let base_name = format!("{}-{}", app.name(), app.version());
+    let aar = out_dir.join(format!("{}.aar", base_name));
+    fs::copy(
+        stage.join(format!(
+            "build/outputs/aar/{}-{}.aar",
+            app.name(),
+            profile.as_str()
+        )),
+        &aar,
+    )
+    .map_err(output_failed(&aar))?;
+    let pom_path = out_dir.join(format!("{}.pom", base_name));
+    fs::write(&pom_path, pom(config)).map_err(output_failed(&pom_path))?;
+    Ok(aar)
+}
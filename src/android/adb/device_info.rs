@@ -0,0 +1,129 @@
+use super::{adb, get_prop};
+use crate::{
+    android::env::Env,
+    util::cli::{Report, Reportable},
+};
+use std::str;
+
+#[derive(Debug)]
+pub enum Error {
+    PropFailed(get_prop::Error),
+    ShellFailed {
+        command: String,
+        cause: super::RunCheckedError,
+    },
+    InvalidUtf8 {
+        command: String,
+        cause: str::Utf8Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::PropFailed(err) => err.report(),
+            Self::ShellFailed { command, cause } => {
+                cause.report(&format!("Failed to run `adb shell {}`", command))
+            }
+            Self::InvalidUtf8 { command, cause } => Report::error(
+                format!("Failed to run `adb shell {}`", command),
+                format!("Output contained invalid UTF-8: {}", cause),
+            ),
+        }
+    }
+}
+
+fn shell(env: &Env, serial_no: &str, args: &[&str]) -> Result<String, Error> {
+    let command = args.join(" ");
+    let output = super::run_checked(&mut adb(env, serial_no).with_arg("shell").with_args(args))
+        .map_err(|cause| Error::ShellFailed {
+            command: command.clone(),
+            cause,
+        })?;
+    output
+        .stdout_str()
+        .map(ToOwned::to_owned)
+        .map_err(|cause| Error::InvalidUtf8 { command, cause })
+}
+
+// `df` output looks like:
+// Filesystem  Size  Used Avail Use% Mounted on
+// /dev/block/dm-5  110G  38G  72G  35% /data
+fn free_storage(df: &str) -> Option<String> {
+    let columns = df.lines().last()?.split_whitespace().collect::<Vec<_>>();
+    match columns.as_slice() {
+        [_, size, _, avail, ..] => Some(format!("{} of {}", avail, size)),
+        _ => None,
+    }
+}
+
+fn battery(dumpsys: &str) -> Option<String> {
+    let field = |name: &str| {
+        dumpsys.lines().find_map(|line| {
+            let mut parts = line.trim().splitn(2, ':');
+            if parts.next()? == name {
+                parts.next().map(|value| value.trim().to_owned())
+            } else {
+                None
+            }
+        })
+    };
+    let level = field("level")?;
+    // 2 is `BATTERY_STATUS_CHARGING`
+    let charging = field("status").as_deref() == Some("2");
+    Some(format!(
+        "{}%{}",
+        level,
+        if charging { " (charging)" } else { "" }
+    ))
+}
+
+/// Details that are handy to have when debugging issues that only happen on
+/// a specific device.
+pub fn device_info(env: &Env, serial_no: &str) -> Result<Vec<(&'static str, String)>, Error> {
+    let prop = |prop| get_prop(env, serial_no, prop).map_err(Error::PropFailed);
+    let unknown = || "unknown".to_owned();
+    Ok(vec![
+        (
+            "Model",
+            format!(
+                "{} {}",
+                prop("ro.product.manufacturer")?,
+                prop("ro.product.model")?
+            ),
+        ),
+        (
+            "Android version",
+            format!(
+                "{} (API {})",
+                prop("ro.build.version.release")?,
+                prop("ro.build.version.sdk")?
+            ),
+        ),
+        ("OS build", prop("ro.build.display_id")?),
+        ("ABI", prop("ro.product.cpu.abi")?),
+        (
+            "Free storage",
+            free_storage(&shell(env, serial_no, &["df", "-h", "/data"])?).unwrap_or_else(unknown),
+        ),
+        (
+            "Battery",
+            battery(&shell(env, serial_no, &["dumpsys", "battery"])?).unwrap_or_else(unknown),
+        ),
+        (
+            "Developer mode",
+            match shell(
+                env,
+                serial_no,
+                &["settings", "get", "global", "development_settings_enabled"],
+            )?
+            .trim()
+            {
+                "1" => "enabled",
+                "0" => "disabled",
+                _ => "unknown",
+            }
+            .to_owned(),
+        ),
+    ])
+}
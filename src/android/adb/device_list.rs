@@ -2,10 +2,18 @@ use super::{device_name, get_prop};
 use crate::{
     android::{device::Device, env::Env, target::Target},
     env::ExplicitEnv as _,
-    util::cli::{Report, Reportable},
+    util::{
+        cache,
+        cli::{Report, Reportable},
+    },
 };
 use once_cell_regex::regex_multi_line;
-use std::{collections::BTreeSet, str};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, str, time::Duration};
+
+static CACHE_NAME: &str = "android-devices";
+// Scanning is slow, but devices come and go, so this is kept short.
+static CACHE_MAX_AGE: Duration = Duration::from_secs(30);
 
 #[derive(Debug)]
 pub enum Error {
@@ -13,6 +21,7 @@ pub enum Error {
     InvalidUtf8(str::Utf8Error),
     NameFailed(device_name::Error),
     ModelFailed(get_prop::Error),
+    OsVersionFailed(get_prop::Error),
     AbiFailed(get_prop::Error),
     AbiInvalid(String),
 }
@@ -26,7 +35,9 @@ impl Reportable for Error {
                 Report::error(msg, format!("Device list contained invalid UTF-8: {}", err))
             }
             Self::NameFailed(err) => err.report(),
-            Self::ModelFailed(err) | Self::AbiFailed(err) => err.report(),
+            Self::ModelFailed(err) | Self::OsVersionFailed(err) | Self::AbiFailed(err) => {
+                err.report()
+            }
             Self::AbiInvalid(abi) => {
                 Report::error(msg, format!("{:?} isn't a valid target ABI.", abi))
             }
@@ -34,10 +45,71 @@ impl Reportable for Error {
     }
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedDevice {
+    serial_no: String,
+    name: String,
+    model: String,
+    os_version: String,
+    abi: String,
+}
+
+impl CachedDevice {
+    fn from_device(device: &Device<'_>) -> Self {
+        Self {
+            serial_no: device.serial_no().to_owned(),
+            name: device.name().to_owned(),
+            model: device.model().to_owned(),
+            os_version: device.os_version().unwrap_or_default().to_owned(),
+            abi: device.target().abi.to_owned(),
+        }
+    }
+
+    fn into_device(self) -> Option<Device<'static>> {
+        Target::for_abi(&self.abi).map(|target| {
+            Device::new(
+                self.serial_no,
+                self.name,
+                self.model,
+                self.os_version,
+                target,
+            )
+        })
+    }
+}
+
+/// Forgets the last scan, i.e. after a deploy fails, since the device may
+/// well be gone.
+pub fn clear_cache() {
+    cache::clear(CACHE_NAME);
+}
+
+/// Lists connected devices, reusing the last scan if it was recent enough.
 pub fn device_list(env: &Env) -> Result<BTreeSet<Device<'static>>, Error> {
+    if let Some(devices) = cache::load::<Vec<CachedDevice>>(CACHE_NAME, CACHE_MAX_AGE)
+        .and_then(|cached| cached.into_iter().map(CachedDevice::into_device).collect())
+    {
+        return Ok(devices);
+    }
+    let devices = scan(env)?;
+    // An empty list is never worth caching, since we'd just be delaying
+    // noticing a device that was plugged in.
+    if !devices.is_empty() {
+        cache::store(
+            CACHE_NAME,
+            &devices
+                .iter()
+                .map(CachedDevice::from_device)
+                .collect::<Vec<_>>(),
+        );
+    }
+    Ok(devices)
+}
+
+fn scan(env: &Env) -> Result<BTreeSet<Device<'static>>, Error> {
     let serial_re = regex_multi_line!(r"^([\w\d]{6,20})	\b");
     let output = super::run_checked(
-        &mut bossy::Command::pure("adb")
+        &mut bossy::Command::pure(env.adb())
             .with_env_vars(env.explicit_env())
             .with_args(&["devices"]),
     )
@@ -51,9 +123,11 @@ pub fn device_list(env: &Env) -> Result<BTreeSet<Device<'static>>, Error> {
             let name = device_name(env, &serial_no).map_err(Error::NameFailed)?;
             let model =
                 get_prop(env, &serial_no, "ro.product.model").map_err(Error::ModelFailed)?;
+            let os_version = get_prop(env, &serial_no, "ro.build.version.release")
+                .map_err(Error::OsVersionFailed)?;
             let abi = get_prop(env, &serial_no, "ro.product.cpu.abi").map_err(Error::AbiFailed)?;
             let target = Target::for_abi(&abi).ok_or_else(|| Error::AbiInvalid(abi.clone()))?;
-            Ok(Device::new(serial_no, name, model, target))
+            Ok(Device::new(serial_no, name, model, os_version, target))
         })
         .collect()
 }
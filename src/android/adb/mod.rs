@@ -1,15 +1,19 @@
+pub mod device_info;
 pub mod device_list;
 pub mod device_name;
 pub mod get_prop;
 
-pub use self::{device_list::device_list, device_name::device_name, get_prop::get_prop};
+pub use self::{
+    device_info::device_info, device_list::device_list, device_name::device_name,
+    get_prop::get_prop,
+};
 
 use super::env::Env;
 use crate::{env::ExplicitEnv as _, util::cli::Report};
 use std::str;
 
 pub fn adb(env: &Env, serial_no: &str) -> bossy::Command {
-    bossy::Command::pure("adb")
+    bossy::Command::pure(env.adb())
         .with_env_vars(env.explicit_env())
         .with_args(&["-s", serial_no])
 }
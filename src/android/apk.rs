@@ -0,0 +1,138 @@
+use crate::util::install::Contents;
+use std::{fs, io, path::Path};
+
+static END_OF_CENTRAL_DIR_SIG: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+static CENTRAL_DIR_HEADER_SIG: [u8; 4] = [0x50, 0x4b, 0x01, 0x02];
+const END_OF_CENTRAL_DIR_LEN: usize = 22;
+const CENTRAL_DIR_HEADER_LEN: usize = 46;
+
+fn u16_at(bytes: &[u8], at: usize) -> Option<usize> {
+    bytes
+        .get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn parse(bytes: &[u8]) -> Option<Contents> {
+    // The end of central directory record can be followed by a comment of up
+    // to 64K, so we have to search backwards for it.
+    let last = bytes.len().checked_sub(END_OF_CENTRAL_DIR_LEN)?;
+    let end = (last.saturating_sub(0xffff)..=last)
+        .rev()
+        .find(|&at| bytes[at..at + 4] == END_OF_CENTRAL_DIR_SIG)?;
+    let count = u16_at(bytes, end + 10)?;
+    let mut at = u32_at(bytes, end + 16)? as usize;
+    let mut contents = Contents::new();
+    for _ in 0..count {
+        if bytes.get(at..at + 4)? != CENTRAL_DIR_HEADER_SIG {
+            return None;
+        }
+        let crc = u32_at(bytes, at + 16)?;
+        let size = u32_at(bytes, at + 24)?;
+        let name_len = u16_at(bytes, at + 28)?;
+        let extra_len = u16_at(bytes, at + 30)?;
+        let comment_len = u16_at(bytes, at + 32)?;
+        let name =
+            bytes.get(at + CENTRAL_DIR_HEADER_LEN..at + CENTRAL_DIR_HEADER_LEN + name_len)?;
+        contents.insert(
+            String::from_utf8_lossy(name).into_owned(),
+            format!("{:08x}-{}", crc, size),
+        );
+        at += CENTRAL_DIR_HEADER_LEN + name_len + extra_len + comment_len;
+    }
+    Some(contents)
+}
+
+/// The entries in an APK, with their CRC-32s and sizes. That's all in the
+/// zip's central directory, so nothing has to be decompressed to tell what
+/// changed.
+pub fn contents(path: &Path) -> io::Result<Contents> {
+    parse(&fs::read(path)?).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{:?} isn't a valid APK", path),
+        )
+    })
+}
+
+/// Only native libs and assets changing (along with the signature, which
+/// changes whenever anything else does) is worth a streamed install. Anything
+/// else changing
+/// means the package manager has real work to do (new components,
+/// permissions, or code to optimize), so it may as well be a full install.
+pub fn patchable(path: &str) -> bool {
+    path.starts_with("lib/") || path.starts_with("assets/") || path.starts_with("META-INF/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Just the central directory and its end record, after some filler
+    // standing in for the entries themselves, which `parse` never reads.
+    fn zip(entries: &[(&str, u32, u32)], comment: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0; 16];
+        let offset = bytes.len() as u32;
+        for (name, crc, size) in entries {
+            let mut header = vec![0; CENTRAL_DIR_HEADER_LEN];
+            header[..4].copy_from_slice(&CENTRAL_DIR_HEADER_SIG);
+            header[16..20].copy_from_slice(&crc.to_le_bytes());
+            header[24..28].copy_from_slice(&size.to_le_bytes());
+            header[28..30].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend(header);
+            bytes.extend(name.as_bytes());
+        }
+        let mut end = vec![0; END_OF_CENTRAL_DIR_LEN];
+        end[..4].copy_from_slice(&END_OF_CENTRAL_DIR_SIG);
+        end[10..12].copy_from_slice(&(entries.len() as u16).to_le_bytes());
+        end[16..20].copy_from_slice(&offset.to_le_bytes());
+        end[20..22].copy_from_slice(&(comment.len() as u16).to_le_bytes());
+        bytes.extend(end);
+        bytes.extend(comment);
+        bytes
+    }
+
+    #[test]
+    fn parses_entries() {
+        let contents = parse(&zip(
+            &[
+                ("classes.dex", 0xdeadbeef, 1024),
+                ("lib/arm64-v8a/libapp.so", 0x12, 7),
+            ],
+            b"",
+        ))
+        .unwrap();
+        assert_eq!(contents.len(), 2);
+        assert_eq!(contents["classes.dex"], "deadbeef-1024");
+        assert_eq!(contents["lib/arm64-v8a/libapp.so"], "00000012-7");
+    }
+
+    #[test]
+    fn finds_end_record_before_comment() {
+        let contents = parse(&zip(&[("AndroidManifest.xml", 1, 2)], b"signed")).unwrap();
+        assert_eq!(contents["AndroidManifest.xml"], "00000001-2");
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        assert!(parse(b"").is_none());
+        assert!(parse(&[0; 64]).is_none());
+        let mut truncated = zip(&[("classes.dex", 1, 2)], b"");
+        truncated.drain(16..32);
+        assert!(parse(&truncated).is_none());
+    }
+
+    #[test]
+    fn patchable_paths() {
+        assert!(patchable("lib/arm64-v8a/libapp.so"));
+        assert!(patchable("assets/index.html"));
+        assert!(patchable("META-INF/CERT.SF"));
+        assert!(!patchable("classes.dex"));
+        assert!(!patchable("AndroidManifest.xml"));
+    }
+}
@@ -1,28 +1,34 @@
 use crate::{
     android::{
-        adb,
+        aar, adb,
         config::{Config, Metadata},
-        device::{Device, RunError, StacktraceError},
+        device::{assemble_instrumented, Device, RunError, StacktraceError, TestError},
+        doctor, emulator,
         env::{Env, Error as EnvError},
-        target::{BuildError, CompileLibError, Target},
+        signer,
+        target::{BuildError, CompileLibError, Target, TestBuildError},
         NAME,
     },
     config::{
+        app::cbindgen,
         metadata::{self, Metadata as OmniMetadata},
-        Config as OmniConfig, LoadOrGenError,
+        Config as OmniConfig, LoadOrGenError, Overrides,
     },
     define_device_prompt,
-    device::PromptError,
-    opts, os,
+    device::{self, PromptError},
+    i18n, opts, os,
     target::{call_for_targets_with_fallback, TargetInvalid, TargetTrait as _},
+    test_lab,
     util::{
+        self,
         cli::{
             self, Exec, GlobalFlags, Report, Reportable, TextWrapper, VERSION_LONG, VERSION_SHORT,
         },
+        progress::Step,
         prompt,
     },
 };
-use std::path::PathBuf;
+use std::{cell::RefCell, collections::BTreeSet, path::PathBuf, time::Duration};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -54,6 +60,8 @@ pub enum Command {
     Check {
         #[structopt(name = "targets", default_value = Target::DEFAULT_KEY, possible_values = Target::name_list())]
         targets: Vec<String>,
+        #[structopt(flatten)]
+        force: cli::Force,
     },
     #[structopt(name = "build", about = "Builds dynamic libraries for target(s)")]
     Build {
@@ -61,16 +69,137 @@ pub enum Command {
         targets: Vec<String>,
         #[structopt(flatten)]
         profile: cli::Profile,
+        #[structopt(flatten)]
+        force: cli::Force,
+        #[structopt(flatten)]
+        entry_point: cli::EntryPoint,
+        #[structopt(flatten)]
+        metrics_file: cli::MetricsFile,
+        #[structopt(
+            long = "aar",
+            help = "Also package the built targets into an AAR with Kotlin bindings and a POM, for use outside of this project"
+        )]
+        aar: bool,
     },
     #[structopt(name = "run", about = "Deploys APK to connected device")]
     Run {
         #[structopt(flatten)]
         profile: cli::Profile,
+        #[structopt(flatten)]
+        entry_point: cli::EntryPoint,
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+        #[structopt(flatten)]
+        wait: cli::Wait,
+        #[structopt(
+            long = "all-devices",
+            help = "Deploy to every connected device at once"
+        )]
+        all_devices: bool,
+        #[structopt(flatten)]
+        run_args: cli::RunArgs,
+    },
+    #[structopt(name = "test", about = "Runs tests on a connected device")]
+    Test {
+        #[structopt(flatten)]
+        profile: cli::Profile,
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+        #[structopt(flatten)]
+        wait: cli::Wait,
+        #[structopt(
+            long = "native",
+            help = "Run the crate's Rust tests, instead of the project's instrumented tests"
+        )]
+        native: bool,
+        #[structopt(
+            long = "coverage",
+            help = "Collect code coverage from native tests, and generate lcov and HTML reports",
+            parse(from_flag = opts::Coverage::from_bool),
+        )]
+        coverage: opts::Coverage,
+        #[structopt(
+            name = "ARGS",
+            help = "Arguments passed to the test harness",
+            last = true
+        )]
+        args: Vec<String>,
+    },
+    #[structopt(
+        name = "test-lab",
+        about = "Runs the project's instrumented tests on Firebase Test Lab"
+    )]
+    TestLab {
+        #[structopt(flatten)]
+        test_lab: cli::TestLab,
+    },
+    #[structopt(
+        name = "emulator",
+        about = "Provisions and boots an emulator, and prints its serial once it's booted"
+    )]
+    Emulator {
+        #[structopt(
+            long = "api",
+            help = "API level of the system image",
+            default_value = "34"
+        )]
+        api: u32,
+        #[structopt(
+            long = "abi",
+            help = "ABI of the system image (`arm64`, `x86_64`, or `x86`)",
+            default_value = "arm64"
+        )]
+        abi: String,
+        #[structopt(long = "headless", help = "Run without a window, i.e. on CI")]
+        headless: bool,
+        #[structopt(
+            long = "boot-timeout",
+            help = "How many seconds to wait for the emulator to boot",
+            default_value = "300"
+        )]
+        boot_timeout: u64,
     },
     #[structopt(name = "st", about = "Displays a detailed stacktrace for a device")]
-    Stacktrace,
+    Stacktrace {
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+        #[structopt(flatten)]
+        wait: cli::Wait,
+    },
+    #[structopt(
+        name = "device-info",
+        about = "Prints details about a connected device"
+    )]
+    DeviceInfo {
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+        #[structopt(flatten)]
+        wait: cli::Wait,
+    },
     #[structopt(name = "list", about = "Lists connected devices")]
-    List,
+    List {
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+    },
+    #[structopt(
+        name = "sign",
+        about = "Signs an APK or AAB with the KMS or HSM key configured in `android.signer`"
+    )]
+    Sign {
+        #[structopt(name = "ARTIFACT", help = "Unsigned APK or AAB", parse(from_os_str))]
+        artifact: PathBuf,
+        #[structopt(
+            long = "out",
+            help = "Where to write the signed artifact (defaults to next to the unsigned one, with `-signed` appended)",
+            parse(from_os_str)
+        )]
+        out: Option<PathBuf>,
+    },
+    #[structopt(
+        name = "doctor",
+        about = "Checks the Android SDK and NDK, offering to install anything missing"
+    )]
+    Doctor,
 }
 
 #[derive(Debug)]
@@ -82,12 +211,30 @@ pub enum Error {
     MetadataFailed(metadata::Error),
     Unsupported,
     ProjectDirAbsent { project_dir: PathBuf },
-    OpenFailed(bossy::Error),
+    OpenFailed { editor: String, cause: bossy::Error },
     CheckFailed(CompileLibError),
+    I18nSyncFailed(i18n::Error),
+    CbindgenFailed(cbindgen::Error),
+    SentryUploadFailed(sentry::Error),
+    RustupFailed(util::rustup::Error),
     BuildFailed(BuildError),
+    AarFailed(aar::Error),
     RunFailed(RunError),
+    TestBuildFailed(TestBuildError),
+    TestFailed(TestError),
+    TestLabBuildFailed(bossy::Error),
+    TestLabFailed(test_lab::Error),
+    EmulatorFailed(emulator::Error),
+    BinUnsupported(cli::BinUnsupported),
+    CoverageFailed(util::coverage::Error),
     StacktraceFailed(StacktraceError),
+    DeployAllFailed(device::DeployAllError),
+    DeviceInfoFailed(adb::device_info::Error),
     ListFailed(adb::device_list::Error),
+    OutDirFailed(util::out_dir::Error),
+    SignerMissing,
+    SignFailed(signer::Error),
+    DoctorFailed(doctor::Error),
 }
 
 impl Reportable for Error {
@@ -106,12 +253,37 @@ impl Reportable for Error {
                     project_dir
                 ),
             ),
-            Self::OpenFailed(err) => Report::error("Failed to open project in Android Studio", err),
+            Self::OpenFailed { editor, cause } => {
+                Report::error(format!("Failed to open project in {}", editor), cause)
+            }
             Self::CheckFailed(err) => err.report(),
+            Self::I18nSyncFailed(err) => Report::error("Failed to sync localized strings", err),
+            Self::CbindgenFailed(err) => err.report(),
+            Self::SentryUploadFailed(err) => err.report(),
+            Self::RustupFailed(err) => err.report(),
             Self::BuildFailed(err) => err.report(),
+            Self::AarFailed(err) => err.report(),
             Self::RunFailed(err) => err.report(),
+            Self::TestBuildFailed(err) => err.report(),
+            Self::TestFailed(err) => err.report(),
+            Self::TestLabBuildFailed(err) => {
+                Report::error("Failed to assemble APKs for Firebase Test Lab", err)
+            }
+            Self::TestLabFailed(err) => err.report(),
+            Self::EmulatorFailed(err) => err.report(),
+            Self::BinUnsupported(err) => err.report(),
+            Self::CoverageFailed(err) => err.report(),
             Self::StacktraceFailed(err) => err.report(),
+            Self::DeployAllFailed(err) => err.report(),
+            Self::DeviceInfoFailed(err) => err.report(),
             Self::ListFailed(err) => err.report(),
+            Self::OutDirFailed(err) => err.report(),
+            Self::SignerMissing => Report::action_request(
+                format!("Please configure `{}.signer` and try again.", NAME),
+                "No signer is configured",
+            ),
+            Self::SignFailed(err) => Report::error("Failed to sign artifact", err),
+            Self::DoctorFailed(err) => err.report(),
         }
     }
 }
@@ -120,24 +292,28 @@ impl Exec for Input {
     type Report = Error;
 
     fn global_flags(&self) -> GlobalFlags {
-        self.flags
+        self.flags.clone()
     }
 
     fn exec(self, wrapper: &TextWrapper) -> Result<(), Self::Report> {
         define_device_prompt!(adb::device_list, adb::device_list::Error, Android);
         fn detect_target_ok<'a>(env: &Env) -> Option<&'a Target<'a>> {
-            device_prompt(env).map(|device| device.target()).ok()
+            device_prompt(env, None, &Default::default(), None)
+                .map(|device| device.target())
+                .ok()
         }
 
         fn with_config(
             non_interactive: opts::NonInteractive,
+            overrides: &Overrides,
             wrapper: &TextWrapper,
             f: impl FnOnce(&Config, &Metadata) -> Result<(), Error>,
         ) -> Result<(), Error> {
-            let (config, _origin) = OmniConfig::load_or_gen(".", non_interactive, wrapper)
-                .map_err(Error::ConfigFailed)?;
-            let metadata =
-                OmniMetadata::load(&config.app().root_dir()).map_err(Error::MetadataFailed)?;
+            let (config, _origin) =
+                OmniConfig::load_or_gen(".", non_interactive, overrides, wrapper)
+                    .map_err(Error::ConfigFailed)?;
+            let metadata = OmniMetadata::load(&config.app().crate_dir(), overrides)
+                .map_err(Error::MetadataFailed)?;
             if metadata.android().supported() {
                 f(config.android(), metadata.android())
             } else {
@@ -155,79 +331,379 @@ impl Exec for Input {
             }
         }
 
-        fn open_in_android_studio(config: &Config) -> Result<(), Error> {
-            os::open_file_with("Android Studio", config.project_dir()).map_err(Error::OpenFailed)
+        fn open_in_editor(config: &Config) -> Result<(), Error> {
+            os::open_file_with(config.editor(), config.project_dir()).map_err(|cause| {
+                Error::OpenFailed {
+                    editor: config.editor().to_owned(),
+                    cause,
+                }
+            })
         }
 
-        let Self {
-            flags:
-                GlobalFlags {
-                    noise_level,
-                    non_interactive,
-                },
-            command,
-        } = self;
+        let Self { flags, command } = self;
+        let overrides = flags.config_overrides();
+        let GlobalFlags {
+            noise_level,
+            non_interactive,
+            ..
+        } = flags;
+        // This is what fixes a broken environment, so it can't require a
+        // working one.
+        if let Command::Doctor = command {
+            return doctor::exec(non_interactive).map_err(Error::DoctorFailed);
+        }
         let env = Env::new().map_err(Error::EnvInitFailed)?;
         match command {
-            Command::Open => with_config(non_interactive, wrapper, |config, _| {
+            Command::Open => with_config(non_interactive, &overrides, wrapper, |config, _| {
                 ensure_init(config)?;
-                open_in_android_studio(config)
+                open_in_editor(config)
+            }),
+            Command::Check {
+                targets,
+                force: cli::Force { force },
+            } => with_config(non_interactive, &overrides, wrapper, |config, metadata| {
+                let force_color = opts::ForceColor::Yes;
+                call_for_targets_with_fallback(
+                    targets.iter(),
+                    &detect_target_ok,
+                    &env,
+                    |target: &Target| {
+                        util::fingerprint::run_unless_fresh(
+                            config.app(),
+                            "check",
+                            target.triple,
+                            &(config, metadata, env.dotenv()),
+                            force,
+                            || {
+                                target
+                                    .check(config, metadata, &env, noise_level, force_color)
+                                    .map_err(Error::CheckFailed)
+                            },
+                        )
+                        .map(|_fresh| ())
+                    },
+                )
+                .map_err(Error::TargetInvalid)?
             }),
-            Command::Check { targets } => {
-                with_config(non_interactive, wrapper, |config, metadata| {
-                    let force_color = opts::ForceColor::Yes;
-                    call_for_targets_with_fallback(
-                        targets.iter(),
-                        &detect_target_ok,
-                        &env,
-                        |target: &Target| {
-                            target
-                                .check(config, metadata, &env, noise_level, force_color)
-                                .map_err(Error::CheckFailed)
-                        },
-                    )
-                    .map_err(Error::TargetInvalid)?
-                })
-            }
             Command::Build {
                 targets,
                 profile: cli::Profile { profile },
-            } => with_config(non_interactive, wrapper, |config, metadata| {
+                force: cli::Force { force },
+                entry_point,
+                metrics_file,
+                aar,
+            } => with_config(non_interactive, &overrides, wrapper, |config, metadata| {
                 ensure_init(config)?;
+                let entry_point = entry_point.entry_point().map_err(Error::BinUnsupported)?;
+                let metrics = metrics_file.metrics();
+                i18n::sync_android(config).map_err(Error::I18nSyncFailed)?;
+                if let Some(cbindgen) = config.app().cbindgen() {
+                    cbindgen
+                        .generate(
+                            &config
+                                .rust_module_dir()
+                                .join("src/main/cpp")
+                                .join(cbindgen.header()),
+                        )
+                        .map_err(Error::CbindgenFailed)?;
+                }
                 let force_color = opts::ForceColor::Yes;
+                let built = RefCell::new(Vec::new());
                 call_for_targets_with_fallback(
                     targets.iter(),
                     &detect_target_ok,
                     &env,
                     |target: &Target| {
-                        target
-                            .build(config, metadata, &env, noise_level, force_color, profile)
-                            .map_err(Error::BuildFailed)
+                        util::rustup::ensure_targets(Some(target.triple), non_interactive)
+                            .map_err(Error::RustupFailed)?;
+                        let lib_path = target.lib_path(config, profile, &entry_point);
+                        // There's nothing to skip to if the library's gone.
+                        let force = if lib_path.is_file() {
+                            force
+                        } else {
+                            opts::Force::Yes
+                        };
+                        metrics.record("android", target.triple, profile, &lib_path, || {
+                            util::fingerprint::run_unless_fresh(
+                                config.app(),
+                                &match entry_point.example() {
+                                    Some(example) => format!(
+                                        "{} build of example {:?}",
+                                        profile.as_str(),
+                                        example
+                                    ),
+                                    None => format!("{} build", profile.as_str()),
+                                },
+                                target.triple,
+                                &(config, metadata, env.dotenv()),
+                                force,
+                                || {
+                                    Step::run(format!("Compiling {}", target.triple), || {
+                                        target.build(
+                                            config,
+                                            metadata,
+                                            &env,
+                                            noise_level,
+                                            force_color,
+                                            profile,
+                                            &entry_point,
+                                        )
+                                    })
+                                    .map_err(Error::BuildFailed)
+                                },
+                            )
+                        })?;
+                        cli::print_artifact(&lib_path);
+                        // Debug builds are too frequent to be worth uploading.
+                        if let (Some(sentry), opts::Profile::Release) =
+                            (config.app().sentry(), profile)
+                        {
+                            Step::run("Uploading debug symbols to Sentry", || {
+                                sentry.upload(&[lib_path.clone()])
+                            })
+                            .map_err(Error::SentryUploadFailed)?;
+                        }
+                        if let Err(err) = util::compile_commands::update(
+                            config.app().root_dir(),
+                            config.app().target_dir(),
+                            &[target.triple],
+                            profile,
+                        ) {
+                            log::warn!("failed to update compile commands: {}", err);
+                        }
+                        built.borrow_mut().push(*target);
+                        Ok(())
                     },
                 )
-                .map_err(Error::TargetInvalid)?
+                .map_err(Error::TargetInvalid)??;
+                if aar {
+                    let aar_path = Step::run("Assembling AAR", || {
+                        aar::build(config, &env, profile, &built.borrow())
+                    })
+                    .map_err(Error::AarFailed)?;
+                    cli::print_artifact(&aar_path);
+                    util::out_dir::collect(
+                        config.app(),
+                        "android",
+                        profile,
+                        &[util::out_dir::Artifact::new(
+                            util::out_dir::Kind::Aar,
+                            aar_path,
+                        )],
+                    )
+                    .map_err(Error::OutDirFailed)?;
+                }
+                Ok(())
             }),
             Command::Run {
                 profile: cli::Profile { profile },
-            } => with_config(non_interactive, wrapper, |config, _| {
-                ensure_init(config)?;
-                device_prompt(&env)
-                    .map_err(Error::DevicePromptFailed)?
-                    .run(config, &env, noise_level, profile)
-                    .map_err(Error::RunFailed)
+                entry_point,
+                filter,
+                wait,
+                all_devices,
+                run_args,
+            } => with_config(
+                non_interactive,
+                &run_args.apply(&overrides),
+                wrapper,
+                |config, _| {
+                    ensure_init(config)?;
+                    let entry_point = entry_point.entry_point().map_err(Error::BinUnsupported)?;
+                    // Gradle runs `cargo android build`, which picks this up.
+                    let env = env.clone().with_entry_point(&entry_point);
+                    if all_devices {
+                        let filter = device::Filter::from(filter);
+                        let devices = device::poll(
+                            wait.timeout(),
+                            || {
+                                adb::device_list(&env).map(|device_list| {
+                                    device_list
+                                        .into_iter()
+                                        .filter(|device| {
+                                            filter
+                                                .matches(device.os_version(), device.target().arch)
+                                        })
+                                        .collect::<Vec<_>>()
+                                })
+                            },
+                            |devices| !devices.is_empty(),
+                        )
+                        .map_err(Error::ListFailed)?;
+                        if devices.is_empty() {
+                            return Err(Error::DevicePromptFailed(if filter.is_empty() {
+                                PromptError::none_detected("Android")
+                            } else {
+                                PromptError::none_matched(
+                                    "Android",
+                                    filter.suggest_arch(
+                                        Target::all().values().map(|target| target.arch),
+                                    ),
+                                )
+                            }));
+                        }
+                        // Devices sharing an ABI can share an APK, too.
+                        let mut built = BTreeSet::new();
+                        for device in &devices {
+                            if built.insert(device.target().triple) {
+                                device
+                                    .build(config, &env, noise_level, profile, &Default::default())
+                                    .map_err(Error::RunFailed)?;
+                            }
+                        }
+                        let (config, env) = (config.clone(), env.clone());
+                        return device::deploy_all(wrapper, devices, move |device| {
+                            device.deploy(&config, &env, profile, &Default::default())
+                        })
+                        .map_err(Error::DeployAllFailed);
+                    }
+                    device_prompt(&env, Some(config.app()), &filter.into(), wait.timeout())
+                        .map_err(Error::DevicePromptFailed)?
+                        .run(config, &env, noise_level, profile, &Default::default())
+                        .map_err(Error::RunFailed)
+                },
+            ),
+            Command::Test {
+                profile: cli::Profile { profile },
+                filter,
+                wait,
+                native,
+                coverage,
+                args,
+            } => with_config(non_interactive, &overrides, wrapper, |config, metadata| {
+                let device =
+                    device_prompt(&env, Some(config.app()), &filter.into(), wait.timeout())
+                        .map_err(Error::DevicePromptFailed)?;
+                if !native {
+                    ensure_init(config)?;
+                    return device
+                        .run_instrumented_tests(config, &env)
+                        .map_err(Error::TestFailed);
+                }
+                let target = device.target();
+                util::rustup::ensure_targets(Some(target.triple), non_interactive)
+                    .map_err(Error::RustupFailed)?;
+                let harnesses = Step::run(format!("Building tests for {}", target.triple), || {
+                    target.build_tests(config, metadata, &env, noise_level, profile, coverage)
+                })
+                .map_err(Error::TestBuildFailed)?;
+                let coverage_dir =
+                    util::coverage::output_dir(config.app().target_dir(), target.triple);
+                if coverage.yes() {
+                    util::coverage::prepare(&coverage_dir).map_err(Error::CoverageFailed)?;
+                }
+                let result = device
+                    .run_tests(
+                        &env,
+                        &harnesses,
+                        &args,
+                        Some(util::coverage::profraw_dir(&coverage_dir))
+                            .filter(|_| coverage.yes())
+                            .as_deref(),
+                    )
+                    .map_err(Error::TestFailed);
+                if coverage.yes() {
+                    let executables = harnesses
+                        .iter()
+                        .map(|harness| harness.executable.clone())
+                        .collect::<Vec<_>>();
+                    util::coverage::report(&coverage_dir, &executables)
+                        .map_err(Error::CoverageFailed)?;
+                }
+                result
             }),
-            Command::Stacktrace => with_config(non_interactive, wrapper, |config, _| {
+            Command::TestLab {
+                test_lab: cli::TestLab { devices, timeout },
+            } => with_config(non_interactive, &overrides, wrapper, |config, _| {
                 ensure_init(config)?;
-                device_prompt(&env)
-                    .map_err(Error::DevicePromptFailed)?
-                    .stacktrace(config, &env)
-                    .map_err(Error::StacktraceFailed)
+                let (app, test) =
+                    Step::run("Assembling APKs", || assemble_instrumented(config, &env))
+                        .map_err(Error::TestLabBuildFailed)?;
+                let command = test_lab::command("android", &devices, timeout.as_deref())
+                    .with_args(&["--type", "instrumentation"])
+                    .with_arg("--app")
+                    .with_arg(app)
+                    .with_arg("--test")
+                    .with_arg(test);
+                test_lab::run(command).map_err(Error::TestLabFailed)
             }),
-            Command::List => adb::device_list(&env)
-                .map_err(Error::ListFailed)
-                .map(|device_list| {
-                    prompt::list_display_only(device_list.iter(), device_list.len());
-                }),
+            Command::Emulator {
+                api,
+                abi,
+                headless,
+                boot_timeout,
+            } => {
+                let options = emulator::Options {
+                    api,
+                    abi,
+                    headless,
+                    timeout: Duration::from_secs(boot_timeout),
+                };
+                let serial_no = Step::run("Booting emulator", || emulator::launch(&env, &options))
+                    .map_err(Error::EmulatorFailed)?;
+                // This is the only thing on stdout, so scripts can capture it.
+                println!("{}", serial_no);
+                Ok(())
+            }
+            Command::Stacktrace { filter, wait } => {
+                with_config(non_interactive, &overrides, wrapper, |config, _| {
+                    ensure_init(config)?;
+                    device_prompt(&env, Some(config.app()), &filter.into(), wait.timeout())
+                        .map_err(Error::DevicePromptFailed)?
+                        .stacktrace(config, &env)
+                        .map_err(Error::StacktraceFailed)
+                })
+            }
+            Command::DeviceInfo { filter, wait } => {
+                with_config(non_interactive, &overrides, wrapper, |config, _| {
+                    let device =
+                        device_prompt(&env, Some(config.app()), &filter.into(), wait.timeout())
+                            .map_err(Error::DevicePromptFailed)?;
+                    let info = adb::device_info(&env, device.serial_no())
+                        .map_err(Error::DeviceInfoFailed)?;
+                    device::print_info(&info);
+                    Ok(())
+                })
+            }
+            Command::List { filter } => {
+                let filter = device::Filter::from(filter);
+                // This is how you check what's connected, so it's always fresh.
+                util::cache::skipping(|| adb::device_list(&env))
+                    .map_err(Error::ListFailed)
+                    .map(|device_list| {
+                        let device_list = device_list
+                            .into_iter()
+                            .filter(|device| {
+                                filter.matches(device.os_version(), device.target().arch)
+                            })
+                            .collect::<Vec<_>>();
+                        prompt::list_display_only(device_list.iter(), device_list.len());
+                    })
+            }
+            Command::Sign { artifact, out } => {
+                with_config(non_interactive, &overrides, wrapper, |config, _| {
+                    let signer = config.signer().ok_or(Error::SignerMissing)?;
+                    let out = out.unwrap_or_else(|| {
+                        let stem = artifact
+                            .file_stem()
+                            .map(|stem| stem.to_string_lossy().into_owned())
+                            .unwrap_or_default();
+                        let stem = stem.trim_end_matches("-unsigned");
+                        let extension = artifact
+                            .extension()
+                            .map(|ext| ext.to_string_lossy().into_owned())
+                            .unwrap_or_default();
+                        artifact.with_file_name(format!("{}-signed.{}", stem, extension))
+                    });
+                    signer
+                        .sign(&env, &artifact, &out)
+                        .map_err(Error::SignFailed)?;
+                    cli::print_artifact(&out);
+                    Ok(())
+                })
+            }
+            Command::Doctor => {
+                unreachable!("developer error: `doctor` should've already been handled")
+            }
         }
     }
 }
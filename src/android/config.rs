@@ -1,34 +1,124 @@
+use super::signer::{self, Signer};
 use crate::{
     config::app::App,
+    secret::{self, Secret},
     util::{self, cli::Report},
 };
 use serde::{Deserialize, Serialize};
 use std::{
     fmt::{self, Display},
     path::PathBuf,
+    sync::Arc,
 };
 
 const DEFAULT_MIN_SDK_VERSION: u32 = 24;
 const DEFAULT_VULKAN_VALIDATION: bool = true;
-static DEFAULT_PROJECT_DIR: &str = "gen/android";
+const DEFAULT_EDITOR: &str = "Android Studio";
+const DEFAULT_LIBRARY_MODULE: bool = false;
 
 const fn default_true() -> bool {
     true
 }
 
+fn default_channel_name() -> String {
+    "Background work".to_owned()
+}
+
+/// Generates `RustService`, a foreground service that runs the Rust lib's
+/// `runInBackground` on its own thread, i.e.
+/// `[package.metadata.cargo-android.foreground-service]`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ForegroundService {
+    /// The name of the notification channel, which users see in settings.
+    #[serde(default = "default_channel_name")]
+    channel_name: String,
+    /// The title of the notification shown while the service is running;
+    /// defaults to the app's name.
+    #[serde(default)]
+    notification_title: Option<String>,
+}
+
+/// Generates `RustWorker`, a WorkManager worker that runs the Rust lib's
+/// `runInBackground`, i.e. `[package.metadata.cargo-android.worker]`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Worker {
+    /// How often to run, if periodically; WorkManager won't go below 15
+    /// minutes.
+    #[serde(default)]
+    interval_minutes: Option<u32>,
+}
+
+/// Certificate pins for a domain, i.e.
+/// `{ domain = "api.example.com", sha256 = ["..."] }`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Pin {
+    domain: String,
+    #[serde(default)]
+    include_subdomains: bool,
+    /// Base64-encoded SHA-256 digests of the pinned public keys.
+    sha256: Vec<String>,
+    /// When the pins stop being enforced, as `yyyy-MM-dd`; without this, an
+    /// app that's never updated stops working once the keys are rotated.
+    #[serde(default)]
+    expiration: Option<String>,
+}
+
+/// What goes in `network_security_config.xml`, i.e.
+/// `[android.network-security]`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct NetworkSecurity {
+    /// Domains that may be reached over plain HTTP, which is only ever allowed
+    /// in debug builds.
+    #[serde(default)]
+    cleartext_domains: Vec<String>,
+    #[serde(default)]
+    pins: Vec<Pin>,
+}
+
+impl NetworkSecurity {
+    fn validate(&self) {
+        for pin in &self.pins {
+            if pin.sha256.len() < 2 {
+                log::warn!(
+                    "`{}.network-security.pins` only has one pin for {:?}; without a backup pin, rotating that key breaks the app",
+                    super::NAME,
+                    pin.domain
+                );
+            }
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct Metadata {
     #[serde(default = "default_true")]
     supported: bool,
     #[serde(default)]
+    no_default_features: Option<bool>,
+    #[serde(default)]
     features: Option<Vec<String>>,
+    #[serde(flatten)]
+    profiles: util::Profiles,
+    #[serde(default)]
+    foreground_service: Option<ForegroundService>,
+    #[serde(default)]
+    worker: Option<Worker>,
 }
 
 impl Default for Metadata {
     fn default() -> Self {
         Self {
             supported: true,
+            no_default_features: None,
             features: None,
+            profiles: Default::default(),
+            foreground_service: None,
+            worker: None,
         }
     }
 }
@@ -38,13 +128,28 @@ impl Metadata {
         self.supported
     }
 
+    /// Listing features means the default ones are unwanted, unless
+    /// `no-default-features` explicitly says otherwise.
     pub fn no_default_features(&self) -> bool {
-        self.features.is_some()
+        self.no_default_features
+            .unwrap_or_else(|| self.features.is_some())
     }
 
     pub fn features(&self) -> Option<&[String]> {
         self.features.as_deref()
     }
+
+    pub fn profiles(&self) -> &util::Profiles {
+        &self.profiles
+    }
+
+    pub fn foreground_service(&self) -> Option<&ForegroundService> {
+        self.foreground_service.as_ref()
+    }
+
+    pub fn worker(&self) -> Option<&Worker> {
+        self.worker.as_ref()
+    }
 }
 
 #[derive(Debug)]
@@ -88,6 +193,10 @@ impl Display for ProjectDirInvalid {
 #[derive(Debug)]
 pub enum Error {
     ProjectDirInvalid(ProjectDirInvalid),
+    SecretInvalid {
+        key: &'static str,
+        cause: secret::ParseError,
+    },
 }
 
 impl Error {
@@ -97,6 +206,9 @@ impl Error {
                 msg,
                 format!("`{}.project-dir` invalid: {}", super::NAME, err),
             ),
+            Self::SecretInvalid { key, cause } => {
+                Report::error(msg, format!("`{}.{}` invalid: {}", super::NAME, key, cause))
+            }
         }
     }
 }
@@ -104,11 +216,32 @@ impl Error {
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Raw {
+    label: Option<String>,
     min_sdk_version: Option<u32>,
     vulkan_validation: Option<bool>,
     project_dir: Option<String>,
     no_default_features: Option<bool>,
     features: Option<Vec<String>>,
+    keystore_password: Option<String>,
+    key_password: Option<String>,
+    editor: Option<String>,
+    library_module: Option<bool>,
+    sdk_root: Option<String>,
+    adb: Option<String>,
+    network_security: Option<NetworkSecurity>,
+    signer: Option<signer::Raw>,
+}
+
+impl Raw {
+    /// The SDK to use, overriding `ANDROID_SDK_ROOT` and friends.
+    pub fn sdk_root(&self) -> Option<&str> {
+        self.sdk_root.as_deref()
+    }
+
+    /// The `adb` to use, overriding the SDK's and whichever's on the `PATH`.
+    pub fn adb(&self) -> Option<&str> {
+        self.adb.as_deref()
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -116,15 +249,45 @@ pub struct Raw {
 pub struct Config {
     #[serde(skip_serializing)]
     app: App,
+    label: String,
     min_sdk_version: u32,
     vulkan_validation: bool,
     project_dir: PathBuf,
+    #[serde(skip_serializing)]
+    keystore_password: Option<Secret>,
+    #[serde(skip_serializing)]
+    key_password: Option<Secret>,
+    editor: String,
+    library_module: bool,
+    network_security: Option<NetworkSecurity>,
+    #[serde(skip_serializing)]
+    signer: Option<Arc<dyn Signer>>,
 }
 
 impl Config {
     pub fn from_raw(app: App, raw: Option<Raw>) -> Result<Self, Error> {
         let raw = raw.unwrap_or_default();
 
+        let label = raw
+            .label
+            .map(|label| {
+                if label == app.stylized_name() {
+                    log::warn!(
+                        "`{}.label` is set to the default value; you can remove it from your config",
+                        super::NAME
+                    );
+                }
+                label
+            })
+            .unwrap_or_else(|| {
+                log::info!(
+                    "`{}.label` not set; defaulting to {:?}",
+                    super::NAME,
+                    app.stylized_name()
+                );
+                app.stylized_name().to_owned()
+            });
+
         let min_sdk_version = raw.min_sdk_version.unwrap_or_else(|| {
             log::info!(
                 "`{}.min-sdk-version` not set; defaulting to {}",
@@ -144,7 +307,7 @@ impl Config {
         });
 
         let project_dir = if let Some(project_dir) = raw.project_dir {
-            if project_dir == DEFAULT_PROJECT_DIR {
+            if app.prefix_path(&project_dir) == app.gen_dir().join(super::NAME) {
                 log::warn!(
                     "`{}.project-dir` is set to the default value; you can remove it from your config",
                     super::NAME
@@ -172,19 +335,86 @@ impl Config {
                 ))
             }
         } else {
+            let project_dir = app.gen_dir().join(super::NAME);
             log::info!(
                 "`{}.project-dir` not set; defaulting to {:?}",
                 super::NAME,
-                DEFAULT_PROJECT_DIR
+                project_dir
             );
-            Ok(DEFAULT_PROJECT_DIR.into())
+            let project_dir_str = project_dir.display().to_string();
+            if !project_dir_str.contains(' ') {
+                Ok(project_dir)
+            } else {
+                Err(Error::ProjectDirInvalid(
+                    ProjectDirInvalid::ContainsSpaces {
+                        project_dir: project_dir_str,
+                    },
+                ))
+            }
         }?;
 
+        let parse_secret = |key: &'static str, value: Option<String>| {
+            value
+                .map(|value| {
+                    value
+                        .parse::<Secret>()
+                        .map_err(|cause| Error::SecretInvalid { key, cause })
+                })
+                .transpose()
+        };
+        let keystore_password = parse_secret("keystore-password", raw.keystore_password)?;
+        let key_password = parse_secret("key-password", raw.key_password)?;
+        let signer = raw
+            .signer
+            .map(|signer| signer.into_signer(app.root_dir()))
+            .transpose()
+            .map_err(|cause| Error::SecretInvalid {
+                key: "signer.pin",
+                cause,
+            })?;
+        if signer.is_some() && keystore_password.is_some() {
+            log::warn!(
+                "`{}.signer` is set, so `{}.keystore-password` will be ignored",
+                super::NAME,
+                super::NAME
+            );
+        }
+
+        let editor = raw.editor.unwrap_or_else(|| {
+            log::info!(
+                "`{}.editor` not set; defaulting to {:?}",
+                super::NAME,
+                DEFAULT_EDITOR
+            );
+            DEFAULT_EDITOR.to_owned()
+        });
+
+        let library_module = raw.library_module.unwrap_or_else(|| {
+            log::info!(
+                "`{}.library-module` not set; defaulting to {}",
+                super::NAME,
+                DEFAULT_LIBRARY_MODULE
+            );
+            DEFAULT_LIBRARY_MODULE
+        });
+
+        let network_security = raw.network_security;
+        if let Some(network_security) = &network_security {
+            network_security.validate();
+        }
+
         Ok(Self {
             app,
+            label,
             min_sdk_version,
             vulkan_validation,
             project_dir,
+            keystore_password,
+            key_password,
+            editor,
+            library_module,
+            network_security,
+            signer,
         })
     }
 
@@ -192,8 +422,12 @@ impl Config {
         &self.app
     }
 
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
     pub fn so_name(&self) -> String {
-        format!("lib{}.so", self.app().name_snake())
+        format!("lib{}.so", self.app().lib_name())
     }
 
     pub fn min_sdk_version(&self) -> u32 {
@@ -206,7 +440,43 @@ impl Config {
             .join(self.app().name())
     }
 
+    pub fn keystore_password(&self) -> Option<&Secret> {
+        self.keystore_password.as_ref()
+    }
+
+    pub fn key_password(&self) -> Option<&Secret> {
+        self.key_password.as_ref()
+    }
+
+    /// Set if release builds are signed with a key held in a KMS or HSM,
+    /// rather than a local keystore.
+    pub fn signer(&self) -> Option<&dyn Signer> {
+        self.signer.as_deref()
+    }
+
+    /// The application `cargo android open` opens the project with.
+    pub fn editor(&self) -> &str {
+        &self.editor
+    }
+
     pub fn project_dir_exists(&self) -> bool {
         self.project_dir().is_dir()
     }
+
+    /// Whether the Rust lib is built and packaged by its own `rust` library
+    /// module, which the app module depends on, rather than by the app module
+    /// itself.
+    pub fn library_module(&self) -> bool {
+        self.library_module
+    }
+
+    pub fn network_security(&self) -> Option<&NetworkSecurity> {
+        self.network_security.as_ref()
+    }
+
+    /// The Gradle module the Rust lib is packaged into.
+    pub fn rust_module_dir(&self) -> PathBuf {
+        self.project_dir()
+            .join(if self.library_module { "rust" } else { "app" })
+    }
 }
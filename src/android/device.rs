@@ -1,33 +1,85 @@
 use super::{
-    adb,
+    adb, apk,
     config::Config,
     env::Env,
     jnilibs::{self, JniLibs},
-    target::{BuildError, Target},
+    signer,
+    target::{BuildError, Target, TestHarness},
 };
 use crate::{
     env::ExplicitEnv as _,
+    event::{Event, Listener},
     opts::{NoiseLevel, Profile},
+    secret,
     util::{
         self,
         cli::{Report, Reportable},
+        install,
+        progress::Step,
+        repro::{self, Recorded as _},
     },
 };
-use std::fmt::{self, Display};
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+// `adb install` failures that'll just happen again, so aren't worth retrying.
+static PERMANENT_INSTALL_FAILURES: &[&str] = &[
+    "INSTALL_FAILED_VERSION_DOWNGRADE",
+    "INSTALL_FAILED_UPDATE_INCOMPATIBLE",
+    "INSTALL_FAILED_INSUFFICIENT_STORAGE",
+    "INSTALL_FAILED_NO_MATCHING_ABIS",
+    "INSTALL_FAILED_OLDER_SDK",
+    "INSTALL_FAILED_INVALID_APK",
+    "INSTALL_PARSE_FAILED",
+];
 
 fn gradlew(config: &Config, env: &Env) -> bossy::Command {
     let gradlew_path = config.project_dir().join("gradlew");
-    bossy::Command::pure(&gradlew_path)
-        .with_env_vars(env.explicit_env())
-        .with_arg("--project-dir")
-        .with_arg(config.project_dir())
+    util::quiet_stdout(
+        bossy::Command::pure(&gradlew_path)
+            .with_env_vars(env.explicit_env())
+            .with_arg("--project-dir")
+            .with_arg(config.project_dir())
+            .with_args(util::proxy::gradle_args()),
+    )
+}
+
+// `adb shell` hands the command to a shell, so arguments need quoting.
+fn shell_args(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| format!("'{}'", arg.replace('\'', "'\\''")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Assembles the debug app and its instrumented test APKs for arm64, which is
+/// what device farms run, and returns their paths.
+pub fn assemble_instrumented(config: &Config, env: &Env) -> bossy::Result<(PathBuf, PathBuf)> {
+    gradlew(config, env)
+        .with_args(&["assembleArm64Debug", "assembleArm64DebugAndroidTest"])
+        .recorded(env, Default::default())
+        .run_and_wait()?;
+    let outputs = config.project_dir().join("app/build/outputs/apk");
+    Ok((
+        outputs.join("arm64/debug/app-arm64-debug.apk"),
+        outputs.join("androidTest/arm64/debug/app-arm64-debug-androidTest.apk"),
+    ))
 }
 
 #[derive(Debug)]
 pub enum ApkBuildError {
     LibSymlinkCleaningFailed(jnilibs::RemoveBrokenLinksError),
     LibBuildFailed(BuildError),
+    SecretResolveFailed {
+        key: &'static str,
+        cause: secret::ResolveError,
+    },
     AssembleFailed(bossy::Error),
+    SignFailed(signer::Error),
 }
 
 impl Reportable for ApkBuildError {
@@ -35,7 +87,12 @@ impl Reportable for ApkBuildError {
         match self {
             Self::LibSymlinkCleaningFailed(err) => err.report(),
             Self::LibBuildFailed(err) => err.report(),
+            Self::SecretResolveFailed { key, cause } => Report::error(
+                format!("Failed to resolve `{}.{}`", super::NAME, key),
+                cause,
+            ),
             Self::AssembleFailed(err) => Report::error("Failed to assemble APK", err),
+            Self::SignFailed(err) => Report::error("Failed to sign APK", err),
         }
     }
 }
@@ -74,6 +131,37 @@ impl Reportable for RunError {
     }
 }
 
+#[derive(Debug)]
+pub enum TestError {
+    MkdirFailed(bossy::Error),
+    PushFailed { path: PathBuf, cause: bossy::Error },
+    TestsFailed { name: String, cause: bossy::Error },
+    BenchesFailed { name: String, cause: bossy::Error },
+    PullFailed(bossy::Error),
+    InstrumentedTestsFailed(bossy::Error),
+}
+
+impl Reportable for TestError {
+    fn report(&self) -> Report {
+        match self {
+            Self::MkdirFailed(err) => {
+                Report::error("Failed to create test directory on device", err)
+            }
+            Self::PushFailed { path, cause } => {
+                Report::error(format!("Failed to push {:?} to device", path), cause)
+            }
+            Self::TestsFailed { name, cause } => {
+                Report::error(format!("Tests in {:?} failed", name), cause)
+            }
+            Self::BenchesFailed { name, cause } => {
+                Report::error(format!("Benchmarks in {:?} failed", name), cause)
+            }
+            Self::PullFailed(err) => Report::error("Failed to pull results from device", err),
+            Self::InstrumentedTestsFailed(err) => Report::error("Instrumented tests failed", err),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum StacktraceError {
     PipeFailed(util::PipeError),
@@ -92,6 +180,7 @@ pub struct Device<'a> {
     serial_no: String,
     name: String,
     model: String,
+    os_version: String,
     target: &'a Target<'a>,
 }
 
@@ -101,7 +190,7 @@ impl<'a> Display for Device<'a> {
         if self.model != self.name {
             write!(f, " ({})", self.model)?;
         }
-        Ok(())
+        write!(f, " - Android {}, {}", self.os_version, self.target.arch)
     }
 }
 
@@ -110,16 +199,39 @@ impl<'a> Device<'a> {
         serial_no: String,
         name: String,
         model: String,
+        os_version: String,
         target: &'a Target<'a>,
     ) -> Self {
         Self {
             serial_no,
             name,
             model,
+            os_version,
             target,
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn os_version(&self) -> Option<&str> {
+        Some(&self.os_version)
+    }
+
+    pub fn serial_no(&self) -> &str {
+        &self.serial_no
+    }
+
+    /// The serial number, named to match Apple devices.
+    pub fn id(&self) -> &str {
+        &self.serial_no
+    }
+
     pub fn target(&self) -> &'a Target<'a> {
         self.target
     }
@@ -136,46 +248,178 @@ impl<'a> Device<'a> {
         profile: Profile,
     ) -> Result<(), ApkBuildError> {
         use heck::CamelCase as _;
+        let _lock = util::lock::ProjectLock::acquire(config.app().gen_dir());
         JniLibs::remove_broken_links(config).map_err(ApkBuildError::LibSymlinkCleaningFailed)?;
         let flavor = self.target.arch.to_camel_case();
         let build_ty = profile.as_str().to_camel_case();
-        gradlew(config, env)
+        let command = gradlew(config, env);
+        let mut vars = repro::Vars::default();
+        // With a signer, Gradle builds an unsigned APK, which we sign below.
+        if profile.release() && config.signer().is_none() {
+            // These are only ever passed through the environment, so they
+            // don't end up in any generated files.
+            let root_dir = config.app().root_dir();
+            let secrets = [
+                (
+                    "keystore-password",
+                    "CARGO_MOBILE_KEYSTORE_PASSWORD",
+                    config.keystore_password(),
+                ),
+                (
+                    "key-password",
+                    "CARGO_MOBILE_KEY_PASSWORD",
+                    config.key_password(),
+                ),
+            ];
+            for (key, var, secret) in secrets.iter() {
+                if let Some(secret) = secret {
+                    let value = secret
+                        .resolve(root_dir)
+                        .map_err(|cause| ApkBuildError::SecretResolveFailed { key: *key, cause })?;
+                    vars.add(*var, value);
+                }
+            }
+        }
+        command
             .with_arg(format!("assemble{}{}", flavor, build_ty))
             .with_arg(match noise_level {
+                _ if util::cli::quiet() => "--quiet",
                 NoiseLevel::Polite => "--warn",
                 NoiseLevel::LoudAndProud => "--info",
                 NoiseLevel::FranklyQuitePedantic => "--debug",
             })
+            .recorded(env, vars)
             .run_and_wait()
             .map_err(ApkBuildError::AssembleFailed)?;
+        if let (Profile::Release, Some(signer)) = (profile, config.signer()) {
+            let signed = self.apk_path(config, profile);
+            let unsigned =
+                signed.with_file_name(format!("app-{}-release-unsigned.apk", self.target.arch));
+            signer
+                .sign(env, &unsigned, &signed)
+                .map_err(ApkBuildError::SignFailed)?;
+        }
         Ok(())
     }
 
-    fn install_apk(
-        &self,
-        config: &Config,
-        env: &Env,
-        profile: Profile,
-    ) -> Result<(), ApkInstallError> {
+    fn apk_path(&self, config: &Config, profile: Profile) -> PathBuf {
         let flavor = self.target.arch;
         let build_ty = profile.as_str();
+        let signed = config.keystore_password().is_some() || config.signer().is_some();
         let apk_suffix = match profile {
             Profile::Debug => build_ty,
-            // TODO: how to handle signed APKs?
+            Profile::Release if signed => build_ty,
             Profile::Release => "release-unsigned",
         };
-        let apk_path = config.project_dir().join(format!(
+        config.project_dir().join(format!(
             "app/build/outputs/apk/{}/{}/app-{}-{}.apk",
             flavor, build_ty, flavor, apk_suffix
-        ));
-        self.adb(env)
-            .with_arg("install")
+        ))
+    }
+
+    fn install_apk(
+        &self,
+        config: &Config,
+        env: &Env,
+        profile: Profile,
+    ) -> Result<(), ApkInstallError> {
+        let apk_path = self.apk_path(config, profile);
+        let target_dir = config.app().target_dir();
+        let key = format!(
+            "android-{}-{}-{}",
+            self.serial_no,
+            self.target.arch,
+            profile.as_str()
+        );
+        let contents = apk::contents(&apk_path)
+            .map_err(|err| log::info!("failed to read APK contents: {}", err))
+            .ok();
+        let plan = match &contents {
+            Some(contents) => {
+                install::plan(target_dir, &key, &self.serial_no, contents, apk::patchable)
+            }
+            None => install::Plan::Full,
+        };
+        let installed = || {
+            self.adb(env)
+                .with_args(&["shell", "pm", "path", &self.package(config)])
+                .run_and_wait_for_output()
+                .map(|output| !output.stdout().is_empty())
+                .unwrap_or(false)
+        };
+        let result = match plan {
+            // It could've been uninstalled since, which we wouldn't know about.
+            install::Plan::Skip if installed() => {
+                log::info!("APK is unchanged since the last install, so skipping install");
+                return Ok(());
+            }
+            install::Plan::Patch { changed, .. } if installed() => {
+                log::info!("only {:?} changed, so doing a streamed install", changed);
+                self.install_streamed(env, &apk_path).or_else(|err| {
+                    log::warn!(
+                        "streamed install failed, so falling back to a full install: {}",
+                        err
+                    );
+                    self.install_full(env, &apk_path)
+                })
+            }
+            _ => self.install_full(env, &apk_path),
+        };
+        match result {
+            Ok(()) => {
+                if let Some(contents) = contents {
+                    install::record(target_dir, &key, &self.serial_no, contents);
+                }
+                Ok(())
+            }
+            Err(err) => {
+                install::forget(target_dir, &key);
+                Err(ApkInstallError::InstallFailed(err))
+            }
+        }
+    }
+
+    /// The output's captured so we can tell which failures are worth
+    /// retrying; it's just `Success` otherwise.
+    fn install_full(&self, env: &Env, apk_path: &Path) -> bossy::Result<()> {
+        let transient = util::retry::unless_output_contains(PERMANENT_INSTALL_FAILURES);
+        util::retry::retry("Installing APK", transient, || {
+            self.adb(env)
+                .with_arg("install")
+                .with_arg(apk_path)
+                .run_and_wait_for_output()
+        })?;
+        Ok(())
+    }
+
+    /// This still installs the whole APK, rather than splitting out what
+    /// changed, but gets it onto the device sooner: with a v4 signature (the
+    /// `.idsig` Gradle writes next to the APK), Android 11+ can start the app
+    /// before the APK's done streaming, and otherwise `--fastdeploy` diffs
+    /// against what's installed and only sends the parts that changed.
+    fn install_streamed(&self, env: &Env, apk_path: &Path) -> bossy::Result<()> {
+        let mut idsig = apk_path.as_os_str().to_owned();
+        idsig.push(".idsig");
+        let mode = if Path::new(&idsig).is_file() {
+            "--incremental"
+        } else {
+            "--fastdeploy"
+        };
+        util::quiet_stdout(self.adb(env))
+            .with_args(&["install", "-r", mode])
             .with_arg(apk_path)
-            .run_and_wait()
-            .map_err(ApkInstallError::InstallFailed)?;
+            .run_and_wait()?;
         Ok(())
     }
 
+    fn package(&self, config: &Config) -> String {
+        format!(
+            "{}.{}",
+            config.app().reverse_domain(),
+            config.app().name_snake()
+        )
+    }
+
     fn wake_screen(&self, env: &Env) -> bossy::Result<()> {
         self.adb(env)
             .with_args(&["shell", "input", "keyevent", "KEYCODE_WAKEUP"])
@@ -183,27 +427,96 @@ impl<'a> Device<'a> {
         Ok(())
     }
 
-    pub fn run(
+    /// Builds the APK for this device's target, without deploying it.
+    pub fn build(
         &self,
         config: &Config,
         env: &Env,
         noise_level: NoiseLevel,
         profile: Profile,
+        listener: &Listener,
     ) -> Result<(), RunError> {
-        self.build_apk(config, env, noise_level, profile)
-            .map_err(RunError::ApkBuildFailed)?;
-        self.install_apk(config, env, profile)
-            .map_err(RunError::ApkInstallFailed)?;
+        listener.emit(Event::BuildStarted);
+        listener.emit(Event::CompilingTarget {
+            triple: self.target.triple,
+        });
+        Step::run("Building APK", || {
+            self.build_apk(config, env, noise_level, profile)
+        })
+        .map_err(RunError::ApkBuildFailed)?;
+        util::cli::print_artifact(&self.apk_path(config, profile));
+        Ok(())
+    }
+
+    /// Installs and launches an already built APK.
+    pub fn deploy(
+        &self,
+        config: &Config,
+        env: &Env,
+        profile: Profile,
+        listener: &Listener,
+    ) -> Result<(), RunError> {
+        listener.emit(Event::Deploying { device: &self.name });
+        Step::run(format!("Installing on {}", self.name), || {
+            self.install_apk(config, env, profile)
+        })
+        .map_err(|err| {
+            adb::device_list::clear_cache();
+            RunError::ApkInstallFailed(err)
+        })?;
         let activity = format!(
-            "{}.{}/android.app.NativeActivity",
+            "{}.{}/{}",
             config.app().reverse_domain(),
             config.app().name_snake(),
+            // WebView apps get their own activity, which `wry` generates.
+            if config.app().template_pack().webview() {
+                ".MainActivity"
+            } else {
+                "android.app.NativeActivity"
+            },
         );
-        self.adb(env)
-            .with_args(&["shell", "am", "start", "-n", &activity])
-            .run_and_wait()
-            .map_err(RunError::StartFailed)?;
-        self.wake_screen(env).map_err(RunError::WakeScreenFailed)?;
+        // The app can't see our environment, so `.env` values are passed as
+        // string extras on the launch intent instead.
+        let mut command = self
+            .adb(env)
+            .with_args(&["shell", "am", "start", "-n", &activity]);
+        for (key, value) in env.dotenv() {
+            // `adb shell` hands this to a shell, so the value needs quoting.
+            let value = format!("'{}'", value.replace('\'', "'\\''"));
+            command.add_args(&["--es", key.as_str(), value.as_str()]);
+        }
+        let run_args = config.app().run_args();
+        for (key, value) in run_args.extras() {
+            command.add_args(&["--es", key.as_str(), shell_args(&[value.clone()]).as_str()]);
+        }
+        if !run_args.args().is_empty() {
+            // `--esa` splits on commas, unless they're escaped.
+            let args = run_args
+                .args()
+                .iter()
+                .map(|arg| arg.replace(',', "\\,"))
+                .collect::<Vec<_>>()
+                .join(",");
+            command.add_args(&["--esa", "args", shell_args(&[args]).as_str()]);
+        }
+        command.run_and_wait().map_err(|err| {
+            adb::device_list::clear_cache();
+            RunError::StartFailed(err)
+        })?;
+        listener.emit(Event::Launched);
+        self.wake_screen(env).map_err(RunError::WakeScreenFailed)
+    }
+
+    pub fn run(
+        &self,
+        config: &Config,
+        env: &Env,
+        noise_level: NoiseLevel,
+        profile: Profile,
+        listener: &Listener,
+    ) -> Result<(), RunError> {
+        self.build(config, env, noise_level, profile, listener)?;
+        self.deploy(config, env, profile, listener)?;
         let filter = format!(
             "{}:{}",
             config.app().name(),
@@ -220,6 +533,208 @@ impl<'a> Device<'a> {
         Ok(())
     }
 
+    /// Pushes a harness and the libs it needs into `dir`, returning the
+    /// harness's file name.
+    fn push_harness(
+        &self,
+        env: &Env,
+        harness: &TestHarness,
+        dir: &str,
+    ) -> Result<String, TestError> {
+        for path in harness.libs.iter().chain(Some(&harness.executable)) {
+            util::quiet_stdout(self.adb(env))
+                .with_arg("push")
+                .with_arg(path)
+                .with_arg(dir)
+                .run_and_wait()
+                .map_err(|cause| TestError::PushFailed {
+                    path: path.clone(),
+                    cause,
+                })?;
+        }
+        Ok(harness
+            .executable
+            .file_name()
+            .expect("test harness path had no file name")
+            .to_string_lossy()
+            .into_owned())
+    }
+
+    /// Runs native test harnesses directly with `adb shell`, which (unlike on
+    /// iOS) doesn't require wrapping them in an app. Output is streamed as
+    /// the tests run, and we stop at the first harness that fails.
+    ///
+    /// With `coverage_dir`, the harnesses' raw coverage profiles are pulled
+    /// into it afterwards, even if tests failed.
+    pub fn run_tests(
+        &self,
+        env: &Env,
+        harnesses: &[TestHarness],
+        args: &[String],
+        coverage_dir: Option<&Path>,
+    ) -> Result<(), TestError> {
+        static TEST_DIR: &str = "/data/local/tmp/cargo-mobile-tests";
+        let device_coverage_dir = format!("{}/coverage", TEST_DIR);
+        self.adb(env)
+            .with_args(&["shell", "rm", "-rf", &device_coverage_dir])
+            .run_and_wait()
+            .map_err(TestError::MkdirFailed)?;
+        self.adb(env)
+            .with_args(&["shell", "mkdir", "-p", &device_coverage_dir])
+            .run_and_wait()
+            .map_err(TestError::MkdirFailed)?;
+        let profile_file = if coverage_dir.is_some() {
+            format!(
+                "LLVM_PROFILE_FILE={}/{} ",
+                device_coverage_dir,
+                util::coverage::PROFILE_FILE
+            )
+        } else {
+            String::new()
+        };
+        let args = shell_args(args);
+        let result = harnesses.iter().try_for_each(|harness| {
+            let name = self.push_harness(env, harness, TEST_DIR)?;
+            println!("Running {} on {}", name, self.name);
+            self.adb(env)
+                .with_arg("shell")
+                .with_arg(format!(
+                    "cd {dir} && chmod 755 {name} && {profile_file}LD_LIBRARY_PATH={dir} ./{name} {args}",
+                    dir = TEST_DIR,
+                    name = name,
+                    profile_file = profile_file,
+                    args = args,
+                ))
+                .run_and_wait()
+                .map(|_| ())
+                .map_err(|cause| TestError::TestsFailed { name, cause })
+        });
+        if let Some(coverage_dir) = coverage_dir {
+            util::quiet_stdout(self.adb(env))
+                .with_arg("pull")
+                .with_arg(format!("{}/.", device_coverage_dir))
+                .with_arg(coverage_dir)
+                .run_and_wait()
+                .map_err(TestError::PullFailed)?;
+        }
+        result
+    }
+
+    /// Runs a single harness and returns its exit code, for when we're acting
+    /// as a cargo target runner. Nothing else is printed to stdout, since
+    /// test runners like nextest parse it. Each invocation gets its own
+    /// directory, since nextest runs many of these at once.
+    pub fn run_harness(
+        &self,
+        env: &Env,
+        harness: &TestHarness,
+        args: &[String],
+        harness_env: &[(String, String)],
+    ) -> Result<i32, TestError> {
+        let dir = format!("/data/local/tmp/cargo-mobile-runner/{}", std::process::id());
+        self.adb(env)
+            .with_args(&["shell", "mkdir", "-p", &dir])
+            .run_and_wait()
+            .map_err(TestError::MkdirFailed)?;
+        let name = self.push_harness(env, harness, &dir)?;
+        let harness_env = harness_env
+            .iter()
+            .map(|(key, value)| format!("{}={} ", key, shell_args(&[value.clone()])))
+            .collect::<String>();
+        let result = self
+            .adb(env)
+            .with_arg("shell")
+            .with_arg(format!(
+                "cd {dir} && chmod 755 {name} && {harness_env}LD_LIBRARY_PATH={dir} ./{name} {args}",
+                dir = dir,
+                name = name,
+                harness_env = harness_env,
+                args = shell_args(args),
+            ))
+            .run_and_wait();
+        if let Err(err) = self
+            .adb(env)
+            .with_args(&["shell", "rm", "-rf", &dir])
+            .run_and_wait()
+        {
+            log::warn!("failed to clean up {:?} on device: {}", dir, err);
+        }
+        match result {
+            Ok(_) => Ok(0),
+            Err(cause) => match cause.status().and_then(|status| status.code()) {
+                Some(code) => Ok(code),
+                None => Err(TestError::TestsFailed { name, cause }),
+            },
+        }
+    }
+
+    /// Runs benchmark harnesses the same way as `run_tests`. With `warm_up`,
+    /// the device is left idle for that long before each harness, so heat
+    /// from installing (or from the previous harness) doesn't get it
+    /// throttled mid-run. Criterion's results are pulled into `results_dir`
+    /// afterwards; they're kept on the device between runs, so criterion can
+    /// still compare against the last run.
+    pub fn run_benches(
+        &self,
+        env: &Env,
+        harnesses: &[TestHarness],
+        args: &[String],
+        warm_up: Option<Duration>,
+        results_dir: &Path,
+    ) -> Result<(), TestError> {
+        static BENCH_DIR: &str = "/data/local/tmp/cargo-mobile-benches";
+        let criterion_dir = format!("{}/criterion", BENCH_DIR);
+        self.adb(env)
+            .with_args(&["shell", "mkdir", "-p", &criterion_dir])
+            .run_and_wait()
+            .map_err(TestError::MkdirFailed)?;
+        let args = shell_args(args);
+        let result = harnesses.iter().try_for_each(|harness| {
+            let name = self.push_harness(env, harness, BENCH_DIR)?;
+            if let Some(warm_up) = warm_up {
+                println!(
+                    "Letting {} settle for {} seconds",
+                    self.name,
+                    warm_up.as_secs()
+                );
+                thread::sleep(warm_up);
+            }
+            println!("Running {} on {}", name, self.name);
+            self.adb(env)
+                .with_arg("shell")
+                .with_arg(format!(
+                    "cd {dir} && chmod 755 {name} && CRITERION_HOME={criterion_dir} LD_LIBRARY_PATH={dir} ./{name} --bench {args}",
+                    dir = BENCH_DIR,
+                    name = name,
+                    criterion_dir = criterion_dir,
+                    args = args,
+                ))
+                .run_and_wait()
+                .map(|_| ())
+                .map_err(|cause| TestError::BenchesFailed { name, cause })
+        });
+        util::quiet_stdout(self.adb(env))
+            .with_arg("pull")
+            .with_arg(format!("{}/.", criterion_dir))
+            .with_arg(results_dir)
+            .run_and_wait()
+            .map_err(TestError::PullFailed)?;
+        result
+    }
+
+    /// Runs the Android project's instrumented tests with Gradle.
+    pub fn run_instrumented_tests(&self, config: &Config, env: &Env) -> Result<(), TestError> {
+        gradlew(config, env)
+            .with_arg("connectedAndroidTest")
+            .recorded(
+                env,
+                repro::Vars::default().with("ANDROID_SERIAL", &self.serial_no),
+            )
+            .run_and_wait()
+            .map_err(TestError::InstrumentedTestsFailed)?;
+        Ok(())
+    }
+
     pub fn stacktrace(&self, config: &Config, env: &Env) -> Result<(), StacktraceError> {
         // -d = print and exit
         let logcat_command = adb::adb(env, &self.serial_no).with_args(&["logcat", "-d"]);
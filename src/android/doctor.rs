@@ -0,0 +1,200 @@
+use super::{env, ndk, target::Target};
+use crate::{
+    target::TargetTrait as _,
+    templating::Lockfile,
+    util::{
+        self,
+        cli::{Report, Reportable},
+        prompt,
+    },
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+// This needs to match `compileSdkVersion` in the Android Studio template pack.
+static COMPILE_SDK_VERSION: u32 = 28;
+
+#[derive(Debug)]
+pub enum Error {
+    SdkMissing(env::Error),
+    SdkManagerMissing {
+        sdk_root: PathBuf,
+        missing: Vec<String>,
+    },
+    PackagesMissing {
+        command: String,
+    },
+    PromptFailed(io::Error),
+    LicensesFailed(bossy::Error),
+    InstallFailed(bossy::Error),
+    RustupFailed(util::rustup::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::SdkMissing(err) => Report::action_request(
+                "Install Android Studio or the Android command-line tools, then run this again (or set `ANDROID_SDK_ROOT` if they're somewhere unusual)",
+                err,
+            ),
+            Self::SdkManagerMissing { sdk_root, missing } => Report::action_request(
+                format!("Install the Android command-line tools into {:?}, or install the missing packages from Android Studio's SDK Manager", sdk_root),
+                format!("`sdkmanager` wasn't found, so we can't install {}", util::list_display(missing)),
+            ),
+            Self::PackagesMissing { command } => Report::action_request(
+                "Some SDK packages are missing; install them by running this (or run this command interactively to do it for you):",
+                command,
+            ),
+            Self::PromptFailed(err) => Report::error("Failed to prompt for installing SDK packages", err),
+            Self::LicensesFailed(err) => Report::error("Failed to accept Android SDK licenses", err),
+            Self::InstallFailed(err) => Report::error("Failed to install Android SDK packages", err),
+            Self::RustupFailed(err) => err.report(),
+        }
+    }
+}
+
+fn sdkmanager(sdk_root: &Path) -> Option<PathBuf> {
+    super::emulator::cmdline_tool(sdk_root, "sdkmanager")
+}
+
+fn check(name: &str, ok: bool) -> bool {
+    util::cli::status(format!(
+        "  [{}] {}",
+        if ok { "ok" } else { "missing" },
+        name
+    ));
+    ok
+}
+
+fn check_rust_targets(non_interactive: crate::opts::NonInteractive) -> Result<(), Error> {
+    let toolchain = util::rustup::Toolchain::active().map_err(Error::RustupFailed)?;
+    let triples = Target::all().values().map(|target| target.triple);
+    let missing = toolchain
+        .missing_targets(triples.clone())
+        .map_err(Error::RustupFailed)?;
+    util::cli::status(format!("Using Rust toolchain {}", toolchain));
+    if !check("Rust targets", missing.is_empty()) {
+        util::rustup::ensure_targets(triples, non_interactive).map_err(Error::RustupFailed)?;
+    }
+    Ok(())
+}
+
+/// There's nothing to install for this, but when a generated project isn't
+/// behaving, template packs that drifted from the lockfile are worth knowing
+/// about.
+fn check_template_packs() {
+    let cwd = Path::new(".");
+    for path in Lockfile::paths_in(cwd) {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        match Lockfile::load_from(path) {
+            Ok(Some(lockfile)) => {
+                let problems = lockfile.check(cwd);
+                util::cli::status(format!(
+                    "  [{}] template packs ({})",
+                    if problems.is_empty() { "ok" } else { "drifted" },
+                    name
+                ));
+                for problem in problems {
+                    util::cli::status(format!("      {}", problem));
+                }
+            }
+            Ok(None) => (),
+            Err(err) => log::warn!("{}", err),
+        }
+    }
+}
+
+/// Checks that the Rust targets and SDK have everything we build with, and offers to install
+/// whatever's missing using `sdkmanager`.
+pub fn exec(non_interactive: crate::opts::NonInteractive) -> Result<(), Error> {
+    check_rust_targets(non_interactive)?;
+    check_template_packs();
+    let sdk_root = env::find_sdk_root().map_err(Error::SdkMissing)?;
+    util::cli::status(format!("Found Android SDK at {:?}", sdk_root));
+
+    let platform = format!("platforms;android-{}", COMPILE_SDK_VERSION);
+    let mut missing = Vec::new();
+    if !check(
+        "platform-tools",
+        sdk_root.join("platform-tools/adb").is_file(),
+    ) {
+        missing.push("platform-tools".to_owned());
+    }
+    if !check(
+        &platform,
+        sdk_root
+            .join(format!("platforms/android-{}", COMPILE_SDK_VERSION))
+            .is_dir(),
+    ) {
+        missing.push(platform.clone());
+    }
+    let ndk = ndk::Env::new(&sdk_root);
+    if let Err(err) = &ndk {
+        log::info!("NDK check failed: {}", err);
+    }
+    if !check("NDK", ndk.is_ok()) {
+        missing.push("ndk-bundle".to_owned());
+    }
+
+    if missing.is_empty() {
+        util::cli::status("Everything's installed!");
+        return Ok(());
+    }
+    let sdkmanager = sdkmanager(&sdk_root).ok_or_else(|| Error::SdkManagerMissing {
+        sdk_root: sdk_root.clone(),
+        missing: missing.clone(),
+    })?;
+    let sdk_root_arg = format!("--sdk_root={}", sdk_root.display());
+    if non_interactive.yes() {
+        return Err(Error::PackagesMissing {
+            command: format!(
+                "{} {} {}",
+                sdkmanager.display(),
+                sdk_root_arg,
+                missing
+                    .iter()
+                    .map(|package| format!("{:?}", package))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        });
+    }
+    let answer = loop {
+        if let Some(answer) = prompt::yes_no(
+            format!(
+                "Install {} with `sdkmanager`?",
+                util::list_display(&missing)
+            ),
+            Some(prompt::YesOrNo::Yes),
+        )
+        .map_err(Error::PromptFailed)?
+        {
+            break answer;
+        }
+    };
+    if answer.no() {
+        return Ok(());
+    }
+    // Installing fails outright if the licenses haven't been accepted, so we
+    // let the user go through them first.
+    bossy::Command::impure(&sdkmanager)
+        .with_arg(&sdk_root_arg)
+        .with_args(util::proxy::sdkmanager_args())
+        .with_arg("--licenses")
+        .run_and_wait()
+        .map_err(Error::LicensesFailed)?;
+    bossy::Command::impure(&sdkmanager)
+        .with_arg(&sdk_root_arg)
+        .with_args(util::proxy::sdkmanager_args())
+        .with_args(&missing)
+        .run_and_wait()
+        .map_err(Error::InstallFailed)?;
+    util::cli::status(format!("Installed {}!", util::list_display(&missing)));
+    Ok(())
+}
@@ -0,0 +1,254 @@
+use super::{adb, env::Env};
+use crate::{
+    env::ExplicitEnv as _,
+    util::{
+        self,
+        cli::{Report, Reportable},
+    },
+};
+use std::{
+    io::Write as _,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    AbiInvalid {
+        abi: String,
+    },
+    ToolMissing {
+        tool: &'static str,
+    },
+    InstallFailed {
+        package: String,
+        cause: bossy::Error,
+    },
+    ListFailed(bossy::Error),
+    CreateFailed {
+        name: String,
+        cause: bossy::Error,
+    },
+    NoFreePort,
+    LaunchFailed(std::io::Error),
+    BootTimedOut {
+        serial_no: String,
+        timeout: Duration,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::AbiInvalid { abi } => Report::error(
+                "Failed to provision emulator",
+                format!(
+                    "{:?} isn't a supported ABI; use `arm64`, `x86_64`, or `x86`",
+                    abi
+                ),
+            ),
+            Self::ToolMissing { tool } => Report::action_request(
+                "Please install the Android SDK command-line tools and emulator, and try again.",
+                format!("`{}` wasn't found in the SDK or on the `PATH`", tool),
+            ),
+            Self::InstallFailed { package, cause } => {
+                Report::error(format!("Failed to install {:?}", package), cause)
+            }
+            Self::ListFailed(err) => Report::error("Failed to list existing AVDs", err),
+            Self::CreateFailed { name, cause } => {
+                Report::error(format!("Failed to create AVD {:?}", name), cause)
+            }
+            Self::NoFreePort => Report::error(
+                "Failed to launch emulator",
+                "Every emulator port is already in use",
+            ),
+            Self::LaunchFailed(err) => Report::error("Failed to launch emulator", err),
+            Self::BootTimedOut { serial_no, timeout } => Report::error(
+                "Failed to boot emulator",
+                format!(
+                    "{} didn't finish booting within {} seconds",
+                    serial_no,
+                    timeout.as_secs()
+                ),
+            ),
+        }
+    }
+}
+
+/// Finds a tool from the SDK's command-line tools, falling back to the
+/// `PATH`.
+pub(super) fn cmdline_tool(sdk_root: &Path, name: &str) -> Option<PathBuf> {
+    [
+        format!("cmdline-tools/latest/bin/{}", name),
+        format!("tools/bin/{}", name),
+    ]
+    .iter()
+    .map(|path| sdk_root.join(path))
+    .find(|path| path.is_file())
+    .or_else(|| {
+        util::command_present(name)
+            .ok()
+            .filter(|present| *present)
+            .map(|_| PathBuf::from(name))
+    })
+}
+
+fn tool(env: &Env, name: &'static str) -> Result<PathBuf, Error> {
+    cmdline_tool(env.sdk_root(), name).ok_or(Error::ToolMissing { tool: name })
+}
+
+// The SDK's tools ask to accept licenses and such on stdin, which CI can't
+// answer.
+fn run_with_input(command: bossy::Command, input: &str) -> bossy::Result<()> {
+    let mut handle = command.with_stdin_piped().run()?;
+    if let Some(stdin) = handle.stdin() {
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    handle.wait_for_output().map(|_| ())
+}
+
+/// The options for provisioning and booting an emulator.
+#[derive(Debug)]
+pub struct Options {
+    pub api: u32,
+    pub abi: String,
+    pub headless: bool,
+    pub timeout: Duration,
+}
+
+impl Options {
+    fn abi(&self) -> Result<&'static str, Error> {
+        match self.abi.as_str() {
+            "arm64" | "arm64-v8a" | "aarch64" => Ok("arm64-v8a"),
+            "x86_64" => Ok("x86_64"),
+            "x86" | "i686" => Ok("x86"),
+            _ => Err(Error::AbiInvalid {
+                abi: self.abi.clone(),
+            }),
+        }
+    }
+
+    fn system_image(&self) -> Result<String, Error> {
+        Ok(format!(
+            "system-images;android-{};google_apis;{}",
+            self.api,
+            self.abi()?
+        ))
+    }
+
+    fn avd_name(&self) -> Result<String, Error> {
+        Ok(format!("cargo-mobile-{}-{}", self.api, self.abi()?))
+    }
+}
+
+fn ensure_avd(env: &Env, options: &Options) -> Result<String, Error> {
+    let name = options.avd_name()?;
+    let avdmanager = tool(env, "avdmanager")?;
+    let existing = bossy::Command::impure(&avdmanager)
+        .with_env_vars(env.explicit_env())
+        .with_args(&["list", "avd", "-c"])
+        .run_and_wait_for_output()
+        .map_err(Error::ListFailed)?;
+    if String::from_utf8_lossy(existing.stdout())
+        .lines()
+        .any(|line| line.trim() == name)
+    {
+        log::info!("using existing AVD {:?}", name);
+        return Ok(name);
+    }
+    let package = options.system_image()?;
+    log::info!("installing {:?}", package);
+    run_with_input(
+        bossy::Command::impure(tool(env, "sdkmanager")?)
+            .with_env_vars(env.explicit_env())
+            .with_arg(format!("--sdk_root={}", env.sdk_root().display()))
+            .with_args(util::proxy::sdkmanager_args())
+            .with_args(&["--install", "emulator", "platform-tools", &package]),
+        &"y\n".repeat(16),
+    )
+    .map_err(|cause| Error::InstallFailed {
+        package: package.clone(),
+        cause,
+    })?;
+    log::info!("creating AVD {:?}", name);
+    // This asks if we want a custom hardware profile, which we don't.
+    run_with_input(
+        bossy::Command::impure(&avdmanager)
+            .with_env_vars(env.explicit_env())
+            .with_args(&["create", "avd", "--force", "-n", &name, "-k", &package]),
+        "no\n",
+    )
+    .map_err(|cause| Error::CreateFailed {
+        name: name.clone(),
+        cause,
+    })?;
+    Ok(name)
+}
+
+// Emulators listen on a pair of ports starting at an even number from 5554,
+// and the console port is what ends up in the serial.
+fn free_port(env: &Env) -> Result<u16, Error> {
+    let in_use = adb::device_list(env)
+        .map(|devices| {
+            devices
+                .iter()
+                .map(|device| device.serial_no().to_owned())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    (5554..=5584)
+        .step_by(2)
+        .find(|port| !in_use.contains(&format!("emulator-{}", port)))
+        .ok_or(Error::NoFreePort)
+}
+
+fn boot_completed(env: &Env, serial_no: &str) -> bool {
+    adb::adb(env, serial_no)
+        .with_args(&["shell", "getprop", "sys.boot_completed"])
+        .run_and_wait_for_output()
+        .ok()
+        .map_or(false, |output| {
+            String::from_utf8_lossy(output.stdout()).trim() == "1"
+        })
+}
+
+/// Provisions an AVD for the requested API level and ABI if there isn't one
+/// already, boots it, and waits until Android finishes booting. The emulator
+/// keeps running after we exit; the returned serial can be used to shut it
+/// down with `adb -s <serial> emu kill`.
+pub fn launch(env: &Env, options: &Options) -> Result<String, Error> {
+    let name = ensure_avd(env, options)?;
+    let emulator = env.sdk_root().join("emulator/emulator");
+    if !emulator.is_file() {
+        return Err(Error::ToolMissing { tool: "emulator" });
+    }
+    let port = free_port(env)?;
+    let serial_no = format!("emulator-{}", port);
+    let mut command = Command::new(&emulator);
+    command
+        .envs(env.explicit_env())
+        .args(&["-avd", &name, "-port", &port.to_string()])
+        .args(&["-no-audio", "-no-boot-anim", "-no-snapshot-save"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if options.headless {
+        command.args(&["-no-window", "-gpu", "swiftshader_indirect"]);
+    }
+    // This is deliberately not waited on, since the emulator needs to outlive
+    // us.
+    command.spawn().map_err(Error::LaunchFailed)?;
+    let start = Instant::now();
+    while !boot_completed(env, &serial_no) {
+        if start.elapsed() > options.timeout {
+            return Err(Error::BootTimedOut {
+                serial_no,
+                timeout: options.timeout,
+            });
+        }
+        thread::sleep(Duration::from_secs(2));
+    }
+    Ok(serial_no)
+}
@@ -1,11 +1,16 @@
 use super::ndk;
 use crate::{
+    config,
     env::{Env as CoreEnv, Error as CoreError, ExplicitEnv},
-    util::cli::{Report, Reportable},
+    opts::EntryPoint,
+    util::{
+        self,
+        cli::{Report, Reportable},
+    },
 };
 use std::{
     fmt::{self, Display},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 #[derive(Debug)]
@@ -14,6 +19,30 @@ pub enum Error {
     // TODO: we should be nice and provide a platform-specific suggestion
     AndroidSdkRootNotSet(std::env::VarError),
     AndroidSdkRootNotADir,
+    PinnedSdkRootInvalid(PathBuf),
+    PinnedAdbMissing(PathBuf),
+    AdbVersionFailed {
+        adb: PathBuf,
+        cause: bossy::Error,
+    },
+    AdbVersionInvalid {
+        adb: PathBuf,
+        output: String,
+    },
+    AdbTooOld {
+        adb: PathBuf,
+        version: AdbVersion,
+    },
+    ConflictingSdkRoots {
+        sdk_root: PathBuf,
+        android_home: PathBuf,
+    },
+    ConflictingAdbs {
+        adb: PathBuf,
+        version: AdbVersion,
+        other: PathBuf,
+        other_version: AdbVersion,
+    },
     NdkEnvError(ndk::Error),
 }
 
@@ -22,12 +51,51 @@ impl Display for Error {
         match self {
             Self::CoreEnvError(err) => write!(f, "{}", err),
             Self::AndroidSdkRootNotSet(err) => {
-                write!(f, "Have you installed the Android SDK? The `ANDROID_SDK_ROOT` environment variable isn't set, and is required: {}", err)
+                write!(f, "Have you installed the Android SDK? The `ANDROID_SDK_ROOT` environment variable isn't set, and the SDK wasn't found in any of the usual places: {}", err)
             }
             Self::AndroidSdkRootNotADir => write!(
                 f,
                 "Have you installed the Android SDK? The `ANDROID_SDK_ROOT` environment variable is set, but doesn't point to an existing directory."
             ),
+            Self::PinnedSdkRootInvalid(sdk_root) => write!(
+                f,
+                "`android.sdk-root` is set to {:?}, which isn't an Android SDK (it has no `platform-tools` dir).",
+                sdk_root
+            ),
+            Self::PinnedAdbMissing(adb) => {
+                write!(f, "`android.adb` is set to {:?}, which doesn't exist.", adb)
+            }
+            Self::AdbVersionFailed { adb, cause } => {
+                write!(f, "Failed to check version of {:?}: {}", adb, cause)
+            }
+            Self::AdbVersionInvalid { adb, output } => write!(
+                f,
+                "Failed to parse version of {:?} from output {:?}",
+                adb, output
+            ),
+            Self::AdbTooOld { adb, version } => write!(
+                f,
+                "{:?} is version {}, but at least {} is required; update the SDK's platform-tools.",
+                adb, version, MIN_ADB_VERSION
+            ),
+            Self::ConflictingSdkRoots {
+                sdk_root,
+                android_home,
+            } => write!(
+                f,
+                "`ANDROID_SDK_ROOT` ({:?}) and `ANDROID_HOME` ({:?}) point to different SDKs. Unset one of them, or set `android.sdk-root` in your config to pick one.",
+                sdk_root, android_home
+            ),
+            Self::ConflictingAdbs {
+                adb,
+                version,
+                other,
+                other_version,
+            } => write!(
+                f,
+                "The SDK's `adb` ({:?}, version {}) doesn't match the one on your `PATH` ({:?}, version {}). They'll keep restarting each other's server, which makes devices randomly disconnect. Remove the other SDK from your `PATH`, or set `android.adb` in your config to pick one.",
+                adb, version, other, other_version
+            ),
             Self::NdkEnvError(err) => write!(f, "{}", err),
         }
     }
@@ -49,60 +117,272 @@ impl Error {
     }
 }
 
-#[derive(Debug)]
+/// Older versions can't talk to servers started by Android Studio's, and lack
+/// `adb reverse`.
+const MIN_ADB_VERSION: AdbVersion = AdbVersion(1, 0, 39);
+
+/// The protocol version `adb version` reports, i.e. `1.0.41`.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct AdbVersion(u32, u32, u32);
+
+impl Display for AdbVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+impl AdbVersion {
+    fn check(adb: &Path) -> Result<Self, Error> {
+        let output = bossy::Command::impure(adb)
+            .with_arg("version")
+            .run_and_wait_for_output()
+            .map_err(|cause| Error::AdbVersionFailed {
+                adb: adb.to_owned(),
+                cause,
+            })?;
+        let output = String::from_utf8_lossy(output.stdout()).into_owned();
+        // The first line is `Android Debug Bridge version 1.0.41`.
+        output
+            .lines()
+            .next()
+            .and_then(|line| line.rsplit(' ').next())
+            .and_then(|version| {
+                let mut parts = version.trim().split('.').map(|part| part.parse().ok());
+                Some(Self(parts.next()??, parts.next()??, parts.next()??))
+            })
+            .ok_or_else(|| Error::AdbVersionInvalid {
+                adb: adb.to_owned(),
+                output,
+            })
+    }
+}
+
+/// Paths set in the config that take precedence over what's detected, i.e.
+/// for when there are multiple SDKs installed and the wrong one keeps getting
+/// picked up.
+#[derive(Debug, Default)]
+struct Pins {
+    sdk_root: Option<PathBuf>,
+    adb: Option<PathBuf>,
+}
+
+impl Pins {
+    // This happens before the config is loaded properly, so paths are
+    // resolved against the app root here.
+    fn load() -> Self {
+        config::load_android_raw(".")
+            .map(|(root_dir, raw)| {
+                let resolve = |path: &str| {
+                    let path = util::expand_home(path).unwrap_or_else(|_| path.into());
+                    root_dir.join(path)
+                };
+                Self {
+                    sdk_root: raw.sdk_root().map(resolve),
+                    adb: raw.adb().map(resolve),
+                }
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Where Android Studio and the standalone command-line tools install the SDK
+/// by default.
+pub fn standard_sdk_roots() -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+    if let Ok(home) = crate::util::home_dir() {
+        if cfg!(target_os = "macos") {
+            roots.push(home.join("Library/Android/sdk"));
+        } else {
+            roots.push(home.join("Android/Sdk"));
+        }
+    }
+    roots.extend(
+        [
+            "/opt/android-sdk",
+            "/usr/lib/android-sdk",
+            "/usr/local/share/android-sdk",
+            "/usr/local/share/android-commandlinetools",
+        ]
+        .iter()
+        .map(PathBuf::from),
+    );
+    roots
+}
+
+/// Finds the SDK the same way `Env::new` does, without requiring the rest of
+/// the environment to be valid.
+pub fn find_sdk_root() -> Result<PathBuf, Error> {
+    sdk_root(Pins::load().sdk_root)
+}
+
+fn sdk_root(pinned: Option<PathBuf>) -> Result<PathBuf, Error> {
+    match pinned {
+        Some(sdk_root) => {
+            if sdk_root.join("platform-tools").is_dir() {
+                log::info!("using SDK pinned by `android.sdk-root` at {:?}", sdk_root);
+                Ok(sdk_root)
+            } else {
+                Err(Error::PinnedSdkRootInvalid(sdk_root))
+            }
+        }
+        None => detect_sdk_root(),
+    }
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn detect_sdk_root() -> Result<PathBuf, Error> {
+    // Some tools read one and some read the other, so if they disagree,
+    // there's no telling which SDK anything actually ends up using.
+    if let (Some(sdk_root), Some(android_home)) = (
+        std::env::var_os("ANDROID_SDK_ROOT").map(PathBuf::from),
+        std::env::var_os("ANDROID_HOME").map(PathBuf::from),
+    ) {
+        if sdk_root.is_dir() && android_home.is_dir() && !same_dir(&sdk_root, &android_home) {
+            return Err(Error::ConflictingSdkRoots {
+                sdk_root,
+                android_home,
+            });
+        }
+    }
+    std::env::var("ANDROID_SDK_ROOT")
+        .map_err(Error::AndroidSdkRootNotSet)
+        .map(PathBuf::from)
+        .and_then(|sdk_root| {
+            if sdk_root.is_dir() {
+                Ok(sdk_root)
+            } else {
+                Err(Error::AndroidSdkRootNotADir)
+            }
+        })
+        .or_else(|err| {
+            if let Some(android_home) = std::env::var("ANDROID_HOME")
+                .ok()
+                .map(PathBuf::from)
+                .filter(|android_home| android_home.is_dir())
+            {
+                log::warn!("`ANDROID_SDK_ROOT` isn't set; falling back to `ANDROID_HOME`, which is deprecated");
+                Ok(android_home)
+            } else {
+                Err(err)
+            }
+        })
+        .or_else(|err| {
+            // A stray `ANDROID_SDK_ROOT` is worth reporting, rather than
+            // quietly papering over.
+            if let Error::AndroidSdkRootNotSet(_) = err {
+                if let Some(sdk_root) = standard_sdk_roots()
+                    .into_iter()
+                    .find(|root| root.join("platform-tools").is_dir())
+                {
+                    log::info!(
+                        "`ANDROID_SDK_ROOT` isn't set; using SDK found at {:?}",
+                        sdk_root
+                    );
+                    return Ok(sdk_root);
+                }
+            }
+            Err(err)
+        })
+}
+
+/// Picks the `adb` to use, checking that it's new enough and that a different
+/// version isn't lurking on the `PATH`.
+fn find_adb(sdk_root: &Path, pinned: Option<PathBuf>) -> Result<PathBuf, Error> {
+    let is_pinned = pinned.is_some();
+    let adb = match pinned {
+        Some(adb) if adb.is_file() => adb,
+        Some(adb) => return Err(Error::PinnedAdbMissing(adb)),
+        None => sdk_root.join("platform-tools/adb"),
+    };
+    if !adb.is_file() {
+        // `doctor` reports a missing `platform-tools`, so this just defers to
+        // whatever's on the `PATH` like we always have.
+        log::warn!(
+            "no `adb` found in SDK at {:?}; using the one on the `PATH`",
+            sdk_root
+        );
+        return Ok(PathBuf::from("adb"));
+    }
+    let version = AdbVersion::check(&adb)?;
+    if version < MIN_ADB_VERSION {
+        return Err(Error::AdbTooOld { adb, version });
+    }
+    if !is_pinned {
+        let other = util::command_path("adb")
+            .ok()
+            .and_then(|output| {
+                output
+                    .stdout_str()
+                    .ok()
+                    .map(|path| PathBuf::from(path.trim()))
+            })
+            .filter(|other| other.is_file() && !same_dir(other, &adb));
+        if let Some(other) = other {
+            let other_version = AdbVersion::check(&other)?;
+            if other_version != version {
+                return Err(Error::ConflictingAdbs {
+                    adb,
+                    version,
+                    other,
+                    other_version,
+                });
+            }
+        }
+    }
+    Ok(adb)
+}
+
+#[derive(Clone, Debug)]
 pub struct Env {
     base: CoreEnv,
     sdk_root: PathBuf,
+    adb: PathBuf,
     pub ndk: ndk::Env,
 }
 
 impl Env {
     pub fn new() -> Result<Self, Error> {
         let base = CoreEnv::new().map_err(Error::CoreEnvError)?;
-        let sdk_root = std::env::var("ANDROID_SDK_ROOT")
-            .map_err(Error::AndroidSdkRootNotSet)
-            .map(PathBuf::from)
-            .and_then(|sdk_root| {
-                if sdk_root.is_dir() {
-                    Ok(sdk_root)
-                } else {
-                    Err(Error::AndroidSdkRootNotADir)
-                }
-            })
-            .or_else(|err| {
-                if let Some(android_home) = std::env::var("ANDROID_HOME")
-                    .ok()
-                    .map(PathBuf::from)
-                    .filter(|android_home| android_home.is_dir())
-                {
-                    log::warn!("`ANDROID_SDK_ROOT` isn't set; falling back to `ANDROID_HOME`, which is deprecated");
-                    Ok(android_home)
-                } else {
-                    Err(err)
-                }
-            })
-            .or_else(|err| {
-                if let Some(android_home) = std::env::var("ANDROID_HOME")
-                    .ok()
-                    .map(PathBuf::from)
-                    .filter(|android_home| android_home.is_dir())
-                {
-                    log::warn!("`ANDROID_SDK_ROOT` isn't set; falling back to `ANDROID_HOME`, which is deprecated");
-                    Ok(android_home)
-                } else {
-                    Err(err)
-                }
-            })?;
+        let pins = Pins::load();
+        let sdk_root = sdk_root(pins.sdk_root)?;
+        let adb = find_adb(&sdk_root, pins.adb)?;
+        let ndk = ndk::Env::new(&sdk_root).map_err(Error::NdkEnvError)?;
         Ok(Self {
             base,
             sdk_root,
-            ndk: ndk::Env::new().map_err(Error::NdkEnvError)?,
+            adb,
+            ndk,
         })
     }
 
+    pub fn dotenv(&self) -> &[(String, String)] {
+        self.base.dotenv()
+    }
+
+    pub fn sdk_root(&self) -> &Path {
+        &self.sdk_root
+    }
+
+    /// The `adb` every command should run, so they all agree on which server
+    /// they're talking to.
+    pub fn adb(&self) -> &Path {
+        &self.adb
+    }
+
     pub fn path(&self) -> &str {
         self.base.path()
     }
+
+    pub fn with_entry_point(mut self, entry_point: &EntryPoint) -> Self {
+        self.base = self.base.with_entry_point(entry_point);
+        self
+    }
 }
 
 impl ExplicitEnv for Env {
@@ -64,8 +64,8 @@ impl Reportable for SymlinkLibError {
 
 pub fn path(config: &Config, target: Target<'_>) -> PathBuf {
     config
-        .project_dir()
-        .join(format!("app/src/main/jniLibs/{}", &target.abi))
+        .rust_module_dir()
+        .join(format!("src/main/jniLibs/{}", &target.abi))
 }
 
 #[derive(Debug)]
@@ -116,17 +116,23 @@ impl JniLibs {
     }
 
     pub fn symlink_lib(&self, src: &Path) -> Result<(), SymlinkLibError> {
+        self.symlink_lib_as(
+            src,
+            src.file_name()
+                .expect("developer error: file had no file name"),
+        )
+    }
+
+    /// Like `symlink_lib`, but under a different name.
+    pub fn symlink_lib_as(
+        &self,
+        src: &Path,
+        name: impl AsRef<std::ffi::OsStr>,
+    ) -> Result<(), SymlinkLibError> {
         log::info!("symlinking lib {:?} in jniLibs dir {:?}", src, self.path);
         if src.is_file() {
-            ln::force_symlink(
-                src,
-                self.path.join(
-                    src.file_name()
-                        .expect("developer error: file had no file name"),
-                ),
-                ln::TargetStyle::File,
-            )
-            .map_err(SymlinkLibError::SymlinkFailed)
+            ln::force_symlink(src, self.path.join(name.as_ref()), ln::TargetStyle::File)
+                .map_err(SymlinkLibError::SymlinkFailed)
         } else {
             Err(SymlinkLibError::SourceMissing(src.to_owned()))
         }
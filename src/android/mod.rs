@@ -1,11 +1,19 @@
-mod adb;
+mod aar;
+pub(crate) mod adb;
+mod api;
+mod apk;
 pub mod cli;
 pub(crate) mod config;
-mod device;
+pub(crate) mod device;
+mod doctor;
+mod emulator;
 pub(crate) mod env;
 mod jnilibs;
 mod ndk;
 pub(crate) mod project;
-mod target;
+pub(crate) mod signer;
+pub(crate) mod target;
+
+pub use self::api::{Builder, Error, Runner};
 
 pub static NAME: &str = "android";
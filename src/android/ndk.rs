@@ -188,7 +188,7 @@ impl Display for Error {
         match self {
             Self::NdkHomeNotSet(err) => write!(
                 f,
-                "Have you installed the NDK? The `NDK_HOME` environment variable isn't set, and is required: {}",
+                "Have you installed the NDK? The `NDK_HOME` environment variable isn't set, and no NDK was found in the Android SDK: {}",
                 err,
             ),
             Self::NdkHomeNotADir => write!(
@@ -230,23 +230,57 @@ impl Reportable for RequiredLibsError {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Env {
     ndk_home: PathBuf,
 }
 
+/// Finds the newest NDK installed through the SDK manager, which puts them in
+/// `ndk/<version>` (or `ndk-bundle`, for older SDK tools).
+pub fn find_in_sdk(sdk_root: &Path) -> Option<PathBuf> {
+    let versioned = std::fs::read_dir(sdk_root.join("ndk"))
+        .ok()
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.join("source.properties").is_file())
+        .max_by_key(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| {
+                    name.split('.')
+                        .map(|component| component.parse::<u32>().unwrap_or(0))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        });
+    versioned.or_else(|| {
+        Some(sdk_root.join("ndk-bundle")).filter(|path| path.join("source.properties").is_file())
+    })
+}
+
 impl Env {
-    pub fn new() -> Result<Self, Error> {
-        let ndk_home = std::env::var("NDK_HOME")
-            .map_err(Error::NdkHomeNotSet)
-            .map(PathBuf::from)
-            .and_then(|ndk_home| {
+    pub fn new(sdk_root: &Path) -> Result<Self, Error> {
+        let ndk_home = match std::env::var("NDK_HOME") {
+            Ok(ndk_home) => {
+                let ndk_home = PathBuf::from(ndk_home);
                 if ndk_home.is_dir() {
                     Ok(ndk_home)
                 } else {
                     Err(Error::NdkHomeNotADir)
                 }
-            })?;
+            }
+            Err(err) => find_in_sdk(sdk_root)
+                .map(|ndk_home| {
+                    log::info!(
+                        "`NDK_HOME` isn't set; using NDK found in the Android SDK at {:?}",
+                        ndk_home
+                    );
+                    ndk_home
+                })
+                .ok_or(Error::NdkHomeNotSet(err)),
+        }?;
         let env = Self { ndk_home };
         let version = env.version().map_err(Error::VersionLookupFailed)?;
         if version >= MIN_NDK_VERSION {
@@ -1,27 +1,37 @@
-use super::{config::Config, env::Env, ndk, target::Target};
+use super::{
+    config::{Config, Metadata},
+    env::Env,
+    ndk,
+    target::Target,
+};
 use crate::{
     dot_cargo,
     target::TargetTrait as _,
-    templating::{self, Pack},
+    templating::{self, DryRun, Pack, Review},
     util::{
         self,
         cli::{Report, Reportable},
         ln,
     },
 };
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 pub static TEMPLATE_PACK: &str = "android-studio";
 
 #[derive(Debug)]
 pub enum Error {
-    RustupFailed(bossy::Error),
+    RustupFailed(util::rustup::Error),
     MissingPack(templating::LookupError),
     TemplateProcessingFailed(bicycle::ProcessingError),
     DirectoryCreationFailed {
         path: PathBuf,
         cause: std::io::Error,
     },
+    StagingFailed(templating::DryRunError),
+    ReviewFailed(templating::ReviewError),
     AssetDirSymlinkFailed(ln::Error),
     DotCargoGenFailed(ndk::MissingToolError),
 }
@@ -29,7 +39,7 @@ pub enum Error {
 impl Reportable for Error {
     fn report(&self) -> Report {
         match self {
-            Self::RustupFailed(err) => Report::error("Failed to `rustup` Android toolchains", err),
+            Self::RustupFailed(err) => err.report(),
             Self::MissingPack(err) => Report::error("Failed to locate Android template pack", err),
             Self::TemplateProcessingFailed(err) => {
                 Report::error("Android template processing failed", err)
@@ -38,6 +48,12 @@ impl Reportable for Error {
                 format!("Failed to create Android assets directory at {:?}", path),
                 cause,
             ),
+            Self::StagingFailed(err) => {
+                Report::error("Failed to stage Android Studio project", err)
+            }
+            Self::ReviewFailed(err) => {
+                Report::error("Failed to update Android Studio project", err)
+            }
             Self::AssetDirSymlinkFailed(err) => {
                 Report::error("Asset dir couldn't be symlinked into Android project", err)
             }
@@ -48,23 +64,30 @@ impl Reportable for Error {
     }
 }
 
-pub fn gen(
+/// Renders the Android Studio template pack into `dest`, which is normally the
+/// project dir, but may be somewhere else for dry runs.
+pub fn process_templates(
     config: &Config,
-    env: &Env,
+    metadata: &Metadata,
     bike: &bicycle::Bicycle,
-    filter: &templating::Filter,
-    dot_cargo: &mut dot_cargo::DotCargo,
+    dest: &Path,
+    filter: impl FnMut(&bicycle::Action) -> bool,
 ) -> Result<(), Error> {
-    println!("Installing Android toolchains...");
-    Target::install_all().map_err(Error::RustupFailed)?;
-    println!("Generating Android Studio project...");
     let src = Pack::lookup_platform(TEMPLATE_PACK)
         .map_err(Error::MissingPack)?
         .expect_local();
-    let dest = config.project_dir();
+    let mut excluded = if config.library_module() {
+        Vec::new()
+    } else {
+        vec![src.join("rust")]
+    };
+    if config.network_security().is_none() {
+        excluded.push(src.join("app/src/main/res/xml"));
+        excluded.push(src.join("app/src/debug"));
+    }
     bike.filter_and_process(
         src,
-        &dest,
+        dest,
         |map| {
             map.insert(
                 "root-dir-rel",
@@ -79,10 +102,44 @@ pub fn gen(
                     .map(|target| target.arch)
                     .collect::<Vec<_>>(),
             );
+            map.insert("foreground-service", metadata.foreground_service());
+            map.insert("worker", metadata.worker());
+            // Anything beyond `NativeActivity` is written in Kotlin.
+            let kotlin = config.app().template_pack().webview()
+                || metadata.foreground_service().is_some()
+                || metadata.worker().is_some();
+            map.insert("kotlin", kotlin);
+            map.insert("app-dependencies", kotlin || config.library_module());
         },
-        filter.fun(),
+        templating::excluding(&excluded, filter),
     )
-    .map_err(Error::TemplateProcessingFailed)?;
+    .map_err(Error::TemplateProcessingFailed)
+}
+
+pub fn gen(
+    config: &Config,
+    metadata: &Metadata,
+    env: &Env,
+    bike: &bicycle::Bicycle,
+    filter: &templating::Filter,
+    review: Review,
+    dot_cargo: &mut dot_cargo::DotCargo,
+) -> Result<(), Error> {
+    util::cli::status("Installing Android toolchains...");
+    Target::install_all().map_err(Error::RustupFailed)?;
+    util::cli::status("Generating Android Studio project...");
+    let dest = config.project_dir();
+    {
+        let staging = DryRun::new(&dest).map_err(Error::StagingFailed)?;
+        process_templates(
+            config,
+            metadata,
+            bike,
+            &staging.stage(&dest),
+            staging.filter(filter),
+        )?;
+        review.apply(&staging).map_err(Error::ReviewFailed)?;
+    }
 
     let dest = dest.join("app/src/main/assets/");
     fs::create_dir_all(&dest).map_err(|cause| Error::DirectoryCreationFailed {
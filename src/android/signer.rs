@@ -0,0 +1,279 @@
+//! Signing release builds with keys that live somewhere other than a local
+//! keystore, like a cloud KMS or an HSM. When `android.signer` is set, Gradle
+//! builds unsigned artifacts and we sign them afterwards, so the key itself
+//! never has to be on the build machine.
+//!
+//! Most KMSes and HSMs ship a PKCS#11 library (Cloud KMS has `libkmsp11`,
+//! CloudHSM and YubiHSM have their own), which `apksigner` and `jarsigner` can
+//! use directly. Anything else can be plugged in as an external command.
+
+use super::env::Env;
+use crate::{
+    env::ExplicitEnv as _,
+    secret::{self, Secret},
+    util,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Debug, Display},
+    fs::{self, OpenOptions},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Passed through the environment rather than the command line, so it doesn't
+/// show up in `ps`.
+static PIN_ENV_VAR: &str = "CARGO_MOBILE_SIGNER_PASSWORD";
+
+#[derive(Debug)]
+pub enum Error {
+    ArtifactUnsupported(PathBuf),
+    BuildToolsMissing { sdk_root: PathBuf },
+    PinResolveFailed(secret::ResolveError),
+    ProviderConfigWriteFailed { path: PathBuf, cause: io::Error },
+    SignFailed(bossy::Error),
+    CommandEmpty,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ArtifactUnsupported(path) => {
+                write!(f, "{:?} isn't an APK or AAB, so it can't be signed", path)
+            }
+            Self::BuildToolsMissing { sdk_root } => write!(
+                f,
+                "No build tools are installed in {:?}; install them with `sdkmanager` or Android Studio",
+                sdk_root
+            ),
+            Self::PinResolveFailed(err) => write!(f, "Failed to resolve signer PIN: {}", err),
+            Self::ProviderConfigWriteFailed { path, cause } => {
+                write!(f, "Failed to write PKCS#11 config to {:?}: {}", path, cause)
+            }
+            Self::SignFailed(err) => write!(f, "Signing failed: {}", err),
+            Self::CommandEmpty => write!(f, "`android.signer.command` is empty"),
+        }
+    }
+}
+
+/// Something that can sign an artifact without a local keystore.
+pub trait Signer: Debug + Send + Sync {
+    /// Signs `unsigned`, writing the signed artifact to `signed`.
+    fn sign(&self, env: &Env, unsigned: &Path, signed: &Path) -> Result<(), Error>;
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Artifact {
+    Apk,
+    Aab,
+}
+
+impl Artifact {
+    fn from_path(path: &Path) -> Result<Self, Error> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("apk") => Ok(Self::Apk),
+            Some("aab") => Ok(Self::Aab),
+            _ => Err(Error::ArtifactUnsupported(path.to_owned())),
+        }
+    }
+}
+
+/// The newest installed build tools, which is where `apksigner` lives.
+fn build_tools(env: &Env) -> Result<PathBuf, Error> {
+    let dir = env.sdk_root().join("build-tools");
+    let version = |name: &str| {
+        name.split(|c: char| !c.is_ascii_digit())
+            .map(|part| part.parse::<u32>().unwrap_or_default())
+            .collect::<Vec<_>>()
+    };
+    fs::read_dir(&dir)
+        .ok()
+        .and_then(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.join("apksigner").is_file())
+                .max_by_key(|path| {
+                    version(
+                        &path
+                            .file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                    )
+                })
+        })
+        .ok_or_else(|| Error::BuildToolsMissing {
+            sdk_root: env.sdk_root().to_owned(),
+        })
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RawPkcs11 {
+    library: PathBuf,
+    slot: Option<u32>,
+    key_alias: String,
+    pin: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RawCommand {
+    command: Vec<String>,
+}
+
+/// `[android.signer]`, tagged by `type`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Raw {
+    Pkcs11(RawPkcs11),
+    Command(RawCommand),
+}
+
+impl Raw {
+    pub fn into_signer(self, root_dir: &Path) -> Result<Arc<dyn Signer>, secret::ParseError> {
+        Ok(match self {
+            Self::Pkcs11(raw) => Arc::new(Pkcs11 {
+                library: root_dir.join(raw.library),
+                slot: raw.slot,
+                key_alias: raw.key_alias,
+                pin: raw.pin.map(|pin| pin.parse()).transpose()?,
+                root_dir: root_dir.to_owned(),
+            }),
+            Self::Command(raw) => Arc::new(External {
+                command: raw.command,
+            }),
+        })
+    }
+}
+
+/// Signs using a key held by a PKCS#11 token, via the JDK's SunPKCS11
+/// provider. The token has to hold the certificate too, which for Cloud KMS
+/// means setting `generate_certs` in `libkmsp11`'s config.
+#[derive(Debug)]
+pub struct Pkcs11 {
+    library: PathBuf,
+    slot: Option<u32>,
+    key_alias: String,
+    pin: Option<Secret>,
+    root_dir: PathBuf,
+}
+
+impl Pkcs11 {
+    // Every signing gets its own file, so concurrent runs (or other users)
+    // can't swap in a different library; it's removed once signing's done.
+    fn provider_config(&self) -> Result<PathBuf, Error> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = util::temp_dir().join(format!(
+            "pkcs11-{}-{}.cfg",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut contents = format!(
+            "name = cargo-mobile\nlibrary = {}\n",
+            self.library.display()
+        );
+        if let Some(slot) = self.slot {
+            contents.push_str(&format!("slotListIndex = {}\n", slot));
+        }
+        fs::create_dir_all(util::temp_dir())
+            .and_then(|()| {
+                OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)?
+                    .write_all(contents.as_bytes())
+            })
+            .map_err(|cause| Error::ProviderConfigWriteFailed {
+                path: path.clone(),
+                cause,
+            })?;
+        Ok(path)
+    }
+}
+
+impl Signer for Pkcs11 {
+    fn sign(&self, env: &Env, unsigned: &Path, signed: &Path) -> Result<(), Error> {
+        let artifact = Artifact::from_path(unsigned)?;
+        // Tokens that don't need a PIN still need one to be given, or the
+        // JDK prompts for it.
+        let pin = self
+            .pin
+            .as_ref()
+            .map(|pin| pin.resolve(&self.root_dir))
+            .transpose()
+            .map_err(Error::PinResolveFailed)?
+            .unwrap_or_default();
+        let provider_config = self.provider_config()?;
+        let command = match artifact {
+            Artifact::Apk => bossy::Command::pure(build_tools(env)?.join("apksigner"))
+                .with_env_vars(env.explicit_env())
+                .with_args(&[
+                    "sign",
+                    "--provider-class",
+                    "sun.security.pkcs11.SunPKCS11",
+                    "--provider-arg",
+                ])
+                .with_arg(&provider_config)
+                .with_args(&["--ks", "NONE", "--ks-type", "PKCS11", "--ks-key-alias"])
+                .with_arg(&self.key_alias)
+                .with_args(&["--ks-pass", &format!("env:{}", PIN_ENV_VAR), "--out"])
+                .with_arg(signed)
+                .with_arg(unsigned),
+            // Bundles are signed like any other JAR.
+            Artifact::Aab => bossy::Command::impure("jarsigner")
+                .with_args(&[
+                    "-keystore",
+                    "NONE",
+                    "-storetype",
+                    "PKCS11",
+                    "-providerClass",
+                    "sun.security.pkcs11.SunPKCS11",
+                    "-providerArg",
+                ])
+                .with_arg(&provider_config)
+                .with_args(&["-storepass:env", PIN_ENV_VAR, "-signedjar"])
+                .with_arg(signed)
+                .with_arg(unsigned)
+                .with_arg(&self.key_alias),
+        };
+        let result = command.with_env_var(PIN_ENV_VAR, pin).run_and_wait();
+        if let Err(err) = fs::remove_file(&provider_config) {
+            log::warn!(
+                "failed to remove PKCS#11 provider config at {:?}: {}",
+                provider_config,
+                err
+            );
+        }
+        result.map_err(Error::SignFailed)?;
+        Ok(())
+    }
+}
+
+/// Runs a command of your choosing, with `{input}` and `{output}` replaced by
+/// the unsigned and signed artifact paths. It's run with your environment, so
+/// it can use whatever credentials your KMS's CLI is logged in with.
+#[derive(Debug)]
+pub struct External {
+    command: Vec<String>,
+}
+
+impl Signer for External {
+    fn sign(&self, _env: &Env, unsigned: &Path, signed: &Path) -> Result<(), Error> {
+        Artifact::from_path(unsigned)?;
+        let mut args = self.command.iter().map(|arg| {
+            arg.replace("{input}", &unsigned.display().to_string())
+                .replace("{output}", &signed.display().to_string())
+        });
+        let program = args.next().ok_or(Error::CommandEmpty)?;
+        bossy::Command::impure(program)
+            .with_args(args)
+            .run_and_wait()
+            .map_err(Error::SignFailed)?;
+        Ok(())
+    }
+}
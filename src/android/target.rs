@@ -6,21 +6,31 @@ use super::{
 };
 use crate::{
     dot_cargo::DotCargoTarget,
-    opts::{ForceColor, NoiseLevel, Profile},
+    opts::{Coverage, EntryPoint, ForceColor, NoiseLevel, Profile},
     target::TargetTrait,
     util::{
+        self,
         cli::{Report, Reportable},
+        repro::{self, Recorded as _},
         CargoCommand,
     },
 };
 use once_cell_regex::exports::once_cell::sync::OnceCell;
 use serde::Serialize;
-use std::{collections::BTreeMap, fmt, io, str};
+use std::{collections::BTreeMap, fmt, io, path::PathBuf, str};
+
+static RUSTFLAGS: &[&str] = &[
+    "-Clink-arg=-landroid",
+    "-Clink-arg=-llog",
+    "-Clink-arg=-lOpenSLES",
+];
 
 #[derive(Clone, Copy, Debug)]
 pub enum CargoMode {
     Check,
     Build,
+    Test,
+    Bench,
 }
 
 impl fmt::Display for CargoMode {
@@ -28,6 +38,8 @@ impl fmt::Display for CargoMode {
         match self {
             CargoMode::Check => write!(f, "check"),
             CargoMode::Build => write!(f, "build"),
+            CargoMode::Test => write!(f, "test"),
+            CargoMode::Bench => write!(f, "bench"),
         }
     }
 }
@@ -37,6 +49,8 @@ impl CargoMode {
         match self {
             CargoMode::Check => "check",
             CargoMode::Build => "build",
+            CargoMode::Test => "test",
+            CargoMode::Bench => "bench",
         }
     }
 }
@@ -99,6 +113,47 @@ impl Reportable for BuildError {
     }
 }
 
+#[derive(Debug)]
+pub enum TestBuildError {
+    BuildFailed(CompileLibError),
+    OutputInvalid(str::Utf8Error),
+    NoTests,
+    NoBenches,
+    RequiredLibsFailed(ndk::RequiredLibsError),
+    LibcxxSharedPathFailed(ndk::MissingToolError),
+}
+
+impl Reportable for TestBuildError {
+    fn report(&self) -> Report {
+        match self {
+            Self::BuildFailed(err) => err.report(),
+            Self::OutputInvalid(err) => {
+                Report::error("`cargo` output contained invalid UTF-8", err)
+            }
+            Self::NoTests => Report::error(
+                "Failed to build tests",
+                "`cargo test` didn't produce any test harnesses",
+            ),
+            Self::NoBenches => Report::error(
+                "Failed to build benchmarks",
+                "`cargo bench` didn't produce any benchmark harnesses",
+            ),
+            Self::RequiredLibsFailed(err) => err.report(),
+            Self::LibcxxSharedPathFailed(err) => {
+                Report::error("Failed to locate \"libc++_shared.so\"", err)
+            }
+        }
+    }
+}
+
+/// A test harness built for a device, along with the shared libs it needs
+/// pushed alongside it.
+#[derive(Debug)]
+pub struct TestHarness {
+    pub executable: PathBuf,
+    pub libs: Vec<PathBuf>,
+}
+
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct Target<'a> {
     pub triple: &'a str,
@@ -204,15 +259,15 @@ impl<'a> Target<'a> {
         Ok(DotCargoTarget {
             ar: Some(ar),
             linker: Some(linker),
-            rustflags: vec![
-                "-Clink-arg=-landroid".to_owned(),
-                "-Clink-arg=-llog".to_owned(),
-                "-Clink-arg=-lOpenSLES".to_owned(),
-            ],
+            rustflags: RUSTFLAGS.iter().map(|flag| (*flag).to_owned()).collect(),
+            // Lets `cargo test` and `cargo nextest run` run on a device.
+            runner: Some(crate::runner::RUNNER.to_owned()),
         })
     }
 
-    fn compile_lib(
+    // The vars are left for the caller to set with `Recorded::recorded`, so
+    // that they end up in `repro.sh`.
+    fn cargo_command(
         &self,
         config: &Config,
         metadata: &Metadata,
@@ -221,45 +276,219 @@ impl<'a> Target<'a> {
         force_color: ForceColor,
         profile: Profile,
         mode: CargoMode,
-    ) -> Result<(), CompileLibError> {
+        entry_point: &EntryPoint,
+    ) -> Result<(bossy::Command, repro::Vars), CompileLibError> {
         let min_sdk_version = config.min_sdk_version();
         // Force color, since gradle would otherwise give us uncolored output
         // (which Android Studio makes red, which is extra gross!)
         let color = if force_color.yes() { "always" } else { "auto" };
-        CargoCommand::new(mode.as_str())
+        let cargo = CargoCommand::new(mode.as_str())
             .with_verbose(noise_level.pedantic())
             .with_package(Some(config.app().name()))
             .with_manifest_path(Some(config.app().manifest_path()))
+            .with_target_dir(config.app().configured_target_dir().map(ToOwned::to_owned))
             .with_target(Some(self.triple))
             .with_no_default_features(metadata.no_default_features())
             .with_features(metadata.features())
+            .with_example(entry_point.example())
             .with_release(profile.release())
-            .into_command_pure(env)
-            .with_env_var("ANDROID_NATIVE_API_LEVEL", min_sdk_version.to_string())
-            .with_env_var(
+            .with_profiles(Some(metadata.profiles()));
+        let mut vars = repro::Vars::default();
+        vars.extend(cargo.env_vars());
+        vars.add("ANDROID_NATIVE_API_LEVEL", min_sdk_version.to_string())
+            .add(
                 "TARGET_AR",
                 env.ndk
                     .binutil_path(ndk::Binutil::Ar, self.binutils_triple())
                     .map_err(CompileLibError::MissingTool)?,
             )
-            .with_env_var(
+            .add(
                 "TARGET_CC",
                 env.ndk
                     .compiler_path(ndk::Compiler::Clang, self.clang_triple(), min_sdk_version)
                     .map_err(CompileLibError::MissingTool)?,
             )
-            .with_env_var(
+            .add(
                 "TARGET_CXX",
                 env.ndk
                     .compiler_path(ndk::Compiler::Clangxx, self.clang_triple(), min_sdk_version)
                     .map_err(CompileLibError::MissingTool)?,
-            )
-            .with_args(&["--color", color])
+            );
+        if config.app().template_pack().webview() {
+            // `wry` generates the Kotlin half of its WebView activity at build
+            // time, and needs to know where it's going and what it's called.
+            vars.add(
+                "WRY_ANDROID_PACKAGE",
+                format!(
+                    "{}.{}",
+                    config.app().reverse_domain(),
+                    config.app().name_snake()
+                ),
+            );
+            vars.add("WRY_ANDROID_LIBRARY", config.app().lib_name());
+            vars.add(
+                "WRY_ANDROID_KOTLIN_FILES_OUT_DIR",
+                config.project_dir().join("app/src/main/kotlin/generated"),
+            );
+        }
+        if !profile.release() {
+            if let Some(url) = crate::dev::url(config.app()) {
+                // `adb reverse` makes this reachable from the device as is.
+                vars.add(crate::dev::URL_ENV_VAR, url);
+            }
+        }
+        if util::reproducible::enabled() {
+            vars.add(
+                util::reproducible::RUSTFLAGS_ENV_VAR,
+                util::reproducible::rustflags(config.app(), self.triple, None),
+            );
+        }
+        let command = cargo.into_command_pure(env).with_args(&["--color", color]);
+        Ok((command, vars))
+    }
+
+    fn compile_lib(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        env: &Env,
+        noise_level: NoiseLevel,
+        force_color: ForceColor,
+        profile: Profile,
+        mode: CargoMode,
+        entry_point: &EntryPoint,
+    ) -> Result<(), CompileLibError> {
+        let (command, vars) = self.cargo_command(
+            config,
+            metadata,
+            env,
+            noise_level,
+            force_color,
+            profile,
+            mode,
+            entry_point,
+        )?;
+        command
+            .recorded(env, vars)
             .run_and_wait()
             .map_err(|cause| CompileLibError::CargoFailed { mode, cause })?;
         Ok(())
     }
 
+    /// Builds the crate's test harnesses without running them, since they
+    /// need to be pushed to a device first.
+    pub fn build_tests(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        env: &Env,
+        noise_level: NoiseLevel,
+        profile: Profile,
+        coverage: Coverage,
+    ) -> Result<Vec<TestHarness>, TestBuildError> {
+        self.build_harnesses(
+            config,
+            metadata,
+            env,
+            noise_level,
+            profile,
+            CargoMode::Test,
+            coverage,
+        )
+    }
+
+    /// Like `build_tests`, but for the crate's benchmarks. `cargo bench`
+    /// always uses the `bench` profile, and doesn't take `--release`.
+    pub fn build_benches(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        env: &Env,
+        noise_level: NoiseLevel,
+    ) -> Result<Vec<TestHarness>, TestBuildError> {
+        self.build_harnesses(
+            config,
+            metadata,
+            env,
+            noise_level,
+            Profile::Debug,
+            CargoMode::Bench,
+            Coverage::No,
+        )
+    }
+
+    fn build_harnesses(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        env: &Env,
+        noise_level: NoiseLevel,
+        profile: Profile,
+        mode: CargoMode,
+        coverage: Coverage,
+    ) -> Result<Vec<TestHarness>, TestBuildError> {
+        let (command, mut vars) = self
+            .cargo_command(
+                config,
+                metadata,
+                env,
+                noise_level,
+                ForceColor::No,
+                profile,
+                mode,
+                &EntryPoint::Lib,
+            )
+            .map_err(TestBuildError::BuildFailed)?;
+        if coverage.yes() {
+            vars.add(
+                util::reproducible::RUSTFLAGS_ENV_VAR,
+                util::reproducible::rustflags(
+                    config.app(),
+                    self.triple,
+                    Some(util::coverage::RUSTFLAG),
+                ),
+            );
+        }
+        let output = command
+            .with_args(&["--no-run", "--message-format=json"])
+            .recorded(env, vars)
+            .run_and_wait_for_output()
+            .map_err(|cause| {
+                TestBuildError::BuildFailed(CompileLibError::CargoFailed { mode, cause })
+            })?;
+        let executables =
+            util::test_executables(output.stdout_str().map_err(TestBuildError::OutputInvalid)?);
+        if executables.is_empty() {
+            return Err(match mode {
+                CargoMode::Bench => TestBuildError::NoBenches,
+                _ => TestBuildError::NoTests,
+            });
+        }
+        executables
+            .into_iter()
+            .map(|executable| self.harness(env, executable))
+            .collect()
+    }
+
+    /// Works out which libs need to be pushed alongside an already built
+    /// test executable.
+    pub fn harness(&self, env: &Env, executable: PathBuf) -> Result<TestHarness, TestBuildError> {
+        let needs_cxx_shared = env
+            .ndk
+            .required_libs(&executable, self.binutils_triple())
+            .map_err(TestBuildError::RequiredLibsFailed)?
+            .contains("libc++_shared.so");
+        let libs = if needs_cxx_shared {
+            vec![env
+                .ndk
+                .libcxx_shared_path(*self)
+                .map_err(TestBuildError::LibcxxSharedPathFailed)?]
+        } else {
+            Vec::new()
+        };
+        Ok(TestHarness { executable, libs })
+    }
+
     pub fn check(
         &self,
         config: &Config,
@@ -276,26 +505,35 @@ impl<'a> Target<'a> {
             force_color,
             Profile::Debug,
             CargoMode::Check,
+            &EntryPoint::Lib,
         )
     }
 
+    /// Where cargo puts the dynamic library built for this target.
+    pub fn lib_path(&self, config: &Config, profile: Profile, entry_point: &EntryPoint) -> PathBuf {
+        config
+            .app()
+            .target_dir()
+            .join(self.triple)
+            .join(profile.as_str())
+            .join(entry_point.artifact(&config.so_name(), "so"))
+    }
+
     pub fn symlink_libs(
         &self,
         config: &Config,
         ndk: &ndk::Env,
         profile: Profile,
+        entry_point: &EntryPoint,
     ) -> Result<(), SymlinkLibsError> {
         let jnilibs =
             JniLibs::create(config, *self).map_err(SymlinkLibsError::JniLibsCreationFailed)?;
 
-        let src = config.app().prefix_path(format!(
-            "target/{}/{}/{}",
-            &self.triple,
-            profile.as_str(),
-            config.so_name(),
-        ));
+        let src = self.lib_path(config, profile, entry_point);
+        // Examples are named after themselves, but the app loads the lib by
+        // the lib's name.
         jnilibs
-            .symlink_lib(&src)
+            .symlink_lib_as(&src, &config.so_name())
             .map_err(SymlinkLibsError::SymlinkFailed)?;
 
         let needs_cxx_shared = ndk
@@ -323,7 +561,9 @@ impl<'a> Target<'a> {
         noise_level: NoiseLevel,
         force_color: ForceColor,
         profile: Profile,
+        entry_point: &EntryPoint,
     ) -> Result<(), BuildError> {
+        let _lock = util::lock::ProjectLock::acquire(config.app().gen_dir());
         self.compile_lib(
             config,
             metadata,
@@ -332,9 +572,10 @@ impl<'a> Target<'a> {
             force_color,
             profile,
             CargoMode::Build,
+            entry_point,
         )
         .map_err(BuildError::BuildFailed)?;
-        self.symlink_libs(config, &env.ndk, profile)
+        self.symlink_libs(config, &env.ndk, profile, entry_point)
             .map_err(BuildError::SymlinkLibsFailed)
     }
 }
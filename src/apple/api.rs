@@ -0,0 +1,266 @@
+//! Builder types for driving the iOS pipelines from other tools, without
+//! going through the CLI.
+
+use crate::{
+    apple::{
+        config::{Config, Metadata},
+        device::{self, Device, DeviceListError, RunError},
+        target::{BuildError, Target},
+    },
+    config::{
+        metadata::{self, Metadata as OmniMetadata},
+        Config as OmniConfig, LoadOrGenError, Overrides,
+    },
+    env::{Env, Error as EnvError},
+    event::{Event, Listener},
+    opts,
+    target::{get_targets, TargetInvalid},
+    util::cli::{self, Report, Reportable},
+};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    EnvInitFailed(EnvError),
+    ConfigFailed(LoadOrGenError),
+    MetadataFailed(metadata::Error),
+    Unsupported,
+    ProjectDirAbsent { project_dir: PathBuf },
+    TargetInvalid(TargetInvalid),
+    BuildFailed(BuildError),
+    DeviceListFailed(DeviceListError),
+    DeviceNotFound { id: String },
+    NoneDetected,
+    RunFailed(RunError),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::EnvInitFailed(err) => err.report(),
+            Self::ConfigFailed(err) => err.report(),
+            Self::MetadataFailed(err) => err.report(),
+            Self::Unsupported => Report::error("iOS is marked as unsupported in your Cargo.toml metadata", "If your project should support iOS, modify your Cargo.toml, then run `cargo mobile init` and try again."),
+            Self::ProjectDirAbsent { project_dir } => Report::action_request(
+                "Please run `cargo mobile init` and try again!",
+                format!("Xcode project directory {:?} doesn't exist.", project_dir),
+            ),
+            Self::TargetInvalid(err) => Report::error("Specified target was invalid", err),
+            Self::BuildFailed(err) => err.report(),
+            Self::DeviceListFailed(err) => err.report(),
+            Self::DeviceNotFound { id } => Report::error(
+                "Failed to find iOS device",
+                format!("No connected iOS device has the ID {:?}", id),
+            ),
+            Self::NoneDetected => Report::error(
+                "Failed to find iOS device",
+                "No connected iOS devices detected",
+            ),
+            Self::RunFailed(err) => err.report(),
+        }
+    }
+}
+
+fn with_config<T>(
+    root_dir: &Path,
+    overrides: &Overrides,
+    f: impl FnOnce(&Config, &Metadata) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let (config, _origin) = OmniConfig::load_or_gen(
+        root_dir,
+        opts::NonInteractive::Yes,
+        overrides,
+        &cli::default_wrapper(),
+    )
+    .map_err(Error::ConfigFailed)?;
+    let metadata =
+        OmniMetadata::load(&config.app().crate_dir(), overrides).map_err(Error::MetadataFailed)?;
+    if !metadata.apple().supported() {
+        return Err(Error::Unsupported);
+    }
+    if !config.apple().project_dir_exists() {
+        return Err(Error::ProjectDirAbsent {
+            project_dir: config.apple().project_dir(),
+        });
+    }
+    f(config.apple(), metadata.apple())
+}
+
+/// Builds the Xcode project for one or more targets.
+///
+/// If no targets are specified, the default target is used; unlike
+/// `cargo apple build`, connected devices are never consulted.
+#[derive(Clone, Debug)]
+pub struct Builder {
+    root_dir: PathBuf,
+    overrides: Overrides,
+    targets: Vec<String>,
+    noise_level: opts::NoiseLevel,
+    profile: opts::Profile,
+    listener: Listener,
+}
+
+impl Builder {
+    pub fn new(root_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            root_dir: root_dir.into(),
+            overrides: Default::default(),
+            targets: Vec::new(),
+            noise_level: opts::NoiseLevel::Polite,
+            profile: opts::Profile::Debug,
+            listener: Default::default(),
+        }
+    }
+
+    pub fn gen_dir(mut self, gen_dir: impl Into<PathBuf>) -> Self {
+        self.overrides.gen_dir = Some(gen_dir.into());
+        self
+    }
+
+    pub fn config_profile(mut self, profile: impl Into<String>) -> Self {
+        self.overrides.profile = Some(profile.into());
+        self
+    }
+
+    pub fn app(mut self, app: impl Into<String>) -> Self {
+        self.overrides.app = Some(app.into());
+        self
+    }
+
+    pub fn targets(mut self, targets: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.targets = targets.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn noise_level(mut self, noise_level: opts::NoiseLevel) -> Self {
+        self.noise_level = noise_level;
+        self
+    }
+
+    pub fn profile(mut self, profile: opts::Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn on_event(mut self, callback: impl Fn(&Event<'_>) + Send + Sync + 'static) -> Self {
+        self.listener = Listener::new(callback);
+        self
+    }
+
+    /// Emits `Finished` or `Failed` once everything's built (or not).
+    pub fn build(&self) -> Result<(), Error> {
+        self.listener.finish(self.try_build())
+    }
+
+    fn try_build(&self) -> Result<(), Error> {
+        let env = Env::new().map_err(Error::EnvInitFailed)?;
+        with_config(&self.root_dir, &self.overrides, |config, _| {
+            let targets = get_targets::<_, _, Target, ()>(self.targets.iter(), None)
+                .map_err(Error::TargetInvalid)?;
+            self.listener.emit(Event::BuildStarted);
+            for target in targets {
+                self.listener.emit(Event::CompilingTarget {
+                    triple: target.triple,
+                });
+                target
+                    .build(config, &env, self.noise_level, self.profile)
+                    .map_err(Error::BuildFailed)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Builds, deploys, and launches the app on a connected iOS device.
+///
+/// If no device ID is specified, the first detected device is used.
+#[derive(Clone, Debug)]
+pub struct Runner {
+    root_dir: PathBuf,
+    overrides: Overrides,
+    device_id: Option<String>,
+    noise_level: opts::NoiseLevel,
+    profile: opts::Profile,
+    listener: Listener,
+}
+
+impl Runner {
+    pub fn new(root_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            root_dir: root_dir.into(),
+            overrides: Default::default(),
+            device_id: None,
+            noise_level: opts::NoiseLevel::Polite,
+            profile: opts::Profile::Debug,
+            listener: Default::default(),
+        }
+    }
+
+    pub fn gen_dir(mut self, gen_dir: impl Into<PathBuf>) -> Self {
+        self.overrides.gen_dir = Some(gen_dir.into());
+        self
+    }
+
+    pub fn config_profile(mut self, profile: impl Into<String>) -> Self {
+        self.overrides.profile = Some(profile.into());
+        self
+    }
+
+    pub fn app(mut self, app: impl Into<String>) -> Self {
+        self.overrides.app = Some(app.into());
+        self
+    }
+
+    pub fn device_id(mut self, device_id: impl Into<String>) -> Self {
+        self.device_id = Some(device_id.into());
+        self
+    }
+
+    pub fn noise_level(mut self, noise_level: opts::NoiseLevel) -> Self {
+        self.noise_level = noise_level;
+        self
+    }
+
+    pub fn profile(mut self, profile: opts::Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn on_event(mut self, callback: impl Fn(&Event<'_>) + Send + Sync + 'static) -> Self {
+        self.listener = Listener::new(callback);
+        self
+    }
+
+    fn device<'a>(&self, config: &Config, env: &Env) -> Result<Device<'a>, Error> {
+        let mut devices = device::device_list(env, config.deploy_backend())
+            .map_err(Error::DeviceListFailed)?
+            .into_iter();
+        match &self.device_id {
+            Some(id) => devices
+                .find(|device| device.id() == id.as_str())
+                .ok_or_else(|| Error::DeviceNotFound { id: id.clone() }),
+            None => devices.next().ok_or(Error::NoneDetected),
+        }
+    }
+
+    /// Emits `Finished` or `Failed` once the app's launched (or not).
+    pub fn run(&self) -> Result<(), Error> {
+        self.listener.finish(self.try_run())
+    }
+
+    fn try_run(&self) -> Result<(), Error> {
+        let env = Env::new().map_err(Error::EnvInitFailed)?;
+        with_config(&self.root_dir, &self.overrides, |config, _| {
+            self.device(config, &env)?
+                .run(
+                    config,
+                    &env,
+                    self.noise_level,
+                    opts::NonInteractive::Yes,
+                    self.profile,
+                    &self.listener,
+                )
+                .map_err(Error::RunFailed)
+        })
+    }
+}
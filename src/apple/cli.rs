@@ -1,29 +1,43 @@
 use crate::{
     apple::{
-        config::{Config, Metadata},
-        device::{Device, RunError},
-        ios_deploy, rust_version_check,
-        target::{ArchiveError, BuildError, CheckError, CompileLibError, ExportError, Target},
+        config::{Config, Metadata, APP_CLIP_SIZE_BUDGET},
+        device::{
+            self as ios_device, Backend, Device, DeviceListError, InfoError, RunError, TestError,
+        },
+        framework, rust_version_check, simctl,
+        target::{
+            ArchiveError, BuildError, CheckError, CompileLibError, ExampleLibError, ExportError,
+            Target, TestBuildError, TestBundleError,
+        },
         NAME,
     },
     config::{
+        app::cbindgen,
         metadata::{self, Metadata as OmniMetadata},
-        Config as OmniConfig, LoadOrGenError,
+        Config as OmniConfig, LoadOrGenError, Overrides,
     },
     define_device_prompt,
-    device::PromptError,
+    device::{self, PromptError},
     env::{Env, Error as EnvError},
-    opts, os,
+    i18n, opts, os,
     target::{call_for_targets_with_fallback, TargetInvalid, TargetTrait as _},
+    test_lab,
     util::{
         self,
         cli::{
             self, Exec, GlobalFlags, Report, Reportable, TextWrapper, VERSION_LONG, VERSION_SHORT,
         },
+        progress::Step,
         prompt,
     },
 };
-use std::{collections::HashMap, ffi::OsStr, path::PathBuf};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+    ffi::OsStr,
+    io::{self, Write as _},
+    path::PathBuf,
+};
 use structopt::{clap::AppSettings, StructOpt};
 
 #[derive(Debug, StructOpt)]
@@ -47,6 +61,25 @@ impl Input {
     }
 }
 
+/// App Clips over the size budget get rejected by App Store Connect, so it's
+/// better to find out now. This only warns, since archiving still succeeded.
+fn check_app_clip_size(config: &Config, wrapper: &TextWrapper) {
+    let path = config.app_clip_archive_path();
+    match util::dir_size(&path) {
+        Ok(size) if size > APP_CLIP_SIZE_BUDGET => Report::action_request(
+            format!(
+                "App Clip is {:.1} MB, which is over the {} MB budget",
+                size as f64 / (1024.0 * 1024.0),
+                APP_CLIP_SIZE_BUDGET / (1024 * 1024)
+            ),
+            "Trim the assets it bundles, or build the Rust lib with `opt-level = \"z\"` and LTO in your release profile.",
+        )
+        .print(wrapper),
+        Ok(size) => log::info!("App Clip is {} bytes", size),
+        Err(err) => log::warn!("failed to measure App Clip at {:?}: {}", path, err),
+    }
+}
+
 fn macos_from_platform(platform: &str) -> bool {
     platform == "macOS"
 }
@@ -67,6 +100,8 @@ pub enum Command {
     Check {
         #[structopt(name = "targets", default_value = Target::DEFAULT_KEY, possible_values = Target::name_list())]
         targets: Vec<String>,
+        #[structopt(flatten)]
+        force: cli::Force,
     },
     #[structopt(name = "build", about = "Builds static libraries for target(s)")]
     Build {
@@ -74,6 +109,23 @@ pub enum Command {
         targets: Vec<String>,
         #[structopt(flatten)]
         profile: cli::Profile,
+        #[structopt(flatten)]
+        force: cli::Force,
+        #[structopt(flatten)]
+        entry_point: cli::EntryPoint,
+        #[structopt(flatten)]
+        metrics_file: cli::MetricsFile,
+        #[structopt(
+            long = "framework",
+            help = "Package the built targets into a signed XCFramework in a Swift package instead, for use outside of this project"
+        )]
+        framework: bool,
+        #[structopt(
+            long = "sign-identity",
+            help = "The identity to sign the XCFramework with",
+            default_value = framework::DEFAULT_SIGN_IDENTITY
+        )]
+        sign_identity: String,
     },
     #[structopt(name = "archive", about = "Builds and archives for targets(s)")]
     Archive {
@@ -86,9 +138,105 @@ pub enum Command {
     Run {
         #[structopt(flatten)]
         profile: cli::Profile,
+        #[structopt(flatten)]
+        entry_point: cli::EntryPoint,
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+        #[structopt(flatten)]
+        wait: cli::Wait,
+        #[structopt(
+            long = "all-devices",
+            help = "Deploy to every connected device at once"
+        )]
+        all_devices: bool,
+        #[structopt(flatten)]
+        run_args: cli::RunArgs,
+    },
+    #[structopt(name = "test", about = "Runs the crate's tests")]
+    Test {
+        #[structopt(flatten)]
+        profile: cli::Profile,
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+        #[structopt(flatten)]
+        wait: cli::Wait,
+        #[structopt(
+            long = "on-device",
+            help = "Run the tests on a connected device, instead of on this Mac"
+        )]
+        on_device: bool,
+        #[structopt(
+            long = "coverage",
+            help = "Collect code coverage from on-device tests, and generate lcov and HTML reports",
+            parse(from_flag = opts::Coverage::from_bool),
+        )]
+        coverage: opts::Coverage,
+        #[structopt(
+            name = "ARGS",
+            help = "Arguments passed to the test harness",
+            last = true
+        )]
+        args: Vec<String>,
+    },
+    #[structopt(
+        name = "test-lab",
+        about = "Runs the project's XCTests on Firebase Test Lab"
+    )]
+    TestLab {
+        #[structopt(flatten)]
+        test_lab: cli::TestLab,
+    },
+    #[structopt(
+        name = "record",
+        about = "Runs the app on a simulator and records a video of the session"
+    )]
+    Record {
+        #[structopt(flatten)]
+        profile: cli::Profile,
+        #[structopt(
+            long = "out",
+            help = "Where to write the video",
+            default_value = "recording.mp4"
+        )]
+        out: PathBuf,
+        #[structopt(
+            long = "simulator",
+            help = "Name or UDID of the simulator to use (defaults to `CARGO_MOBILE_DEVICE` if that's a simulator, and otherwise whichever is booted)"
+        )]
+        simulator: Option<String>,
+    },
+    #[structopt(
+        name = "debug",
+        about = "Deploys IPA to connected device and attaches lldb with Rust symbols"
+    )]
+    Debug {
+        #[structopt(flatten)]
+        profile: cli::Profile,
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+        #[structopt(flatten)]
+        wait: cli::Wait,
+        #[structopt(
+            long = "wait-for-launch",
+            help = "Install without launching, and attach once the app is launched on the device"
+        )]
+        wait_for_launch: bool,
+    },
+    #[structopt(
+        name = "device-info",
+        about = "Prints details about a connected device"
+    )]
+    DeviceInfo {
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+        #[structopt(flatten)]
+        wait: cli::Wait,
     },
     #[structopt(name = "list", about = "Lists connected devices")]
-    List,
+    List {
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+    },
     #[structopt(
         name = "xcode-script",
         about = "Compiles static lib (should only be called by Xcode!)",
@@ -122,6 +270,14 @@ pub enum Command {
             required = true
         )]
         arches: Vec<String>,
+        #[structopt(
+            short = "j",
+            long = "jobs",
+            help = "How many arches to compile at once (defaults to 1). Each concurrent build gets a target dir of its own, so dependencies and build scripts are built once per arch and take up that much more disk space; this is only a win with cores to spare and a warm cache"
+        )]
+        jobs: Option<usize>,
+        #[structopt(flatten)]
+        entry_point: cli::EntryPoint,
     },
 }
 
@@ -129,19 +285,30 @@ pub enum Command {
 pub enum Error {
     EnvInitFailed(EnvError),
     RustVersionCheckFailed(util::RustVersionError),
-    DevicePromptFailed(PromptError<ios_deploy::DeviceListError>),
+    DevicePromptFailed(PromptError<DeviceListError>),
     TargetInvalid(TargetInvalid),
     ConfigFailed(LoadOrGenError),
     MetadataFailed(metadata::Error),
     Unsupported,
     ProjectDirAbsent { project_dir: PathBuf },
-    OpenFailed(bossy::Error),
+    OpenFailed { editor: String, cause: bossy::Error },
     CheckFailed(CheckError),
     BuildFailed(BuildError),
     ArchiveFailed(ArchiveError),
+    OutDirFailed(util::out_dir::Error),
     ExportFailed(ExportError),
     RunFailed(RunError),
-    ListFailed(ios_deploy::DeviceListError),
+    TestBuildFailed(TestBuildError),
+    TestFailed(TestError),
+    TestBundleFailed(TestBundleError),
+    TestLabFailed(test_lab::Error),
+    SimulatorBuildFailed(BuildError),
+    RecordFailed(simctl::Error),
+    HostTestFailed(bossy::Error),
+    CoverageFailed(util::coverage::Error),
+    DeployAllFailed(device::DeployAllError),
+    DeviceInfoFailed(InfoError),
+    ListFailed(DeviceListError),
     NoHomeDir(util::NoHomeDir),
     CargoEnvFailed(bossy::Error),
     SdkRootInvalid { sdk_root: PathBuf },
@@ -149,6 +316,13 @@ pub enum Error {
     MacosSdkRootInvalid { macos_sdk_root: PathBuf },
     ArchInvalid { arch: String },
     CompileLibFailed(CompileLibError),
+    ExampleLibFailed(ExampleLibError),
+    BinUnsupported(cli::BinUnsupported),
+    I18nSyncFailed(i18n::Error),
+    CbindgenFailed(cbindgen::Error),
+    FrameworkFailed(framework::Error),
+    SentryUploadFailed(sentry::Error),
+    RustupFailed(util::rustup::Error),
 }
 
 impl Reportable for Error {
@@ -165,12 +339,25 @@ impl Reportable for Error {
                 "Please run `cargo mobile init` and try again!",
                 format!("Xcode project directory {:?} doesn't exist.", project_dir),
             ),
-            Self::OpenFailed(err) => Report::error("Failed to open project in Xcode", err),
+            Self::OpenFailed { editor, cause } => {
+                Report::error(format!("Failed to open project in {}", editor), cause)
+            }
             Self::CheckFailed(err) => err.report(),
             Self::BuildFailed(err) => err.report(),
             Self::ArchiveFailed(err) => err.report(),
+            Self::OutDirFailed(err) => err.report(),
             Self::ExportFailed(err) => err.report(),
             Self::RunFailed(err) => err.report(),
+            Self::TestBuildFailed(err) => err.report(),
+            Self::TestFailed(err) => err.report(),
+            Self::TestBundleFailed(err) => err.report(),
+            Self::TestLabFailed(err) => err.report(),
+            Self::SimulatorBuildFailed(err) => err.report(),
+            Self::RecordFailed(err) => err.report(),
+            Self::HostTestFailed(err) => Report::error("Tests failed", err),
+            Self::CoverageFailed(err) => err.report(),
+            Self::DeployAllFailed(err) => err.report(),
+            Self::DeviceInfoFailed(err) => err.report(),
             Self::ListFailed(err) => err.report(),
             Self::NoHomeDir(err) => Report::error("Failed to load cargo env profile", err),
             Self::CargoEnvFailed(err) => Report::error("Failed to load cargo env profile", err),
@@ -191,6 +378,13 @@ impl Reportable for Error {
                 format!("{:?} isn't a known arch", arch),
             ),
             Self::CompileLibFailed(err) => err.report(),
+            Self::ExampleLibFailed(err) => err.report(),
+            Self::BinUnsupported(err) => err.report(),
+            Self::I18nSyncFailed(err) => Report::error("Failed to sync localized strings", err),
+            Self::CbindgenFailed(err) => err.report(),
+            Self::FrameworkFailed(err) => err.report(),
+            Self::SentryUploadFailed(err) => err.report(),
+            Self::RustupFailed(err) => err.report(),
         }
     }
 }
@@ -199,24 +393,33 @@ impl Exec for Input {
     type Report = Error;
 
     fn global_flags(&self) -> GlobalFlags {
-        self.flags
+        self.flags.clone()
     }
 
     fn exec(self, wrapper: &TextWrapper) -> Result<(), Self::Report> {
-        define_device_prompt!(ios_deploy::device_list, ios_deploy::DeviceListError, iOS);
+        define_device_prompt!(
+            ios_device::device_list,
+            DeviceListError,
+            iOS,
+            backend: Backend
+        );
         fn detect_target_ok<'a>(env: &Env) -> Option<&'a Target<'a>> {
-            device_prompt(env).map(|device| device.target()).ok()
+            device_prompt(env, Backend::detect(), None, &Default::default(), None)
+                .map(|device| device.target())
+                .ok()
         }
 
         fn with_config(
             non_interactive: opts::NonInteractive,
+            overrides: &Overrides,
             wrapper: &TextWrapper,
             f: impl FnOnce(&Config, &Metadata) -> Result<(), Error>,
         ) -> Result<(), Error> {
-            let (config, _origin) = OmniConfig::load_or_gen(".", non_interactive, wrapper)
-                .map_err(Error::ConfigFailed)?;
-            let metadata =
-                OmniMetadata::load(&config.app().root_dir()).map_err(Error::MetadataFailed)?;
+            let (config, _origin) =
+                OmniConfig::load_or_gen(".", non_interactive, overrides, wrapper)
+                    .map_err(Error::ConfigFailed)?;
+            let metadata = OmniMetadata::load(&config.app().crate_dir(), overrides)
+                .map_err(Error::MetadataFailed)?;
             if metadata.apple().supported() {
                 f(config.apple(), metadata.apple())
             } else {
@@ -234,40 +437,57 @@ impl Exec for Input {
             }
         }
 
-        fn open_in_xcode(config: &Config) -> Result<(), Error> {
-            os::open_file_with("Xcode", config.project_dir()).map_err(Error::OpenFailed)
+        fn open_in_editor(config: &Config) -> Result<(), Error> {
+            os::open_file_with(config.editor(), config.project_dir()).map_err(|cause| {
+                Error::OpenFailed {
+                    editor: config.editor().to_owned(),
+                    cause,
+                }
+            })
         }
 
         let version_check = || rust_version_check(wrapper).map_err(Error::RustVersionCheckFailed);
 
-        let Self {
-            flags:
-                GlobalFlags {
-                    noise_level,
-                    non_interactive,
-                },
-            command,
-        } = self;
+        let Self { flags, command } = self;
+        let overrides = flags.config_overrides();
+        let GlobalFlags {
+            noise_level,
+            non_interactive,
+            ..
+        } = flags;
         let env = Env::new().map_err(Error::EnvInitFailed)?;
         match command {
             Command::Open => {
                 version_check()?;
-                with_config(non_interactive, wrapper, |config, _| {
+                with_config(non_interactive, &overrides, wrapper, |config, _| {
                     ensure_init(config)?;
-                    open_in_xcode(config)
+                    open_in_editor(config)
                 })
             }
-            Command::Check { targets } => {
+            Command::Check {
+                targets,
+                force: cli::Force { force },
+            } => {
                 version_check()?;
-                with_config(non_interactive, wrapper, |config, metadata| {
+                with_config(non_interactive, &overrides, wrapper, |config, metadata| {
                     call_for_targets_with_fallback(
                         targets.iter(),
                         &detect_target_ok,
                         &env,
                         |target: &Target| {
-                            target
-                                .check(config, metadata, &env, noise_level)
-                                .map_err(Error::CheckFailed)
+                            util::fingerprint::run_unless_fresh(
+                                config.app(),
+                                "check",
+                                target.triple,
+                                &(config, metadata, env.dotenv()),
+                                force,
+                                || {
+                                    target
+                                        .check(config, metadata, &env, noise_level)
+                                        .map_err(Error::CheckFailed)
+                                },
+                            )
+                            .map(|_fresh| ())
                         },
                     )
                     .map_err(Error::TargetInvalid)?
@@ -276,25 +496,118 @@ impl Exec for Input {
             Command::Build {
                 targets,
                 profile: cli::Profile { profile },
-            } => with_config(non_interactive, wrapper, |config, _| {
+                force: cli::Force { force },
+                entry_point,
+                metrics_file,
+                framework: false,
+                ..
+            } => with_config(non_interactive, &overrides, wrapper, |config, _| {
                 version_check()?;
                 ensure_init(config)?;
+                let entry_point = entry_point.entry_point().map_err(Error::BinUnsupported)?;
+                let metrics = metrics_file.metrics();
+                // Xcode runs `cargo apple xcode-script`, which picks these up.
+                let env = env
+                    .clone()
+                    .with_entry_point(&entry_point)
+                    .with_metrics(&metrics);
                 call_for_targets_with_fallback(
                     targets.iter(),
                     &detect_target_ok,
                     &env,
                     |target: &Target| {
-                        target
-                            .build(config, &env, noise_level, profile)
-                            .map_err(Error::BuildFailed)
+                        util::rustup::ensure_targets(Some(target.triple), non_interactive)
+                            .map_err(Error::RustupFailed)?;
+                        let lib_path = target.lib_path(config, profile);
+                        // There's nothing to skip to if the library's gone.
+                        let force = if lib_path.is_file() {
+                            force
+                        } else {
+                            opts::Force::Yes
+                        };
+                        metrics.record("ios", target.triple, profile, &lib_path, || {
+                            util::fingerprint::run_unless_fresh(
+                                config.app(),
+                                &match entry_point.example() {
+                                    Some(example) => format!(
+                                        "{} build of example {:?}",
+                                        profile.as_str(),
+                                        example
+                                    ),
+                                    None => format!("{} build", profile.as_str()),
+                                },
+                                target.triple,
+                                &(config, env.dotenv()),
+                                force,
+                                || {
+                                    Step::run(format!("Building {}", target.triple), || {
+                                        target.build(config, &env, noise_level, profile)
+                                    })
+                                    .map_err(Error::BuildFailed)
+                                },
+                            )
+                        })?;
+                        cli::print_artifact(&lib_path);
+                        Ok(())
                     },
                 )
                 .map_err(Error::TargetInvalid)?
             }),
+            // There's no Xcode project involved here, so the libs are built
+            // with cargo directly, the same way `xcode-script` would.
+            Command::Build {
+                targets,
+                profile: cli::Profile { profile },
+                entry_point,
+                metrics_file,
+                framework: true,
+                sign_identity,
+                ..
+            } => with_config(non_interactive, &overrides, wrapper, |config, metadata| {
+                version_check()?;
+                let entry_point = entry_point.entry_point().map_err(Error::BinUnsupported)?;
+                let metrics = metrics_file.metrics();
+                let built = RefCell::new(Vec::new());
+                call_for_targets_with_fallback(
+                    targets.iter(),
+                    &detect_target_ok,
+                    &env,
+                    |target: &Target| {
+                        util::rustup::ensure_targets(Some(target.triple), non_interactive)
+                            .map_err(Error::RustupFailed)?;
+                        let lib_path = target.lib_path(config, profile);
+                        metrics.record("ios", target.triple, profile, &lib_path, || {
+                            Step::run(format!("Building {}", target.triple), || {
+                                target.compile_lib(
+                                    config,
+                                    metadata,
+                                    noise_level,
+                                    opts::ForceColor::Yes,
+                                    profile,
+                                    &entry_point,
+                                    &env,
+                                    HashMap::new(),
+                                )
+                            })
+                            .map(|()| true)
+                            .map_err(Error::CompileLibFailed)
+                        })?;
+                        built.borrow_mut().push(*target);
+                        Ok(())
+                    },
+                )
+                .map_err(Error::TargetInvalid)??;
+                let package_dir = Step::run("Assembling XCFramework", || {
+                    framework::build(config, profile, &built.borrow(), &sign_identity)
+                })
+                .map_err(Error::FrameworkFailed)?;
+                cli::print_artifact(&package_dir);
+                Ok(())
+            }),
             Command::Archive {
                 targets,
                 profile: cli::Profile { profile },
-            } => with_config(non_interactive, wrapper, |config, _| {
+            } => with_config(non_interactive, &overrides, wrapper, |config, _| {
                 version_check()?;
                 ensure_init(config)?;
                 call_for_targets_with_fallback(
@@ -302,38 +615,324 @@ impl Exec for Input {
                     &detect_target_ok,
                     &env,
                     |target: &Target| {
-                        target
-                            .build(config, &env, noise_level, profile)
-                            .map_err(Error::BuildFailed)?;
-                        target
-                            .archive(config, &env, noise_level, profile)
-                            .map_err(Error::ArchiveFailed)
+                        util::rustup::ensure_targets(Some(target.triple), non_interactive)
+                            .map_err(Error::RustupFailed)?;
+                        Step::run(format!("Building {}", target.triple), || {
+                            target.build(config, &env, noise_level, profile)
+                        })
+                        .map_err(Error::BuildFailed)?;
+                        Step::run(format!("Archiving {}", target.triple), || {
+                            target.archive(config, &env, noise_level, profile)
+                        })
+                        .map_err(Error::ArchiveFailed)?;
+                        cli::print_artifact(&config.archive_path());
+                        let mut artifacts = util::out_dir::archive_dsyms(&config.archive_path());
+                        // Only if it was exported from this archive.
+                        if let Ok(ipa_path) = config.ipa_path() {
+                            if util::out_dir::newer(&ipa_path, &config.archive_path()) {
+                                artifacts.push(util::out_dir::Artifact::new(
+                                    util::out_dir::Kind::Ipa,
+                                    ipa_path,
+                                ));
+                            }
+                        }
+                        util::out_dir::collect(config.app(), "apple", profile, &artifacts)
+                            .map_err(Error::OutDirFailed)?;
+                        if config.app_clip() {
+                            check_app_clip_size(config, wrapper);
+                        }
+                        if let Some(sentry) = config.app().sentry() {
+                            Step::run("Uploading dSYMs to Sentry", || {
+                                sentry.upload(&[config.archive_path().join("dSYMs")])
+                            })
+                            .map_err(Error::SentryUploadFailed)?;
+                        }
+                        Ok(())
                     },
                 )
                 .map_err(Error::TargetInvalid)?
             }),
             Command::Run {
                 profile: cli::Profile { profile },
-            } => with_config(non_interactive, wrapper, |config, _| {
+                entry_point,
+                filter,
+                wait,
+                all_devices,
+                run_args,
+            } => with_config(
+                non_interactive,
+                &run_args.apply(&overrides),
+                wrapper,
+                |config, _| {
+                    version_check()?;
+                    ensure_init(config)?;
+                    let entry_point = entry_point.entry_point().map_err(Error::BinUnsupported)?;
+                    let env = env.clone().with_entry_point(&entry_point);
+                    if all_devices {
+                        let filter = device::Filter::from(filter);
+                        let backend = config.deploy_backend();
+                        let devices = device::poll(
+                            wait.timeout(),
+                            || {
+                                ios_device::device_list(&env, backend).map(|device_list| {
+                                    device_list
+                                        .into_iter()
+                                        .filter(|device| {
+                                            filter
+                                                .matches(device.os_version(), device.target().arch)
+                                        })
+                                        .collect::<Vec<_>>()
+                                })
+                            },
+                            |devices| !devices.is_empty(),
+                        )
+                        .map_err(Error::ListFailed)?;
+                        if devices.is_empty() {
+                            return Err(Error::DevicePromptFailed(if filter.is_empty() {
+                                PromptError::none_detected("iOS")
+                            } else {
+                                PromptError::none_matched(
+                                    "iOS",
+                                    filter.suggest_arch(
+                                        Target::all().values().map(|target| target.arch),
+                                    ),
+                                )
+                            }));
+                        }
+                        // Devices sharing a target can share a build, too.
+                        let mut built = BTreeSet::new();
+                        for device in &devices {
+                            if built.insert(device.target().triple) {
+                                device
+                                    .build(config, &env, noise_level, profile, &Default::default())
+                                    .map_err(Error::RunFailed)?;
+                            }
+                        }
+                        let (config, env) = (config.clone(), env.clone());
+                        return device::deploy_all(wrapper, devices, move |device| {
+                            device.deploy(&config, &env, &Default::default())
+                        })
+                        .map_err(Error::DeployAllFailed);
+                    }
+                    device_prompt(
+                        &env,
+                        config.deploy_backend(),
+                        Some(config.app()),
+                        &filter.into(),
+                        wait.timeout(),
+                    )
+                    .map_err(Error::DevicePromptFailed)?
+                    .run(
+                        config,
+                        &env,
+                        noise_level,
+                        non_interactive,
+                        profile,
+                        &Default::default(),
+                    )
+                    .map_err(Error::RunFailed)
+                },
+            ),
+            Command::Test {
+                profile: cli::Profile { profile },
+                filter,
+                wait,
+                on_device,
+                coverage,
+                args,
+            } => with_config(non_interactive, &overrides, wrapper, |config, metadata| {
+                if !on_device {
+                    return util::CargoCommand::new("test")
+                        .with_package(Some(config.app().name()))
+                        .with_manifest_path(Some(config.app().manifest_path()))
+                        .with_target_dir(
+                            config.app().configured_target_dir().map(ToOwned::to_owned),
+                        )
+                        .with_no_default_features(metadata.macos().no_default_features())
+                        .with_features(metadata.features())
+                        .with_features(metadata.macos().features())
+                        .with_release(profile.release())
+                        .into_command_impure()
+                        .with_arg("--")
+                        .with_args(&args)
+                        .run_and_wait()
+                        .map(|_| ())
+                        .map_err(Error::HostTestFailed);
+                }
                 version_check()?;
                 ensure_init(config)?;
-                device_prompt(&env)
-                    .map_err(Error::DevicePromptFailed)?
-                    .run(config, &env, noise_level, non_interactive, profile)
+                let device = device_prompt(
+                    &env,
+                    config.deploy_backend(),
+                    Some(config.app()),
+                    &filter.into(),
+                    wait.timeout(),
+                )
+                .map_err(Error::DevicePromptFailed)?;
+                let target = device.target();
+                util::rustup::ensure_targets(Some(target.triple), non_interactive)
+                    .map_err(Error::RustupFailed)?;
+                // The runner borrows the app's signing, so the app needs to
+                // have been built at least once.
+                if !config.app_path().is_dir() {
+                    device
+                        .build(config, &env, noise_level, profile, &Default::default())
+                        .map_err(Error::RunFailed)?;
+                }
+                let executables =
+                    Step::run(format!("Building tests for {}", target.triple), || {
+                        target.build_tests(config, metadata, &env, noise_level, profile, coverage)
+                    })
+                    .map_err(Error::TestBuildFailed)?;
+                let coverage_dir =
+                    util::coverage::output_dir(config.app().target_dir(), target.triple);
+                if coverage.yes() {
+                    util::coverage::prepare(&coverage_dir).map_err(Error::CoverageFailed)?;
+                }
+                let result = device
+                    .run_tests(
+                        config,
+                        &env,
+                        &executables,
+                        &args,
+                        Some(util::coverage::profraw_dir(&coverage_dir))
+                            .filter(|_| coverage.yes())
+                            .as_deref(),
+                    )
+                    .map_err(Error::TestFailed);
+                if coverage.yes() {
+                    util::coverage::report(&coverage_dir, &executables)
+                        .map_err(Error::CoverageFailed)?;
+                }
+                result
+            }),
+            Command::TestLab {
+                test_lab: cli::TestLab { devices, timeout },
+            } => with_config(non_interactive, &overrides, wrapper, |config, _| {
+                version_check()?;
+                ensure_init(config)?;
+                // Test Lab only has physical devices for iOS.
+                let target = Target::for_arch("arm64").expect("developer error: no arm64 target");
+                let bundle = Step::run("Building tests", || {
+                    target.build_for_testing(config, &env, noise_level)
+                })
+                .map_err(Error::TestBundleFailed)?;
+                let command = test_lab::command("ios", &devices, timeout.as_deref())
+                    .with_arg("--test")
+                    .with_arg(bundle);
+                test_lab::run(command).map_err(Error::TestLabFailed)
+            }),
+            Command::Record {
+                profile: cli::Profile { profile },
+                out,
+                simulator,
+            } => with_config(non_interactive, &overrides, wrapper, |config, _| {
+                version_check()?;
+                ensure_init(config)?;
+                let simulator = simulator
+                    .or_else(simctl::env_simulator)
+                    .unwrap_or_else(|| "booted".to_owned());
+                let target = Target::for_arch("x86_64").expect("developer error: no x86_64 target");
+                util::rustup::ensure_targets(Some(target.triple), non_interactive)
+                    .map_err(Error::RustupFailed)?;
+                let app_path = Step::run("Building for simulator", || {
+                    target.build_for_simulator(config, &env, noise_level, profile)
+                })
+                .map_err(Error::SimulatorBuildFailed)?;
+                simctl::boot(&simulator).map_err(Error::RecordFailed)?;
+                simctl::install(&simulator, &app_path).map_err(Error::RecordFailed)?;
+                simctl::record(&simulator, &out, || {
+                    simctl::launch(&simulator, &config.bundle_id())?;
+                    print!("Recording to {:?}; press Enter to stop ", out);
+                    io::stdout().flush().map_err(simctl::Error::PromptFailed)?;
+                    io::stdin()
+                        .read_line(&mut String::new())
+                        .map_err(simctl::Error::PromptFailed)
+                })
+                .map_err(Error::RecordFailed)?;
+                cli::print_artifact(&out);
+                Ok(())
+            }),
+            Command::Debug {
+                profile: cli::Profile { profile },
+                filter,
+                wait,
+                wait_for_launch,
+            } => with_config(non_interactive, &overrides, wrapper, |config, _| {
+                version_check()?;
+                ensure_init(config)?;
+                let device = device_prompt(
+                    &env,
+                    config.deploy_backend(),
+                    Some(config.app()),
+                    &filter.into(),
+                    wait.timeout(),
+                )
+                .map_err(Error::DevicePromptFailed)?;
+                util::rustup::ensure_targets(Some(device.target().triple), non_interactive)
+                    .map_err(Error::RustupFailed)?;
+                device
+                    .debug(config, &env, noise_level, profile, wait_for_launch)
                     .map_err(Error::RunFailed)
             }),
-            Command::List => ios_deploy::device_list(&env)
-                .map_err(Error::ListFailed)
-                .map(|device_list| {
-                    prompt::list_display_only(device_list.iter(), device_list.len());
-                }),
+            Command::DeviceInfo { filter, wait } => {
+                with_config(non_interactive, &overrides, wrapper, |config, _| {
+                    let device = device_prompt(
+                        &env,
+                        config.deploy_backend(),
+                        Some(config.app()),
+                        &filter.into(),
+                        wait.timeout(),
+                    )
+                    .map_err(Error::DevicePromptFailed)?;
+                    let info = device.info(&env).map_err(Error::DeviceInfoFailed)?;
+                    device::print_info(&info);
+                    Ok(())
+                })
+            }
+            Command::List { filter } => {
+                let filter = device::Filter::from(filter);
+                // There's no config to consult here, since this works outside
+                // of projects. This is how you check what's connected, so it's
+                // always fresh.
+                util::cache::skipping(|| ios_device::device_list(&env, Backend::detect()))
+                    .map_err(Error::ListFailed)
+                    .map(|device_list| {
+                        let device_list = device_list
+                            .into_iter()
+                            .filter(|device| {
+                                filter.matches(device.os_version(), device.target().arch)
+                            })
+                            .collect::<Vec<_>>();
+                        prompt::list_display_only(device_list.iter(), device_list.len());
+                    })
+            }
             Command::XcodeScript {
                 macos,
                 sdk_root,
                 profile,
                 force_color,
                 arches,
-            } => with_config(non_interactive, wrapper, |config, metadata| {
+                jobs,
+                entry_point,
+            } => with_config(non_interactive, &overrides, wrapper, |config, metadata| {
+                let entry_point = entry_point.entry_point().map_err(Error::BinUnsupported)?;
+                i18n::sync_apple(config).map_err(Error::I18nSyncFailed)?;
+                // This runs before Xcode compiles the app's sources, so the
+                // header's always current by then.
+                if let Some(cbindgen) = config.app().cbindgen() {
+                    cbindgen
+                        .generate(
+                            &config
+                                .project_dir()
+                                .join("Sources")
+                                .join(config.app().name())
+                                .join("bindings")
+                                .join(cbindgen.header()),
+                        )
+                        .map_err(Error::CbindgenFailed)?;
+                }
+
                 // The `PATH` env var Xcode gives us is missing any additions
                 // made by the user's profile, so we'll manually add cargo's
                 // `PATH`.
@@ -377,39 +976,107 @@ impl Exec for Input {
 
                 let isysroot = format!("-isysroot {}", sdk_root.display());
 
-                for arch in arches {
+                let mut builds = arches
+                    .into_iter()
+                    .map(|arch| {
+                        let triple = match arch.as_str() {
+                            "arm64" => "aarch64_apple_ios",
+                            "x86_64" => "x86_64_apple_ios",
+                            _ => return Err(Error::ArchInvalid { arch }),
+                        };
+                        let target = if macos {
+                            &macos_target
+                        } else {
+                            Target::for_arch(&arch).ok_or_else(|| Error::ArchInvalid {
+                                arch: arch.to_owned(),
+                            })?
+                        };
+                        Ok((arch, triple, target))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                // On macOS, every arch maps to the same target, and building
+                // it more than once at a time would just fight over the lock.
+                builds.dedup_by_key(|(_, _, target)| target.triple);
+
+                // When building one at a time, the arch is just `None`.
+                let compile = |triple: &str, target: &Target, arch: Option<&str>| {
                     // Set target-specific flags
-                    let triple = match arch.as_str() {
-                        "arm64" => "aarch64_apple_ios",
-                        "x86_64" => "x86_64_apple_ios",
-                        _ => return Err(Error::ArchInvalid { arch }),
-                    };
                     let cflags = format!("CFLAGS_{}", triple);
-                    let cxxflags = format!("CFLAGS_{}", triple);
                     let objc_include_path = format!("OBJC_INCLUDE_PATH_{}", triple);
                     let mut target_env = host_env.clone();
                     target_env.insert(cflags.as_ref(), isysroot.as_ref());
-                    target_env.insert(cxxflags.as_ref(), isysroot.as_ref());
                     target_env.insert(objc_include_path.as_ref(), include_dir.as_ref());
-
-                    let target = if macos {
-                        &macos_target
-                    } else {
-                        Target::for_arch(&arch).ok_or_else(|| Error::ArchInvalid {
-                            arch: arch.to_owned(),
-                        })?
-                    };
-                    target
-                        .compile_lib(
+                    match arch {
+                        Some(arch) => target.compile_lib_isolated(
                             config,
                             metadata,
                             noise_level,
                             force_color,
                             profile,
+                            &entry_point,
                             &env,
                             target_env,
-                        )
-                        .map_err(Error::CompileLibFailed)?;
+                            arch,
+                        ),
+                        None => target.compile_lib(
+                            config,
+                            metadata,
+                            noise_level,
+                            force_color,
+                            profile,
+                            &entry_point,
+                            &env,
+                            target_env,
+                        ),
+                    }
+                };
+
+                // Concurrent builds can't share a target dir, which makes them
+                // cost more than they save on a cold build, so they're opt-in.
+                let jobs = jobs.unwrap_or(1).max(1);
+                for chunk in builds.chunks(jobs) {
+                    if let [(_, triple, target)] = chunk {
+                        compile(triple, target, None).map_err(Error::CompileLibFailed)?;
+                        continue;
+                    }
+                    crossbeam_utils::thread::scope(|scope| {
+                        let handles = chunk
+                            .iter()
+                            .map(|(arch, triple, target)| {
+                                let compile = &compile;
+                                scope.spawn(move |_| compile(triple, target, Some(arch.as_str())))
+                            })
+                            .collect::<Vec<_>>();
+                        // Every build gets to finish, so that one failing
+                        // doesn't leave the others' output cut off.
+                        handles
+                            .into_iter()
+                            .map(|handle| {
+                                handle
+                                    .join()
+                                    .expect("developer error: compile thread panicked")
+                            })
+                            .fold(Ok(()), Result::and)
+                    })
+                    .expect("developer error: compile thread panicked")
+                    .map_err(Error::CompileLibFailed)?;
+                }
+                for (_, _, target) in &builds {
+                    target
+                        .link_example_lib(config, profile, &entry_point)
+                        .map_err(Error::ExampleLibFailed)?;
+                }
+                let triples = builds
+                    .iter()
+                    .map(|(_, _, target)| target.triple)
+                    .collect::<Vec<_>>();
+                if let Err(err) = util::compile_commands::update(
+                    config.app().root_dir(),
+                    config.app().target_dir(),
+                    &triples,
+                    profile,
+                ) {
+                    log::warn!("failed to update compile commands: {}", err);
                 }
                 Ok(())
             }),
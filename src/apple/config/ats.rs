@@ -0,0 +1,19 @@
+use serde::Deserialize;
+
+/// App Transport Security exceptions, i.e.
+/// `[package.metadata.cargo-apple.ios.app-transport-security]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AppTransportSecurity {
+    /// Domains that may be reached over plain HTTP. These only ever apply to
+    /// debug builds; they're stripped from anything else, and exporting an
+    /// archive that still has them fails.
+    #[serde(default)]
+    insecure_domains: Vec<String>,
+}
+
+impl AppTransportSecurity {
+    pub fn insecure_domains(&self) -> &[String] {
+        &self.insecure_domains
+    }
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+
+/// A value for `UIBackgroundModes`, i.e. `background-modes = ["audio"]`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackgroundMode {
+    Audio,
+    Location,
+    Fetch,
+    Processing,
+    RemoteNotification,
+}
+
+impl BackgroundMode {
+    pub fn info_plist_value(self) -> &'static str {
+        match self {
+            Self::Audio => "audio",
+            Self::Location => "location",
+            Self::Fetch => "fetch",
+            Self::Processing => "processing",
+            Self::RemoteNotification => "remote-notification",
+        }
+    }
+}
+
+/// Background modes and capabilities, which otherwise only live in the Xcode
+/// project and are lost whenever it's regenerated, i.e.
+/// `[package.metadata.cargo-apple.ios]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Capabilities {
+    #[serde(default)]
+    background_modes: Vec<BackgroundMode>,
+    #[serde(default)]
+    background_task_identifiers: Vec<String>,
+    #[serde(default)]
+    location_usage_description: Option<String>,
+    #[serde(default)]
+    push_notifications: bool,
+    #[serde(default)]
+    associated_domains: Vec<String>,
+    #[serde(default)]
+    app_groups: Vec<String>,
+    #[serde(default)]
+    sign_in_with_apple: bool,
+}
+
+/// An `Info.plist` key or entitlement, with its value as JSON, which the
+/// template can drop straight into `project.yml` as YAML.
+#[derive(Debug, Serialize)]
+pub struct PlistEntry {
+    key: &'static str,
+    value: String,
+}
+
+impl PlistEntry {
+    fn new(key: &'static str, value: impl Serialize) -> Self {
+        Self {
+            key,
+            value: serde_json::to_string(&value).expect("developer error: unserializable value"),
+        }
+    }
+}
+
+impl Capabilities {
+    fn has_background_mode(&self, mode: BackgroundMode) -> bool {
+        self.background_modes.contains(&mode)
+    }
+
+    pub fn info_plist(&self) -> Vec<PlistEntry> {
+        let mut entries = Vec::new();
+        if !self.background_modes.is_empty() {
+            entries.push(PlistEntry::new(
+                "UIBackgroundModes",
+                self.background_modes
+                    .iter()
+                    .map(|mode| mode.info_plist_value())
+                    .collect::<Vec<_>>(),
+            ));
+        }
+        if self.has_background_mode(BackgroundMode::Processing)
+            || self.has_background_mode(BackgroundMode::Fetch)
+        {
+            // `BGTaskScheduler` refuses to register tasks that aren't listed.
+            if self.background_task_identifiers.is_empty() {
+                log::warn!("`background-task-identifiers` is empty, so `BGTaskScheduler` won't be able to register any tasks");
+            } else {
+                entries.push(PlistEntry::new(
+                    "BGTaskSchedulerPermittedIdentifiers",
+                    &self.background_task_identifiers,
+                ));
+            }
+        }
+        if self.has_background_mode(BackgroundMode::Location) {
+            // The app's killed as soon as it asks for location access without
+            // these.
+            let description = self.location_usage_description.as_deref().unwrap_or_else(|| {
+                log::warn!("`location-usage-description` not set; the default will be shown when asking for location access");
+                "This app uses your location in the background."
+            });
+            entries.push(PlistEntry::new(
+                "NSLocationWhenInUseUsageDescription",
+                description,
+            ));
+            entries.push(PlistEntry::new(
+                "NSLocationAlwaysAndWhenInUseUsageDescription",
+                description,
+            ));
+        }
+        entries
+    }
+
+    pub fn entitlements(&self) -> Vec<PlistEntry> {
+        let mut entries = Vec::new();
+        if self.push_notifications || self.has_background_mode(BackgroundMode::RemoteNotification) {
+            // Exporting for distribution switches this to `production`.
+            entries.push(PlistEntry::new("aps-environment", "development"));
+        }
+        if !self.associated_domains.is_empty() {
+            entries.push(PlistEntry::new(
+                "com.apple.developer.associated-domains",
+                &self.associated_domains,
+            ));
+        }
+        if !self.app_groups.is_empty() {
+            entries.push(PlistEntry::new(
+                "com.apple.security.application-groups",
+                &self.app_groups,
+            ));
+        }
+        if self.sign_in_with_apple {
+            entries.push(PlistEntry::new(
+                "com.apple.developer.applesignin",
+                &["Default"],
+            ));
+        }
+        entries
+    }
+}
@@ -1,9 +1,14 @@
+mod ats;
+mod capabilities;
+mod privacy;
 mod raw;
 
-pub use self::raw::*;
+pub use self::{ats::*, capabilities::*, privacy::*, raw::*};
 
 use crate::{
+    apple::device::Backend,
     config::app::App,
+    secret::{self, Secret},
     util::{self, cli::Report},
 };
 use serde::{Deserialize, Serialize};
@@ -12,19 +17,38 @@ use std::{
     path::PathBuf,
 };
 
-static DEFAULT_PROJECT_DIR: &str = "gen/apple";
+const DEFAULT_EDITOR: &str = "Xcode";
+const DEFAULT_APP_CLIP: bool = false;
+
+/// What App Clips are limited to, uncompressed, on iOS 15 and earlier; iOS 16
+/// raised it to 15 MB, but the App Clip target supports iOS 14.
+pub const APP_CLIP_SIZE_BUDGET: u64 = 10 * 1024 * 1024;
 
 #[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct Platform {
+    #[serde(default)]
+    no_default_features: Option<bool>,
     #[serde(default)]
     features: Option<Vec<String>>,
     #[serde(default)]
     frameworks: Option<Vec<String>>,
+    #[serde(flatten)]
+    profiles: util::Profiles,
+    #[serde(flatten)]
+    capabilities: Capabilities,
+    #[serde(default)]
+    privacy: Privacy,
+    #[serde(default)]
+    app_transport_security: AppTransportSecurity,
 }
 
 impl Platform {
+    /// Listing features means the default ones are unwanted, unless
+    /// `no-default-features` explicitly says otherwise.
     pub fn no_default_features(&self) -> bool {
-        self.features.is_some()
+        self.no_default_features
+            .unwrap_or_else(|| self.features.is_some())
     }
 
     pub fn features(&self) -> Option<&[String]> {
@@ -34,6 +58,22 @@ impl Platform {
     pub fn frameworks(&self) -> &[String] {
         self.frameworks.as_deref().unwrap_or_else(|| &[])
     }
+
+    pub fn profiles(&self) -> &util::Profiles {
+        &self.profiles
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    pub fn privacy(&self) -> &Privacy {
+        &self.privacy
+    }
+
+    pub fn app_transport_security(&self) -> &AppTransportSecurity {
+        &self.app_transport_security
+    }
 }
 
 const fn default_true() -> bool {
@@ -45,6 +85,8 @@ pub struct Metadata {
     #[serde(default = "default_true")]
     supported: bool,
     #[serde(default)]
+    features: Option<Vec<String>>,
+    #[serde(default)]
     ios: Platform,
     #[serde(default)]
     macos: Platform,
@@ -54,6 +96,7 @@ impl Default for Metadata {
     fn default() -> Self {
         Self {
             supported: true,
+            features: None,
             ios: Default::default(),
             macos: Default::default(),
         }
@@ -65,6 +108,12 @@ impl Metadata {
         self.supported
     }
 
+    /// Features enabled for both iOS and macOS, in addition to any that are
+    /// specific to either.
+    pub fn features(&self) -> Option<&[String]> {
+        self.features.as_deref()
+    }
+
     pub fn ios(&self) -> &Platform {
         &self.ios
     }
@@ -111,6 +160,10 @@ pub enum Error {
     DevelopmentTeamMissing,
     DevelopmentTeamEmpty,
     ProjectDirInvalid(ProjectDirInvalid),
+    SecretInvalid {
+        key: &'static str,
+        cause: secret::ParseError,
+    },
 }
 
 impl Error {
@@ -127,17 +180,34 @@ impl Error {
                 msg,
                 format!("`{}.project-dir` invalid: {}", super::NAME, err),
             ),
+            Self::SecretInvalid { key, cause } => {
+                Report::error(msg, format!("`{}.{}` invalid: {}", super::NAME, key, cause))
+            }
         }
     }
 }
 
+/// What `altool` needs to talk to App Store Connect.
+#[derive(Clone, Debug)]
+pub struct AppStoreConnect {
+    pub key_id: Secret,
+    pub issuer_id: Secret,
+    pub private_key: Secret,
+}
+
 #[serde(rename_all = "kebab-case")]
 #[derive(Clone, Debug, Serialize)]
 pub struct Config {
     #[serde(skip_serializing)]
     app: App,
     development_team: String,
-    project_dir: String,
+    name: String,
+    project_dir: PathBuf,
+    editor: String,
+    app_clip: bool,
+    deploy_backend: Option<Backend>,
+    #[serde(skip_serializing)]
+    app_store_connect: Option<AppStoreConnect>,
 }
 
 impl Config {
@@ -148,10 +218,30 @@ impl Config {
             return Err(Error::DevelopmentTeamEmpty);
         }
 
+        let name = raw
+            .name
+            .map(|name| {
+                if name == app.stylized_name() {
+                    log::warn!(
+                        "`{}.name` is set to the default value; you can remove it from your config",
+                        super::NAME
+                    );
+                }
+                name
+            })
+            .unwrap_or_else(|| {
+                log::info!(
+                    "`{}.name` not set; defaulting to {:?}",
+                    super::NAME,
+                    app.stylized_name()
+                );
+                app.stylized_name().to_owned()
+            });
+
         let project_dir = raw
             .project_dir
             .map(|project_dir| {
-                if project_dir == DEFAULT_PROJECT_DIR {
+                if app.prefix_path(&project_dir) == app.gen_dir().join(super::NAME) {
                     log::warn!("`{}.project-dir` is set to the default value; you can remove it from your config", super::NAME);
                 }
                 if util::under_root(&project_dir, app.root_dir())
@@ -160,7 +250,7 @@ impl Config {
                         cause,
                     }))?
                 {
-                    Ok(project_dir)
+                    Ok(project_dir.into())
                 } else {
                     Err(Error::ProjectDirInvalid(ProjectDirInvalid::OutsideOfAppRoot {
                         project_dir,
@@ -168,17 +258,64 @@ impl Config {
                     }))
                 }
             }).unwrap_or_else(|| {
+                let project_dir = app.gen_dir().join(super::NAME);
                 log::info!(
-                    "`{}.project-dir` not set; defaulting to {}",
-                    super::NAME, DEFAULT_PROJECT_DIR
+                    "`{}.project-dir` not set; defaulting to {:?}",
+                    super::NAME, project_dir
                 );
-                Ok(DEFAULT_PROJECT_DIR.to_owned())
+                Ok(project_dir)
             })?;
 
+        let editor = raw.editor.unwrap_or_else(|| {
+            log::info!(
+                "`{}.editor` not set; defaulting to {:?}",
+                super::NAME,
+                DEFAULT_EDITOR
+            );
+            DEFAULT_EDITOR.to_owned()
+        });
+
+        let app_clip = raw.app_clip.unwrap_or_else(|| {
+            log::info!(
+                "`{}.app-clip` not set; defaulting to {}",
+                super::NAME,
+                DEFAULT_APP_CLIP
+            );
+            DEFAULT_APP_CLIP
+        });
+
+        if raw.deploy_backend.is_none() {
+            log::info!(
+                "`{}.deploy-backend` not set; defaulting to whichever is installed",
+                super::NAME
+            );
+        }
+
+        let parse_secret = |key: &'static str, value: String| {
+            value
+                .parse::<Secret>()
+                .map_err(|cause| Error::SecretInvalid { key, cause })
+        };
+        let app_store_connect = raw
+            .app_store_connect
+            .map(|raw| {
+                Ok(AppStoreConnect {
+                    key_id: parse_secret("app-store-connect.key-id", raw.key_id)?,
+                    issuer_id: parse_secret("app-store-connect.issuer-id", raw.issuer_id)?,
+                    private_key: parse_secret("app-store-connect.private-key", raw.private_key)?,
+                })
+            })
+            .transpose()?;
+
         Ok(Self {
             app,
             development_team: raw.development_team,
+            name,
             project_dir,
+            editor,
+            app_clip,
+            deploy_backend: raw.deploy_backend,
+            app_store_connect,
         })
     }
 
@@ -186,10 +323,35 @@ impl Config {
         &self.app
     }
 
+    /// The display name shown on the home screen.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn project_dir(&self) -> PathBuf {
         self.app.prefix_path(&self.project_dir)
     }
 
+    /// The application `cargo apple open` opens the project with.
+    pub fn editor(&self) -> &str {
+        &self.editor
+    }
+
+    /// Whether the project has an App Clip target, which shares the app's
+    /// sources and Rust lib.
+    pub fn app_clip(&self) -> bool {
+        self.app_clip
+    }
+
+    pub fn app_store_connect(&self) -> Option<&AppStoreConnect> {
+        self.app_store_connect.as_ref()
+    }
+
+    /// What's used to find devices and deploy to them.
+    pub fn deploy_backend(&self) -> Backend {
+        self.deploy_backend.unwrap_or_else(Backend::detect)
+    }
+
     pub fn project_dir_exists(&self) -> bool {
         self.project_dir().is_dir()
     }
@@ -205,6 +367,13 @@ impl Config {
         self.project_dir().join("build")
     }
 
+    /// The archive produced by `xcodebuild archive`, which gets the
+    /// `.xcarchive` extension tacked on to the path we pass it.
+    pub fn archive_path(&self) -> PathBuf {
+        self.archive_dir()
+            .join(&format!("{}.xcarchive", self.scheme()))
+    }
+
     pub fn export_dir(&self) -> PathBuf {
         self.project_dir().join("build")
     }
@@ -238,4 +407,22 @@ impl Config {
     pub fn scheme(&self) -> String {
         format!("{}_iOS", self.app.name())
     }
+
+    /// The `PRODUCT_BUNDLE_IDENTIFIER` set in the generated project.
+    pub fn bundle_id(&self) -> String {
+        format!("{}.{}", self.app.reverse_domain(), self.app.name())
+    }
+
+    /// App Clips' bundle IDs have to be prefixed by the app's.
+    pub fn app_clip_bundle_id(&self) -> String {
+        format!("{}.Clip", self.bundle_id())
+    }
+
+    /// Where the App Clip ends up in the archive, embedded in the app.
+    pub fn app_clip_archive_path(&self) -> PathBuf {
+        self.archive_path().join(format!(
+            "Products/Applications/{name}.app/AppClips/{name}Clip.app",
+            name = self.app.name()
+        ))
+    }
 }
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+
+static COLLECTED_DATA_TYPE_PREFIX: &str = "NSPrivacyCollectedDataType";
+static PURPOSE_PREFIX: &str = "NSPrivacyCollectedDataTypePurpose";
+static API_CATEGORY_PREFIX: &str = "NSPrivacyAccessedAPICategory";
+
+// Apple's identifiers are long and repetitive, so they can be given without
+// their prefix, i.e. `EmailAddress` for `NSPrivacyCollectedDataTypeEmailAddress`.
+fn identifier(prefix: &str, value: &str) -> String {
+    if value.starts_with(prefix) {
+        value.to_owned()
+    } else {
+        format!("{}{}", prefix, value)
+    }
+}
+
+/// A kind of data the app collects, i.e.
+/// `{ type = "EmailAddress", linked = true, purposes = ["AppFunctionality"] }`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CollectedData {
+    #[serde(rename = "type")]
+    data_type: String,
+    #[serde(default)]
+    linked: bool,
+    #[serde(default)]
+    tracking: bool,
+    #[serde(default)]
+    purposes: Vec<String>,
+}
+
+/// A required-reason API the app (or the Rust lib) uses, i.e.
+/// `{ category = "FileTimestamp", reasons = ["C617.1"] }`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AccessedApi {
+    category: String,
+    reasons: Vec<String>,
+}
+
+/// What goes in `PrivacyInfo.xcprivacy`, i.e.
+/// `[package.metadata.cargo-apple.ios.privacy]`. The manifest's always
+/// generated, since App Store Connect rejects apps without one, so leaving
+/// this out declares that nothing's collected or tracked.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Privacy {
+    #[serde(default)]
+    tracking: bool,
+    #[serde(default)]
+    tracking_domains: Vec<String>,
+    #[serde(default)]
+    collected_data: Vec<CollectedData>,
+    #[serde(default)]
+    accessed_apis: Vec<AccessedApi>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CollectedDataEntry {
+    data_type: String,
+    linked: bool,
+    tracking: bool,
+    purposes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AccessedApiEntry {
+    category: String,
+    reasons: Vec<String>,
+}
+
+/// The manifest with identifiers spelled out in full, for the template.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PrivacyManifest {
+    tracking: bool,
+    tracking_domains: Vec<String>,
+    collected_data: Vec<CollectedDataEntry>,
+    accessed_apis: Vec<AccessedApiEntry>,
+}
+
+impl Privacy {
+    pub fn manifest(&self) -> PrivacyManifest {
+        if self.tracking && self.tracking_domains.is_empty() {
+            log::warn!("`privacy.tracking` is set, but `privacy.tracking-domains` is empty; App Store review expects the domains used for tracking to be listed");
+        }
+        PrivacyManifest {
+            tracking: self.tracking,
+            tracking_domains: self.tracking_domains.clone(),
+            collected_data: self
+                .collected_data
+                .iter()
+                .map(|data| CollectedDataEntry {
+                    data_type: identifier(COLLECTED_DATA_TYPE_PREFIX, &data.data_type),
+                    linked: data.linked,
+                    tracking: data.tracking,
+                    purposes: data
+                        .purposes
+                        .iter()
+                        .map(|purpose| identifier(PURPOSE_PREFIX, purpose))
+                        .collect(),
+                })
+                .collect(),
+            accessed_apis: self
+                .accessed_apis
+                .iter()
+                .map(|api| {
+                    if api.reasons.is_empty() {
+                        log::warn!(
+                            "no reasons given for accessing {:?}; App Store Connect will reject this",
+                            api.category
+                        );
+                    }
+                    AccessedApiEntry {
+                        category: identifier(API_CATEGORY_PREFIX, &api.category),
+                        reasons: api.reasons.clone(),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
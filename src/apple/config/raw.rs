@@ -1,5 +1,5 @@
 use crate::{
-    apple::teams,
+    apple::{device::Backend, teams},
     util::{cli::TextWrapper, prompt},
 };
 use colored::{Color, Colorize as _};
@@ -42,15 +42,30 @@ impl Display for PromptError {
     }
 }
 
+/// App Store Connect API credentials, each a secret reference like
+/// `env:VAR`, so that none of them end up in the config.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AppStoreConnectRaw {
+    pub key_id: String,
+    pub issuer_id: String,
+    pub private_key: String,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Raw {
     pub development_team: String,
+    pub name: Option<String>,
     pub project_dir: Option<String>,
     pub ios_no_default_features: Option<bool>,
     pub ios_features: Option<Vec<String>>,
     pub macos_no_default_features: Option<bool>,
     pub macos_features: Option<Vec<String>>,
+    pub editor: Option<String>,
+    pub app_clip: Option<bool>,
+    pub deploy_backend: Option<Backend>,
+    pub app_store_connect: Option<AppStoreConnectRaw>,
 }
 
 impl Raw {
@@ -62,11 +77,16 @@ impl Raw {
                 .get(0)
                 .map(|development_team| development_team.id.clone())
                 .ok_or_else(|| DetectError::DeveloperTeamsEmpty)?,
+            name: None,
             project_dir: None,
             ios_no_default_features: None,
             ios_features: None,
             macos_no_default_features: None,
             macos_features: None,
+            editor: None,
+            app_clip: None,
+            deploy_backend: None,
+            app_store_connect: None,
         })
     }
 
@@ -137,11 +157,16 @@ impl Raw {
         };
         Ok(Self {
             development_team,
+            name: None,
             project_dir: None,
             ios_no_default_features: None,
             ios_features: None,
             macos_no_default_features: None,
             macos_features: None,
+            editor: None,
+            app_clip: None,
+            deploy_backend: None,
+            app_store_connect: None,
         })
     }
 }
@@ -45,7 +45,7 @@ pub fn install(
         let found = util::command_present(package)
             .map_err(|source| Error::PresenceCheckFailed { package, source })?;
         if !found || reinstall_deps.yes() {
-            println!("Installing `{}`...", package);
+            util::cli::status(format!("Installing `{}`...", package));
             // reinstall works even if it's not installed yet, and will upgrade
             // if it's already installed!
             bossy::Command::impure_parse("brew reinstall")
@@ -237,7 +237,7 @@ pub fn install(
     )?;
     log::info!("`rust-xcode-plugin` installation status: {:?}", status);
     if status.stale() {
-        println!("Installing `rust-xcode-plugin`...");
+        util::cli::status("Installing `rust-xcode-plugin`...");
         repo.update("https://github.com/BrainiumLLC/rust-xcode-plugin.git")
             .map_err(Error::UpdateFailed)?;
         if check_uuid(wrapper, xcode_version, repo.path(), &xcode_app_dir)? {
@@ -1,18 +1,135 @@
 use super::{
     config::Config,
-    ios_deploy,
+    devicectl, ios_deploy, libimobiledevice, lldb,
     target::{ArchiveError, BuildError, ExportError, Target},
+    test_runner,
 };
 use crate::{
     env::{Env, ExplicitEnv as _},
+    event::{Event, Listener},
     opts,
-    util::cli::{Report, Reportable},
+    util::{
+        self,
+        cli::{Report, Reportable},
+        progress::Step,
+    },
 };
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeSet,
     fmt::{self, Display},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
 };
 
+/// What's used to find devices and deploy to them, i.e.
+/// `deploy-backend = "devicectl"` in the `apple` section of `mobile.toml`.
+/// Debugging, tests, and benchmarks always use `ios-deploy`, since they need
+/// its lldb integration.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    IosDeploy,
+    Devicectl,
+    Libimobiledevice,
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IosDeploy => write!(f, "ios-deploy"),
+            Self::Devicectl => write!(f, "devicectl"),
+            Self::Libimobiledevice => write!(f, "libimobiledevice"),
+        }
+    }
+}
+
+impl Backend {
+    /// Picks whichever backend is installed, preferring `ios-deploy` since
+    /// it's what `cargo mobile init` installs and works with every iOS
+    /// version. `devicectl` can't see devices older than iOS 17, so it has to
+    /// be opted into when `ios-deploy` is around.
+    pub fn detect() -> Self {
+        let backend = if util::command_present("ios-deploy").unwrap_or(false) {
+            Self::IosDeploy
+        } else if devicectl::present() {
+            Self::Devicectl
+        } else if libimobiledevice::present() {
+            Self::Libimobiledevice
+        } else {
+            // This gives the most familiar error when it's not found.
+            Self::IosDeploy
+        };
+        log::info!("using {} to deploy to iOS devices", backend);
+        backend
+    }
+}
+
+#[derive(Debug)]
+pub enum DeviceListError {
+    IosDeployFailed(ios_deploy::DeviceListError),
+    DevicectlFailed(devicectl::Error),
+    LibimobiledeviceFailed(libimobiledevice::Error),
+}
+
+impl Reportable for DeviceListError {
+    fn report(&self) -> Report {
+        match self {
+            Self::IosDeployFailed(err) => err.report(),
+            Self::DevicectlFailed(err) => err.report(),
+            Self::LibimobiledeviceFailed(err) => err.report(),
+        }
+    }
+}
+
+/// Lists connected devices using `backend`.
+pub fn device_list<'a>(
+    env: &Env,
+    backend: Backend,
+) -> Result<BTreeSet<Device<'a>>, DeviceListError> {
+    match backend {
+        Backend::IosDeploy => {
+            ios_deploy::device_list(env).map_err(DeviceListError::IosDeployFailed)
+        }
+        Backend::Devicectl => devicectl::device_list(env).map_err(DeviceListError::DevicectlFailed),
+        Backend::Libimobiledevice => {
+            libimobiledevice::device_list(env).map_err(DeviceListError::LibimobiledeviceFailed)
+        }
+    }
+}
+
+/// How often to check whether a device that wasn't ready to deploy to is
+/// ready now.
+const READY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Something that has to be sorted out on the device itself before we can
+/// deploy to it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Blocker {
+    Locked,
+    Untrusted,
+    DeveloperModeDisabled,
+}
+
+impl Blocker {
+    fn problem(self) -> &'static str {
+        match self {
+            Self::Locked => "is locked",
+            Self::Untrusted => "doesn't trust this computer",
+            Self::DeveloperModeDisabled => "has Developer Mode turned off",
+        }
+    }
+
+    fn remediation(self) -> &'static str {
+        match self {
+            Self::Locked => "Unlock it, and keep it unlocked until the app launches.",
+            Self::Untrusted => "Unlock it and tap \"Trust\" when asked whether to trust this computer; if you're never asked, unplug it and plug it back in. If you tapped \"Don't Trust\" before, you'll need to reset that in Settings > General > Transfer or Reset > Reset > Reset Location & Privacy.",
+            Self::DeveloperModeDisabled => "Turn it on in Settings > Privacy & Security > Developer Mode, then confirm once the device restarts. If the setting isn't there, opening the project in Xcode with the device connected makes it show up.",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum RunError {
     BuildFailed(BuildError),
@@ -21,6 +138,10 @@ pub enum RunError {
     IpaMissing { old: PathBuf, new: PathBuf },
     UnzipFailed(bossy::Error),
     DeployFailed(ios_deploy::RunAndDebugError),
+    DevicectlFailed(devicectl::Error),
+    LibimobiledeviceFailed(libimobiledevice::Error),
+    LldbScriptFailed(lldb::Error),
+    DeviceNotReady { device: String, blocker: Blocker },
 }
 
 impl Reportable for RunError {
@@ -35,38 +156,349 @@ impl Reportable for RunError {
             ),
             Self::UnzipFailed(err) => Report::error("Failed to unzip archive", err),
             Self::DeployFailed(err) => err.report(),
+            Self::DevicectlFailed(err) => err.report(),
+            Self::LibimobiledeviceFailed(err) => err.report(),
+            Self::LldbScriptFailed(err) => Report::error("Failed to prepare lldb", err),
+            Self::DeviceNotReady { device, blocker } => Report::action_request(
+                format!("Can't deploy to {}, since it {}", device, blocker.problem()),
+                blocker.remediation(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TestError {
+    BundleFailed(test_runner::Error),
+    TestsFailed { name: String, cause: bossy::Error },
+    BenchesFailed { name: String, cause: bossy::Error },
+    DownloadFailed(bossy::Error),
+}
+
+impl Reportable for TestError {
+    fn report(&self) -> Report {
+        match self {
+            Self::BundleFailed(err) => err.report(),
+            Self::TestsFailed { name, cause } => {
+                Report::error(format!("Tests in {:?} failed", name), cause)
+            }
+            Self::BenchesFailed { name, cause } => {
+                Report::error(format!("Benchmarks in {:?} failed", name), cause)
+            }
+            Self::DownloadFailed(err) => {
+                Report::error("Failed to download coverage profiles from device", err)
+            }
         }
     }
 }
 
+#[derive(Debug)]
+pub enum InfoError {
+    IdeviceinfoPresentFailed(bossy::Error),
+    IdeviceinfoFailed(bossy::Error),
+    InvalidUtf8(std::str::Utf8Error),
+}
+
+impl Reportable for InfoError {
+    fn report(&self) -> Report {
+        match self {
+            Self::IdeviceinfoPresentFailed(err) => {
+                Report::error("Failed to check for presence of `ideviceinfo`", err)
+            }
+            Self::IdeviceinfoFailed(err) => Report::error("Failed to run `ideviceinfo`", err),
+            Self::InvalidUtf8(err) => Report::error(
+                "Failed to run `ideviceinfo`",
+                format!("Output contained invalid UTF-8: {}", err),
+            ),
+        }
+    }
+}
+
+fn format_bytes(bytes: &str) -> Option<String> {
+    let bytes = bytes.parse::<f64>().ok()?;
+    Some(format!("{:.1} GB", bytes / 1_000_000_000.0))
+}
+
 #[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Device<'a> {
     id: String,
     name: String,
     model: String,
+    os_version: Option<String>,
     target: &'a Target<'a>,
+    backend: Backend,
 }
 
 impl<'a> Display for Device<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} ({})", self.name, self.model)
+        write!(f, "{} ({}) - ", self.name, self.model)?;
+        if let Some(os_version) = &self.os_version {
+            write!(f, "iOS {}, ", os_version)?;
+        }
+        write!(f, "{}", self.target.arch)
     }
 }
 
 impl<'a> Device<'a> {
-    pub(super) fn new(id: String, name: String, model: String, target: &'a Target<'a>) -> Self {
+    pub(super) fn new(
+        id: String,
+        name: String,
+        model: String,
+        os_version: Option<String>,
+        target: &'a Target<'a>,
+        backend: Backend,
+    ) -> Self {
         Self {
             id,
             name,
             model,
+            os_version,
             target,
+            backend,
         }
     }
 
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn os_version(&self) -> Option<&str> {
+        self.os_version.as_deref()
+    }
+
+    fn ideviceinfo(&self, env: &Env, domain: Option<&str>, key: &str) -> Result<String, InfoError> {
+        let output = bossy::Command::pure("ideviceinfo")
+            .with_env_vars(env.explicit_env())
+            .with_args(&["-u", &self.id])
+            .with_args(domain.map(|domain| vec!["-q", domain]).unwrap_or_default())
+            .with_args(&["-k", key])
+            .run_and_wait_for_output()
+            .map_err(InfoError::IdeviceinfoFailed)?;
+        output
+            .stdout_str()
+            .map(|value| value.trim().to_owned())
+            .map_err(InfoError::InvalidUtf8)
+    }
+
+    /// Checks for anything on the device that'd make deploying fail, which
+    /// otherwise only shows up as an inscrutable exit code. This needs
+    /// `libimobiledevice`; without it, we just assume the device is ready.
+    pub fn blocker(&self, env: &Env) -> Option<Blocker> {
+        if !util::command_present("idevicepair").unwrap_or(false) {
+            return None;
+        }
+        let paired = bossy::Command::pure("idevicepair")
+            .with_env_vars(env.explicit_env())
+            .with_args(&["-u", &self.id, "validate"])
+            .run_and_wait_for_output()
+            .is_ok();
+        if !paired {
+            return Some(Blocker::Untrusted);
+        }
+        // This is only true while the passcode is needed to unlock it.
+        if self
+            .ideviceinfo(env, None, "PasswordProtected")
+            .ok()
+            .as_deref()
+            == Some("true")
+        {
+            return Some(Blocker::Locked);
+        }
+        // Devices older than iOS 16 don't have Developer Mode at all, and
+        // don't report it.
+        if self
+            .ideviceinfo(
+                env,
+                Some("com.apple.security.mac.amfi"),
+                "DeveloperModeStatus",
+            )
+            .ok()
+            .as_deref()
+            == Some("false")
+        {
+            return Some(Blocker::DeveloperModeDisabled);
+        }
+        None
+    }
+
+    fn not_ready(&self, blocker: Blocker) -> RunError {
+        RunError::DeviceNotReady {
+            device: self.name.clone(),
+            blocker,
+        }
+    }
+
+    /// Makes sure the device is ready to deploy to. When interactive, this
+    /// explains what to do and waits until it's done; otherwise, it fails
+    /// right away.
+    fn wait_until_ready(
+        &self,
+        env: &Env,
+        non_interactive: opts::NonInteractive,
+    ) -> Result<(), RunError> {
+        let mut blocker = match self.blocker(env) {
+            Some(blocker) => blocker,
+            None => return Ok(()),
+        };
+        if non_interactive.yes() {
+            return Err(self.not_ready(blocker));
+        }
+        println!(
+            "{} {}. {}",
+            self.name,
+            blocker.problem(),
+            blocker.remediation()
+        );
+        println!("Waiting for that to be sorted out; press Ctrl+C to give up.");
+        loop {
+            thread::sleep(READY_POLL_INTERVAL);
+            match self.blocker(env) {
+                None => return Ok(()),
+                Some(next) if next != blocker => {
+                    blocker = next;
+                    println!(
+                        "{} {}. {}",
+                        self.name,
+                        blocker.problem(),
+                        blocker.remediation()
+                    );
+                }
+                Some(_) => (),
+            }
+        }
+    }
+
+    /// If deploying failed because of something on the device, that's much
+    /// more useful to report than whatever the backend exited with.
+    fn diagnose(&self, env: &Env, err: RunError) -> RunError {
+        match self.blocker(env) {
+            Some(blocker) => self.not_ready(blocker),
+            None => err,
+        }
+    }
+
+    /// Details that are handy to have when debugging issues that only happen
+    /// on a specific device. `ios-deploy` only tells us so much, so the rest
+    /// comes from `ideviceinfo` (from `libimobiledevice`) when it's installed.
+    pub fn info(&self, env: &Env) -> Result<Vec<(&'static str, String)>, InfoError> {
+        let unknown = || "unknown".to_owned();
+        let mut info = vec![
+            ("Model", format!("{} ({})", self.name, self.model)),
+            (
+                "iOS version",
+                self.os_version.clone().unwrap_or_else(unknown),
+            ),
+            ("Arch", self.target.arch.to_owned()),
+        ];
+        if !crate::util::command_present("ideviceinfo")
+            .map_err(InfoError::IdeviceinfoPresentFailed)?
+        {
+            println!("Install `libimobiledevice` (i.e. `brew install libimobiledevice`) to see storage, battery, and developer mode info.");
+            return Ok(info);
+        }
+        // Not every device reports every key, so missing values aren't fatal.
+        let get = |domain, key| {
+            self.ideviceinfo(env, domain, key)
+                .ok()
+                .filter(|value| !value.is_empty())
+        };
+        info.push((
+            "OS build",
+            get(None, "BuildVersion").unwrap_or_else(unknown),
+        ));
+        info.push((
+            "Free storage",
+            get(Some("com.apple.disk_usage"), "AmountDataAvailable")
+                .and_then(|bytes| format_bytes(&bytes))
+                .unwrap_or_else(unknown),
+        ));
+        info.push((
+            "Battery",
+            get(Some("com.apple.mobile.battery"), "BatteryCurrentCapacity")
+                .map(|level| {
+                    let charging = get(Some("com.apple.mobile.battery"), "BatteryIsCharging")
+                        .as_deref()
+                        == Some("true");
+                    format!("{}%{}", level, if charging { " (charging)" } else { "" })
+                })
+                .unwrap_or_else(unknown),
+        ));
+        info.push((
+            "Developer mode",
+            match get(Some("com.apple.security.mac.amfi"), "DeveloperModeStatus").as_deref() {
+                Some("true") => "enabled",
+                Some("false") => "disabled",
+                _ => "unknown",
+            }
+            .to_owned(),
+        ));
+        Ok(info)
+    }
+
     pub fn target(&self) -> &'a Target<'a> {
         self.target
     }
 
+    /// Builds and extracts the IPA for this device's target, without
+    /// deploying it.
+    pub fn build(
+        &self,
+        config: &Config,
+        env: &Env,
+        noise_level: opts::NoiseLevel,
+        profile: opts::Profile,
+        listener: &Listener,
+    ) -> Result<(), RunError> {
+        // TODO: These steps are run unconditionally, which is slooooooow
+        listener.emit(Event::BuildStarted);
+        listener.emit(Event::CompilingTarget {
+            triple: self.target.triple,
+        });
+        Step::run("Building app", || {
+            self.target
+                .build(config, env, noise_level, profile)
+                .map_err(RunError::BuildFailed)
+        })?;
+        listener.emit(Event::Packaging);
+        Step::run("Archiving app", || {
+            self.target
+                .archive(config, env, noise_level, profile)
+                .map_err(RunError::ArchiveFailed)
+        })?;
+        Step::run("Exporting app", || {
+            self.target
+                .export(config, env, noise_level)
+                .map_err(RunError::ExportFailed)
+        })?;
+        let ipa_path = config
+            .ipa_path()
+            .map_err(|(old, new)| RunError::IpaMissing { old, new })?;
+        Step::run("Extracting IPA", || {
+            bossy::Command::pure("unzip")
+                .with_env_vars(env.explicit_env())
+                .with_args(if noise_level.pedantic() {
+                    None
+                } else {
+                    Some("-q")
+                })
+                .with_arg("-o") // -o = always overwrite
+                .with_arg(&ipa_path)
+                .with_arg("-d")
+                .with_arg(&config.export_dir())
+                .run_and_wait()
+                .map_err(RunError::UnzipFailed)
+        })?;
+        Ok(())
+    }
+
     pub fn run(
         &self,
         config: &Config,
@@ -74,40 +506,144 @@ impl<'a> Device<'a> {
         noise_level: opts::NoiseLevel,
         non_interactive: opts::NonInteractive,
         profile: opts::Profile,
+        listener: &Listener,
     ) -> Result<(), RunError> {
-        // TODO: These steps are run unconditionally, which is slooooooow
-        println!("Building app...");
-        self.target
-            .build(config, env, noise_level, profile)
-            .map_err(RunError::BuildFailed)?;
-        println!("Archiving app...");
-        self.target
-            .archive(config, env, noise_level, profile)
-            .map_err(RunError::ArchiveFailed)?;
-        println!("Exporting app...");
-        self.target
-            .export(config, env, noise_level)
-            .map_err(RunError::ExportFailed)?;
-        println!("Extracting IPA...");
-        bossy::Command::pure("unzip")
-            .with_env_vars(env.explicit_env())
-            .with_args(if noise_level.pedantic() {
-                None
-            } else {
-                Some("-q")
-            })
-            .with_arg("-o") // -o = always overwrite
-            .with_arg(
-                &config
-                    .ipa_path()
-                    .map_err(|(old, new)| RunError::IpaMissing { old, new })?,
-            )
-            .with_arg("-d")
-            .with_arg(&config.export_dir())
-            .run_and_wait()
-            .map_err(RunError::UnzipFailed)?;
-        ios_deploy::run_and_debug(config, env, non_interactive, &self.id)
-            .map_err(RunError::DeployFailed)?;
+        self.build(config, env, noise_level, profile, listener)?;
+        self.wait_until_ready(env, non_interactive)?;
+        listener.emit(Event::Deploying { device: &self.name });
+        match self.backend {
+            Backend::IosDeploy => ios_deploy::run_and_debug(config, env, non_interactive, &self.id)
+                .map_err(RunError::DeployFailed),
+            Backend::Devicectl => {
+                devicectl::run(config, env, &self.id).map_err(RunError::DevicectlFailed)
+            }
+            Backend::Libimobiledevice => libimobiledevice::run(config, env, &self.id)
+                .map_err(RunError::LibimobiledeviceFailed),
+        }
+        .map_err(|err| self.diagnose(env, err))
+    }
+
+    /// Like `run`, but the debugger knows where to find the Rust sources and
+    /// our static lib's symbols. With `wait_for_launch`, the app isn't
+    /// launched for you; lldb attaches once you launch it on the device.
+    pub fn debug(
+        &self,
+        config: &Config,
+        env: &Env,
+        noise_level: opts::NoiseLevel,
+        profile: opts::Profile,
+        wait_for_launch: bool,
+    ) -> Result<(), RunError> {
+        self.build(config, env, noise_level, profile, &Default::default())?;
+        let script = lldb::write_script(config, self.target, profile, wait_for_launch)
+            .map_err(RunError::LldbScriptFailed)?;
+        if wait_for_launch {
+            util::cli::status(format!(
+                "Waiting for {} to be launched...",
+                config.app().name()
+            ));
+        }
+        ios_deploy::debug_with_script(config, env, &self.id, &script, wait_for_launch)
+            .map_err(|err| self.diagnose(env, RunError::DeployFailed(err)))
+    }
+
+    /// Bundles each test harness into a runner app and runs it, stopping at
+    /// the first one that fails. With `coverage_dir`, each harness's raw
+    /// coverage profiles are downloaded into it afterwards.
+    pub fn run_tests(
+        &self,
+        config: &Config,
+        env: &Env,
+        executables: &[PathBuf],
+        args: &[String],
+        coverage_dir: Option<&Path>,
+    ) -> Result<(), TestError> {
+        // The app can only write inside its container, and `%t` expands to
+        // the container's temp dir.
+        let app_env = if coverage_dir.is_some() {
+            vec![(
+                "LLVM_PROFILE_FILE",
+                format!("%t/{}", util::coverage::PROFILE_FILE),
+            )]
+        } else {
+            Vec::new()
+        };
+        for executable in executables {
+            let bundle =
+                test_runner::bundle(config, executable).map_err(TestError::BundleFailed)?;
+            let name = executable
+                .file_name()
+                .expect("test harness path had no file name")
+                .to_string_lossy()
+                .into_owned();
+            util::cli::status(format!("Running {} on {}", name, self.name));
+            let result = ios_deploy::run_test_bundle(env, &self.id, &bundle, args, &app_env);
+            if let Some(coverage_dir) = coverage_dir {
+                let bundle_id = test_runner::bundle_id(&bundle).map_err(TestError::BundleFailed)?;
+                ios_deploy::download(env, &self.id, &bundle_id, "/tmp", &coverage_dir.join(&name))
+                    .map_err(TestError::DownloadFailed)?;
+            }
+            result.map_err(|cause| TestError::TestsFailed { name, cause })?;
+        }
+        Ok(())
+    }
+
+    /// Bundles each benchmark harness into a runner app and runs it, waiting
+    /// `warm_up` beforehand so the device can settle. The results are only
+    /// printed; unlike on Android, there's nowhere outside the app's container
+    /// for criterion to write them to.
+    pub fn run_benches(
+        &self,
+        config: &Config,
+        env: &Env,
+        executables: &[PathBuf],
+        args: &[String],
+        warm_up: Option<Duration>,
+    ) -> Result<(), TestError> {
+        let args = std::iter::once("--bench".to_owned())
+            .chain(args.iter().cloned())
+            .collect::<Vec<_>>();
+        for executable in executables {
+            let bundle =
+                test_runner::bundle(config, executable).map_err(TestError::BundleFailed)?;
+            let name = executable
+                .file_name()
+                .expect("benchmark harness path had no file name")
+                .to_string_lossy()
+                .into_owned();
+            if let Some(warm_up) = warm_up {
+                util::cli::status(format!(
+                    "Letting {} settle for {} seconds",
+                    self.name,
+                    warm_up.as_secs()
+                ));
+                thread::sleep(warm_up);
+            }
+            util::cli::status(format!("Running {} on {}", name, self.name));
+            ios_deploy::run_test_bundle(env, &self.id, &bundle, &args, &[])
+                .map_err(|cause| TestError::BenchesFailed { name, cause })?;
+        }
+        Ok(())
+    }
+
+    /// Installs and launches an already built IPA, without attaching a
+    /// debugger.
+    pub fn deploy(&self, config: &Config, env: &Env, listener: &Listener) -> Result<(), RunError> {
+        listener.emit(Event::Deploying { device: &self.name });
+        Step::run(format!("Deploying to {}", self.name), || {
+            match self.backend {
+                Backend::IosDeploy => ios_deploy::install_and_launch(config, env, &self.id)
+                    .map_err(RunError::DeployFailed),
+                Backend::Devicectl => devicectl::install_and_launch(config, env, &self.id)
+                    .map_err(RunError::DevicectlFailed),
+                Backend::Libimobiledevice => {
+                    libimobiledevice::install_and_launch(config, env, &self.id)
+                        .map_err(RunError::LibimobiledeviceFailed)
+                }
+            }
+            .map_err(|err| self.diagnose(env, err))
+        })?;
+        listener.emit(Event::Launched);
         Ok(())
     }
 }
@@ -0,0 +1,213 @@
+//! Deploys with `xcrun devicectl`, which ships with Xcode 15 and talks to
+//! devices over CoreDevice. That's the only way to reach devices running iOS
+//! 17 and later reliably, but it can't see devices running anything older.
+
+use crate::{
+    apple::{
+        config::Config,
+        device::{Backend, Device},
+        target::Target,
+    },
+    env::{Env, ExplicitEnv as _},
+    util::{
+        self,
+        cli::{Report, Reportable},
+    },
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::PathBuf,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    ListFailed(bossy::Error),
+    ListReadFailed { path: PathBuf, cause: io::Error },
+    ListParseFailed(serde_json::Error),
+    ArchInvalid(String),
+    InstallFailed(bossy::Error),
+    LaunchFailed(bossy::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ListFailed(err) => Report::error(
+                "Failed to detect connected iOS devices",
+                format!("Failed to request device list from `devicectl`: {}", err),
+            ),
+            Self::ListReadFailed { path, cause } => Report::error(
+                "Failed to detect connected iOS devices",
+                format!("Failed to read `devicectl` output at {:?}: {}", path, cause),
+            ),
+            Self::ListParseFailed(err) => Report::error(
+                "Failed to detect connected iOS devices",
+                format!("Failed to parse `devicectl` output: {}", err),
+            ),
+            Self::ArchInvalid(arch) => Report::error(
+                "Failed to detect connected iOS devices",
+                format!("{:?} isn't a valid target arch.", arch),
+            ),
+            Self::InstallFailed(err) => Report::error("Failed to install app on device", err),
+            Self::LaunchFailed(err) => Report::error("Failed to launch app on device", err),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ListOutput {
+    result: ListResult,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListResult {
+    devices: Vec<DeviceInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceInfo {
+    connection_properties: ConnectionProperties,
+    device_properties: DeviceProperties,
+    hardware_properties: HardwareProperties,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConnectionProperties {
+    tunnel_state: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceProperties {
+    name: String,
+    #[serde(default)]
+    os_version_number: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HardwareProperties {
+    udid: String,
+    platform: String,
+    product_type: String,
+    #[serde(default)]
+    marketing_name: Option<String>,
+    cpu_type: CpuType,
+}
+
+#[derive(Debug, Deserialize)]
+struct CpuType {
+    name: String,
+}
+
+fn xcrun() -> bossy::Command {
+    bossy::Command::impure("xcrun").with_arg("devicectl")
+}
+
+/// Whether `devicectl` is available, which is only the case with Xcode 15 and
+/// later.
+pub fn present() -> bool {
+    bossy::Command::impure_parse("xcrun --find devicectl")
+        .run_and_wait_for_output()
+        .is_ok()
+}
+
+pub fn device_list<'a>(env: &Env) -> Result<BTreeSet<Device<'a>>, Error> {
+    // `devicectl` only writes JSON to a file; stdout is for humans.
+    let path = util::temp_dir().join("devicectl-devices.json");
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|cause| Error::ListReadFailed {
+            path: parent.to_owned(),
+            cause,
+        })?;
+    }
+    xcrun()
+        .with_env_vars(env.explicit_env())
+        .with_args(&["list", "devices", "--quiet", "--json-output"])
+        .with_arg(&path)
+        .run_and_wait_for_output()
+        .map_err(Error::ListFailed)?;
+    let contents = fs::read(&path).map_err(|cause| Error::ListReadFailed {
+        path: path.clone(),
+        cause,
+    })?;
+    let output: ListOutput = serde_json::from_slice(&contents).map_err(Error::ListParseFailed)?;
+    output
+        .result
+        .devices
+        .into_iter()
+        // Every device that was ever paired is listed, connected or not.
+        .filter(|info| {
+            info.hardware_properties.platform == "iOS"
+                && info.connection_properties.tunnel_state != "unavailable"
+        })
+        .map(|info| {
+            let hardware = info.hardware_properties;
+            Target::for_arch(&hardware.cpu_type.name)
+                .map(|target| {
+                    Device::new(
+                        hardware.udid,
+                        info.device_properties.name,
+                        hardware.marketing_name.unwrap_or(hardware.product_type),
+                        info.device_properties.os_version_number,
+                        target,
+                        Backend::Devicectl,
+                    )
+                })
+                .ok_or_else(|| Error::ArchInvalid(hardware.cpu_type.name))
+        })
+        .collect()
+}
+
+fn install(config: &Config, env: &Env, id: &str) -> Result<(), Error> {
+    util::quiet_stdout(xcrun())
+        .with_env_vars(env.explicit_env())
+        .with_args(&["device", "install", "app", "--device", id])
+        .with_arg(&config.app_path())
+        .run_and_wait()
+        .map_err(Error::InstallFailed)?;
+    Ok(())
+}
+
+fn launch_command(config: &Config, env: &Env, id: &str) -> bossy::Command {
+    let command = xcrun()
+        .with_env_vars(env.explicit_env())
+        .with_args(&["device", "process", "launch", "--device", id])
+        .with_arg("--terminate-existing");
+    let command = if env.dotenv().is_empty() {
+        command
+    } else {
+        let vars = env.dotenv().iter().cloned().collect::<BTreeMap<_, _>>();
+        command
+            .with_arg("--environment-variables")
+            .with_arg(serde_json::to_string(&vars).expect("developer error: unserializable env"))
+    };
+    // Unlike with `ios-deploy`, launch arguments are passed through as-is.
+    command
+        .with_arg(config.bundle_id())
+        .with_args(config.app().run_args().launch_args())
+}
+
+/// Installs and launches the app, streaming its output until it exits.
+pub fn run(config: &Config, env: &Env, id: &str) -> Result<(), Error> {
+    install(config, env, id)?;
+    util::cli::status("Launching app on device...");
+    launch_command(config, env, id)
+        .with_arg("--console")
+        .run_and_wait()
+        .map_err(Error::LaunchFailed)?;
+    Ok(())
+}
+
+/// Like `run`, but exits as soon as the app is launched.
+pub fn install_and_launch(config: &Config, env: &Env, id: &str) -> Result<(), Error> {
+    install(config, env, id)?;
+    util::quiet_stdout(launch_command(config, env, id))
+        .run_and_wait()
+        .map_err(Error::LaunchFailed)?;
+    Ok(())
+}
@@ -0,0 +1,231 @@
+//! Packages the Rust lib as a signed XCFramework with a Clang module, for
+//! Xcode projects that aren't generated by us. It's wrapped in a Swift package,
+//! so adding it is just a matter of adding a local package dependency.
+
+use super::{config::Config, target::Target};
+use crate::{
+    config::app::cbindgen,
+    opts::Profile,
+    util::cli::{Report, Reportable},
+};
+use heck::CamelCase as _;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// What `codesign` signs with when no identity's given; this is matched
+/// against the certificates in the keychain, so any team's will do.
+pub static DEFAULT_SIGN_IDENTITY: &str = "Apple Distribution";
+
+#[derive(Debug)]
+pub enum Error {
+    StageFailed { path: PathBuf, cause: io::Error },
+    LibCopyFailed { path: PathBuf, cause: io::Error },
+    CbindgenFailed(cbindgen::Error),
+    CreateFailed(bossy::Error),
+    SignFailed(bossy::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::StageFailed { path, cause } => {
+                Report::error(format!("Failed to write framework file {:?}", path), cause)
+            }
+            Self::LibCopyFailed { path, cause } => Report::error(
+                format!("Failed to copy lib {:?} into framework", path),
+                cause,
+            ),
+            Self::CbindgenFailed(err) => err.report(),
+            Self::CreateFailed(err) => Report::error("Failed to create XCFramework", err),
+            Self::SignFailed(err) => Report::action_request(
+                "Make sure the signing identity's certificate is in your keychain, or pass a different one with `--sign-identity`.",
+                format!("Failed to sign XCFramework: {}", err),
+            ),
+        }
+    }
+}
+
+fn module_name(config: &Config) -> String {
+    config.app().name().to_camel_case()
+}
+
+/// Where the Swift package, and the XCFramework in it, end up.
+pub fn package_dir(config: &Config) -> PathBuf {
+    config
+        .app()
+        .target_dir()
+        .join("apple-framework")
+        .join(module_name(config))
+}
+
+fn info_plist(config: &Config) -> String {
+    let name = module_name(config);
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>CFBundleExecutable</key>
+	<string>{name}</string>
+	<key>CFBundleIdentifier</key>
+	<string>{bundle_id}</string>
+	<key>CFBundleName</key>
+	<string>{name}</string>
+	<key>CFBundlePackageType</key>
+	<string>FMWK</string>
+	<key>CFBundleShortVersionString</key>
+	<string>{version}</string>
+	<key>CFBundleVersion</key>
+	<string>{version}</string>
+	<key>MinimumOSVersion</key>
+	<string>9.0</string>
+</dict>
+</plist>
+"#,
+        name = name,
+        bundle_id = config.bundle_id(),
+        version = config.app().version(),
+    )
+}
+
+fn module_map(name: &str) -> String {
+    format!(
+        "framework module {name} {{\n    umbrella header \"{name}.h\"\n    export *\n    module * {{ export * }}\n}}\n",
+        name = name,
+    )
+}
+
+// Without `cbindgen`, there's nothing to declare, but the module still needs
+// an umbrella header to be importable.
+fn stub_header(config: &Config) -> String {
+    format!(
+        "// Generated by `cargo apple build --framework`. Configure `cbindgen` to\n// declare `{}`'s exported functions here.\n",
+        config.app().lib_name()
+    )
+}
+
+fn package_swift(config: &Config) -> String {
+    format!(
+        r#"// swift-tools-version:5.3
+// Generated by `cargo apple build --framework`; changes will be overwritten.
+
+import PackageDescription
+
+let package = Package(
+    name: "{name}",
+    platforms: [.iOS(.v9)],
+    products: [
+        .library(name: "{name}", targets: ["{name}"]),
+    ],
+    targets: [
+        .binaryTarget(name: "{name}", path: "{name}.xcframework"),
+    ]
+)
+"#,
+        name = module_name(config),
+    )
+}
+
+fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), Error> {
+    path.parent()
+        .map(fs::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|()| fs::write(path, contents))
+        .map_err(|cause| Error::StageFailed {
+            path: path.to_owned(),
+            cause,
+        })
+}
+
+fn remove_stale(path: &Path) -> Result<(), Error> {
+    if path.exists() {
+        fs::remove_dir_all(path).map_err(|cause| Error::StageFailed {
+            path: path.to_owned(),
+            cause,
+        })?;
+    }
+    Ok(())
+}
+
+/// Lays out a static framework around the lib built for `target`. The
+/// framework's binary is just the static lib under the module's name.
+fn stage_framework(
+    config: &Config,
+    profile: Profile,
+    target: Target<'_>,
+    header: &str,
+    stage: &Path,
+) -> Result<PathBuf, Error> {
+    let name = module_name(config);
+    let framework = stage
+        .join(target.triple)
+        .join(format!("{}.framework", name));
+    remove_stale(&framework)?;
+    let lib_path = target.lib_path(config, profile);
+    let binary = framework.join(&name);
+    fs::create_dir_all(&framework)
+        .and_then(|()| fs::copy(&lib_path, &binary))
+        .map_err(|cause| Error::LibCopyFailed {
+            path: lib_path.clone(),
+            cause,
+        })?;
+    write(
+        &framework.join("Headers").join(format!("{}.h", name)),
+        header,
+    )?;
+    write(
+        &framework.join("Modules/module.modulemap"),
+        module_map(&name),
+    )?;
+    write(&framework.join("Info.plist"), info_plist(config))?;
+    Ok(framework)
+}
+
+/// Assembles a signed XCFramework from the libs already built for `targets`,
+/// and writes a Swift package around it. Returns the package's path.
+pub fn build(
+    config: &Config,
+    profile: Profile,
+    targets: &[Target<'_>],
+    sign_identity: &str,
+) -> Result<PathBuf, Error> {
+    let name = module_name(config);
+    let package_dir = package_dir(config);
+    let stage = config.app().target_dir().join("apple-framework/stage");
+    let header = match config.app().cbindgen() {
+        Some(cbindgen) => {
+            let path = stage.join(cbindgen.header());
+            cbindgen.generate(&path).map_err(Error::CbindgenFailed)?;
+            fs::read_to_string(&path).map_err(|cause| Error::StageFailed { path, cause })?
+        }
+        None => stub_header(config),
+    };
+    let frameworks = targets
+        .iter()
+        .map(|target| stage_framework(config, profile, *target, &header, &stage))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let xcframework = package_dir.join(format!("{}.xcframework", name));
+    // `xcodebuild` refuses to overwrite an existing XCFramework.
+    remove_stale(&xcframework)?;
+    let mut command = bossy::Command::impure("xcodebuild").with_arg("-create-xcframework");
+    for framework in &frameworks {
+        command.add_arg("-framework").add_arg(framework);
+    }
+    command
+        .with_arg("-output")
+        .with_arg(&xcframework)
+        .run_and_wait()
+        .map_err(Error::CreateFailed)?;
+
+    bossy::Command::impure("codesign")
+        .with_args(&["--force", "--timestamp", "--sign", sign_identity])
+        .with_arg(&xcframework)
+        .run_and_wait()
+        .map_err(Error::SignFailed)?;
+
+    write(&package_dir.join("Package.swift"), package_swift(config))?;
+    Ok(package_dir)
+}
@@ -1,10 +1,22 @@
 use super::{DeviceInfo, Event};
 use crate::{
-    apple::{device::Device, target::Target},
+    apple::{
+        device::{Backend, Device},
+        target::Target,
+    },
     env::{Env, ExplicitEnv as _},
-    util::cli::{Report, Reportable},
+    util::{
+        cache,
+        cli::{Report, Reportable},
+        retry::retry,
+    },
 };
-use std::collections::BTreeSet;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, time::Duration};
+
+static CACHE_NAME: &str = "ios-devices";
+// Scanning is slow, but devices come and go, so this is kept short.
+static CACHE_MAX_AGE: Duration = Duration::from_secs(30);
 
 #[derive(Debug)]
 pub enum DeviceListError {
@@ -41,31 +53,107 @@ fn parse_device_list<'a>(output: &bossy::Output) -> Result<BTreeSet<Device<'a>>,
                  device_name,
                  model_arch,
                  model_name,
+                 product_version,
              }| {
                 Target::for_arch(&model_arch)
-                    .map(|target| Device::new(device_identifier, device_name, model_name, target))
+                    .map(|target| {
+                        Device::new(
+                            device_identifier,
+                            device_name,
+                            model_name,
+                            product_version,
+                            target,
+                            Backend::IosDeploy,
+                        )
+                    })
                     .ok_or_else(|| DeviceListError::ArchInvalid(model_arch))
             },
         )
         .collect::<Result<_, _>>()
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedDevice {
+    id: String,
+    name: String,
+    model: String,
+    os_version: Option<String>,
+    arch: String,
+}
+
+impl CachedDevice {
+    fn from_device(device: &Device<'_>) -> Self {
+        Self {
+            id: device.id().to_owned(),
+            name: device.name().to_owned(),
+            model: device.model().to_owned(),
+            os_version: device.os_version().map(ToOwned::to_owned),
+            arch: device.target().arch.to_owned(),
+        }
+    }
+
+    fn into_device<'a>(self) -> Option<Device<'a>> {
+        Target::for_arch(&self.arch).map(|target| {
+            Device::new(
+                self.id,
+                self.name,
+                self.model,
+                self.os_version,
+                target,
+                Backend::IosDeploy,
+            )
+        })
+    }
+}
+
+/// Forgets the last scan, i.e. after a deploy fails, since the device may
+/// well be gone.
+pub fn clear_cache() {
+    cache::clear(CACHE_NAME);
+}
+
+/// Lists connected devices, reusing the last scan if it was recent enough.
 pub fn device_list<'a>(env: &Env) -> Result<BTreeSet<Device<'a>>, DeviceListError> {
-    let result = bossy::Command::pure_parse("ios-deploy --detect --timeout 1 --json --no-wifi")
-        .with_env_vars(env.explicit_env())
-        .run_and_wait_for_output();
-    match result {
-        Ok(output) => parse_device_list(&output),
-        Err(err) => {
-            let output = err
-                .output()
-                .expect("developer error: `ios-deploy --detect` output wasn't collected");
-            if output.stdout().is_empty() && output.stderr().is_empty() {
-                log::info!("device detection returned a non-zero exit code, but stdout and stderr are both empty; interpreting as a successful run with no devices connected");
-                Ok(Default::default())
-            } else {
-                Err(DeviceListError::DetectionFailed(err))
+    if let Some(devices) = cache::load::<Vec<CachedDevice>>(CACHE_NAME, CACHE_MAX_AGE)
+        .and_then(|cached| cached.into_iter().map(CachedDevice::into_device).collect())
+    {
+        return Ok(devices);
+    }
+    let devices = scan(env)?;
+    // An empty list is never worth caching, since we'd just be delaying
+    // noticing a device that was plugged in.
+    if !devices.is_empty() {
+        cache::store(
+            CACHE_NAME,
+            &devices
+                .iter()
+                .map(CachedDevice::from_device)
+                .collect::<Vec<_>>(),
+        );
+    }
+    Ok(devices)
+}
+
+fn scan<'a>(env: &Env) -> Result<BTreeSet<Device<'a>>, DeviceListError> {
+    // The handshake with the device is what fails, when something does.
+    let transient = |err: &DeviceListError| matches!(err, DeviceListError::DetectionFailed(_));
+    retry("Detecting iOS devices", transient, || {
+        let result = bossy::Command::pure_parse("ios-deploy --detect --timeout 1 --json --no-wifi")
+            .with_env_vars(env.explicit_env())
+            .run_and_wait_for_output();
+        match result {
+            Ok(output) => parse_device_list(&output),
+            Err(err) => {
+                let output = err
+                    .output()
+                    .expect("developer error: `ios-deploy --detect` output wasn't collected");
+                if output.stdout().is_empty() && output.stderr().is_empty() {
+                    log::info!("device detection returned a non-zero exit code, but stdout and stderr are both empty; interpreting as a successful run with no devices connected");
+                    Ok(Default::default())
+                } else {
+                    Err(DeviceListError::DetectionFailed(err))
+                }
             }
         }
-    }
+    })
 }
@@ -16,6 +16,8 @@ struct DeviceInfo {
     model_arch: String,
     #[serde(rename = "modelName")]
     model_name: String,
+    #[serde(default, rename = "ProductVersion")]
+    product_version: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -2,8 +2,12 @@ use crate::{
     apple::config::Config,
     env::{Env, ExplicitEnv as _},
     opts,
-    util::cli::{Report, Reportable},
+    util::{
+        self,
+        cli::{Report, Reportable},
+    },
 };
+use std::path::Path;
 
 #[derive(Debug)]
 pub enum RunAndDebugError {
@@ -18,19 +22,53 @@ impl Reportable for RunAndDebugError {
     }
 }
 
+// `ios-deploy` sets these in the launched app's environment.
+fn envs_arg(env: &Env) -> Vec<String> {
+    if env.dotenv().is_empty() {
+        Vec::new()
+    } else {
+        let envs = env
+            .dotenv()
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join("&");
+        vec!["--envs".to_owned(), envs]
+    }
+}
+
+// `app.run-args`, as launch arguments. `ios-deploy` splits these on
+// whitespace, so arguments containing spaces won't survive intact.
+fn args_arg(config: &Config) -> Option<String> {
+    let args = config.app().run_args().launch_args();
+    if args.is_empty() {
+        None
+    } else {
+        Some(format!("--args={}", args.join(" ")))
+    }
+}
+
+// The device may well be gone, so the next scan shouldn't trust the cache.
+fn deploy_failed(err: bossy::Error) -> RunAndDebugError {
+    super::clear_cache();
+    RunAndDebugError::DeployFailed(err)
+}
+
 pub fn run_and_debug(
     config: &Config,
     env: &Env,
     non_interactive: opts::NonInteractive,
     id: &str,
 ) -> Result<(), RunAndDebugError> {
-    println!("Deploying app to device...");
+    util::cli::status("Deploying app to device...");
     bossy::Command::pure("ios-deploy")
         .with_env_vars(env.explicit_env())
         .with_arg("--debug")
         .with_args(&["--id", id])
         .with_arg("--bundle")
         .with_arg(&config.app_path())
+        .with_args(envs_arg(env))
+        .with_args(args_arg(config))
         .with_args(if non_interactive.yes() {
             Some("--noninteractive")
         } else {
@@ -39,5 +77,122 @@ pub fn run_and_debug(
         .with_arg("--no-wifi")
         .run_and_wait()
         .map(|_| ())
-        .map_err(RunAndDebugError::DeployFailed)
+        .map_err(deploy_failed)
+}
+
+/// Like `run_and_debug`, but runs `script` in lldb first. With `no_start`, the
+/// app is installed but not launched, so the script can attach to it later.
+pub fn debug_with_script(
+    config: &Config,
+    env: &Env,
+    id: &str,
+    script: &Path,
+    no_start: bool,
+) -> Result<(), RunAndDebugError> {
+    util::cli::status("Deploying app to device...");
+    bossy::Command::pure("ios-deploy")
+        .with_env_vars(env.explicit_env())
+        .with_arg("--debug")
+        .with_args(&["--id", id])
+        .with_arg("--bundle")
+        .with_arg(&config.app_path())
+        .with_args(envs_arg(env))
+        .with_args(args_arg(config))
+        .with_arg("--custom")
+        .with_arg(script)
+        .with_args(if no_start { Some("--nostart") } else { None })
+        .with_arg("--no-wifi")
+        .run_and_wait()
+        .map(|_| ())
+        .map_err(deploy_failed)
+}
+
+/// Installs and runs a bundled test harness, streaming its output. This exits
+/// with the harness's exit code, so failing tests fail the command.
+pub fn run_test_bundle(
+    env: &Env,
+    id: &str,
+    bundle: &Path,
+    args: &[String],
+    app_env: &[(&str, String)],
+) -> bossy::Result<()> {
+    bossy::Command::pure("ios-deploy")
+        .with_env_vars(env.explicit_env())
+        .with_args(&["--debug", "--noninteractive"])
+        .with_args(&["--id", id])
+        .with_arg("--bundle")
+        .with_arg(bundle)
+        .with_args(if app_env.is_empty() {
+            Vec::new()
+        } else {
+            let envs = app_env
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect::<Vec<_>>()
+                .join("&");
+            vec!["--envs".to_owned(), envs]
+        })
+        .with_args(if args.is_empty() {
+            None
+        } else {
+            Some(format!("--args={}", args.join(" ")))
+        })
+        .with_arg("--no-wifi")
+        .run_and_wait()
+        .map(|_| ())
+}
+
+/// Copies `remote` (relative to the app's container) into `to`.
+pub fn download(
+    env: &Env,
+    id: &str,
+    bundle_id: &str,
+    remote: &str,
+    to: &Path,
+) -> bossy::Result<()> {
+    util::quiet_stdout(bossy::Command::pure("ios-deploy"))
+        .with_env_vars(env.explicit_env())
+        .with_args(&["--id", id])
+        .with_args(&["--bundle_id", bundle_id])
+        .with_arg(format!("--download={}", remote))
+        .with_arg("--to")
+        .with_arg(to)
+        .with_arg("--no-wifi")
+        .run_and_wait()
+        .map(|_| ())
+}
+
+// Signing and provisioning problems, which retrying won't fix.
+static PERMANENT_FAILURES: &[&str] = &[
+    "ApplicationVerificationFailed",
+    "0xe8008015", // no valid provisioning profile
+    "0xe8008016", // entitlements don't match the provisioning profile
+    "0xe8008018", // signing identity no longer valid
+    "0xe800801c", // no code signature
+];
+
+/// Like `run_and_debug`, but exits as soon as the app is launched.
+pub fn install_and_launch(config: &Config, env: &Env, id: &str) -> Result<(), RunAndDebugError> {
+    // Nothing's left running, so this is safe to retry. The output's captured
+    // so we can tell which failures are worth retrying.
+    let transient = util::retry::unless_output_contains(PERMANENT_FAILURES);
+    util::retry::retry("Deploying app to device", transient, || {
+        bossy::Command::pure("ios-deploy")
+            .with_env_vars(env.explicit_env())
+            .with_arg("--justlaunch")
+            .with_args(&["--id", id])
+            .with_arg("--bundle")
+            .with_arg(&config.app_path())
+            .with_args(envs_arg(env))
+            .with_args(args_arg(config))
+            .with_arg("--no-wifi")
+            .run_and_wait_for_output()
+    })
+    .map(|output| {
+        log::info!(
+            "`ios-deploy` output:\n{}",
+            String::from_utf8_lossy(output.stdout())
+        )
+    })
+    .map_err(deploy_failed)
 }
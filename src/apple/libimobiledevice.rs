@@ -0,0 +1,147 @@
+//! Deploys with `libimobiledevice`'s tools, which don't need Xcode at all and
+//! keep working when `ios-deploy` can't find the debugger.
+
+use crate::{
+    apple::{
+        config::Config,
+        device::{Backend, Device},
+        target::Target,
+    },
+    env::{Env, ExplicitEnv as _},
+    util::{
+        self,
+        cli::{Report, Reportable},
+    },
+};
+use std::collections::BTreeSet;
+
+#[derive(Debug)]
+pub enum Error {
+    ListFailed(bossy::Error),
+    InfoFailed { id: String, cause: bossy::Error },
+    InvalidUtf8(std::str::Utf8Error),
+    ArchInvalid(String),
+    InstallFailed(bossy::Error),
+    LaunchFailed(bossy::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        let msg = "Failed to detect connected iOS devices";
+        match self {
+            Self::ListFailed(err) => Report::error(
+                msg,
+                format!("Failed to request device list from `idevice_id`: {}", err),
+            ),
+            Self::InfoFailed { id, cause } => Report::error(
+                msg,
+                format!("Failed to get info for device {:?}: {}", id, cause),
+            ),
+            Self::InvalidUtf8(err) => {
+                Report::error(msg, format!("Device info contained invalid UTF-8: {}", err))
+            }
+            Self::ArchInvalid(arch) => {
+                Report::error(msg, format!("{:?} isn't a valid target arch.", arch))
+            }
+            Self::InstallFailed(err) => Report::error("Failed to install app on device", err),
+            Self::LaunchFailed(err) => Report::error("Failed to launch app on device", err),
+        }
+    }
+}
+
+/// Whether the tools we need are installed.
+pub fn present() -> bool {
+    ["idevice_id", "ideviceinstaller", "idevicedebug"]
+        .iter()
+        .all(|name| util::command_present(name).unwrap_or(false))
+}
+
+fn ideviceinfo(env: &Env, id: &str, key: &str) -> Result<String, Error> {
+    bossy::Command::pure("ideviceinfo")
+        .with_env_vars(env.explicit_env())
+        .with_args(&["-u", id, "-k", key])
+        .run_and_wait_for_output()
+        .map_err(|cause| Error::InfoFailed {
+            id: id.to_owned(),
+            cause,
+        })?
+        .stdout_str()
+        .map(|value| value.trim().to_owned())
+        .map_err(Error::InvalidUtf8)
+}
+
+pub fn device_list<'a>(env: &Env) -> Result<BTreeSet<Device<'a>>, Error> {
+    let output = bossy::Command::pure("idevice_id")
+        .with_env_vars(env.explicit_env())
+        .with_arg("-l")
+        .run_and_wait_for_output()
+        .map_err(Error::ListFailed)?;
+    output
+        .stdout_str()
+        .map_err(Error::InvalidUtf8)?
+        .lines()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            let arch = ideviceinfo(env, id, "CPUArchitecture")?;
+            let target = Target::for_arch(&arch).ok_or_else(|| Error::ArchInvalid(arch))?;
+            let os_version = ideviceinfo(env, id, "ProductVersion")
+                .ok()
+                .filter(|version| !version.is_empty());
+            Ok(Device::new(
+                id.to_owned(),
+                ideviceinfo(env, id, "DeviceName")?,
+                ideviceinfo(env, id, "ProductType")?,
+                os_version,
+                target,
+                Backend::Libimobiledevice,
+            ))
+        })
+        .collect()
+}
+
+fn install(config: &Config, env: &Env, id: &str) -> Result<(), Error> {
+    util::quiet_stdout(bossy::Command::pure("ideviceinstaller"))
+        .with_env_vars(env.explicit_env())
+        .with_args(&["-u", id, "-i"])
+        .with_arg(&config.app_path())
+        .run_and_wait()
+        .map_err(Error::InstallFailed)?;
+    Ok(())
+}
+
+fn launch_command(env: &Env, id: &str) -> bossy::Command {
+    let mut command = bossy::Command::pure("idevicedebug")
+        .with_env_vars(env.explicit_env())
+        .with_args(&["-u", id]);
+    for (key, value) in env.dotenv() {
+        command.add_arg("-e").add_arg(format!("{}={}", key, value));
+    }
+    command
+}
+
+/// Installs and launches the app, streaming its output until it exits.
+pub fn run(config: &Config, env: &Env, id: &str) -> Result<(), Error> {
+    install(config, env, id)?;
+    util::cli::status("Launching app on device...");
+    launch_command(env, id)
+        .with_arg("run")
+        .with_arg(config.bundle_id())
+        .with_args(config.app().run_args().launch_args())
+        .run_and_wait()
+        .map_err(Error::LaunchFailed)?;
+    Ok(())
+}
+
+/// Like `run`, but exits as soon as the app is launched.
+pub fn install_and_launch(config: &Config, env: &Env, id: &str) -> Result<(), Error> {
+    install(config, env, id)?;
+    util::quiet_stdout(launch_command(env, id))
+        .with_arg("--detach")
+        .with_arg("run")
+        .with_arg(config.bundle_id())
+        .with_args(config.app().run_args().launch_args())
+        .run_and_wait()
+        .map_err(Error::LaunchFailed)?;
+    Ok(())
+}
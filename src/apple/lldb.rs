@@ -0,0 +1,121 @@
+use super::{config::Config, target::Target};
+use crate::{opts, util};
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    RustcFailed(bossy::Error),
+    OutputInvalid(std::str::Utf8Error),
+    WriteFailed { path: PathBuf, cause: io::Error },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RustcFailed(err) => write!(f, "Failed to query `rustc`: {}", err),
+            Self::OutputInvalid(err) => {
+                write!(f, "`rustc` output contained invalid UTF-8: {}", err)
+            }
+            Self::WriteFailed { path, cause } => {
+                write!(f, "Failed to write lldb script to {:?}: {}", path, cause)
+            }
+        }
+    }
+}
+
+fn rustc_output(args: &[&str]) -> Result<String, Error> {
+    bossy::Command::impure("rustc")
+        .with_args(args)
+        .run_and_wait_for_output()
+        .map_err(Error::RustcFailed)?
+        .stdout_str()
+        .map(|s| s.trim().to_owned())
+        .map_err(Error::OutputInvalid)
+}
+
+/// The standard library's debug info points into `/rustc/<commit-hash>`,
+/// which only exists on the machine that built the toolchain; the same
+/// sources ship with the `rust-src` component in the sysroot.
+fn std_source_map(sysroot: &Path) -> Result<Option<(String, PathBuf)>, Error> {
+    let commit_hash = rustc_output(&["-vV"])?.lines().find_map(|line| {
+        let mut parts = line.splitn(2, ": ");
+        match (parts.next(), parts.next()) {
+            (Some("commit-hash"), Some(hash)) if hash != "unknown" => Some(hash.to_owned()),
+            _ => None,
+        }
+    });
+    Ok(commit_hash.map(|hash| {
+        (
+            format!("/rustc/{}", hash),
+            sysroot.join("lib/rustlib/src/rust"),
+        )
+    }))
+}
+
+/// Writes an lldb script that loads Rust's pretty printers, maps the standard
+/// library's sources, and adds the symbols from our static lib. If
+/// `wait_for_launch` is set, it waits for the app to be launched by hand
+/// instead of launching it.
+pub fn write_script(
+    config: &Config,
+    target: &Target<'_>,
+    profile: opts::Profile,
+    wait_for_launch: bool,
+) -> Result<PathBuf, Error> {
+    let mut script = String::new();
+    let sysroot = PathBuf::from(rustc_output(&["--print", "sysroot"])?);
+    let formatters_dir = sysroot.join("lib/rustlib/etc");
+    if formatters_dir.join("lldb_lookup.py").is_file() {
+        writeln!(
+            script,
+            "command script import {:?}",
+            formatters_dir.join("lldb_lookup.py")
+        )
+        .unwrap();
+        writeln!(
+            script,
+            "command source -s 0 {:?}",
+            formatters_dir.join("lldb_commands")
+        )
+        .unwrap();
+    } else {
+        log::info!("no Rust lldb formatters found in {:?}", formatters_dir);
+    }
+    if let Some((from, to)) = std_source_map(&sysroot)? {
+        writeln!(
+            script,
+            "settings append target.source-map {:?} {:?}",
+            from, to
+        )
+        .unwrap();
+    }
+    let lib_path = target.lib_path(config, profile);
+    if lib_path.is_file() {
+        writeln!(script, "target symbols add {:?}", lib_path).unwrap();
+    } else {
+        log::warn!("static lib not found at {:?}", lib_path);
+    }
+    if wait_for_launch {
+        writeln!(
+            script,
+            "process attach --name {:?} --waitfor",
+            config.app().name()
+        )
+        .unwrap();
+    }
+    let path = util::temp_dir().join(format!("{}-lldb-script", config.app().name()));
+    path.parent()
+        .map(fs::create_dir_all)
+        .transpose()
+        .and_then(|_| fs::write(&path, script))
+        .map_err(|cause| Error::WriteFailed {
+            path: path.clone(),
+            cause,
+        })?;
+    log::info!("wrote lldb script to {:?}", path);
+    Ok(path)
+}
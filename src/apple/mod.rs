@@ -1,12 +1,21 @@
+mod api;
 pub mod cli;
 pub(crate) mod config;
 mod deps;
-mod device;
-mod ios_deploy;
+pub(crate) mod device;
+mod devicectl;
+mod framework;
+pub(crate) mod ios_deploy;
+mod libimobiledevice;
+mod lldb;
 pub(crate) mod project;
-mod system_profile;
-mod target;
+pub(crate) mod simctl;
+pub(crate) mod system_profile;
+pub(crate) mod target;
 mod teams;
+mod test_runner;
+
+pub use self::api::{Builder, Error, Runner};
 
 use crate::util::{
     self,
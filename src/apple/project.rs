@@ -6,7 +6,7 @@ use super::{
 use crate::{
     opts,
     target::TargetTrait as _,
-    templating::{self, Pack},
+    templating::{self, DryRun, Pack, Review},
     util::{
         self,
         cli::{Report, Reportable, TextWrapper},
@@ -19,11 +19,13 @@ pub static TEMPLATE_PACK: &str = "xcode";
 
 #[derive(Debug)]
 pub enum Error {
-    RustupFailed(bossy::Error),
+    RustupFailed(util::rustup::Error),
     RustVersionCheckFailed(util::RustVersionError),
     DepsInstallFailed(deps::Error),
     MissingPack(templating::LookupError),
     TemplateProcessingFailed(bicycle::ProcessingError),
+    StagingFailed(templating::DryRunError),
+    ReviewFailed(templating::ReviewError),
     AssetDirSymlinkFailed(ln::Error),
     XcodegenFailed(bossy::Error),
 }
@@ -31,7 +33,7 @@ pub enum Error {
 impl Reportable for Error {
     fn report(&self) -> Report {
         match self {
-            Self::RustupFailed(err) => Report::error("Failed to `rustup` Apple toolchains", err),
+            Self::RustupFailed(err) => err.report(),
             Self::RustVersionCheckFailed(err) => err.report(),
             Self::DepsInstallFailed(err) => {
                 Report::error("Failed to install Apple dependencies", err)
@@ -40,6 +42,8 @@ impl Reportable for Error {
             Self::TemplateProcessingFailed(err) => {
                 Report::error("Xcode template processing failed", err)
             }
+            Self::StagingFailed(err) => Report::error("Failed to stage Xcode project", err),
+            Self::ReviewFailed(err) => Report::error("Failed to update Xcode project", err),
             Self::AssetDirSymlinkFailed(err) => {
                 Report::error("Asset dir couldn't be symlinked into Xcode project", err)
             }
@@ -48,6 +52,59 @@ impl Reportable for Error {
     }
 }
 
+/// Renders the Xcode template pack into `dest`, which is normally the project
+/// dir, but may be somewhere else for dry runs.
+pub fn process_templates(
+    config: &Config,
+    metadata: &Metadata,
+    submodule_path: Option<&Path>,
+    bike: &bicycle::Bicycle,
+    dest: &Path,
+    filter: impl FnMut(&bicycle::Action) -> bool,
+) -> Result<(), Error> {
+    // Paths in the project are relative to where it'll actually live.
+    let rel_prefix = util::relativize_path(config.app().root_dir(), config.project_dir());
+    let crate_rel_prefix = util::relativize_path(config.app().crate_dir(), config.project_dir());
+    let source_dirs = std::iter::once(crate_rel_prefix.join("src"))
+        .chain(submodule_path.map(|path| rel_prefix.join(path)))
+        .collect::<Vec<PathBuf>>();
+
+    let src = Pack::lookup_platform(TEMPLATE_PACK)
+        .map_err(Error::MissingPack)?
+        .expect_local();
+
+    bike.filter_and_process(
+        src,
+        dest,
+        |map| {
+            map.insert("root-dir-rel", &rel_prefix);
+            map.insert("file-groups", &source_dirs);
+            map.insert("ios-frameworks", metadata.ios().frameworks());
+            map.insert("macos-frameworks", metadata.macos().frameworks());
+            map.insert("ios-info", metadata.ios().capabilities().info_plist());
+            map.insert(
+                "ios-entitlements",
+                metadata.ios().capabilities().entitlements(),
+            );
+            map.insert("ios-privacy", metadata.ios().privacy().manifest());
+            let insecure_domains = metadata.ios().app_transport_security().insecure_domains();
+            map.insert("ios-insecure-domains", insecure_domains);
+            map.insert(
+                "ios-ats",
+                config.app().template_pack().webview() || !insecure_domains.is_empty(),
+            );
+            map.insert(
+                "ios-post-build-scripts",
+                config.app().version_from_git()
+                    || config.app().version_from_cargo()
+                    || !insecure_domains.is_empty(),
+            );
+        },
+        filter,
+    )
+    .map_err(Error::TemplateProcessingFailed)
+}
+
 // unprefixed app_root seems pretty dangerous!!
 // TODO: figure out what I meant by that
 pub fn gen(
@@ -60,8 +117,9 @@ pub fn gen(
     skip_dev_tools: opts::SkipDevTools,
     reinstall_deps: opts::ReinstallDeps,
     filter: &templating::Filter,
+    review: Review,
 ) -> Result<(), Error> {
-    println!("Installing iOS toolchains...");
+    util::cli::status("Installing iOS toolchains...");
     Target::install_all().map_err(Error::RustupFailed)?;
     rust_version_check(wrapper).map_err(Error::RustVersionCheckFailed)?;
 
@@ -69,38 +127,33 @@ pub fn gen(
         .map_err(Error::DepsInstallFailed)?;
 
     let dest = config.project_dir();
-    let rel_prefix = util::relativize_path(config.app().root_dir(), &dest);
-    let source_dirs = std::iter::once("src".as_ref())
-        .chain(submodule_path)
-        .map(|path| rel_prefix.join(path))
-        .collect::<Vec<PathBuf>>();
-
-    let src = Pack::lookup_platform(TEMPLATE_PACK)
-        .map_err(Error::MissingPack)?
-        .expect_local();
-
-    bike.filter_and_process(
-        src,
-        &dest,
-        |map| {
-            map.insert("file-groups", &source_dirs);
-            map.insert("ios-frameworks", metadata.ios().frameworks());
-            map.insert("macos-frameworks", metadata.macos().frameworks());
-        },
-        filter.fun(),
-    )
-    .map_err(Error::TemplateProcessingFailed)?;
+    {
+        let staging = DryRun::new(&dest).map_err(Error::StagingFailed)?;
+        process_templates(
+            config,
+            metadata,
+            submodule_path,
+            bike,
+            &staging.stage(&dest),
+            staging.filter(filter),
+        )?;
+        review.apply(&staging).map_err(Error::ReviewFailed)?;
+    }
 
     ln::force_symlink_relative(config.app().asset_dir(), &dest, ln::TargetStyle::Directory)
         .map_err(Error::AssetDirSymlinkFailed)?;
 
-    // Note that Xcode doesn't always reload the project nicely; reopening is
-    // often necessary.
-    println!("Generating Xcode project...");
+    xcodegen(config).map_err(Error::XcodegenFailed)
+}
+
+/// Generates the Xcode project from the already rendered `project.yml`. Note
+/// that Xcode doesn't always reload the project nicely; reopening is often
+/// necessary.
+pub fn xcodegen(config: &Config) -> bossy::Result<()> {
+    util::cli::status("Generating Xcode project...");
     bossy::Command::impure("xcodegen")
         .with_args(&["generate", "--spec"])
-        .with_arg(dest.join("project.yml"))
-        .run_and_wait()
-        .map_err(Error::XcodegenFailed)?;
+        .with_arg(config.project_dir().join("project.yml"))
+        .run_and_wait()?;
     Ok(())
 }
@@ -0,0 +1,322 @@
+use crate::{
+    device,
+    util::{
+        cli::{Report, Reportable},
+        install,
+        retry::retry,
+    },
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    BootFailed(bossy::Error),
+    InstallFailed(bossy::Error),
+    LaunchFailed(bossy::Error),
+    SpawnFailed(bossy::Error),
+    ContainerFailed(bossy::Error),
+    ContainerInvalid(std::str::Utf8Error),
+    RecordFailed(io::Error),
+    PromptFailed(io::Error),
+    StopFailed(bossy::Error),
+    WaitFailed(io::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::BootFailed(err) => Report::error("Failed to boot simulator", err),
+            Self::InstallFailed(err) => Report::error("Failed to install app on simulator", err),
+            Self::LaunchFailed(err) => Report::error("Failed to launch app on simulator", err),
+            Self::SpawnFailed(err) => Report::error("Failed to run executable on simulator", err),
+            Self::ContainerFailed(err) => {
+                Report::error("Failed to find app's container on simulator", err)
+            }
+            Self::ContainerInvalid(err) => {
+                Report::error("`simctl` output contained invalid UTF-8", err)
+            }
+            Self::RecordFailed(err) => Report::error("Failed to start recording simulator", err),
+            Self::PromptFailed(err) => Report::error("Failed to wait for input", err),
+            Self::StopFailed(err) => Report::error("Failed to stop recording simulator", err),
+            Self::WaitFailed(err) => {
+                Report::error("Failed to wait for recording to be written", err)
+            }
+        }
+    }
+}
+
+fn simctl() -> bossy::Command {
+    bossy::Command::impure("xcrun").with_arg("simctl")
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Simulator {
+    name: String,
+    udid: String,
+    // Older versions of Xcode leave this out.
+    is_available: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SimulatorList {
+    // Keyed by runtime.
+    devices: BTreeMap<String, Vec<Simulator>>,
+}
+
+/// The UDID of the simulator named by `CARGO_MOBILE_DEVICE`, if it names one
+/// at all, since it can just as well be a real device.
+pub fn env_simulator() -> Option<String> {
+    let wanted = device::env_device()?;
+    let list = simctl()
+        .with_args(&["list", "devices", "--json"])
+        .run_and_wait_for_output()
+        .map_err(|err| log::info!("failed to list simulators: {}", err))
+        .ok()
+        .and_then(|output| {
+            serde_json::from_slice::<SimulatorList>(output.stdout())
+                .map_err(|err| log::info!("failed to parse simulator list: {}", err))
+                .ok()
+        })?;
+    let simulators = list
+        .devices
+        .into_values()
+        .flatten()
+        .filter(|simulator| simulator.is_available != Some(false))
+        .collect::<Vec<_>>();
+    device::env_device_index(
+        &wanted,
+        simulators
+            .iter()
+            .map(|simulator| (simulator.udid.as_str(), simulator.name.as_str())),
+    )
+    .map(|index| {
+        let simulator = &simulators[index];
+        log::info!(
+            "using simulator {:?} from `{}`",
+            simulator.name,
+            device::DEVICE_ENV_VAR
+        );
+        simulator.udid.clone()
+    })
+}
+
+/// Boots `simulator` if it isn't already, and waits until it's usable.
+/// `booted` refers to whichever simulator is already running, so there's
+/// nothing to do for it.
+pub fn boot(simulator: &str) -> Result<(), Error> {
+    if simulator != "booted" {
+        retry(
+            "Booting simulator",
+            |_| true,
+            || {
+                simctl()
+                    .with_args(&["bootstatus", simulator, "-b"])
+                    .run_and_wait_for_output()
+            },
+        )
+        .map_err(Error::BootFailed)?;
+    }
+    Ok(())
+}
+
+pub fn install(simulator: &str, app_path: &Path) -> Result<(), Error> {
+    simctl()
+        .with_args(&["install", simulator])
+        .with_arg(app_path)
+        .run_and_wait()
+        .map_err(Error::InstallFailed)?;
+    Ok(())
+}
+
+pub fn launch(simulator: &str, bundle_id: &str) -> Result<(), Error> {
+    simctl()
+        .with_args(&[
+            "launch",
+            "--terminate-running-process",
+            simulator,
+            bundle_id,
+        ])
+        .run_and_wait_for_output()
+        .map_err(Error::LaunchFailed)?;
+    Ok(())
+}
+
+/// Bundle files that can be copied over the installed app. Anything that
+/// Launch Services or the simulator's installer looks at needs a real
+/// install.
+fn patchable(path: &str) -> bool {
+    path != "Info.plist"
+        && !path.starts_with("Frameworks/")
+        && !path.starts_with("PlugIns/")
+        && !path.starts_with("Watch/")
+}
+
+/// Copies `changed` from `app_path` into the installed bundle, and removes
+/// `removed` from it. The simulator runs apps straight off the host's disk,
+/// so this is all an install of only new resources or a new executable
+/// would've done.
+fn patch(
+    container: &Path,
+    app_path: &Path,
+    changed: &[String],
+    removed: &[String],
+) -> io::Result<()> {
+    for path in changed {
+        let to = container.join(path);
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Written beside and renamed over, so an app that's still running
+        // never sees a partial file.
+        let staged = to.with_file_name(format!(
+            ".{}.tmp",
+            to.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        fs::copy(app_path.join(path), &staged)?;
+        fs::rename(&staged, &to)?;
+    }
+    for path in removed {
+        match fs::remove_file(container.join(path)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Installs `app_path`, unless only its executable or resources changed since
+/// it was last installed, in which case those are copied into the installed
+/// app instead. If nothing changed, there's nothing to do.
+pub fn install_changed(
+    simulator: &str,
+    app_path: &Path,
+    bundle_id: &str,
+    target_dir: &Path,
+) -> Result<(), Error> {
+    let key = format!("simulator-{}", bundle_id);
+    let contents = install::dir_contents(app_path)
+        .map_err(|err| log::info!("failed to read app contents: {}", err))
+        .ok();
+    // The container path includes the simulator's UDID, so it tells us
+    // whether `booted` is still the simulator we installed on last time. If
+    // it's not installed, there's no container.
+    let installed = container(simulator, bundle_id, "app").ok();
+    let target = installed
+        .as_ref()
+        .map(|container| container.display().to_string())
+        .unwrap_or_default();
+    let plan = match (&contents, &installed) {
+        (Some(contents), Some(_)) => install::plan(target_dir, &key, &target, contents, patchable),
+        _ => install::Plan::Full,
+    };
+    match (plan, &installed) {
+        (install::Plan::Skip, _) => {
+            log::info!("app is unchanged since the last install, so skipping install");
+            return Ok(());
+        }
+        (install::Plan::Patch { changed, removed }, Some(container)) => {
+            log::info!("only {:?} changed, so patching installed app", changed);
+            if let Err(err) = patch(container, app_path, &changed, &removed) {
+                log::warn!(
+                    "failed to patch installed app, so falling back to a full install: {}",
+                    err
+                );
+                install::forget(target_dir, &key);
+                install(simulator, app_path)?;
+            }
+        }
+        _ => {
+            if let Err(err) = install(simulator, app_path) {
+                install::forget(target_dir, &key);
+                return Err(err);
+            }
+        }
+    }
+    if let Some(contents) = contents {
+        // A fresh install can land in a new container.
+        let target = container(simulator, bundle_id, "app")
+            .map(|container| container.display().to_string())
+            .unwrap_or(target);
+        install::record(target_dir, &key, &target, contents);
+    }
+    Ok(())
+}
+
+fn container(simulator: &str, bundle_id: &str, kind: &str) -> Result<PathBuf, Error> {
+    simctl()
+        .with_args(&["get_app_container", simulator, bundle_id, kind])
+        .run_and_wait_for_output()
+        .map_err(Error::ContainerFailed)?
+        .stdout_str()
+        .map(|s| PathBuf::from(s.trim()))
+        .map_err(Error::ContainerInvalid)
+}
+
+/// Runs `executable` directly on `simulator`, without bundling it into an
+/// app, and returns its exit code. Output goes straight to ours.
+pub fn spawn(
+    simulator: &str,
+    executable: &Path,
+    args: &[String],
+    child_env: &[(String, String)],
+) -> Result<i32, Error> {
+    let result = simctl()
+        // `simctl` only forwards variables with this prefix, minus the prefix.
+        .with_env_vars(
+            child_env
+                .iter()
+                .map(|(key, value)| (format!("SIMCTL_CHILD_{}", key), value)),
+        )
+        .with_args(&["spawn", simulator])
+        .with_arg(executable)
+        .with_args(args)
+        .run_and_wait();
+    match result {
+        Ok(_) => Ok(0),
+        Err(err) => err
+            .status()
+            .and_then(|status| status.code())
+            .ok_or(Error::SpawnFailed(err)),
+    }
+}
+
+/// Records `simulator`'s screen to `out` while `session` runs, and stops once
+/// it returns.
+pub fn record<T>(
+    simulator: &str,
+    out: &Path,
+    session: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    // This has to be spawned directly, since it needs to be interrupted rather
+    // than waited on.
+    let mut recorder = Command::new("xcrun")
+        .args(&[
+            "simctl",
+            "io",
+            simulator,
+            "recordVideo",
+            "--codec=h264",
+            "--force",
+        ])
+        .arg(out)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(Error::RecordFailed)?;
+    let result = session();
+    // `recordVideo` only finalizes the file when it gets `SIGINT`; killing it
+    // outright leaves an unplayable video behind.
+    bossy::Command::impure("kill")
+        .with_args(&["-INT", &recorder.id().to_string()])
+        .run_and_wait()
+        .map_err(Error::StopFailed)?;
+    recorder.wait().map_err(Error::WaitFailed)?;
+    result
+}
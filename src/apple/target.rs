@@ -4,10 +4,12 @@ use super::{
 };
 use crate::{
     env::{Env, ExplicitEnv as _},
-    opts::{self, ForceColor, NoiseLevel, Profile},
+    opts::{self, EntryPoint, ForceColor, NoiseLevel, Profile},
     target::TargetTrait,
     util::{
+        self,
         cli::{Report, Reportable},
+        repro::{self, Recorded as _},
         CargoCommand,
     },
 };
@@ -15,6 +17,9 @@ use once_cell_regex::exports::once_cell::sync::OnceCell;
 use std::{
     collections::{BTreeMap, HashMap},
     ffi::OsStr,
+    fs,
+    io::{self, BufRead as _, Write as _},
+    path::PathBuf,
 };
 
 fn verbosity(noise_level: opts::NoiseLevel) -> Option<&'static str> {
@@ -73,6 +78,12 @@ impl Reportable for CheckError {
 pub enum CompileLibError {
     VersionCheckFailed(VersionCheckError),
     CargoBuildFailed(bossy::Error),
+    OutputFailed(io::Error),
+    CopyFailed {
+        from: PathBuf,
+        to: PathBuf,
+        cause: io::Error,
+    },
 }
 
 impl Reportable for CompileLibError {
@@ -80,10 +91,63 @@ impl Reportable for CompileLibError {
         match self {
             Self::VersionCheckFailed(err) => err.report(),
             Self::CargoBuildFailed(err) => Report::error("Failed to run `cargo build`", err),
+            Self::OutputFailed(err) => Report::error("Failed to read `cargo build` output", err),
+            Self::CopyFailed { from, to, cause } => Report::error(
+                format!("Failed to copy built lib from {:?} to {:?}", from, to),
+                cause,
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TestBuildError {
+    VersionCheckFailed(VersionCheckError),
+    CargoTestFailed(bossy::Error),
+    OutputInvalid(std::str::Utf8Error),
+    NoTests,
+    NoBenches,
+}
+
+impl Reportable for TestBuildError {
+    fn report(&self) -> Report {
+        match self {
+            Self::VersionCheckFailed(err) => err.report(),
+            Self::CargoTestFailed(err) => Report::error("Failed to build test harnesses", err),
+            Self::OutputInvalid(err) => {
+                Report::error("`cargo` output contained invalid UTF-8", err)
+            }
+            Self::NoTests => Report::error(
+                "Failed to build tests",
+                "`cargo test` didn't produce any test harnesses",
+            ),
+            Self::NoBenches => Report::error(
+                "Failed to build benchmarks",
+                "`cargo bench` didn't produce any benchmark harnesses",
+            ),
         }
     }
 }
 
+#[derive(Debug)]
+pub struct ExampleLibError {
+    from: PathBuf,
+    to: PathBuf,
+    cause: io::Error,
+}
+
+impl Reportable for ExampleLibError {
+    fn report(&self) -> Report {
+        Report::error(
+            format!(
+                "Failed to copy example lib {:?} to {:?}",
+                self.from, self.to
+            ),
+            &self.cause,
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct BuildError(bossy::Error);
 
@@ -103,11 +167,74 @@ impl Reportable for ArchiveError {
 }
 
 #[derive(Debug)]
-pub struct ExportError(bossy::Error);
+pub enum ExportError {
+    InsecureDomains(Vec<String>),
+    ExportFailed(bossy::Error),
+}
 
 impl Reportable for ExportError {
     fn report(&self) -> Report {
-        Report::error("Failed to export archive via `xcodebuild`", &self.0)
+        match self {
+            Self::InsecureDomains(domains) => Report::action_request(
+                "Refusing to export an archive that allows insecure HTTP loads",
+                format!(
+                    "The archive's `Info.plist` still has ATS exceptions for {:?}, which are only meant for debug builds; archive with `--release` instead.",
+                    domains
+                ),
+            ),
+            Self::ExportFailed(err) => {
+                Report::error("Failed to export archive via `xcodebuild`", err)
+            }
+        }
+    }
+}
+
+/// The domains an archived app's `Info.plist` allows plain HTTP loads from,
+/// which are supposed to be stripped from anything but debug builds.
+fn insecure_domains(config: &Config) -> Vec<String> {
+    let plist = config
+        .archive_path()
+        .join("Products/Applications")
+        .join(format!("{}.app", config.app().name()))
+        .join("Info.plist");
+    // This fails if there aren't any exceptions at all.
+    bossy::Command::impure("plutil")
+        .with_args(&[
+            "-extract",
+            "NSAppTransportSecurity.NSExceptionDomains",
+            "json",
+        ])
+        .with_args(&["-o", "-"])
+        .with_arg(&plist)
+        .run_and_wait_for_output()
+        .ok()
+        .and_then(|output| {
+            serde_json::from_slice::<BTreeMap<String, serde_json::Value>>(output.stdout()).ok()
+        })
+        .map(|exceptions| {
+            exceptions
+                .into_iter()
+                .filter(|(_, exception)| {
+                    exception["NSExceptionAllowsInsecureHTTPLoads"].as_bool() == Some(true)
+                })
+                .map(|(domain, _)| domain)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+pub enum TestBundleError {
+    BuildFailed(bossy::Error),
+    ZipFailed(bossy::Error),
+}
+
+impl Reportable for TestBundleError {
+    fn report(&self) -> Report {
+        match self {
+            Self::BuildFailed(err) => Report::error("Failed to build tests via `xcodebuild`", err),
+            Self::ZipFailed(err) => Report::error("Failed to zip test bundle", err),
+        }
     }
 }
 
@@ -207,7 +334,7 @@ impl<'a> Target<'a> {
         metadata: &'a Metadata,
         subcommand: &'a str,
     ) -> Result<CargoCommand<'a>, VersionCheckError> {
-        let metadata = if self.is_macos() {
+        let platform = if self.is_macos() {
             metadata.macos()
         } else {
             metadata.ios()
@@ -216,9 +343,12 @@ impl<'a> Target<'a> {
             CargoCommand::new(subcommand)
                 .with_package(Some(config.app().name()))
                 .with_manifest_path(Some(config.app().manifest_path()))
+                .with_target_dir(config.app().configured_target_dir().map(ToOwned::to_owned))
                 .with_target(Some(&self.triple))
-                .with_no_default_features(metadata.no_default_features())
+                .with_no_default_features(platform.no_default_features())
                 .with_features(metadata.features())
+                .with_features(platform.features())
+                .with_profiles(Some(platform.profiles()))
         })
     }
 
@@ -241,30 +371,249 @@ impl<'a> Target<'a> {
     // NOTE: it's up to Xcode to pass the verbose flag here, so even when
     // using our build/run commands it won't get passed.
     // TODO: do something about that?
-    pub fn compile_lib(
+    fn compile_lib_command(
         &self,
         config: &Config,
         metadata: &Metadata,
         noise_level: NoiseLevel,
         force_color: ForceColor,
         profile: Profile,
+        entry_point: &EntryPoint,
         env: &Env,
         cc_env: HashMap<&str, &OsStr>,
-    ) -> Result<(), CompileLibError> {
+        target_dir: Option<PathBuf>,
+    ) -> Result<bossy::Command, CompileLibError> {
         // Force color when running from CLI
         let color = if force_color.yes() { "always" } else { "auto" };
-        self.cargo(config, metadata, "build")
-            .map_err(CompileLibError::VersionCheckFailed)?
-            .with_verbose(noise_level.pedantic())
-            .with_release(profile.release())
+        let cargo = self
+            .cargo(config, metadata, "build")
+            .map_err(CompileLibError::VersionCheckFailed)?;
+        let cargo = match target_dir {
+            Some(target_dir) => cargo.with_target_dir(Some(target_dir)),
+            None => cargo,
+        }
+        .with_verbose(noise_level.pedantic())
+        .with_example(entry_point.example())
+        .with_release(profile.release());
+        let mut vars = repro::Vars::default();
+        vars.extend(cargo.env_vars());
+        vars.extend(cc_env);
+        if !profile.release() {
+            if let Some(url) = crate::dev::url(config.app()) {
+                vars.add(crate::dev::URL_ENV_VAR, url);
+            }
+        }
+        if util::reproducible::enabled() {
+            vars.add(
+                util::reproducible::RUSTFLAGS_ENV_VAR,
+                util::reproducible::rustflags(config.app(), self.triple, None),
+            );
+        }
+        Ok(cargo
             .into_command_pure(env)
-            .with_env_vars(cc_env)
             .with_args(&["--color", color])
-            .run_and_wait()
+            .recorded(env, vars))
+    }
+
+    pub fn compile_lib(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        noise_level: NoiseLevel,
+        force_color: ForceColor,
+        profile: Profile,
+        entry_point: &EntryPoint,
+        env: &Env,
+        cc_env: HashMap<&str, &OsStr>,
+    ) -> Result<(), CompileLibError> {
+        self.compile_lib_command(
+            config,
+            metadata,
+            noise_level,
+            force_color,
+            profile,
+            entry_point,
+            env,
+            cc_env,
+            None,
+        )?
+        .run_and_wait()
+        .map_err(CompileLibError::CargoBuildFailed)?;
+        Ok(())
+    }
+
+    fn isolated_target_dir(&self, config: &Config) -> PathBuf {
+        config
+            .app()
+            .target_dir()
+            .join(concat!(".", env!("CARGO_PKG_NAME"), "/isolated"))
+            .join(self.triple)
+    }
+
+    /// Like `compile_lib`, but for building alongside other targets. Cargo
+    /// locks the whole target dir (host artifacts included) for the length
+    /// of a build, so this gets a target dir of its own, and the lib is copied
+    /// to where `compile_lib` would've put it afterwards. That means every
+    /// dependency and build script is built again for each target, and takes
+    /// up that much more disk space, which is why this is only used when
+    /// `xcode-script` is asked for more than one job. Cargo's output is
+    /// prefixed with `prefix`, so that it can be told apart from the rest.
+    pub fn compile_lib_isolated(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        noise_level: NoiseLevel,
+        force_color: ForceColor,
+        profile: Profile,
+        entry_point: &EntryPoint,
+        env: &Env,
+        cc_env: HashMap<&str, &OsStr>,
+        prefix: &str,
+    ) -> Result<(), CompileLibError> {
+        let mut handle = self
+            .compile_lib_command(
+                config,
+                metadata,
+                noise_level,
+                force_color,
+                profile,
+                entry_point,
+                env,
+                cc_env,
+                Some(self.isolated_target_dir(config)),
+            )?
+            .with_stderr(bossy::Stdio::piped())
+            .run()
             .map_err(CompileLibError::CargoBuildFailed)?;
+        // Cargo only writes to stdout when asked for JSON messages, so
+        // stderr is all there is to prefix.
+        if let Some(output) = handle.stderr() {
+            for line in io::BufReader::new(output).split(b'\n') {
+                let line = line.map_err(CompileLibError::OutputFailed)?;
+                let stderr = io::stderr();
+                let mut stderr = stderr.lock();
+                let _ = write!(stderr, "[{}] ", prefix);
+                let _ = stderr.write_all(&line);
+                let _ = writeln!(stderr);
+            }
+        }
+        handle.wait().map_err(CompileLibError::CargoBuildFailed)?;
+        let built = self
+            .isolated_target_dir(config)
+            .join(self.triple)
+            .join(profile.as_str());
+        let lib_path = self.lib_path(config, profile);
+        let dir = lib_path
+            .parent()
+            .expect("developer error: lib path had no parent");
+        let lib_file_name = format!("lib{}.a", config.app().lib_name());
+        let artifacts = match entry_point {
+            EntryPoint::Lib => vec![lib_file_name],
+            EntryPoint::Example(_) => vec![entry_point.artifact(&lib_file_name, "a")],
+        };
+        for artifact in artifacts {
+            let from = built.join(&artifact);
+            let to = dir.join(&artifact);
+            // Cargo hard links its outputs, so writing through `to` could
+            // clobber something in `deps`.
+            let _ = fs::remove_file(&to);
+            to.parent()
+                .map(fs::create_dir_all)
+                .transpose()
+                .and_then(|_| fs::copy(&from, &to))
+                .map_err(|cause| CompileLibError::CopyFailed { from, to, cause })?;
+        }
         Ok(())
     }
 
+    /// Builds the crate's test harnesses without running them, since they
+    /// need to be bundled up and installed on a device first.
+    pub fn build_tests(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        env: &Env,
+        noise_level: NoiseLevel,
+        profile: Profile,
+        coverage: opts::Coverage,
+    ) -> Result<Vec<PathBuf>, TestBuildError> {
+        self.build_harnesses(
+            config,
+            metadata,
+            env,
+            noise_level,
+            profile,
+            "test",
+            coverage,
+        )
+    }
+
+    /// Like `build_tests`, but for the crate's benchmarks. `cargo bench`
+    /// always uses the `bench` profile, and doesn't take `--release`.
+    pub fn build_benches(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        env: &Env,
+        noise_level: NoiseLevel,
+    ) -> Result<Vec<PathBuf>, TestBuildError> {
+        self.build_harnesses(
+            config,
+            metadata,
+            env,
+            noise_level,
+            Profile::Debug,
+            "bench",
+            opts::Coverage::No,
+        )
+    }
+
+    fn build_harnesses(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        env: &Env,
+        noise_level: NoiseLevel,
+        profile: Profile,
+        subcommand: &str,
+        coverage: opts::Coverage,
+    ) -> Result<Vec<PathBuf>, TestBuildError> {
+        let cargo = self
+            .cargo(config, metadata, subcommand)
+            .map_err(TestBuildError::VersionCheckFailed)?
+            .with_verbose(noise_level.pedantic())
+            .with_release(profile.release());
+        let mut vars = repro::Vars::default();
+        vars.extend(cargo.env_vars());
+        if coverage.yes() {
+            vars.add(
+                util::reproducible::RUSTFLAGS_ENV_VAR,
+                util::reproducible::rustflags(
+                    config.app(),
+                    self.triple,
+                    Some(util::coverage::RUSTFLAG),
+                ),
+            );
+        }
+        let output = cargo
+            .into_command_pure(env)
+            .with_args(&["--no-run", "--message-format=json"])
+            .recorded(env, vars)
+            .run_and_wait_for_output()
+            .map_err(TestBuildError::CargoTestFailed)?;
+        let executables =
+            util::test_executables(output.stdout_str().map_err(TestBuildError::OutputInvalid)?);
+        if executables.is_empty() {
+            Err(if subcommand == "bench" {
+                TestBuildError::NoBenches
+            } else {
+                TestBuildError::NoTests
+            })
+        } else {
+            Ok(executables)
+        }
+    }
+
     pub fn build(
         &self,
         config: &Config,
@@ -272,10 +621,10 @@ impl<'a> Target<'a> {
         noise_level: opts::NoiseLevel,
         profile: opts::Profile,
     ) -> Result<(), BuildError> {
+        let _lock = util::lock::ProjectLock::acquire(config.app().gen_dir());
         let configuration = profile.as_str();
-        bossy::Command::pure("xcodebuild")
+        util::quiet_stdout(bossy::Command::pure("xcodebuild"))
             .with_env_vars(env.explicit_env())
-            .with_env_var("FORCE_COLOR", "--force-color")
             .with_args(verbosity(noise_level))
             .with_args(&["-scheme", &config.scheme()])
             .with_arg("-workspace")
@@ -284,11 +633,85 @@ impl<'a> Target<'a> {
             .with_args(&["-arch", self.arch])
             .with_arg("-allowProvisioningUpdates")
             .with_arg("build")
+            .recorded(
+                env,
+                repro::Vars::default().with("FORCE_COLOR", "--force-color"),
+            )
             .run_and_wait()
             .map_err(BuildError)?;
         Ok(())
     }
 
+    /// Builds the app for the simulator, returning the path to the `.app`
+    /// bundle so it can be installed with `simctl`.
+    pub fn build_for_simulator(
+        &self,
+        config: &Config,
+        env: &Env,
+        noise_level: opts::NoiseLevel,
+        profile: opts::Profile,
+    ) -> Result<PathBuf, BuildError> {
+        let configuration = profile.as_str();
+        let derived_data = config.archive_dir().join("simulator");
+        util::quiet_stdout(bossy::Command::pure("xcodebuild"))
+            .with_env_vars(env.explicit_env())
+            .with_args(verbosity(noise_level))
+            .with_args(&["-scheme", &config.scheme()])
+            .with_arg("-workspace")
+            .with_arg(&config.workspace_path())
+            .with_args(&["-sdk", "iphonesimulator"])
+            .with_args(&["-configuration", configuration])
+            .with_args(&["-arch", self.arch])
+            .with_arg("-derivedDataPath")
+            .with_arg(&derived_data)
+            .with_arg("build")
+            .recorded(
+                env,
+                repro::Vars::default().with("FORCE_COLOR", "--force-color"),
+            )
+            .run_and_wait()
+            .map_err(BuildError)?;
+        Ok(derived_data
+            .join("Build/Products")
+            .join(format!("{}-iphonesimulator", configuration))
+            .join(format!("{}.app", config.app().name())))
+    }
+
+    /// Where cargo puts the static library built for this target.
+    pub fn lib_path(&self, config: &Config, profile: opts::Profile) -> PathBuf {
+        config
+            .app()
+            .target_dir()
+            .join(self.triple)
+            .join(profile.as_str())
+            .join(format!("lib{}.a", config.app().lib_name()))
+    }
+
+    /// Xcode only knows to link the lib's archive, so an example's archive
+    /// takes its place. The lib's archive is a hard link into cargo's `deps`,
+    /// so it's replaced rather than written through, and cargo puts it back
+    /// the next time the lib is built.
+    pub fn link_example_lib(
+        &self,
+        config: &Config,
+        profile: opts::Profile,
+        entry_point: &EntryPoint,
+    ) -> Result<(), ExampleLibError> {
+        if let EntryPoint::Example(_) = entry_point {
+            let to = self.lib_path(config, profile);
+            let from = to
+                .parent()
+                .expect("developer error: lib path had no parent")
+                .join(entry_point.artifact("", "a"));
+            let _ = fs::remove_file(&to);
+            fs::copy(&from, &to)
+                .map(|_| ())
+                .map_err(|cause| ExampleLibError { from, to, cause })
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn archive(
         &self,
         config: &Config,
@@ -296,9 +719,10 @@ impl<'a> Target<'a> {
         noise_level: opts::NoiseLevel,
         profile: opts::Profile,
     ) -> Result<(), ArchiveError> {
+        let _lock = util::lock::ProjectLock::acquire(config.app().gen_dir());
         let configuration = profile.as_str();
         let archive_path = config.archive_dir().join(&config.scheme());
-        bossy::Command::pure("xcodebuild")
+        util::quiet_stdout(bossy::Command::pure("xcodebuild"))
             .with_env_vars(env.explicit_env())
             .with_args(verbosity(noise_level))
             .with_args(&["-scheme", &config.scheme()])
@@ -311,22 +735,65 @@ impl<'a> Target<'a> {
             .with_arg("archive")
             .with_arg("-archivePath")
             .with_arg(&archive_path)
+            .recorded(env, Default::default())
             .run_and_wait()
             .map_err(ArchiveError)?;
         Ok(())
     }
 
+    /// Builds the scheme's XCTest bundles for devices, and zips them along
+    /// with the `.xctestrun` file, which is what device farms take.
+    pub fn build_for_testing(
+        &self,
+        config: &Config,
+        env: &Env,
+        noise_level: opts::NoiseLevel,
+    ) -> Result<PathBuf, TestBundleError> {
+        let derived_data = config.archive_dir().join("test-lab");
+        util::quiet_stdout(bossy::Command::pure("xcodebuild"))
+            .with_env_vars(env.explicit_env())
+            .with_args(verbosity(noise_level))
+            .with_args(&["-scheme", &config.scheme()])
+            .with_arg("-workspace")
+            .with_arg(&config.workspace_path())
+            .with_args(&["-sdk", "iphoneos"])
+            .with_args(&["-configuration", "debug"])
+            .with_args(&["-arch", self.arch])
+            .with_arg("-allowProvisioningUpdates")
+            .with_arg("-derivedDataPath")
+            .with_arg(&derived_data)
+            .with_arg("build-for-testing")
+            .recorded(env, Default::default())
+            .run_and_wait()
+            .map_err(TestBundleError::BuildFailed)?;
+        let zip_path = config
+            .archive_dir()
+            .join(format!("{}-tests.zip", config.scheme()));
+        // Without `--keepParent`, the products end up at the root of the zip,
+        // which is where the `.xctestrun` file needs to be.
+        bossy::Command::impure("ditto")
+            .with_args(&["-c", "-k", "--sequesterRsrc"])
+            .with_arg(derived_data.join("Build/Products"))
+            .with_arg(&zip_path)
+            .run_and_wait()
+            .map_err(TestBundleError::ZipFailed)?;
+        Ok(zip_path)
+    }
+
     pub fn export(
         &self,
         config: &Config,
         env: &Env,
         noise_level: opts::NoiseLevel,
     ) -> Result<(), ExportError> {
+        let _lock = util::lock::ProjectLock::acquire(config.app().gen_dir());
+        let insecure_domains = insecure_domains(config);
+        if !insecure_domains.is_empty() {
+            return Err(ExportError::InsecureDomains(insecure_domains));
+        }
         // Super fun discrepancy in expectation of `-archivePath` value
-        let archive_path = config
-            .archive_dir()
-            .join(&format!("{}.xcarchive", config.scheme()));
-        bossy::Command::pure("xcodebuild")
+        let archive_path = config.archive_path();
+        util::quiet_stdout(bossy::Command::pure("xcodebuild"))
             .with_env_vars(env.explicit_env())
             .with_args(verbosity(noise_level))
             .with_arg("-exportArchive")
@@ -336,8 +803,9 @@ impl<'a> Target<'a> {
             .with_arg(&config.export_plist_path())
             .with_arg("-exportPath")
             .with_arg(&config.export_dir())
+            .recorded(env, Default::default())
             .run_and_wait()
-            .map_err(ExportError)?;
+            .map_err(ExportError::ExportFailed)?;
         Ok(())
     }
 }
@@ -0,0 +1,183 @@
+use super::config::Config;
+use crate::util::cli::{Report, Reportable};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    AppMissing { app_path: PathBuf },
+    BundleIdFailed(bossy::Error),
+    SigningInfoFailed(bossy::Error),
+    OutputInvalid(std::str::Utf8Error),
+    IdentityMissing { app_path: PathBuf },
+    EntitlementsFailed(bossy::Error),
+    WriteFailed { path: PathBuf, cause: io::Error },
+    CodesignFailed(bossy::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::AppMissing { app_path } => Report::error(
+                "Failed to bundle test harness",
+                format!(
+                    "The app needs to be built first, but wasn't found at {:?}",
+                    app_path
+                ),
+            ),
+            Self::BundleIdFailed(err) => {
+                Report::error("Failed to read app's bundle identifier", err)
+            }
+            Self::SigningInfoFailed(err) => Report::error("Failed to read app's signing info", err),
+            Self::OutputInvalid(err) => {
+                Report::error("`codesign` output contained invalid UTF-8", err)
+            }
+            Self::IdentityMissing { app_path } => Report::error(
+                "Failed to bundle test harness",
+                format!("No signing identity found for app at {:?}", app_path),
+            ),
+            Self::EntitlementsFailed(err) => {
+                Report::error("Failed to read app's entitlements", err)
+            }
+            Self::WriteFailed { path, cause } => {
+                Report::error(format!("Failed to write {:?}", path), cause)
+            }
+            Self::CodesignFailed(err) => Report::error("Failed to sign test harness", err),
+        }
+    }
+}
+
+fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), Error> {
+    fs::write(path, contents).map_err(|cause| Error::WriteFailed {
+        path: path.to_owned(),
+        cause,
+    })
+}
+
+fn copy(from: &Path, to: &Path) -> Result<(), Error> {
+    fs::copy(from, to)
+        .map(|_| ())
+        .map_err(|cause| Error::WriteFailed {
+            path: to.to_owned(),
+            cause,
+        })
+}
+
+pub fn bundle_id(app_path: &Path) -> Result<String, Error> {
+    bossy::Command::impure("/usr/libexec/PlistBuddy")
+        .with_args(&["-c", "Print :CFBundleIdentifier"])
+        .with_arg(app_path.join("Info.plist"))
+        .run_and_wait_for_output()
+        .map_err(Error::BundleIdFailed)?
+        .stdout_str()
+        .map(|s| s.trim().to_owned())
+        .map_err(Error::OutputInvalid)
+}
+
+// `codesign -d` prints the certificate chain as `Authority=...` lines, with
+// the signing identity first.
+fn signing_identity(app_path: &Path) -> Result<String, Error> {
+    let output = bossy::Command::impure("codesign")
+        .with_args(&["-d", "--verbose=2"])
+        .with_arg(app_path)
+        .run_and_wait_for_output()
+        .map_err(Error::SigningInfoFailed)?;
+    output
+        .stderr_str()
+        .map_err(Error::OutputInvalid)?
+        .lines()
+        .find_map(|line| {
+            if line.starts_with("Authority=") {
+                Some(line["Authority=".len()..].to_owned())
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| Error::IdentityMissing {
+            app_path: app_path.to_owned(),
+        })
+}
+
+fn info_plist(name: &str, bundle_id: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>CFBundleExecutable</key>
+    <string>{name}</string>
+    <key>CFBundleIdentifier</key>
+    <string>{bundle_id}</string>
+    <key>CFBundleName</key>
+    <string>{name}</string>
+    <key>CFBundlePackageType</key>
+    <string>APPL</string>
+    <key>CFBundleShortVersionString</key>
+    <string>1.0</string>
+    <key>CFBundleVersion</key>
+    <string>1</string>
+    <key>CFBundleSupportedPlatforms</key>
+    <array>
+        <string>iPhoneOS</string>
+    </array>
+</dict>
+</plist>
+"#,
+        name = name,
+        bundle_id = bundle_id,
+    )
+}
+
+/// iOS won't run a bare executable, so this wraps a test harness in a
+/// minimal app bundle. Since provisioning is tied to the bundle identifier,
+/// the bundle borrows the identifier, profile, entitlements, and signing
+/// identity of the already built app; installing it replaces the app on the
+/// device.
+pub fn bundle(config: &Config, executable: &Path) -> Result<PathBuf, Error> {
+    let app_path = config.app_path();
+    if !app_path.is_dir() {
+        return Err(Error::AppMissing { app_path });
+    }
+    let name = executable
+        .file_name()
+        .expect("test harness path had no file name")
+        .to_string_lossy()
+        .into_owned();
+    let runners_dir = executable
+        .parent()
+        .expect("test harness path had no parent")
+        .join("test-runners");
+    let bundle = runners_dir.join(format!("{}.app", name));
+    fs::create_dir_all(&bundle).map_err(|cause| Error::WriteFailed {
+        path: bundle.clone(),
+        cause,
+    })?;
+    copy(executable, &bundle.join(&name))?;
+    let profile = app_path.join("embedded.mobileprovision");
+    if profile.is_file() {
+        copy(&profile, &bundle.join("embedded.mobileprovision"))?;
+    } else {
+        log::warn!("no provisioning profile found at {:?}", profile);
+    }
+    write(
+        &bundle.join("Info.plist"),
+        info_plist(&name, &bundle_id(&app_path)?),
+    )?;
+    let entitlements = bossy::Command::impure("codesign")
+        .with_args(&["-d", "--entitlements", ":-"])
+        .with_arg(&app_path)
+        .run_and_wait_for_output()
+        .map_err(Error::EntitlementsFailed)?;
+    let entitlements_path = runners_dir.join(format!("{}.entitlements", name));
+    write(&entitlements_path, entitlements.stdout())?;
+    bossy::Command::impure("codesign")
+        .with_args(&["--force", "--sign", &signing_identity(&app_path)?])
+        .with_arg("--entitlements")
+        .with_arg(&entitlements_path)
+        .with_arg(&bundle)
+        .run_and_wait()
+        .map_err(Error::CodesignFailed)?;
+    Ok(bundle)
+}
@@ -0,0 +1,128 @@
+//! Runs the crate's benchmarks on a connected device. Benchmarks are only
+//! meaningful on real hardware, so unlike `test` there's no emulator or
+//! simulator fallback.
+
+#[cfg(target_os = "macos")]
+use crate::apple;
+use crate::{
+    android,
+    config::Overrides,
+    device, opts,
+    run::{self, Picked},
+    util::{
+        self,
+        cli::{Report, Reportable, TextWrapper},
+        progress::Step,
+    },
+};
+use std::{fs, io, path::PathBuf, time::Duration};
+
+#[derive(Debug)]
+pub enum Error {
+    PickFailed(run::Error),
+    RustupFailed(util::rustup::Error),
+    #[cfg(target_os = "macos")]
+    AppleBuildFailed(apple::device::RunError),
+    #[cfg(target_os = "macos")]
+    AppleBenchBuildFailed(apple::target::TestBuildError),
+    #[cfg(target_os = "macos")]
+    AppleBenchFailed(apple::device::TestError),
+    AndroidBenchBuildFailed(android::target::TestBuildError),
+    AndroidBenchFailed(android::device::TestError),
+    ResultsDirFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::PickFailed(err) => err.report(),
+            Self::RustupFailed(err) => err.report(),
+            #[cfg(target_os = "macos")]
+            Self::AppleBuildFailed(err) => err.report(),
+            #[cfg(target_os = "macos")]
+            Self::AppleBenchBuildFailed(err) => err.report(),
+            #[cfg(target_os = "macos")]
+            Self::AppleBenchFailed(err) => err.report(),
+            Self::AndroidBenchBuildFailed(err) => err.report(),
+            Self::AndroidBenchFailed(err) => err.report(),
+            Self::ResultsDirFailed { path, cause } => Report::error(
+                format!("Failed to create benchmark results directory {:?}", path),
+                cause,
+            ),
+        }
+    }
+}
+
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    noise_level: opts::NoiseLevel,
+    filter: &device::Filter,
+    wait: Option<Duration>,
+    overrides: &Overrides,
+    warm_up: Option<Duration>,
+    args: &[String],
+) -> Result<(), Error> {
+    let (config, metadata, picked) =
+        run::pick_device(wrapper, non_interactive, filter, wait, overrides)
+            .map_err(Error::PickFailed)?;
+    match picked {
+        #[cfg(target_os = "macos")]
+        Picked::Apple { device, env } => {
+            let config = config.apple();
+            let target = device.target();
+            util::rustup::ensure_targets(Some(target.triple), non_interactive)
+                .map_err(Error::RustupFailed)?;
+            // The runner borrows the app's signing, so the app needs to have
+            // been built at least once.
+            if !config.app_path().is_dir() {
+                device
+                    .build(
+                        config,
+                        &env,
+                        noise_level,
+                        opts::Profile::Debug,
+                        &Default::default(),
+                    )
+                    .map_err(Error::AppleBuildFailed)?;
+            }
+            let executables =
+                Step::run(format!("Building benchmarks for {}", target.triple), || {
+                    target.build_benches(config, metadata.apple(), &env, noise_level)
+                })
+                .map_err(Error::AppleBenchBuildFailed)?;
+            device
+                .run_benches(config, &env, &executables, args, warm_up)
+                .map_err(Error::AppleBenchFailed)
+        }
+        Picked::Android { device, env } => {
+            let config = config.android();
+            let target = device.target();
+            util::rustup::ensure_targets(Some(target.triple), non_interactive)
+                .map_err(Error::RustupFailed)?;
+            let harnesses = Step::run(format!("Building benchmarks for {}", target.triple), || {
+                target.build_benches(config, metadata.android(), &env, noise_level)
+            })
+            .map_err(Error::AndroidBenchBuildFailed)?;
+            // Results are kept per device, since comparing runs across
+            // devices isn't meaningful.
+            let results_dir = config
+                .app()
+                .target_dir()
+                .join("criterion")
+                .join(device.id());
+            fs::create_dir_all(&results_dir).map_err(|cause| Error::ResultsDirFailed {
+                path: results_dir.clone(),
+                cause,
+            })?;
+            device
+                .run_benches(&env, &harnesses, args, warm_up, &results_dir)
+                .map_err(Error::AndroidBenchFailed)?;
+            util::cli::status(format!("Benchmark results saved to {:?}", results_dir));
+            Ok(())
+        }
+    }
+}
@@ -1,15 +1,17 @@
 #![forbid(unsafe_code)]
 
 use cargo_mobile::{
-    init, opts, update,
+    bench, collect, config, deinit, dev, distribute, gen, i18n, info, init, opts, run, runner,
+    sbom, update,
     util::{
         self,
         cli::{
             self, Exec, GlobalFlags, Report, Reportable, TextWrapper, VERSION_LONG, VERSION_SHORT,
         },
     },
-    NAME,
+    validate, watch, NAME,
 };
+use std::{path::PathBuf, time::Duration};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -38,17 +40,235 @@ pub enum Command {
         skip_dev_tools: cli::SkipDevTools,
         #[structopt(flatten)]
         reinstall_deps: cli::ReinstallDeps,
+        #[structopt(flatten)]
+        skip_hooks: cli::SkipHooks,
+        #[structopt(flatten)]
+        accept_changes: cli::AcceptChanges,
+        #[structopt(flatten)]
+        allow_unverified: cli::AllowUnverified,
         #[structopt(
             long = "open",
             help = "Open in default code editor",
             parse(from_flag = opts::OpenInEditor::from_bool),
         )]
         open_in_editor: opts::OpenInEditor,
+        #[structopt(
+            long = "dry-run",
+            help = "List what would be generated, without writing anything",
+            parse(from_flag = opts::DryRun::from_bool),
+        )]
+        dry_run: opts::DryRun,
         #[structopt(long = "submodule-commit", help = "Template pack commit to checkout")]
         submodule_commit: Option<String>,
+        #[structopt(
+            long = "template",
+            help = "Template pack to use, instead of being prompted for one"
+        )]
+        template_pack: Option<String>,
+        #[structopt(
+            long = "answers",
+            help = "TOML file of template variable values, used instead of prompting"
+        )]
+        template_answers: Option<PathBuf>,
+        #[structopt(long = "list-templates", help = "List available template packs")]
+        list_templates: bool,
+        #[structopt(
+            long = "watch-template",
+            help = "Re-render the project whenever the template pack changes, overwriting generated files"
+        )]
+        watch_template: bool,
     },
     #[structopt(name = "open", about = "Open project in default code editor")]
     Open,
+    #[structopt(
+        name = "deinit",
+        about = "Removes generated projects, undoing `cargo mobile init`"
+    )]
+    Deinit {
+        #[structopt(
+            long = "yes",
+            help = "Don't ask for confirmation (required with `--non-interactive`)"
+        )]
+        yes: bool,
+        #[structopt(long = "config", help = "Also remove the config file and lockfile")]
+        remove_config: bool,
+    },
+    #[structopt(name = "config", about = "Manage your config file")]
+    Config(ConfigCommand),
+    #[structopt(name = "i18n", about = "Manage localized strings")]
+    I18n(I18nCommand),
+    #[structopt(name = "gen", about = "Generates config for other tools")]
+    Gen(GenCommand),
+    #[structopt(
+        name = "distribute",
+        about = "Uploads a signed build to Firebase App Distribution"
+    )]
+    Distribute {
+        #[structopt(
+            long = "platform",
+            help = "Which platform's build to upload",
+            possible_values = distribute::Platform::POSSIBLE_VALUES
+        )]
+        platform: distribute::Platform,
+        #[structopt(
+            long = "group",
+            help = "Tester group to distribute to (defaults to `firebase.groups`)",
+            number_of_values = 1
+        )]
+        groups: Vec<String>,
+        #[structopt(
+            long = "artifact",
+            help = "APK or IPA to upload, instead of the default release build",
+            parse(from_os_str)
+        )]
+        artifact: Option<PathBuf>,
+        #[structopt(
+            long = "release-notes",
+            help = "File to read release notes from (defaults to `git log` since the last tag)",
+            parse(from_os_str)
+        )]
+        release_notes: Option<PathBuf>,
+    },
+    #[structopt(
+        name = "validate",
+        about = "Checks a signed build for problems the stores would reject it for"
+    )]
+    Validate {
+        #[structopt(
+            long = "platform",
+            help = "Which platform's build to validate",
+            possible_values = distribute::Platform::POSSIBLE_VALUES
+        )]
+        platform: distribute::Platform,
+        #[structopt(
+            long = "artifact",
+            help = "AAB or IPA to validate, instead of the default release build",
+            parse(from_os_str)
+        )]
+        artifact: Option<PathBuf>,
+    },
+    #[structopt(
+        name = "collect",
+        about = "Copies artifacts built by Gradle or Xcode into the out dir, with a manifest describing them"
+    )]
+    Collect {
+        #[structopt(
+            long = "platform",
+            help = "Which platform's artifacts to collect",
+            possible_values = distribute::Platform::POSSIBLE_VALUES
+        )]
+        platform: distribute::Platform,
+        #[structopt(flatten)]
+        profile: cli::Profile,
+    },
+    #[structopt(
+        name = "sbom",
+        about = "Generates a software bill of materials for everything that ships in the app"
+    )]
+    Sbom {
+        #[structopt(
+            long = "platform",
+            help = "Which platform's dependencies to include",
+            possible_values = distribute::Platform::POSSIBLE_VALUES
+        )]
+        platform: distribute::Platform,
+        #[structopt(
+            long = "format",
+            help = "Document format to generate",
+            default_value = "cyclonedx",
+            possible_values = sbom::Format::POSSIBLE_VALUES
+        )]
+        format: sbom::Format,
+        #[structopt(
+            long = "output",
+            help = "File to write the SBOM to, instead of printing it",
+            parse(from_os_str)
+        )]
+        output: Option<PathBuf>,
+    },
+    #[structopt(
+        name = "bench",
+        about = "Runs the crate's benchmarks on a connected iOS or Android device"
+    )]
+    Bench {
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+        #[structopt(flatten)]
+        wait: cli::Wait,
+        #[structopt(
+            long = "warm-up",
+            value_name = "SECS",
+            help = "Let the device sit idle for SECS before each benchmark, so it isn't thermally throttled"
+        )]
+        warm_up: Option<u64>,
+        #[structopt(
+            name = "ARGS",
+            help = "Arguments passed to the benchmark harness",
+            last = true
+        )]
+        args: Vec<String>,
+    },
+    #[structopt(
+        name = "info",
+        about = "Prints versions of everything involved in building, for bug reports"
+    )]
+    Info,
+    #[structopt(name = "run", about = "Deploys to a connected iOS or Android device")]
+    Run {
+        #[structopt(flatten)]
+        profile: cli::Profile,
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+        #[structopt(flatten)]
+        wait: cli::Wait,
+        #[structopt(flatten)]
+        run_args: cli::RunArgs,
+    },
+    #[structopt(
+        name = "watch",
+        about = "Rebuilds and redeploys whenever the crate's sources change"
+    )]
+    Watch {
+        #[structopt(flatten)]
+        profile: cli::Profile,
+        #[structopt(flatten)]
+        filter: cli::DeviceFilter,
+        #[structopt(flatten)]
+        wait: cli::Wait,
+        #[structopt(
+            long = "simulator",
+            help = "Name or UDID of an iOS simulator to use instead of a connected device"
+        )]
+        simulator: Option<String>,
+    },
+    #[structopt(
+        name = "dev",
+        about = "Serves the assets dir with live reload, for debug builds of WebView apps to load from"
+    )]
+    Dev {
+        #[structopt(long = "port", default_value = "8000", help = "Port to serve on")]
+        port: u16,
+        #[structopt(
+            long = "host",
+            help = "Host name or IP devices should reach this machine at, for devices that can't use `adb reverse` (also makes the server reachable from the network)"
+        )]
+        host: Option<String>,
+    },
+    #[structopt(
+        name = "test-runner",
+        about = "Runs a test binary on a connected device or booted simulator (used as cargo's target runner)",
+        setting = structopt::clap::AppSettings::TrailingVarArg
+    )]
+    TestRunner {
+        #[structopt(name = "EXECUTABLE", parse(from_os_str))]
+        executable: PathBuf,
+        #[structopt(
+            name = "ARGS",
+            help = "Arguments passed to the test binary",
+            allow_hyphen_values = true
+        )]
+        args: Vec<String>,
+    },
     #[structopt(name = "update", about = "Update `cargo-mobile`")]
     Update {
         #[structopt(long = "init", help = "Regenerate project if update succeeds")]
@@ -69,10 +289,90 @@ pub enum Command {
     Android(cargo_mobile::android::cli::Command),
 }
 
+#[derive(Clone, Debug, StructOpt)]
+pub enum ConfigCommand {
+    #[structopt(
+        name = "migrate",
+        about = "Rewrites an outdated config file to use the current layout, keeping its comments and formatting"
+    )]
+    Migrate,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum I18nCommand {
+    #[structopt(
+        name = "add",
+        about = "Adds a locale, creating string resources for each platform"
+    )]
+    Add {
+        #[structopt(name = "locale", help = "Locale to add, i.e. \"fr\" or \"pt-BR\"")]
+        locale: String,
+    },
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum GenCommand {
+    #[structopt(
+        name = "ci",
+        about = "Generates a CI pipeline that builds for each supported platform"
+    )]
+    Ci {
+        #[structopt(
+            long = "provider",
+            help = "Which CI service to generate config for",
+            default_value = "github",
+            possible_values = gen::ci::Provider::POSSIBLE_VALUES
+        )]
+        provider: gen::ci::Provider,
+        #[structopt(flatten)]
+        accept_changes: cli::AcceptChanges,
+    },
+    #[structopt(
+        name = "fastlane",
+        about = "Generates a Fastfile with lanes that build using `cargo mobile`"
+    )]
+    Fastlane {
+        #[structopt(flatten)]
+        accept_changes: cli::AcceptChanges,
+    },
+    #[structopt(
+        name = "flutter",
+        about = "Generates a Flutter FFI plugin package that builds the Rust library"
+    )]
+    Flutter {
+        #[structopt(flatten)]
+        accept_changes: cli::AcceptChanges,
+    },
+    #[structopt(
+        name = "react-native",
+        about = "Generates a React Native native module package that builds the Rust library"
+    )]
+    ReactNative {
+        #[structopt(flatten)]
+        accept_changes: cli::AcceptChanges,
+    },
+}
+
 #[derive(Debug)]
 pub enum Error {
     InitFailed(init::Error),
     OpenFailed(util::OpenInEditorError),
+    DeinitFailed(deinit::Error),
+    MigrateFailed(config::migrate::Error),
+    I18nFailed(i18n::AddError),
+    CiFailed(gen::ci::Error),
+    FastlaneFailed(gen::fastlane::Error),
+    FlutterFailed(gen::flutter::Error),
+    ReactNativeFailed(gen::react_native::Error),
+    DistributeFailed(distribute::Error),
+    ValidateFailed(validate::Error),
+    CollectFailed(collect::Error),
+    SbomFailed(sbom::Error),
+    RunFailed(run::Error),
+    TestRunnerFailed(runner::Error),
+    WatchFailed(watch::Error),
+    DevFailed(dev::Error),
+    BenchFailed(bench::Error),
     UpdateFailed(update::Error),
     #[cfg(target_os = "macos")]
     AppleFailed(cargo_mobile::apple::cli::Error),
@@ -86,6 +386,22 @@ impl Reportable for Error {
             Self::OpenFailed(err) => {
                 Report::error("Failed to open project in default code editor", err)
             }
+            Self::DeinitFailed(err) => err.report(),
+            Self::MigrateFailed(err) => err.report(),
+            Self::I18nFailed(err) => err.report(),
+            Self::CiFailed(err) => err.report(),
+            Self::FastlaneFailed(err) => err.report(),
+            Self::FlutterFailed(err) => err.report(),
+            Self::ReactNativeFailed(err) => err.report(),
+            Self::DistributeFailed(err) => err.report(),
+            Self::ValidateFailed(err) => err.report(),
+            Self::CollectFailed(err) => err.report(),
+            Self::SbomFailed(err) => err.report(),
+            Self::RunFailed(err) => err.report(),
+            Self::TestRunnerFailed(err) => err.report(),
+            Self::WatchFailed(err) => err.report(),
+            Self::DevFailed(err) => err.report(),
+            Self::BenchFailed(err) => err.report(),
             Self::UpdateFailed(err) => Report::error("Failed to update `cargo-mobile`", err),
             #[cfg(target_os = "macos")]
             Self::AppleFailed(err) => err.report(),
@@ -98,32 +414,218 @@ impl Exec for Input {
     type Report = Error;
 
     fn global_flags(&self) -> GlobalFlags {
-        self.flags
+        self.flags.clone()
     }
 
     fn exec(self, wrapper: &TextWrapper) -> Result<(), Self::Report> {
         let Self { flags, command } = self;
         let GlobalFlags {
-            non_interactive, ..
+            noise_level,
+            non_interactive,
+            ..
         } = flags;
+        let overrides = flags.config_overrides();
         match command {
             Command::Init {
                 skip_dev_tools: cli::SkipDevTools { skip_dev_tools },
                 reinstall_deps: cli::ReinstallDeps { reinstall_deps },
+                skip_hooks: cli::SkipHooks { skip_hooks },
+                accept_changes: cli::AcceptChanges { accept_changes },
+                allow_unverified: cli::AllowUnverified { allow_unverified },
                 open_in_editor,
+                dry_run,
                 submodule_commit,
-            } => init::exec(
+                template_pack,
+                template_answers,
+                list_templates,
+                watch_template,
+            } => {
+                if list_templates {
+                    init::list_template_packs().map_err(Error::InitFailed)
+                } else if watch_template {
+                    init::watch_template(
+                        wrapper,
+                        non_interactive,
+                        skip_dev_tools,
+                        reinstall_deps,
+                        skip_hooks,
+                        accept_changes,
+                        allow_unverified,
+                        submodule_commit,
+                        &config::Overrides {
+                            template_pack,
+                            template_answers,
+                            ..overrides
+                        },
+                        ".",
+                    )
+                    .map_err(Error::InitFailed)
+                } else {
+                    init::exec(
+                        wrapper,
+                        non_interactive,
+                        skip_dev_tools,
+                        reinstall_deps,
+                        skip_hooks,
+                        accept_changes,
+                        open_in_editor,
+                        dry_run,
+                        allow_unverified,
+                        submodule_commit,
+                        &config::Overrides {
+                            template_pack,
+                            template_answers,
+                            ..overrides
+                        },
+                        ".",
+                    )
+                    .map(|_| ())
+                    .map_err(Error::InitFailed)
+                }
+            }
+            Command::Open => util::open_in_editor(".").map_err(Error::OpenFailed),
+            Command::Deinit { yes, remove_config } => deinit::exec(
                 wrapper,
                 non_interactive,
-                skip_dev_tools,
-                reinstall_deps,
-                open_in_editor,
-                submodule_commit,
+                yes,
+                remove_config,
+                &overrides,
                 ".",
             )
-            .map(|_| ())
-            .map_err(Error::InitFailed),
-            Command::Open => util::open_in_editor(".").map_err(Error::OpenFailed),
+            .map_err(Error::DeinitFailed),
+            Command::Config(ConfigCommand::Migrate) => {
+                config::migrate::exec(wrapper, ".").map_err(Error::MigrateFailed)
+            }
+            Command::I18n(I18nCommand::Add { locale }) => {
+                i18n::add(wrapper, non_interactive, &overrides, &locale).map_err(Error::I18nFailed)
+            }
+            Command::Gen(GenCommand::Ci {
+                provider,
+                accept_changes: cli::AcceptChanges { accept_changes },
+            }) => gen::ci::exec(
+                wrapper,
+                non_interactive,
+                accept_changes,
+                &overrides,
+                provider,
+                ".",
+            )
+            .map_err(Error::CiFailed),
+            Command::Gen(GenCommand::Fastlane {
+                accept_changes: cli::AcceptChanges { accept_changes },
+            }) => gen::fastlane::exec(wrapper, non_interactive, accept_changes, &overrides, ".")
+                .map_err(Error::FastlaneFailed),
+            Command::Gen(GenCommand::Flutter {
+                accept_changes: cli::AcceptChanges { accept_changes },
+            }) => gen::flutter::exec(wrapper, non_interactive, accept_changes, &overrides, ".")
+                .map_err(Error::FlutterFailed),
+            Command::Gen(GenCommand::ReactNative {
+                accept_changes: cli::AcceptChanges { accept_changes },
+            }) => {
+                gen::react_native::exec(wrapper, non_interactive, accept_changes, &overrides, ".")
+                    .map_err(Error::ReactNativeFailed)
+            }
+            Command::Distribute {
+                platform,
+                groups,
+                artifact,
+                release_notes,
+            } => distribute::exec(
+                wrapper,
+                non_interactive,
+                &overrides,
+                platform,
+                groups,
+                artifact,
+                release_notes,
+            )
+            .map_err(Error::DistributeFailed),
+            Command::Validate { platform, artifact } => {
+                validate::exec(wrapper, non_interactive, &overrides, platform, artifact)
+                    .map_err(Error::ValidateFailed)
+            }
+            Command::Collect {
+                platform,
+                profile: cli::Profile { profile },
+            } => collect::exec(wrapper, non_interactive, &overrides, platform, profile)
+                .map_err(Error::CollectFailed),
+            Command::Sbom {
+                platform,
+                format,
+                output,
+            } => sbom::exec(
+                wrapper,
+                non_interactive,
+                &overrides,
+                platform,
+                format,
+                output,
+            )
+            .map_err(Error::SbomFailed),
+            Command::Bench {
+                filter,
+                wait,
+                warm_up,
+                args,
+            } => bench::exec(
+                wrapper,
+                non_interactive,
+                noise_level,
+                &filter.into(),
+                wait.timeout(),
+                &overrides,
+                warm_up.map(Duration::from_secs),
+                &args,
+            )
+            .map_err(Error::BenchFailed),
+            Command::Info => {
+                info::exec(".");
+                Ok(())
+            }
+            Command::Run {
+                profile: cli::Profile { profile },
+                filter,
+                wait,
+                run_args,
+            } => run::exec(
+                wrapper,
+                non_interactive,
+                noise_level,
+                profile,
+                &filter.into(),
+                wait.timeout(),
+                &run_args.apply(&overrides),
+            )
+            .map_err(Error::RunFailed),
+            Command::Watch {
+                profile: cli::Profile { profile },
+                filter,
+                wait,
+                simulator,
+            } => watch::exec(
+                wrapper,
+                non_interactive,
+                noise_level,
+                profile,
+                &filter.into(),
+                wait.timeout(),
+                &overrides,
+                simulator,
+            )
+            .map_err(Error::WatchFailed),
+            Command::Dev { port, host } => {
+                dev::exec(wrapper, non_interactive, &overrides, port, host)
+                    .map_err(Error::DevFailed)
+            }
+            Command::TestRunner { executable, args } => {
+                let code = runner::exec(executable, &args).map_err(Error::TestRunnerFailed)?;
+                // The exit code is how cargo and nextest tell that tests
+                // failed, so it has to make it out as-is.
+                if code != 0 {
+                    std::process::exit(code)
+                }
+                Ok(())
+            }
             Command::Update { init } => {
                 update::update(wrapper).map_err(Error::UpdateFailed)?;
                 if init {
@@ -134,6 +636,11 @@ impl Exec for Input {
                         Default::default(),
                         Default::default(),
                         Default::default(),
+                        Default::default(),
+                        Default::default(),
+                        Default::default(),
+                        Default::default(),
+                        &overrides,
                         ".",
                     )
                     .map_err(Error::InitFailed)?;
@@ -0,0 +1,80 @@
+//! Copies artifacts that were built outside of `cargo mobile` into the out
+//! dir, since CI usually runs Gradle (or fastlane) itself for release builds.
+
+use crate::{
+    config::{Config, LoadOrGenError, Overrides},
+    distribute::Platform,
+    opts::{self, Profile},
+    util::{
+        cli::{Report, Reportable, TextWrapper},
+        out_dir::{self, Artifact},
+    },
+};
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadOrGenError),
+    OutDirMissing,
+    #[cfg(not(target_os = "macos"))]
+    AppleUnsupported,
+    NothingFound(Platform),
+    CollectFailed(out_dir::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            Self::OutDirMissing => Report::action_request(
+                "Pass `--out-dir`, or set `app.out-dir` in your config.",
+                "No out dir to collect artifacts into",
+            ),
+            #[cfg(not(target_os = "macos"))]
+            Self::AppleUnsupported => Report::error(
+                "Failed to collect artifacts",
+                "Apple artifacts can only be collected from macOS",
+            ),
+            Self::NothingFound(platform) => Report::action_request(
+                "Build something first, or check that `--release` matches the build you want.",
+                format!("No {} artifacts found", platform.as_str()),
+            ),
+            Self::CollectFailed(err) => err.report(),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn apple_artifacts(config: &Config) -> Vec<Artifact> {
+    let config = config.apple();
+    let mut artifacts = out_dir::archive_dsyms(&config.archive_path());
+    if let Ok(ipa_path) = config.ipa_path() {
+        artifacts.push(Artifact::new(out_dir::Kind::Ipa, ipa_path));
+    }
+    artifacts
+}
+
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    overrides: &Overrides,
+    platform: Platform,
+    profile: Profile,
+) -> Result<(), Error> {
+    let (config, _origin) = Config::load_or_gen(".", non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigFailed)?;
+    if config.app().out_dir().is_none() {
+        return Err(Error::OutDirMissing);
+    }
+    let artifacts = match platform {
+        Platform::Android => out_dir::gradle_outputs(&config.android().project_dir(), profile),
+        #[cfg(target_os = "macos")]
+        Platform::Apple => apple_artifacts(&config),
+        #[cfg(not(target_os = "macos"))]
+        Platform::Apple => return Err(Error::AppleUnsupported),
+    };
+    if artifacts.is_empty() {
+        return Err(Error::NothingFound(platform));
+    }
+    out_dir::collect(config.app(), platform.as_str(), profile, &artifacts)
+        .map_err(Error::CollectFailed)
+}
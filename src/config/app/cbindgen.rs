@@ -0,0 +1,165 @@
+use crate::util::{
+    self,
+    cli::{Report, Reportable},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+pub static KEY: &str = "app.cbindgen";
+
+pub static DEFAULT_CONFIG: &str = "cbindgen.toml";
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Raw {
+    pub config: Option<PathBuf>,
+    pub header: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    CbindgenMissing,
+    CbindgenPresentFailed(bossy::Error),
+    CbindgenFailed(bossy::Error),
+    ReadFailed { path: PathBuf, cause: io::Error },
+    WriteFailed { path: PathBuf, cause: io::Error },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::CbindgenMissing => Report::action_request(
+                format!(
+                    "`{}` is configured, but `cbindgen` isn't installed; you can install it by running this:",
+                    KEY
+                ),
+                "cargo install cbindgen",
+            ),
+            Self::CbindgenPresentFailed(err) => {
+                Report::error("Failed to check if `cbindgen` is installed", err)
+            }
+            Self::CbindgenFailed(err) => Report::error("Failed to generate C header", err),
+            Self::ReadFailed { path, cause } => {
+                Report::error(format!("Failed to read {:?}", path), cause)
+            }
+            Self::WriteFailed { path, cause } => {
+                Report::error(format!("Failed to write header to {:?}", path), cause)
+            }
+        }
+    }
+}
+
+/// Runs `cbindgen` before builds, and copies the header into the generated
+/// project. Having an `app.cbindgen` section at all is what turns this on.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Cbindgen {
+    #[serde(skip)]
+    root_dir: PathBuf,
+    config: Option<PathBuf>,
+    header: String,
+    #[serde(skip)]
+    staged_path: PathBuf,
+}
+
+impl Cbindgen {
+    pub fn from_raw(root_dir: &Path, target_dir: &Path, lib_name: &str, raw: Raw) -> Self {
+        let config = raw
+            .config
+            .map(|config| util::prefix_path(root_dir, config))
+            .or_else(|| Some(root_dir.join(DEFAULT_CONFIG)).filter(|path| path.is_file()));
+        // The templates ship a hand-written `bindings.h`, so the default
+        // can't be that.
+        let header = raw.header.unwrap_or_else(|| format!("{}.h", lib_name));
+        let staged_path = target_dir
+            .join(concat!(".", env!("CARGO_PKG_NAME")))
+            .join("cbindgen")
+            .join(&header);
+        Self {
+            root_dir: root_dir.to_owned(),
+            config,
+            header,
+            staged_path,
+        }
+    }
+
+    /// The header's file name.
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+
+    // Anything that could change the header: the crate's sources, its
+    // manifest, and the `cbindgen` config.
+    fn inputs_newer_than(&self, time: SystemTime) -> bool {
+        let mut inputs = vec![self.root_dir.join("Cargo.toml")];
+        inputs.extend(self.config.clone());
+        let newer = |path: &Path| {
+            path.metadata()
+                .and_then(|meta| meta.modified())
+                .map_or(true, |modified| modified > time)
+        };
+        inputs.iter().any(|path| newer(path))
+            || ignore::WalkBuilder::new(self.root_dir.join("src"))
+                .build()
+                .filter_map(Result::ok)
+                .filter(|entry| entry.path().extension().map_or(false, |ext| ext == "rs"))
+                .any(|entry| newer(entry.path()))
+    }
+
+    fn stale(&self) -> bool {
+        self.staged_path
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .map_or(true, |generated| self.inputs_newer_than(generated))
+    }
+
+    /// Regenerates the header if the crate changed since it was last
+    /// generated, and then copies it to `dest` if that's missing or outdated.
+    /// Only touching the header when it actually changed keeps Xcode and
+    /// Gradle from rebuilding native code for nothing.
+    pub fn generate(&self, dest: &Path) -> Result<(), Error> {
+        if self.stale() {
+            if !util::command_present("cbindgen").map_err(Error::CbindgenPresentFailed)? {
+                return Err(Error::CbindgenMissing);
+            }
+            if let Some(parent) = self.staged_path.parent() {
+                fs::create_dir_all(parent).map_err(|cause| Error::WriteFailed {
+                    path: parent.to_owned(),
+                    cause,
+                })?;
+            }
+            let command = bossy::Command::impure("cbindgen")
+                .with_arg("--output")
+                .with_arg(&self.staged_path)
+                .with_arg(&self.root_dir);
+            let command = if let Some(config) = &self.config {
+                command.with_arg("--config").with_arg(config)
+            } else {
+                command
+            };
+            command.run_and_wait().map_err(Error::CbindgenFailed)?;
+        } else {
+            log::info!("header {:?} is up to date", self.staged_path);
+        }
+        let header = fs::read(&self.staged_path).map_err(|cause| Error::ReadFailed {
+            path: self.staged_path.clone(),
+            cause,
+        })?;
+        if fs::read(dest).ok().as_deref() != Some(header.as_slice()) {
+            dest.parent()
+                .map(fs::create_dir_all)
+                .transpose()
+                .and_then(|_| fs::write(dest, &header))
+                .map_err(|cause| Error::WriteFailed {
+                    path: dest.to_owned(),
+                    cause,
+                })?;
+            log::info!("copied header to {:?}", dest);
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,13 @@
+pub mod cbindgen;
 mod common_email_providers;
 pub mod name;
 mod raw;
+pub mod run_args;
 
 pub use self::raw::*;
 
 use crate::{
+    config::sentry::Sentry,
     templating::{self, Pack},
     util::{self, cli::Report},
 };
@@ -14,6 +17,15 @@ use std::path::{Path, PathBuf};
 pub static KEY: &str = "app";
 
 pub static DEFAULT_ASSET_DIR: &str = "assets";
+pub static DEFAULT_GEN_DIR: &str = "gen";
+pub static DEFAULT_VERSION: &str = "1.0";
+// Setting `app.version` to this derives the version name and build number from
+// the latest tag (minus any leading `v`) and the commit count at build time,
+// falling back to `1.0` and `1` outside of a repo.
+pub static GIT_VERSION: &str = "git";
+// Setting `app.version` to this uses the crate's `package.version`, and keeps
+// the generated projects in sync with it on every build.
+pub static CARGO_VERSION: &str = "cargo";
 #[cfg(feature = "brainium")]
 pub static DEFAULT_TEMPLATE_PACK: &str = "brainstorm";
 #[cfg(not(feature = "brainium"))]
@@ -22,6 +34,9 @@ pub static DEFAULT_TEMPLATE_PACK: &str = "bevy";
 #[derive(Debug)]
 pub enum Error {
     NameInvalid(name::Invalid),
+    LibNameInvalid {
+        lib_name: String,
+    },
     DomainInvalid {
         domain: String,
     },
@@ -33,7 +48,12 @@ pub enum Error {
         asset_dir: PathBuf,
         root_dir: PathBuf,
     },
+    TargetDirExpansionFailed(util::NoHomeDir),
+    OutDirExpansionFailed(util::NoHomeDir),
     TemplatePackNotFound(templating::LookupError),
+    CargoVersionMissing {
+        manifest_path: PathBuf,
+    },
 }
 
 impl Error {
@@ -42,6 +62,13 @@ impl Error {
             Self::NameInvalid(err) => {
                 Report::error(msg, format!("`{}.name` invalid: {}", KEY, err))
             }
+            Self::LibNameInvalid { lib_name } => Report::error(
+                msg,
+                format!(
+                    "`{}.lib-name` invalid: {:?} isn't a valid Rust identifier",
+                    KEY, lib_name
+                ),
+            ),
             Self::DomainInvalid { domain } => Report::error(
                 msg,
                 format!(
@@ -66,11 +93,38 @@ impl Error {
                     KEY, asset_dir, root_dir,
                 ),
             ),
+            Self::TargetDirExpansionFailed(err) => Report::error(
+                msg,
+                format!("`{}.target-dir` couldn't be expanded: {}", KEY, err),
+            ),
+            Self::OutDirExpansionFailed(err) => Report::error(
+                msg,
+                format!("`{}.out-dir` couldn't be expanded: {}", KEY, err),
+            ),
             Self::TemplatePackNotFound(err) => Report::error(msg, err),
+            Self::CargoVersionMissing { manifest_path } => Report::error(
+                msg,
+                format!(
+                    "`{}.version` is {:?}, but no `package.version` string was found in {:?}",
+                    KEY, CARGO_VERSION, manifest_path
+                ),
+            ),
         }
     }
 }
 
+// Workspace-inherited versions aren't resolved, since that'd mean running
+// `cargo metadata` just to load the config.
+fn cargo_version(manifest_path: &Path) -> Option<String> {
+    let bytes = std::fs::read(manifest_path).ok()?;
+    let manifest = toml::from_slice::<toml::value::Table>(&bytes).ok()?;
+    manifest
+        .get("package")?
+        .get("version")?
+        .as_str()
+        .map(ToOwned::to_owned)
+}
+
 #[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct App {
@@ -79,8 +133,22 @@ pub struct App {
     stylized_name: String,
     domain: String,
     asset_dir: PathBuf,
+    gen_dir: PathBuf,
+    target_dir: PathBuf,
+    #[serde(skip)]
+    target_dir_configured: bool,
+    crate_dir: PathBuf,
+    out_dir: Option<PathBuf>,
+    version: String,
+    version_from_git: bool,
+    version_from_cargo: bool,
+    lib_name: String,
     #[serde(skip)]
     template_pack: Pack,
+    cbindgen: Option<cbindgen::Cbindgen>,
+    run_args: run_args::RunArgs,
+    sentry: Option<Sentry>,
+    selected_app: Option<String>,
 }
 
 impl App {
@@ -91,6 +159,32 @@ impl App {
 
         let stylized_name = raw.stylized_name.unwrap_or_else(|| name.clone());
 
+        let lib_name = {
+            use heck::SnekCase as _;
+            let default = name.to_snek_case();
+            if raw.lib_name.as_ref() == Some(&default) {
+                log::warn!(
+                    "`{}.lib-name` is set to the default value; you can remove it from your config",
+                    KEY
+                );
+            }
+            let lib_name = raw.lib_name.unwrap_or_else(|| {
+                log::info!("`{}.lib-name` not set; defaulting to {}", KEY, default);
+                default
+            });
+            let mut chars = lib_name.chars();
+            let valid = chars
+                .next()
+                .map(|c| c.is_ascii_alphabetic() || c == '_')
+                .unwrap_or_default()
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if valid {
+                Ok(lib_name)
+            } else {
+                Err(Error::LibNameInvalid { lib_name })
+            }
+        }?;
+
         let domain = {
             let domain = raw.domain;
             if publicsuffix::Domain::has_valid_syntax(&domain) {
@@ -126,6 +220,78 @@ impl App {
             });
         }
 
+        if raw.gen_dir.as_deref() == Some(DEFAULT_GEN_DIR.as_ref()) {
+            log::warn!(
+                "`{}.gen-dir` is set to the default value; you can remove it from your config",
+                KEY
+            );
+        }
+        // Unlike the asset dir, the gen dir is allowed to live outside of the
+        // app root, since it only ever contains generated files.
+        let gen_dir = raw.gen_dir.unwrap_or_else(|| {
+            log::info!(
+                "`{}.gen-dir` not set; defaulting to {}",
+                KEY,
+                DEFAULT_GEN_DIR
+            );
+            DEFAULT_GEN_DIR.into()
+        });
+
+        // This can live anywhere, so that artifacts can be shared between
+        // checkouts.
+        let target_dir_configured = raw.target_dir.is_some();
+        let target_dir = match raw.target_dir {
+            Some(target_dir) => util::prefix_path(
+                &root_dir,
+                util::expand_home(target_dir).map_err(Error::TargetDirExpansionFailed)?,
+            ),
+            None => {
+                log::info!("`{}.target-dir` not set; defaulting to `target`", KEY);
+                root_dir.join("target")
+            }
+        };
+
+        // In a monorepo, each app can be built from its own crate.
+        let crate_dir = raw.crate_dir.unwrap_or_else(|| {
+            log::info!("`{}.crate-dir` not set; defaulting to the app root", KEY);
+            PathBuf::new()
+        });
+
+        // Like the target dir, this can live anywhere, since CI might want
+        // artifacts somewhere outside of the checkout.
+        let out_dir = raw
+            .out_dir
+            .map(|out_dir| {
+                util::expand_home(out_dir)
+                    .map(|out_dir| util::prefix_path(&root_dir, out_dir))
+                    .map_err(Error::OutDirExpansionFailed)
+            })
+            .transpose()?;
+
+        if raw.version.as_deref() == Some(DEFAULT_VERSION) {
+            log::warn!(
+                "`{}.version` is set to the default value; you can remove it from your config",
+                KEY
+            );
+        }
+        let version = raw.version.unwrap_or_else(|| {
+            log::info!(
+                "`{}.version` not set; defaulting to {}",
+                KEY,
+                DEFAULT_VERSION
+            );
+            DEFAULT_VERSION.to_owned()
+        });
+        let version_from_git = version == GIT_VERSION;
+        let version_from_cargo = version == CARGO_VERSION;
+        let version = if version_from_cargo {
+            let manifest_path = util::prefix_path(&root_dir, &crate_dir).join("Cargo.toml");
+            cargo_version(&manifest_path)
+                .ok_or_else(|| Error::CargoVersionMissing { manifest_path })?
+        } else {
+            version
+        };
+
         #[cfg(feature = "brainium")]
         let template_pack = {
             if raw.template_pack.as_deref() == Some(DEFAULT_TEMPLATE_PACK) {
@@ -146,7 +312,18 @@ impl App {
         #[cfg(not(feature = "brainium"))]
         let template_pack = &raw.template_pack;
 
-        let template_pack = Pack::lookup_app(template_pack).map_err(Error::TemplatePackNotFound)?;
+        let template_pack = if Pack::is_local(template_pack) {
+            Pack::lookup_local(&root_dir, template_pack)
+        } else {
+            Pack::lookup_app(template_pack)
+        }
+        .map_err(Error::TemplatePackNotFound)?;
+
+        let cbindgen = raw
+            .cbindgen
+            .map(|raw| cbindgen::Cbindgen::from_raw(&root_dir, &target_dir, &lib_name, raw));
+
+        let run_args = run_args::RunArgs::from_raw(raw.run_args.unwrap_or_default());
 
         Ok(Self {
             root_dir,
@@ -154,7 +331,20 @@ impl App {
             stylized_name,
             domain,
             asset_dir,
+            gen_dir,
+            target_dir,
+            target_dir_configured,
+            crate_dir,
+            out_dir,
+            version,
+            version_from_git,
+            version_from_cargo,
+            lib_name,
             template_pack,
+            cbindgen,
+            run_args,
+            sentry: None,
+            selected_app: None,
         })
     }
 
@@ -179,6 +369,13 @@ impl App {
         self.name().to_snek_case()
     }
 
+    /// The name of the library built from the crate, which is what the
+    /// generated projects link against. This only differs from
+    /// `name_snake` if the crate sets `[lib] name` explicitly.
+    pub fn lib_name(&self) -> &str {
+        &self.lib_name
+    }
+
     pub fn stylized_name(&self) -> &str {
         &self.stylized_name
     }
@@ -192,15 +389,88 @@ impl App {
             .join(".")
     }
 
+    /// The directory containing the crate the app is built from.
+    pub fn crate_dir(&self) -> PathBuf {
+        self.prefix_path(&self.crate_dir)
+    }
+
+    /// Where final artifacts are copied to, if `--out-dir` or `app.out-dir`
+    /// is set.
+    pub fn out_dir(&self) -> Option<&Path> {
+        self.out_dir.as_deref()
+    }
+
     pub fn manifest_path(&self) -> PathBuf {
-        self.root_dir().join("Cargo.toml")
+        self.crate_dir().join("Cargo.toml")
     }
 
     pub fn asset_dir(&self) -> PathBuf {
         self.root_dir().join(&self.asset_dir)
     }
 
+    pub fn gen_dir(&self) -> PathBuf {
+        self.prefix_path(&self.gen_dir)
+    }
+
+    /// Where cargo puts build artifacts.
+    pub fn target_dir(&self) -> &Path {
+        &self.target_dir
+    }
+
+    /// The target dir, if it was set in the config; otherwise, we leave it up
+    /// to cargo.
+    pub fn configured_target_dir(&self) -> Option<&Path> {
+        Some(self.target_dir()).filter(|_| self.target_dir_configured)
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn version_from_git(&self) -> bool {
+        self.version_from_git
+    }
+
+    /// Whether the version's read from `Cargo.toml` whenever the app is
+    /// built, rather than baked into the generated projects.
+    pub fn version_from_cargo(&self) -> bool {
+        self.version_from_cargo
+    }
+
     pub fn template_pack(&self) -> &Pack {
         &self.template_pack
     }
+
+    /// Set if the config has an `app.cbindgen` section.
+    pub fn cbindgen(&self) -> Option<&cbindgen::Cbindgen> {
+        self.cbindgen.as_ref()
+    }
+
+    /// What the app is launched with on `run`, from `app.run-args`.
+    pub fn run_args(&self) -> &run_args::RunArgs {
+        &self.run_args
+    }
+
+    // `[sentry]` is its own section, but every platform needs to see it.
+    pub(crate) fn with_sentry(mut self, sentry: Option<Sentry>) -> Self {
+        self.sentry = sentry;
+        self
+    }
+
+    pub(crate) fn with_selected_app(mut self, selected_app: Option<String>) -> Self {
+        self.selected_app = selected_app;
+        self
+    }
+
+    /// Which of the config's `[apps.<name>]` sections this is, if it has any.
+    /// Anything that calls back into us from a generated project has to pass
+    /// this along as `--app`.
+    pub fn selected_app(&self) -> Option<&str> {
+        self.selected_app.as_deref()
+    }
+
+    /// Set if the config has a `sentry` section.
+    pub fn sentry(&self) -> Option<&Sentry> {
+        self.sentry.as_ref()
+    }
 }
@@ -144,33 +144,54 @@ pub struct Raw {
     pub stylized_name: Option<String>,
     pub domain: String,
     pub asset_dir: Option<String>,
+    pub gen_dir: Option<PathBuf>,
+    pub version: Option<String>,
+    pub lib_name: Option<String>,
+    pub target_dir: Option<PathBuf>,
+    pub crate_dir: Option<PathBuf>,
+    pub out_dir: Option<PathBuf>,
     #[cfg(feature = "brainium")]
     pub template_pack: Option<String>,
     #[cfg(not(feature = "brainium"))]
     pub template_pack: String,
+    pub cbindgen: Option<super::cbindgen::Raw>,
+    pub run_args: Option<super::run_args::Raw>,
 }
 
 impl Raw {
-    pub fn detect() -> Result<Self, DetectError> {
+    pub fn detect(template_pack: Option<&str>) -> Result<Self, DetectError> {
         let defaults = Defaults::new().map_err(DetectError::DefaultsFailed)?;
         Ok(Self {
             name: defaults.name.ok_or_else(|| DetectError::NameNotDetected)?,
             stylized_name: Some(defaults.stylized_name),
             domain: defaults.domain,
             asset_dir: None,
+            gen_dir: None,
+            version: None,
+            lib_name: None,
+            target_dir: None,
+            crate_dir: None,
+            out_dir: None,
+            cbindgen: None,
+            run_args: None,
             #[cfg(feature = "brainium")]
-            template_pack: None,
+            template_pack: template_pack.map(ToOwned::to_owned),
             #[cfg(not(feature = "brainium"))]
-            template_pack: super::DEFAULT_TEMPLATE_PACK.to_owned(),
+            template_pack: template_pack
+                .unwrap_or(super::DEFAULT_TEMPLATE_PACK)
+                .to_owned(),
         })
     }
 
-    pub fn prompt(wrapper: &TextWrapper) -> Result<Self, PromptError> {
+    pub fn prompt(wrapper: &TextWrapper, template_pack: Option<&str>) -> Result<Self, PromptError> {
         let defaults = Defaults::new().map_err(PromptError::DefaultsFailed)?;
         let (name, default_stylized) = Self::prompt_name(wrapper, &defaults)?;
         let stylized_name = Self::prompt_stylized_name(&name, default_stylized)?;
         let domain = Self::prompt_domain(wrapper, &defaults)?;
-        let template_pack = Self::prompt_template_pack(wrapper)?;
+        let template_pack = match template_pack {
+            Some(template_pack) => template_pack.to_owned(),
+            None => Self::prompt_template_pack(wrapper)?,
+        };
         #[cfg(feature = "brainium")]
         let template_pack = Some(template_pack).filter(|pack| pack != super::DEFAULT_TEMPLATE_PACK);
         Ok(Self {
@@ -178,6 +199,14 @@ impl Raw {
             stylized_name: Some(stylized_name),
             domain,
             asset_dir: None,
+            gen_dir: None,
+            version: None,
+            lib_name: None,
+            target_dir: None,
+            crate_dir: None,
+            out_dir: None,
+            cbindgen: None,
+            run_args: None,
             template_pack,
         })
     }
@@ -257,6 +286,12 @@ impl Raw {
     }
 
     pub fn prompt_template_pack(wrapper: &TextWrapper) -> Result<String, PromptError> {
+        fn describe(pack: &str) -> String {
+            templating::app_pack_description(pack)
+                .map(|description| format!(" - {}", description))
+                .unwrap_or_default()
+        }
+
         let packs = templating::list_app_packs().map_err(PromptError::ListTemplatePacksFailed)?;
         let mut default_pack = None;
         println!("Detected template packs:");
@@ -266,12 +301,22 @@ impl Raw {
                 default_pack = Some(index.to_string());
                 println!(
                     "{}",
-                    format!("  [{}] {}", index.to_string().bright_green(), pack,)
-                        .bright_white()
-                        .bold()
+                    format!(
+                        "  [{}] {}{}",
+                        index.to_string().bright_green(),
+                        pack,
+                        describe(pack)
+                    )
+                    .bright_white()
+                    .bold()
                 );
             } else {
-                println!("  [{}] {}", index.to_string().green(), pack);
+                println!(
+                    "  [{}] {}{}",
+                    index.to_string().green(),
+                    pack,
+                    describe(pack)
+                );
             }
         }
         if packs.is_empty() {
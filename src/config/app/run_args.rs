@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub static KEY: &str = "app.run-args";
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Raw {
+    pub args: Option<Vec<String>>,
+    pub extras: Option<BTreeMap<String, String>>,
+}
+
+/// What the app is launched with on every `run`. On Android, `extras` become
+/// string extras on the launch intent and `args` a string array extra named
+/// `args`; on iOS, `args` are passed as launch arguments and each extra as
+/// `-KEY VALUE`, which is how `UserDefaults` picks them up.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RunArgs {
+    args: Vec<String>,
+    extras: BTreeMap<String, String>,
+}
+
+impl RunArgs {
+    pub fn from_raw(raw: Raw) -> Self {
+        Self {
+            args: raw.args.unwrap_or_default(),
+            extras: raw.extras.unwrap_or_default(),
+        }
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn extras(&self) -> &BTreeMap<String, String> {
+        &self.extras
+    }
+
+    /// `args` followed by the extras in `-KEY VALUE` form, for platforms that
+    /// only take a plain argument list.
+    pub fn launch_args(&self) -> Vec<String> {
+        self.args
+            .iter()
+            .cloned()
+            .chain(
+                self.extras
+                    .iter()
+                    .flat_map(|(key, value)| vec![format!("-{}", key), value.clone()]),
+            )
+            .collect()
+    }
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+pub static KEY: &str = "firebase";
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Raw {
+    pub android_app_id: Option<String>,
+    pub apple_app_id: Option<String>,
+    pub groups: Option<Vec<String>>,
+}
+
+/// The Firebase app IDs (`1:1234567890:android:...`) to distribute builds
+/// to, which are listed in the Firebase console's project settings.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Firebase {
+    android_app_id: Option<String>,
+    apple_app_id: Option<String>,
+    groups: Vec<String>,
+}
+
+impl Firebase {
+    pub fn from_raw(raw: Option<Raw>) -> Self {
+        let raw = raw.unwrap_or_default();
+        Self {
+            android_app_id: raw.android_app_id,
+            apple_app_id: raw.apple_app_id,
+            groups: raw.groups.unwrap_or_default(),
+        }
+    }
+
+    pub fn android_app_id(&self) -> Option<&str> {
+        self.android_app_id.as_deref()
+    }
+
+    pub fn apple_app_id(&self) -> Option<&str> {
+        self.apple_app_id.as_deref()
+    }
+
+    /// Tester groups to distribute to if none are passed on the command line.
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+}
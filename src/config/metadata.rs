@@ -1,3 +1,4 @@
+use super::Overrides;
 use crate::util::cli::{Report, Reportable};
 use serde::Deserialize;
 use std::{
@@ -26,27 +27,61 @@ impl Reportable for Error {
             }
             Self::ParseFailed { path, cause } => Report::error(
                 msg,
-                format!("Failed to parse contents of {:?}: {}", path, cause),
+                format!(
+                    "Failed to parse contents of {}",
+                    super::toml_error_location(path, cause)
+                ),
             ),
         }
     }
 }
 
+/// Just enough of `cargo-apple` to know whether to generate things for Apple
+/// platforms on hosts that can't build for them.
+#[cfg(not(target_os = "macos"))]
+#[derive(Debug, Deserialize)]
+struct AppleSupport {
+    #[serde(default = "default_true")]
+    supported: bool,
+}
+
+#[cfg(not(target_os = "macos"))]
+impl Default for AppleSupport {
+    fn default() -> Self {
+        Self { supported: true }
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+impl AppleSupport {
+    const fn supported(&self) -> bool {
+        self.supported
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+const fn default_true() -> bool {
+    true
+}
+
 #[derive(Debug, Default, Deserialize)]
 pub struct Metadata {
     #[cfg(target_os = "macos")]
     #[serde(default, rename = "cargo-apple")]
     apple: crate::apple::config::Metadata,
+    #[cfg(not(target_os = "macos"))]
+    #[serde(default, rename = "cargo-apple")]
+    apple: AppleSupport,
     #[serde(default, rename = "cargo-android")]
     android: crate::android::config::Metadata,
 }
 
 impl Metadata {
-    pub fn load(project_root: &Path) -> Result<Self, Error> {
+    pub fn load(project_root: &Path, overrides: &Overrides) -> Result<Self, Error> {
         #[derive(Debug, Deserialize)]
         struct Package {
             #[serde(default)]
-            metadata: Option<Metadata>,
+            metadata: Option<toml::value::Table>,
         }
 
         #[derive(Debug, Deserialize)]
@@ -54,14 +89,39 @@ impl Metadata {
             package: Package,
         }
 
+        // Only used to recover positions for errors, since deserializing
+        // from a `Value` loses them.
+        #[derive(Debug, Deserialize)]
+        struct SpannedPackage {
+            #[serde(default)]
+            metadata: Metadata,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct SpannedCargoToml {
+            package: SpannedPackage,
+        }
+
         let path = project_root.join("Cargo.toml");
         let bytes = fs::read(&path).map_err(|cause| Error::ReadFailed {
             path: path.clone(),
             cause,
         })?;
-        let cargo_toml = toml::from_slice::<CargoToml>(&bytes)
-            .map_err(|cause| Error::ParseFailed { path, cause })?;
-        Ok(cargo_toml.package.metadata.unwrap_or_default())
+        let cargo_toml =
+            toml::from_slice::<CargoToml>(&bytes).map_err(|cause| Error::ParseFailed {
+                path: path.clone(),
+                cause,
+            })?;
+        let mut metadata = cargo_toml.package.metadata.unwrap_or_default();
+        overrides.apply_to_metadata(&mut metadata);
+        toml::Value::Table(metadata)
+            .try_into()
+            .map_err(|cause| Error::ParseFailed {
+                path,
+                cause: toml::from_slice::<SpannedCargoToml>(&bytes)
+                    .err()
+                    .unwrap_or(cause),
+            })
     }
 
     #[cfg(target_os = "macos")]
@@ -69,6 +129,12 @@ impl Metadata {
         &self.apple
     }
 
+    /// Whether Apple platforms are supported, even on hosts that can't build
+    /// for them.
+    pub fn apple_supported(&self) -> bool {
+        self.apple.supported()
+    }
+
     pub fn android(&self) -> &crate::android::config::Metadata {
         &self.android
     }
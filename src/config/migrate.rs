@@ -0,0 +1,383 @@
+//! Rewrites config files from layouts older releases wrote into the current
+//! one.
+//!
+//! Keys are renamed where they are in the original text, so comments,
+//! formatting, and anything else in the file (i.e. the rest of a
+//! `Cargo.toml`) are left alone. The result is checked against the same
+//! renames done on the parsed file, and nothing's written if they disagree.
+
+use super::raw::{Raw, Source, APPS_KEY, CARGO_METADATA_PATH, PROFILE_KEY};
+use crate::util::{
+    self,
+    cli::{self, Report, Reportable, TextWrapper},
+};
+use std::{
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+// (section, old, new); only renames that actually shipped belong here.
+static KEY_RENAMES: &[(&str, &str, &str)] = &[
+    ("apple", "project-root", "project-dir"),
+    ("android", "project-root", "project-dir"),
+];
+
+#[derive(Debug)]
+pub enum Error {
+    DiscoverFailed(io::Error),
+    NoConfig,
+    ReadFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ParseFailed {
+        path: PathBuf,
+        cause: toml::de::Error,
+    },
+    EditFailed {
+        path: PathBuf,
+        changes: Vec<String>,
+    },
+    WriteFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::DiscoverFailed(err) => Report::error("Failed to find config file", err),
+            Self::NoConfig => Report::action_request(
+                "Please run `cargo mobile init` to create one.",
+                format!("No {} was found.", super::file_name()),
+            ),
+            Self::ReadFailed { path, cause } => {
+                Report::error(format!("Failed to read config file at {:?}", path), cause)
+            }
+            Self::ParseFailed { path, cause } => {
+                Report::error(format!("Failed to parse config file at {:?}", path), cause)
+            }
+            Self::EditFailed { path, changes } => Report::action_request(
+                format!(
+                    "Please make these changes to {:?} by hand: {}",
+                    path,
+                    util::list_display(changes)
+                ),
+                "Some of the old keys are written in a way we can't safely edit (i.e. in a nested inline table), so the file was left unchanged.",
+            ),
+            Self::WriteFailed { path, cause } => Report::error(
+                format!("Failed to write migrated config file to {:?}", path),
+                cause,
+            ),
+        }
+    }
+}
+
+/// A key to rename in the table at `table`, which is a full path from the
+/// top of the file.
+#[derive(Debug)]
+struct Rename {
+    table: Vec<String>,
+    old: &'static str,
+    new: &'static str,
+}
+
+impl Rename {
+    fn describe(&self, prefix: usize) -> String {
+        let table = self.table[prefix..].join(".");
+        format!(
+            "renamed `{}.{}` to `{}.{}`",
+            table, self.old, table, self.new
+        )
+    }
+}
+
+// Finds renames for the `[apple]` and `[android]` sections of `table`, which
+// is at `path`, and does them. Keys that are there under both names are
+// returned in `conflicts`, and left alone.
+fn migrate_sections(
+    table: &mut toml::value::Table,
+    path: &[String],
+    renames: &mut Vec<Rename>,
+    conflicts: &mut Vec<String>,
+) {
+    for (section, old, new) in KEY_RENAMES {
+        if let Some(toml::Value::Table(section_table)) = table.get_mut(*section) {
+            if !section_table.contains_key(*old) {
+                continue;
+            }
+            let mut table = path.to_vec();
+            table.push((*section).to_owned());
+            if section_table.contains_key(*new) {
+                conflicts.push(format!("`{}.{}`", table.join("."), old));
+                continue;
+            }
+            let value = section_table.remove(*old).unwrap();
+            section_table.insert((*new).to_owned(), value);
+            renames.push(Rename { table, old, new });
+        }
+    }
+}
+
+// Profiles and apps nest the same sections one level further down (i.e.
+// `[profile.ci.apple]`).
+fn migrate_nested(
+    table: &mut toml::value::Table,
+    path: &[String],
+    key: &str,
+    renames: &mut Vec<Rename>,
+    conflicts: &mut Vec<String>,
+) {
+    if let Some(toml::Value::Table(nested)) = table.get_mut(key) {
+        for (name, value) in nested.iter_mut() {
+            if let toml::Value::Table(value) = value {
+                let mut path = path.to_vec();
+                path.push(key.to_owned());
+                path.push(name.clone());
+                migrate_sections(value, &path, renames, conflicts);
+            }
+        }
+    }
+}
+
+fn config_table<'a>(
+    root: &'a mut toml::value::Table,
+    prefix: &[&str],
+) -> Option<&'a mut toml::value::Table> {
+    prefix
+        .iter()
+        .try_fold(root, |table, key| match table.get_mut(*key) {
+            Some(toml::Value::Table(table)) => Some(table),
+            _ => None,
+        })
+}
+
+fn is_bare(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::CharIndices<'_>>) {
+    while let Some((_, c)) = chars.peek() {
+        if !c.is_whitespace() {
+            break;
+        }
+        chars.next();
+    }
+}
+
+// Splits a dotted key like `profile."ci".apple` into its parts, along with
+// where the last part is in `key`. Escapes in quoted parts are only
+// unescaped enough to compare them; keys we rename never need them.
+fn parse_key(key: &str) -> Option<(Vec<String>, Range<usize>)> {
+    let mut parts = Vec::new();
+    let mut chars = key.char_indices().peekable();
+    loop {
+        skip_whitespace(&mut chars);
+        let (start, c) = chars.next()?;
+        let part = match c {
+            '"' | '\'' => {
+                let mut part = String::new();
+                loop {
+                    match chars.next()? {
+                        (_, next) if next == c => break,
+                        (_, '\\') if c == '"' => part.push(chars.next()?.1),
+                        (_, next) => part.push(next),
+                    }
+                }
+                part
+            }
+            c if is_bare(c) => {
+                let mut part = c.to_string();
+                while let Some((_, c)) = chars.peek() {
+                    if !is_bare(*c) {
+                        break;
+                    }
+                    part.push(*c);
+                    chars.next();
+                }
+                part
+            }
+            _ => return None,
+        };
+        let end = chars.peek().map(|(index, _)| *index).unwrap_or(key.len());
+        parts.push(part);
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            None => return Some((parts, start..end)),
+            Some((_, '.')) => (),
+            Some(_) => return None,
+        }
+    }
+}
+
+// Where the `=` after a key is, skipping over any quoted parts of the key.
+fn find_equals(line: &str) -> Option<usize> {
+    let mut quote = None;
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '=') => return Some(index),
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => (),
+        }
+    }
+    None
+}
+
+// Finds `key` as a key in a one-line inline table like `{ key = "value" }`.
+fn find_inline_key(value: &str, key: &str) -> Option<usize> {
+    value
+        .match_indices(key)
+        .map(|(index, _)| index)
+        .find(|&index| {
+            let before = value[..index].trim_end().chars().last();
+            let after = value[index + key.len()..].trim_start().chars().next();
+            matches!(before, Some('{') | Some(',')) && after == Some('=')
+        })
+}
+
+// Does `renames` in the text of the file, returning it along with how many
+// were found.
+fn rename_in_text(contents: &str, renames: &[Rename]) -> (String, usize) {
+    let mut header = Some(Vec::new());
+    let mut multiline: Option<&str> = None;
+    let mut found = 0;
+    let lines = contents.split('\n').map(|line| {
+        if let Some(delim) = multiline {
+            if line.matches(delim).count() % 2 == 1 {
+                multiline = None;
+            }
+            return line.to_owned();
+        }
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("[[") {
+            // We don't have any arrays of tables, so we don't look inside them.
+            header = None;
+            return line.to_owned();
+        }
+        if trimmed.starts_with('[') {
+            header = trimmed[1..]
+                .find(']')
+                .and_then(|end| parse_key(&trimmed[1..end + 1]))
+                .map(|(parts, _)| parts);
+            return line.to_owned();
+        }
+        let (header, eq) = match (&header, find_equals(line)) {
+            (Some(header), Some(eq)) if !trimmed.starts_with('#') => (header, eq),
+            _ => return line.to_owned(),
+        };
+        let value = &line[eq + 1..];
+        for delim in &["\"\"\"", "'''"] {
+            if value.matches(delim).count() % 2 == 1 {
+                multiline = Some(*delim);
+            }
+        }
+        let (parts, last) = match parse_key(&line[..eq]) {
+            Some(key) => key,
+            None => return line.to_owned(),
+        };
+        let mut path = header.clone();
+        path.extend(parts);
+        for rename in renames {
+            if path.len() == rename.table.len() + 1
+                && path.starts_with(&rename.table)
+                && path.last().map(String::as_str) == Some(rename.old)
+            {
+                found += 1;
+                return format!("{}{}{}", &line[..last.start], rename.new, &line[last.end..]);
+            }
+            if path == rename.table {
+                if let Some(index) = find_inline_key(value, rename.old) {
+                    found += 1;
+                    let index = eq + 1 + index;
+                    return format!(
+                        "{}{}{}",
+                        &line[..index],
+                        rename.new,
+                        &line[index + rename.old.len()..]
+                    );
+                }
+            }
+        }
+        line.to_owned()
+    });
+    let contents = lines.collect::<Vec<_>>().join("\n");
+    (contents, found)
+}
+
+pub fn exec(wrapper: &TextWrapper, cwd: impl AsRef<Path>) -> Result<(), Error> {
+    let (root_dir, source) = Raw::discover(cwd)
+        .map_err(Error::DiscoverFailed)?
+        .ok_or(Error::NoConfig)?;
+    let path = source.path(&root_dir);
+    let contents = fs::read_to_string(&path).map_err(|cause| Error::ReadFailed {
+        path: path.clone(),
+        cause,
+    })?;
+    let parse = |contents: &str| {
+        toml::from_str::<toml::value::Table>(contents).map_err(|cause| Error::ParseFailed {
+            path: path.clone(),
+            cause,
+        })
+    };
+    let mut root = parse(&contents)?;
+    let prefix: &[&str] = match source {
+        Source::File => &[],
+        Source::CargoMetadata => CARGO_METADATA_PATH,
+    };
+    let prefix_path = prefix
+        .iter()
+        .map(|key| (*key).to_owned())
+        .collect::<Vec<_>>();
+    let config = config_table(&mut root, prefix).ok_or(Error::NoConfig)?;
+    let (mut renames, mut conflicts) = (Vec::new(), Vec::new());
+    migrate_sections(config, &prefix_path, &mut renames, &mut conflicts);
+    for key in &[PROFILE_KEY, APPS_KEY] {
+        migrate_nested(config, &prefix_path, key, &mut renames, &mut conflicts);
+    }
+
+    let mut table = config.clone();
+    table.remove(PROFILE_KEY);
+    table.remove(APPS_KEY);
+    if let Err(err) = toml::Value::Table(table).try_into::<Raw>() {
+        Report::action_request(
+            "The migrated config still isn't valid, so you'll need to fix it up by hand.",
+            err,
+        )
+        .print(wrapper);
+    }
+
+    let changes = renames
+        .iter()
+        .map(|rename| rename.describe(prefix.len()))
+        .collect::<Vec<_>>();
+    if renames.is_empty() {
+        cli::status(format!("{:?} is already up to date!", path));
+    } else {
+        let (migrated, found) = rename_in_text(&contents, &renames);
+        if found != renames.len() || parse(&migrated)? != root {
+            return Err(Error::EditFailed { path, changes });
+        }
+        fs::write(&path, migrated).map_err(|cause| Error::WriteFailed {
+            path: path.clone(),
+            cause,
+        })?;
+        for change in &changes {
+            cli::status(format!("  {}", change));
+        }
+        cli::status(format!("Migrated {:?}", path));
+    }
+    if !conflicts.is_empty() {
+        Report::action_request(
+            "Some old config keys are set alongside their new names, and were left as-is.",
+            format!(
+                "Remove {}, since only the new names are used.",
+                util::list_display(&conflicts)
+            ),
+        )
+        .print(wrapper);
+    }
+    Ok(())
+}
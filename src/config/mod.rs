@@ -1,8 +1,18 @@
 pub mod app;
+pub mod firebase;
 pub mod metadata;
+pub mod migrate;
+pub mod network;
+mod overrides;
 mod raw;
+pub mod retry;
+pub mod sentry;
+pub mod template;
 
-use self::{app::App, raw::*};
+pub use self::overrides::{Overrides, Set, SetInvalid, SETS_ENV_VAR};
+pub(crate) use self::raw::Source;
+
+use self::{app::App, firebase::Firebase, raw::*, template::Template};
 #[cfg(target_os = "macos")]
 use crate::apple;
 use crate::{
@@ -22,6 +32,51 @@ pub fn file_name() -> String {
     format!("{}.toml", crate::NAME)
 }
 
+/// Finds the app root and where in it the config lives, if there is one.
+pub(crate) fn discover(cwd: impl AsRef<Path>) -> io::Result<Option<(PathBuf, Source)>> {
+    Raw::discover(cwd)
+}
+
+/// The raw Android config and the app root it's relative to, for the few
+/// settings that are needed before the full config can be loaded. This never
+/// prompts, so a missing or broken config just means there's nothing to use.
+pub(crate) fn load_android_raw(cwd: impl AsRef<Path>) -> Option<(PathBuf, android::config::Raw)> {
+    match Raw::load(cwd, &Default::default()) {
+        Ok(found) => found.and_then(|(root_dir, _, raw)| raw.android.map(|raw| (root_dir, raw))),
+        Err(err) => {
+            log::info!(
+                "couldn't load config to check for pinned Android paths: {}",
+                err
+            );
+            None
+        }
+    }
+}
+
+/// Formats a TOML error as `path:line:column: message`, so terminals and
+/// editors can jump straight to the offending key. Errors that came from
+/// deserializing an already-parsed value have no position, so loaders
+/// re-parse the file to recover one; errors that only show up after layering
+/// (i.e. in a profile or a `--set`) still just get the path.
+pub(crate) fn toml_error_location(path: &Path, err: &toml::de::Error) -> String {
+    let msg = err.to_string();
+    match err.line_col() {
+        Some((line, col)) => {
+            let (line, col) = (line + 1, col + 1);
+            // The message ends with the same position, which is redundant here.
+            let suffix = format!(" at line {} column {}", line, col);
+            format!(
+                "{}:{}:{}: {}",
+                path.display(),
+                line,
+                col,
+                msg.strip_suffix(&suffix).unwrap_or(&msg)
+            )
+        }
+        None => format!("{}: {}", path.display(), msg),
+    }
+}
+
 #[derive(Debug)]
 pub enum FromRawError {
     AppConfigInvalid(app::Error),
@@ -46,6 +101,7 @@ pub enum GenError {
     PromptFailed(PromptError),
     DetectFailed(DetectError),
     CanonicalizeFailed(io::Error),
+    OverrideFailed(OverrideError),
     FromRawFailed(FromRawError),
     WriteFailed(WriteError),
 }
@@ -59,6 +115,7 @@ impl Reportable for GenError {
             Self::CanonicalizeFailed(err) => {
                 Report::error(msg, format!("Failed to canonicalize root dir: {}", err))
             }
+            Self::OverrideFailed(err) => Report::error(msg, err),
             Self::FromRawFailed(err) => err.report(msg),
             Self::WriteFailed(err) => err.report(),
         }
@@ -103,68 +160,159 @@ pub struct Config {
     app: App,
     #[cfg(target_os = "macos")]
     apple: apple::config::Config,
+    #[cfg(not(target_os = "macos"))]
+    #[serde(skip)]
+    apple_project_dir: Option<String>,
     android: android::config::Config,
+    template: Template,
+    firebase: Firebase,
+    #[serde(skip)]
+    source: Source,
 }
 
 impl Config {
-    fn from_raw(root_dir: PathBuf, raw: Raw) -> Result<Self, FromRawError> {
-        let app = App::from_raw(root_dir, raw.app).map_err(FromRawError::AppConfigInvalid)?;
+    fn from_raw(
+        root_dir: PathBuf,
+        source: Source,
+        mut raw: Raw,
+        overrides: &Overrides,
+    ) -> Result<Self, FromRawError> {
+        if let Some(gen_dir) = &overrides.gen_dir {
+            log::info!("overriding `{}.gen-dir` with {:?}", app::KEY, gen_dir);
+            raw.app.gen_dir = Some(gen_dir.to_owned());
+        }
+        if let Some(out_dir) = &overrides.out_dir {
+            log::info!("overriding `{}.out-dir` with {:?}", app::KEY, out_dir);
+            raw.app.out_dir = Some(out_dir.to_owned());
+        }
+        if let Some(template_pack) = &overrides.template_pack {
+            log::info!(
+                "overriding `{}.template-pack` with {:?}",
+                app::KEY,
+                template_pack
+            );
+            #[cfg(feature = "brainium")]
+            {
+                raw.app.template_pack = Some(template_pack.to_owned());
+            }
+            #[cfg(not(feature = "brainium"))]
+            {
+                raw.app.template_pack = template_pack.to_owned();
+            }
+        }
+        let app = App::from_raw(root_dir, raw.app)
+            .map_err(FromRawError::AppConfigInvalid)?
+            .with_sentry(raw.sentry.map(sentry::Sentry::from_raw))
+            .with_selected_app(raw.selected_app);
         #[cfg(target_os = "macos")]
         let apple = apple::config::Config::from_raw(app.clone(), raw.apple)
             .map_err(FromRawError::AppleConfigInvalid)?;
+        #[cfg(not(target_os = "macos"))]
+        let apple_project_dir = raw.apple.and_then(|raw| raw.project_dir);
         let android = android::config::Config::from_raw(app.clone(), raw.android)
             .map_err(FromRawError::AndroidConfigInvalid)?;
+        let template = Template::from_raw(raw.template);
+        let firebase = Firebase::from_raw(raw.firebase);
         Ok(Self {
             app,
             #[cfg(target_os = "macos")]
             apple,
+            #[cfg(not(target_os = "macos"))]
+            apple_project_dir,
             android,
+            template,
+            firebase,
+            source,
         })
     }
 
     fn gen(
         cwd: impl AsRef<Path>,
         non_interactive: NonInteractive,
+        overrides: &Overrides,
         wrapper: &TextWrapper,
+        write: bool,
     ) -> Result<Self, GenError> {
         let raw = if non_interactive.no() {
-            Raw::prompt(wrapper).map_err(GenError::PromptFailed)
+            Raw::prompt(wrapper, overrides.template_pack.as_deref()).map_err(GenError::PromptFailed)
         } else {
-            Raw::detect().map_err(GenError::DetectFailed)
+            Raw::detect(overrides.template_pack.as_deref()).map_err(GenError::DetectFailed)
         }?;
         let root_dir = cwd
             .as_ref()
             .canonicalize()
             .map_err(GenError::CanonicalizeFailed)?;
-        let config =
-            Self::from_raw(root_dir.clone(), raw.clone()).map_err(GenError::FromRawFailed)?;
+        if let Some(profile) = &overrides.profile {
+            log::warn!(
+                "ignoring config profile {:?}, since a fresh config has no profiles",
+                profile
+            );
+        }
+        let overridden = raw
+            .overridden(overrides)
+            .map_err(GenError::OverrideFailed)?;
+        // A fresh config always goes in its own file.
+        let config = Self::from_raw(root_dir.clone(), Source::File, overridden, overrides)
+            .map_err(GenError::FromRawFailed)?;
         log::info!("generated config: {:#?}", config);
-        raw.write(&root_dir).map_err(GenError::WriteFailed)?;
+        if write {
+            raw.write(&root_dir).map_err(GenError::WriteFailed)?;
+        } else {
+            log::info!("not writing generated config, since this is a dry run");
+        }
         Ok(config)
     }
 
     pub fn load_or_gen(
         cwd: impl AsRef<Path>,
         non_interactive: NonInteractive,
+        overrides: &Overrides,
         wrapper: &TextWrapper,
     ) -> Result<(Self, Origin), LoadOrGenError> {
-        let cwd = cwd.as_ref();
-        if let Some((root_dir, raw)) = Raw::load(cwd).map_err(LoadOrGenError::LoadFailed)? {
-            Self::from_raw(root_dir.clone(), raw)
+        Self::load_or_gen_inner(cwd.as_ref(), non_interactive, overrides, wrapper, true)
+    }
+
+    /// Like `load_or_gen`, but a freshly generated config isn't written.
+    pub fn load_or_preview(
+        cwd: impl AsRef<Path>,
+        non_interactive: NonInteractive,
+        overrides: &Overrides,
+        wrapper: &TextWrapper,
+    ) -> Result<(Self, Origin), LoadOrGenError> {
+        Self::load_or_gen_inner(cwd.as_ref(), non_interactive, overrides, wrapper, false)
+    }
+
+    fn load_or_gen_inner(
+        cwd: &Path,
+        non_interactive: NonInteractive,
+        overrides: &Overrides,
+        wrapper: &TextWrapper,
+        write: bool,
+    ) -> Result<(Self, Origin), LoadOrGenError> {
+        if let Some((root_dir, source, raw)) =
+            Raw::load(cwd, overrides).map_err(LoadOrGenError::LoadFailed)?
+        {
+            if overrides.template_pack.is_some() {
+                log::warn!(
+                    "the template pack was overridden, but since a config already exists, this won't be saved; set `{}.template-pack` in your config to make it stick",
+                    app::KEY
+                );
+            }
+            let path = source.path(&root_dir);
+            Self::from_raw(root_dir, source, raw, overrides)
                 .map(|config| (config, Origin::Loaded))
-                .map_err(|cause| LoadOrGenError::FromRawFailed {
-                    path: root_dir,
-                    cause,
-                })
+                .map_err(|cause| LoadOrGenError::FromRawFailed { path, cause })
         } else {
-            Self::gen(cwd, non_interactive, wrapper)
+            Self::gen(cwd, non_interactive, overrides, wrapper, write)
                 .map(|config| (config, Origin::FreshlyMinted))
                 .map_err(LoadOrGenError::GenFailed)
         }
     }
 
+    /// Where the config was loaded from, which is either its own file or
+    /// `Cargo.toml`.
     pub fn path(&self) -> PathBuf {
-        self.app().root_dir().join(file_name())
+        self.source.path(self.app().root_dir())
     }
 
     pub fn app(&self) -> &App {
@@ -176,10 +324,48 @@ impl Config {
         &self.apple
     }
 
+    /// Where the Apple project is generated, even on hosts that can't build
+    /// it.
+    pub fn apple_project_dir(&self) -> PathBuf {
+        #[cfg(target_os = "macos")]
+        {
+            self.apple().project_dir()
+        }
+        #[cfg(not(target_os = "macos"))]
+        {
+            self.apple_project_dir
+                .as_ref()
+                .map(|project_dir| self.app().prefix_path(project_dir))
+                .unwrap_or_else(|| self.app().gen_dir().join("apple"))
+        }
+    }
+
     pub fn android(&self) -> &android::config::Config {
         &self.android
     }
 
+    pub fn template(&self) -> &Template {
+        &self.template
+    }
+
+    pub fn firebase(&self) -> &Firebase {
+        &self.firebase
+    }
+
+    /// Fills in any variables the template pack declares that aren't already
+    /// set in `template.vars`, from `answers` if given or else by prompting
+    /// (or defaulting).
+    pub fn fill_template_vars(
+        &mut self,
+        non_interactive: NonInteractive,
+        answers: Option<&Path>,
+        save: bool,
+    ) -> Result<(), template::VarsError> {
+        let specs = self.app.template_pack().vars();
+        self.template
+            .fill_vars(&specs, self.app.root_dir(), non_interactive, answers, save)
+    }
+
     pub fn build_a_bike(&self) -> bicycle::Bicycle {
         templating::init(Some(self))
     }
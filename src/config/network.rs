@@ -0,0 +1,28 @@
+use super::{raw::Raw as ConfigRaw, Overrides};
+use crate::util;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub static KEY: &str = "network";
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Raw {
+    pub proxy: Option<String>,
+    pub no_proxy: Option<String>,
+}
+
+/// Applies the proxy settings from the config before anything touches the
+/// network. This happens before the config is properly loaded, so if loading
+/// it fails, we leave reporting that to whatever loads it for real.
+pub fn configure(cwd: impl AsRef<Path>, overrides: &Overrides) {
+    match ConfigRaw::load(cwd, overrides) {
+        Ok(Some((_, _, raw))) => {
+            if let Some(network) = raw.network {
+                util::proxy::configure(network.proxy.as_deref(), network.no_proxy.as_deref());
+            }
+        }
+        Ok(None) => (),
+        Err(err) => log::info!("couldn't check config for `{}` settings: {:?}", KEY, err),
+    }
+}
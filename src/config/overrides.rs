@@ -0,0 +1,254 @@
+use once_cell_regex::exports::once_cell::sync::Lazy;
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+};
+
+/// Keys starting with this are applied to `package.metadata` in `Cargo.toml`
+/// rather than to the config file.
+pub static METADATA_PREFIX: &str = "metadata";
+
+/// Builds run by Gradle and Xcode call back into us without any of our flags,
+/// so the overrides they need are passed down through the environment.
+pub static PROFILE_ENV_VAR: &str = "CARGO_MOBILE_CONFIG_PROFILE";
+/// One `--set` per line.
+pub static SETS_ENV_VAR: &str = "CARGO_MOBILE_SET";
+pub static GEN_DIR_ENV_VAR: &str = "CARGO_MOBILE_PROJECT_DIR";
+
+static FORWARDED: Lazy<Mutex<Vec<(&'static str, String)>>> = Lazy::new(Default::default);
+
+#[derive(Debug)]
+pub enum SetInvalid {
+    ValueMissing(String),
+    KeyEmpty(String),
+    TypeUnknown { set: String, ty: String },
+    ValueInvalid { set: String, ty: &'static str },
+}
+
+impl Display for SetInvalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ValueMissing(s) => write!(f, "{:?} isn't of the form `key=value`", s),
+            Self::KeyEmpty(s) => write!(f, "{:?} has an empty key component", s),
+            Self::TypeUnknown { set, ty } => write!(
+                f,
+                "{:?} has an unknown type {:?}; valid types are `str`, `int`, `float`, and `bool`",
+                set, ty
+            ),
+            Self::ValueInvalid { set, ty } => write!(f, "{:?} doesn't have a valid `{}`", set, ty),
+        }
+    }
+}
+
+/// A single `key=value` override, where `key` is a dotted path like
+/// `apple.development-team`.
+///
+/// Values are strings unless they're quoted (`"..."`), booleans, arrays or
+/// tables (`[...]`, `{...}`), or the key says what type they are (i.e.
+/// `android.min-sdk-version:int=24`), since things like versions and team IDs
+/// only look like numbers. Bare values that replace a number or boolean in
+/// the config take on its type.
+#[derive(Clone, Debug)]
+pub struct Set {
+    path: Vec<String>,
+    value: toml::Value,
+    bare: bool,
+}
+
+impl Display for Set {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            toml::Value::String(value) if self.bare => write!(f, "{}={}", self.key(), value),
+            toml::Value::Integer(value) => write!(f, "{}:int={}", self.key(), value),
+            toml::Value::Float(value) => write!(f, "{}:float={}", self.key(), value),
+            value => write!(f, "{}={}", self.key(), value),
+        }
+    }
+}
+
+fn parse_toml(value: &str) -> Option<toml::Value> {
+    toml::from_str::<toml::value::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+}
+
+impl FromStr for Set {
+    type Err = SetInvalid;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        let key = parts.next().unwrap_or_default();
+        let value = parts
+            .next()
+            .ok_or_else(|| SetInvalid::ValueMissing(s.to_owned()))?;
+        let mut key_parts = key.splitn(2, ':');
+        let key = key_parts.next().unwrap_or_default();
+        let ty = key_parts.next().map(str::trim);
+        let path = key
+            .split('.')
+            .map(|component| {
+                let component = component.trim();
+                if component.is_empty() {
+                    Err(SetInvalid::KeyEmpty(s.to_owned()))
+                } else {
+                    Ok(component.to_owned())
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let invalid = |ty| SetInvalid::ValueInvalid {
+            set: s.to_owned(),
+            ty,
+        };
+        let (value, bare) = match ty {
+            Some("str") => (toml::Value::String(value.to_owned()), false),
+            Some("int") => (
+                value
+                    .trim()
+                    .parse()
+                    .map(toml::Value::Integer)
+                    .map_err(|_| invalid("int"))?,
+                false,
+            ),
+            Some("float") => (
+                value
+                    .trim()
+                    .parse()
+                    .map(toml::Value::Float)
+                    .map_err(|_| invalid("float"))?,
+                false,
+            ),
+            Some("bool") => (
+                value
+                    .trim()
+                    .parse()
+                    .map(toml::Value::Boolean)
+                    .map_err(|_| invalid("bool"))?,
+                false,
+            ),
+            Some(ty) => {
+                return Err(SetInvalid::TypeUnknown {
+                    set: s.to_owned(),
+                    ty: ty.to_owned(),
+                })
+            }
+            None => match value.trim() {
+                "true" => (toml::Value::Boolean(true), false),
+                "false" => (toml::Value::Boolean(false), false),
+                trimmed if trimmed.starts_with(&['"', '\'', '[', '{'][..]) => (
+                    parse_toml(trimmed).ok_or_else(|| invalid("TOML value"))?,
+                    false,
+                ),
+                _ => (toml::Value::String(value.to_owned()), true),
+            },
+        };
+        Ok(Self { path, value, bare })
+    }
+}
+
+impl Set {
+    pub fn new(path: Vec<String>, value: toml::Value) -> Self {
+        assert!(!path.is_empty(), "developer error: override path was empty");
+        Self {
+            path,
+            value,
+            bare: false,
+        }
+    }
+
+    pub fn key(&self) -> String {
+        self.path.join(".")
+    }
+
+    fn is_metadata(&self) -> bool {
+        self.path.first().map(String::as_str) == Some(METADATA_PREFIX)
+    }
+
+    fn apply(&self, table: &mut toml::value::Table, path: &[String]) {
+        log::info!("overriding `{}` with {}", self.key(), self.value);
+        let (last, parents) = path
+            .split_last()
+            .expect("developer error: override path was empty");
+        let mut table = table;
+        for component in parents {
+            let entry = table
+                .entry(component.clone())
+                .or_insert_with(|| toml::Value::Table(Default::default()));
+            if !entry.is_table() {
+                *entry = toml::Value::Table(Default::default());
+            }
+            table = entry.as_table_mut().unwrap();
+        }
+        let value = match (&self.value, table.get(last)) {
+            (toml::Value::String(value), Some(existing)) if self.bare => {
+                let value = value.trim();
+                match existing {
+                    toml::Value::Integer(_) => value.parse().ok().map(toml::Value::Integer),
+                    toml::Value::Float(_) => value.parse().ok().map(toml::Value::Float),
+                    toml::Value::Boolean(_) => value.parse().ok().map(toml::Value::Boolean),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        table.insert(last.clone(), value.unwrap_or_else(|| self.value.clone()));
+    }
+}
+
+/// Per-invocation adjustments applied on top of the config file.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    /// Which of the config's `[apps.<name>]` sections to use.
+    pub app: Option<String>,
+    pub gen_dir: Option<PathBuf>,
+    pub out_dir: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub sets: Vec<Set>,
+    pub template_pack: Option<String>,
+    /// A TOML file of template var values, used instead of prompting.
+    pub template_answers: Option<PathBuf>,
+}
+
+impl Overrides {
+    /// Makes builds run by Gradle or Xcode use these overrides too.
+    pub fn forward(&self) {
+        let mut vars = Vec::new();
+        if let Some(gen_dir) = &self.gen_dir {
+            vars.push((GEN_DIR_ENV_VAR, gen_dir.display().to_string()));
+        }
+        if let Some(profile) = &self.profile {
+            vars.push((PROFILE_ENV_VAR, profile.clone()));
+        }
+        if !self.sets.is_empty() {
+            vars.push((
+                SETS_ENV_VAR,
+                self.sets
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ));
+        }
+        *FORWARDED.lock().unwrap() = vars;
+    }
+
+    /// The environment variables that `forward` passes down.
+    pub fn forwarded() -> Vec<(&'static str, String)> {
+        FORWARDED.lock().unwrap().clone()
+    }
+
+    pub(super) fn apply_to_config(&self, table: &mut toml::value::Table) {
+        for set in self.sets.iter().filter(|set| !set.is_metadata()) {
+            set.apply(table, &set.path);
+        }
+    }
+
+    pub(super) fn apply_to_metadata(&self, table: &mut toml::value::Table) {
+        for set in self.sets.iter().filter(|set| set.is_metadata()) {
+            if set.path.len() > 1 {
+                set.apply(table, &set.path[1..]);
+            }
+        }
+    }
+}
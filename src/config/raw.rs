@@ -1,4 +1,4 @@
-use super::app;
+use super::{app, firebase, network, retry, sentry, template, Overrides};
 #[cfg(target_os = "macos")]
 use crate::apple;
 use crate::{
@@ -67,6 +67,30 @@ pub enum LoadError {
         path: PathBuf,
         cause: toml::de::Error,
     },
+    ProfileNotFound {
+        path: PathBuf,
+        profile: String,
+    },
+    ProfileInvalid {
+        path: PathBuf,
+        profile: String,
+    },
+    ProfileCycle {
+        path: PathBuf,
+        profile: String,
+    },
+    AppNotSelected {
+        path: PathBuf,
+        apps: Vec<String>,
+    },
+    AppNotFound {
+        path: PathBuf,
+        app: String,
+    },
+    AppInvalid {
+        path: PathBuf,
+        app: String,
+    },
 }
 
 impl Display for LoadError {
@@ -80,9 +104,258 @@ impl Display for LoadError {
             Self::ReadFailed { path, cause } => {
                 write!(f, "Failed to read config file at {:?}: {}", path, cause)
             }
-            Self::ParseFailed { path, cause } => {
-                write!(f, "Failed to parse config file at {:?}: {}", path, cause)
+            Self::ParseFailed { path, cause } => write!(
+                f,
+                "Failed to parse config file {}",
+                super::toml_error_location(path, cause)
+            ),
+            Self::ProfileNotFound { path, profile } => write!(
+                f,
+                "Config file at {:?} has no `[{}.{}]` section.",
+                path, PROFILE_KEY, profile
+            ),
+            Self::ProfileInvalid { path, profile } => write!(
+                f,
+                "`{}.{}` in config file at {:?} isn't a table, or has a non-string `inherits`.",
+                PROFILE_KEY, profile, path
+            ),
+            Self::ProfileCycle { path, profile } => write!(
+                f,
+                "`{}.{}` in config file at {:?} inherits from itself.",
+                PROFILE_KEY, profile, path
+            ),
+            Self::AppNotSelected { path, apps } => write!(
+                f,
+                "Config file at {:?} has more than one app, so one needs to be picked with `--app`; the choices are: {}",
+                path,
+                apps.join(", ")
+            ),
+            Self::AppNotFound { path, app } => write!(
+                f,
+                "Config file at {:?} has no `[{}.{}]` section.",
+                path, APPS_KEY, app
+            ),
+            Self::AppInvalid { path, app } => write!(
+                f,
+                "`{}.{}` in config file at {:?} isn't a table.",
+                APPS_KEY, app, path
+            ),
+        }
+    }
+}
+
+pub(super) static PROFILE_KEY: &str = "profile";
+// The config can live in `[package.metadata.mobile]` instead of its own file.
+pub(super) static CARGO_METADATA_PATH: &[&str] = &["package", "metadata", "mobile"];
+static INHERITS_KEY: &str = "inherits";
+pub(super) static APPS_KEY: &str = "apps";
+
+// Tables are merged recursively, while anything else in `overlay` replaces
+// what's in `base` wholesale.
+fn merge(base: &mut toml::value::Table, overlay: toml::value::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+// Resolves `profile` along with everything it (transitively) inherits from,
+// with later profiles in the chain taking precedence.
+fn resolve_profile(
+    path: &Path,
+    profiles: &toml::value::Table,
+    profile: &str,
+) -> Result<toml::value::Table, LoadError> {
+    let mut chain = Vec::new();
+    let mut next = Some(profile.to_owned());
+    while let Some(name) = next {
+        if chain.iter().any(|(prev, _)| prev == &name) {
+            return Err(LoadError::ProfileCycle {
+                path: path.to_owned(),
+                profile: name,
+            });
+        }
+        let mut table = match profiles.get(&name) {
+            Some(toml::Value::Table(table)) => table.clone(),
+            Some(_) => {
+                return Err(LoadError::ProfileInvalid {
+                    path: path.to_owned(),
+                    profile: name,
+                })
+            }
+            None => {
+                return Err(LoadError::ProfileNotFound {
+                    path: path.to_owned(),
+                    profile: name,
+                })
+            }
+        };
+        next = match table.remove(INHERITS_KEY) {
+            Some(toml::Value::String(parent)) => Some(parent),
+            Some(_) => {
+                return Err(LoadError::ProfileInvalid {
+                    path: path.to_owned(),
+                    profile: name,
+                })
+            }
+            None => None,
+        };
+        chain.push((name, table));
+    }
+    let mut resolved = toml::value::Table::new();
+    for (_, table) in chain.into_iter().rev() {
+        merge(&mut resolved, table);
+    }
+    Ok(resolved)
+}
+
+// Picks the app out of `[apps.<name>]`, which is layered over the rest of the
+// config just like a profile is. Each app gets its own gen dir by default, so
+// that their projects don't clobber each other.
+fn resolve_app(
+    path: &Path,
+    apps: toml::value::Table,
+    app: Option<&str>,
+) -> Result<(String, toml::value::Table), LoadError> {
+    let name = match app {
+        Some(app) => app.to_owned(),
+        None if apps.len() == 1 => {
+            let name = apps.keys().next().unwrap().clone();
+            log::info!("only one app in config; using {:?}", name);
+            name
+        }
+        None => {
+            return Err(LoadError::AppNotSelected {
+                path: path.to_owned(),
+                apps: apps.keys().cloned().collect(),
+            })
+        }
+    };
+    let mut table = match apps.get(&name) {
+        Some(toml::Value::Table(table)) => table.clone(),
+        Some(_) => {
+            return Err(LoadError::AppInvalid {
+                path: path.to_owned(),
+                app: name,
+            })
+        }
+        None => {
+            return Err(LoadError::AppNotFound {
+                path: path.to_owned(),
+                app: name,
+            })
+        }
+    };
+    let app_table = table
+        .entry(super::app::KEY)
+        .or_insert_with(|| toml::Value::Table(Default::default()));
+    if let toml::Value::Table(app_table) = app_table {
+        if !app_table.contains_key("gen-dir") {
+            let gen_dir = Path::new(super::app::DEFAULT_GEN_DIR).join(&name);
+            log::info!(
+                "`{}.{}.{}.gen-dir` not set; defaulting to {:?}",
+                APPS_KEY,
+                name,
+                super::app::KEY,
+                gen_dir
+            );
+            app_table.insert(
+                "gen-dir".to_owned(),
+                toml::Value::String(gen_dir.display().to_string()),
+            );
+        }
+    }
+    Ok((name, table))
+}
+
+/// Where the config was found. If both are present, `mobile.toml` wins.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Source {
+    File,
+    CargoMetadata,
+}
+
+impl Source {
+    pub fn path(self, root_dir: &Path) -> PathBuf {
+        match self {
+            Self::File => root_dir.join(super::file_name()),
+            Self::CargoMetadata => root_dir.join("Cargo.toml"),
+        }
+    }
+
+    /// Picks the config out of a parsed file, which for `Cargo.toml` means
+    /// digging it out of the package metadata.
+    pub fn extract(self, table: toml::value::Table) -> Option<toml::value::Table> {
+        match self {
+            Self::File => Some(table),
+            Self::CargoMetadata => {
+                let mut table = table;
+                for key in CARGO_METADATA_PATH {
+                    table = match table.remove(*key) {
+                        Some(toml::Value::Table(table)) => table,
+                        _ => return None,
+                    };
+                }
+                Some(table)
+            }
+        }
+    }
+
+    /// Type errors from deserializing the layered `Value` have no position or
+    /// key, so this deserializes the file directly to find them, which gets
+    /// both from `toml`. Profiles and apps are skipped over, since they're
+    /// only checked once they've been layered.
+    fn spanned_error(self, bytes: &[u8]) -> Option<toml::de::Error> {
+        #[derive(Deserialize)]
+        struct Metadata {
+            #[allow(dead_code)]
+            mobile: Raw,
+        }
+
+        #[derive(Deserialize)]
+        struct Package {
+            #[allow(dead_code)]
+            metadata: Metadata,
+        }
+
+        #[derive(Deserialize)]
+        struct CargoToml {
+            #[allow(dead_code)]
+            package: Package,
+        }
+
+        match self {
+            Self::File => toml::from_slice::<Raw>(bytes).err(),
+            Self::CargoMetadata => toml::from_slice::<CargoToml>(bytes).err(),
+        }
+    }
+}
+
+fn cargo_metadata_present(path: &Path) -> bool {
+    fs::read(path)
+        .ok()
+        .and_then(|bytes| toml::from_slice::<toml::value::Table>(&bytes).ok())
+        .and_then(|table| Source::CargoMetadata.extract(table))
+        .is_some()
+}
+
+#[derive(Debug)]
+pub enum OverrideError {
+    SerializeFailed(toml::ser::Error),
+    DeserializeFailed(toml::de::Error),
+}
+
+impl Display for OverrideError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SerializeFailed(err) => {
+                write!(f, "Failed to serialize config for overriding: {}", err)
             }
+            Self::DeserializeFailed(err) => write!(f, "Overridden config was invalid: {}", err),
         }
     }
 }
@@ -102,77 +375,191 @@ impl Reportable for WriteError {
     }
 }
 
+/// Hosts that can't build for Apple platforms don't load the Apple config, but
+/// still need to know where its project is to clean it up.
+#[cfg(not(target_os = "macos"))]
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AppleProjectDirRaw {
+    pub project_dir: Option<String>,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Raw {
     pub app: app::Raw,
     #[cfg(target_os = "macos")]
     pub apple: Option<apple::config::Raw>,
+    #[cfg(not(target_os = "macos"))]
+    #[serde(default, skip_serializing)]
+    pub apple: Option<AppleProjectDirRaw>,
     pub android: Option<android::config::Raw>,
+    pub template: Option<template::Raw>,
+    pub network: Option<network::Raw>,
+    pub retry: Option<retry::Raw>,
+    pub sentry: Option<sentry::Raw>,
+    pub firebase: Option<firebase::Raw>,
+    /// Which of `[apps.<name>]` was layered over the config, if any.
+    #[serde(skip)]
+    pub selected_app: Option<String>,
 }
 
 impl Raw {
-    pub fn prompt(wrapper: &TextWrapper) -> Result<Self, PromptError> {
-        let app = app::Raw::prompt(wrapper).map_err(PromptError::AppFailed)?;
+    pub fn prompt(wrapper: &TextWrapper, template_pack: Option<&str>) -> Result<Self, PromptError> {
+        let app = app::Raw::prompt(wrapper, template_pack).map_err(PromptError::AppFailed)?;
         #[cfg(target_os = "macos")]
         let apple = apple::config::Raw::prompt(wrapper).map_err(PromptError::AppleFailed)?;
         Ok(Self {
             app,
             #[cfg(target_os = "macos")]
             apple: Some(apple),
+            #[cfg(not(target_os = "macos"))]
+            apple: None,
             android: None,
+            template: None,
+            network: None,
+            retry: None,
+            sentry: None,
+            firebase: None,
+            selected_app: None,
         })
     }
 
-    pub fn detect() -> Result<Self, DetectError> {
-        let app = app::Raw::detect().map_err(DetectError::AppFailed)?;
+    pub fn detect(template_pack: Option<&str>) -> Result<Self, DetectError> {
+        let app = app::Raw::detect(template_pack).map_err(DetectError::AppFailed)?;
         #[cfg(target_os = "macos")]
         let apple = apple::config::Raw::detect().map_err(DetectError::AppleFailed)?;
         Ok(Self {
             app,
             #[cfg(target_os = "macos")]
             apple: Some(apple),
+            #[cfg(not(target_os = "macos"))]
+            apple: None,
             android: None,
+            template: None,
+            network: None,
+            retry: None,
+            sentry: None,
+            firebase: None,
+            selected_app: None,
         })
     }
 
-    pub fn discover_root(cwd: impl AsRef<Path>) -> io::Result<Option<PathBuf>> {
-        let file_name = super::file_name();
-        let mut path = cwd.as_ref().canonicalize()?.join(&file_name);
-        log::info!("looking for config file at {:?}", path);
-        while !path.exists() {
-            if let Some(parent) = path.parent().and_then(Path::parent) {
-                path = parent.join(&file_name);
-                log::info!("looking for config file at {:?}", path);
-            } else {
-                log::info!("no config file was ever found");
-                return Ok(None);
+    pub fn discover(cwd: impl AsRef<Path>) -> io::Result<Option<(PathBuf, Source)>> {
+        let mut dir = Some(cwd.as_ref().canonicalize()?);
+        while let Some(current) = dir {
+            let file_path = Source::File.path(&current);
+            let cargo_path = Source::CargoMetadata.path(&current);
+            log::info!("looking for config file at {:?}", file_path);
+            if file_path.exists() {
+                log::info!("found config file at {:?}", file_path);
+                if cargo_metadata_present(&cargo_path) {
+                    log::warn!(
+                        "config found in both {:?} and {:?}; the latter will be ignored",
+                        file_path,
+                        cargo_path
+                    );
+                }
+                return Ok(Some((current, Source::File)));
+            }
+            log::info!("looking for config in metadata at {:?}", cargo_path);
+            if cargo_metadata_present(&cargo_path) {
+                log::info!("found config in metadata at {:?}", cargo_path);
+                return Ok(Some((current, Source::CargoMetadata)));
             }
+            dir = current.parent().map(Path::to_owned);
         }
-        log::info!("found config file at {:?}", path);
-        path.pop();
-        Ok(Some(path))
+        log::info!("no config file was ever found");
+        Ok(None)
     }
 
-    pub fn load(cwd: impl AsRef<Path>) -> Result<Option<(PathBuf, Self)>, LoadError> {
-        Self::discover_root(cwd)
+    pub fn discover_root(cwd: impl AsRef<Path>) -> io::Result<Option<PathBuf>> {
+        Self::discover(cwd).map(|found| found.map(|(root_dir, _)| root_dir))
+    }
+
+    pub fn load(
+        cwd: impl AsRef<Path>,
+        overrides: &Overrides,
+    ) -> Result<Option<(PathBuf, Source, Self)>, LoadError> {
+        Self::discover(cwd)
             .map_err(LoadError::DiscoverFailed)?
-            .map(|root_dir| {
-                let path = root_dir.join(super::file_name());
+            .map(|(root_dir, source)| {
+                let path = source.path(&root_dir);
                 let bytes = fs::read(&path).map_err(|cause| LoadError::ReadFailed {
                     path: path.clone(),
                     cause,
                 })?;
-                toml::from_slice::<Self>(&bytes)
-                    .map(|raw| (root_dir, raw))
-                    .map_err(|cause| LoadError::ParseFailed {
+                let table = toml::from_slice::<toml::value::Table>(&bytes).map_err(|cause| {
+                    LoadError::ParseFailed {
                         path: path.clone(),
                         cause,
+                    }
+                })?;
+                let mut table = source.extract(table).unwrap_or_default();
+                let profiles = match table.remove(PROFILE_KEY) {
+                    Some(toml::Value::Table(profiles)) => profiles,
+                    _ => Default::default(),
+                };
+                let selected_app = match table.remove(APPS_KEY) {
+                    Some(toml::Value::Table(apps)) if !apps.is_empty() => {
+                        let (name, app) = resolve_app(&path, apps, overrides.app.as_deref())?;
+                        log::info!("applying config for app {:?}", name);
+                        merge(&mut table, app);
+                        Some(name)
+                    }
+                    _ => {
+                        if let Some(app) = &overrides.app {
+                            log::warn!(
+                                "`--app {}` was passed, but the config has no `[{}]` sections",
+                                app,
+                                APPS_KEY
+                            );
+                        }
+                        None
+                    }
+                };
+                if let Some(profile) = &overrides.profile {
+                    log::info!("applying config profile {:?}", profile);
+                    merge(&mut table, resolve_profile(&path, &profiles, profile)?);
+                }
+                overrides.apply_to_config(&mut table);
+                toml::Value::Table(table)
+                    .try_into::<Self>()
+                    .map(|raw| {
+                        (
+                            root_dir,
+                            source,
+                            Self {
+                                selected_app,
+                                ..raw
+                            },
+                        )
+                    })
+                    .map_err(|cause| LoadError::ParseFailed {
+                        path: path.clone(),
+                        // Deserializing from a `Value` loses track of where
+                        // everything was, so if the file is broken on its
+                        // own, we'd rather report that error.
+                        cause: source.spanned_error(&bytes).unwrap_or(cause),
                     })
             })
             .transpose()
     }
 
+    pub fn overridden(&self, overrides: &Overrides) -> Result<Self, OverrideError> {
+        if overrides.sets.is_empty() {
+            return Ok(self.clone());
+        }
+        let mut table = match toml::Value::try_from(self).map_err(OverrideError::SerializeFailed)? {
+            toml::Value::Table(table) => table,
+            _ => unreachable!("developer error: config didn't serialize to a table"),
+        };
+        overrides.apply_to_config(&mut table);
+        toml::Value::Table(table)
+            .try_into()
+            .map_err(OverrideError::DeserializeFailed)
+    }
+
     pub fn write(&self, root_dir: &Path) -> Result<(), WriteError> {
         let bytes = toml::to_vec(self).map_err(WriteError::SerializeFailed)?;
         let path = root_dir.join(super::file_name());
@@ -180,3 +567,37 @@ impl Raw {
         fs::write(path, bytes).map_err(WriteError::WriteFailed)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_errors_have_key_and_position() {
+        let dir =
+            std::env::temp_dir().join(format!("cargo-mobile-config-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(super::super::file_name()),
+            r#"[app]
+name = "example"
+domain = "example.com"
+template-pack = "bevy"
+
+[android]
+min-sdk-version = "twenty-four"
+"#,
+        )
+        .unwrap();
+        let result = Raw::load(&dir, &Default::default());
+        fs::remove_dir_all(&dir).unwrap();
+        match result {
+            Err(LoadError::ParseFailed { cause, .. }) => {
+                assert_eq!(cause.line_col().map(|(line, _)| line), Some(6));
+                let msg = cause.to_string();
+                assert!(msg.contains("android.min-sdk-version"), "{}", msg);
+            }
+            other => panic!("expected a parse error, but got {:?}", other),
+        }
+    }
+}
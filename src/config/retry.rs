@@ -0,0 +1,49 @@
+use super::{raw::Raw as ConfigRaw, Overrides};
+use crate::util::retry;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+
+pub static KEY: &str = "retry";
+
+/// How hard to try at device operations that are known to be flaky, i.e.
+/// `[retry]` with `attempts = 5`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Raw {
+    pub attempts: Option<u32>,
+    /// How long to wait before the first retry, which doubles each time.
+    pub backoff_secs: Option<u64>,
+}
+
+/// Applies the retry policy from the config. Like the network settings, this
+/// happens before the config is properly loaded, so failing to load it is left
+/// for later.
+pub fn configure(cwd: impl AsRef<Path>, overrides: &Overrides) {
+    match ConfigRaw::load(cwd, overrides) {
+        Ok(Some((_, _, raw))) => {
+            let raw = raw.retry.unwrap_or_default();
+            let attempts = raw.attempts.unwrap_or_else(|| {
+                log::info!(
+                    "`{}.attempts` not set; defaulting to {}",
+                    KEY,
+                    retry::DEFAULT_ATTEMPTS
+                );
+                retry::DEFAULT_ATTEMPTS
+            });
+            let backoff = raw
+                .backoff_secs
+                .map(Duration::from_secs)
+                .unwrap_or_else(|| {
+                    log::info!(
+                        "`{}.backoff-secs` not set; defaulting to {}",
+                        KEY,
+                        retry::DEFAULT_BACKOFF.as_secs()
+                    );
+                    retry::DEFAULT_BACKOFF
+                });
+            retry::configure(attempts, backoff);
+        }
+        Ok(None) => (),
+        Err(err) => log::info!("couldn't check config for `{}` settings: {:?}", KEY, err),
+    }
+}
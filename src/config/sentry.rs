@@ -0,0 +1,97 @@
+use crate::util::{
+    self,
+    cli::{Report, Reportable},
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub static KEY: &str = "sentry";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Raw {
+    pub org: String,
+    pub project: String,
+    pub url: Option<String>,
+    pub include_sources: Option<bool>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    SentryCliMissing,
+    SentryCliPresentFailed(bossy::Error),
+    UploadFailed(bossy::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::SentryCliMissing => Report::action_request(
+                format!(
+                    "`{}` is configured, but `sentry-cli` isn't installed; you can install it by running this:",
+                    KEY
+                ),
+                "curl -sL https://sentry.io/get-cli/ | sh",
+            ),
+            Self::SentryCliPresentFailed(err) => {
+                Report::error("Failed to check if `sentry-cli` is installed", err)
+            }
+            Self::UploadFailed(err) => Report::error(
+                "Failed to upload debug symbols to Sentry (is `SENTRY_AUTH_TOKEN` set?)",
+                err,
+            ),
+        }
+    }
+}
+
+/// Uploads debug symbols to Sentry after archiving on Apple, and after
+/// release builds on Android. `sentry-cli` picks up `SENTRY_AUTH_TOKEN` (or
+/// `~/.sentryclirc`) on its own, so the token never needs to be in the
+/// config.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Sentry {
+    org: String,
+    project: String,
+    url: Option<String>,
+    include_sources: bool,
+}
+
+impl Sentry {
+    pub fn from_raw(raw: Raw) -> Self {
+        Self {
+            org: raw.org,
+            project: raw.project,
+            url: raw.url,
+            include_sources: raw.include_sources.unwrap_or_default(),
+        }
+    }
+
+    /// Uploads everything at `paths`, which can be dSYM bundles, unstripped
+    /// libraries, or directories containing either.
+    pub fn upload(&self, paths: &[PathBuf]) -> Result<(), Error> {
+        if !util::command_present("sentry-cli").map_err(Error::SentryCliPresentFailed)? {
+            return Err(Error::SentryCliMissing);
+        }
+        let command = bossy::Command::impure("sentry-cli");
+        let command = if let Some(url) = &self.url {
+            command.with_args(&["--url", url.as_str()])
+        } else {
+            command
+        };
+        let command = command
+            .with_args(&["debug-files", "upload"])
+            .with_args(&["--org", self.org.as_str()])
+            .with_args(&["--project", self.project.as_str()]);
+        let command = if self.include_sources {
+            command.with_arg("--include-sources")
+        } else {
+            command
+        };
+        command
+            .with_args(paths)
+            .run_and_wait()
+            .map_err(Error::UploadFailed)?;
+        Ok(())
+    }
+}
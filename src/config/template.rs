@@ -0,0 +1,256 @@
+use super::raw::{Raw as ConfigRaw, Source, CARGO_METADATA_PATH};
+use crate::{
+    opts::NonInteractive,
+    templating::{VarSpec, VarType},
+    util::{
+        cli::{Report, Reportable},
+        prompt,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub static KEY: &str = "template";
+
+#[derive(Debug)]
+pub enum VarsError {
+    AnswersReadFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    AnswersParseFailed {
+        path: PathBuf,
+        cause: toml::de::Error,
+    },
+    VarMissing {
+        name: String,
+    },
+    VarInvalid {
+        name: String,
+        source: String,
+        ty: VarType,
+        value: toml::Value,
+    },
+    PromptFailed(io::Error),
+    DiscoverFailed(io::Error),
+    ReadFailed(io::Error),
+    InlineVars,
+    WriteFailed(io::Error),
+}
+
+impl Reportable for VarsError {
+    fn report(&self) -> Report {
+        match self {
+            Self::AnswersReadFailed { path, cause } => Report::error(
+                format!("Failed to read template answers from {:?}", path),
+                cause,
+            ),
+            Self::AnswersParseFailed { path, cause } => Report::error(
+                format!("Failed to parse template answers from {:?}", path),
+                cause,
+            ),
+            Self::VarMissing { name } => Report::action_request(
+                format!("Please set `{}.vars.{}` in your config (or pass it in an `--answers` file) and try again.", KEY, name),
+                format!("The template pack requires `{}`, which has no default, and we can't prompt for it in non-interactive mode.", name),
+            ),
+            Self::VarInvalid { name, source, ty, value } => Report::error(
+                format!("Template variable `{}` is invalid", name),
+                format!("The template pack expects a {}, but {} has {}", ty, source, value),
+            ),
+            Self::PromptFailed(err) => Report::error("Failed to prompt for template variable", err),
+            Self::DiscoverFailed(err) => Report::error("Failed to find config file to save template variables to", err),
+            Self::ReadFailed(err) => Report::error("Failed to read config file to save template variables to", err),
+            Self::InlineVars => Report::action_request(
+                format!("Please move `{}.vars` into a `[{}.vars]` section, and then try again.", KEY, KEY),
+                "Template variables are specified inline in your config, so we can't add new ones to it.",
+            ),
+            Self::WriteFailed(err) => Report::error("Failed to save template variables to config file", err),
+        }
+    }
+}
+
+fn format_key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        key.to_owned()
+    } else {
+        toml::Value::String(key.to_owned()).to_string()
+    }
+}
+
+// This edits the file as text, since round-tripping it through `toml` would
+// throw away comments and formatting.
+fn save_vars(root_dir: &Path, vars: &[(String, toml::Value)]) -> Result<(), VarsError> {
+    let (root_dir, source) = ConfigRaw::discover(root_dir)
+        .map_err(VarsError::DiscoverFailed)?
+        .expect("developer error: config file vanished");
+    let path = source.path(&root_dir);
+    let contents = fs::read_to_string(&path).map_err(VarsError::ReadFailed)?;
+    let mut header = match source {
+        Source::File => Vec::new(),
+        Source::CargoMetadata => CARGO_METADATA_PATH.to_vec(),
+    };
+    header.extend(&[KEY, "vars"]);
+    let header = format!("[{}]", header.join("."));
+    let lines = vars
+        .iter()
+        .map(|(key, value)| format!("{} = {}\n", format_key(key), value))
+        .collect::<String>();
+    let contents = if let Some(index) = contents.lines().position(|line| line.trim() == header) {
+        let mut out = contents
+            .lines()
+            .take(index + 1)
+            .map(|line| format!("{}\n", line))
+            .collect::<String>();
+        out.push_str(&lines);
+        for line in contents.lines().skip(index + 1) {
+            out.push_str(line);
+            out.push('\n');
+        }
+        out
+    } else {
+        let has_inline_vars = toml::from_str::<toml::value::Table>(&contents)
+            .ok()
+            .and_then(|table| source.extract(table))
+            .and_then(|mut table| table.remove(KEY))
+            .and_then(|template| template.get("vars").cloned())
+            .is_some();
+        if has_inline_vars {
+            return Err(VarsError::InlineVars);
+        }
+        let separator = if contents.ends_with('\n') {
+            "\n"
+        } else {
+            "\n\n"
+        };
+        format!("{}{}{}\n{}", contents, separator, header, lines)
+    };
+    log::info!("saving template vars to {:?}", path);
+    fs::write(&path, contents).map_err(VarsError::WriteFailed)
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Raw {
+    pub vars: Option<toml::value::Table>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Template {
+    vars: toml::value::Table,
+}
+
+impl Template {
+    pub fn from_raw(raw: Option<Raw>) -> Self {
+        let vars = raw.and_then(|raw| raw.vars).unwrap_or_default();
+        log::info!(
+            "{} user-defined template var(s) found in `{}.vars`",
+            vars.len(),
+            KEY
+        );
+        Self { vars }
+    }
+
+    pub fn vars(&self) -> &toml::value::Table {
+        &self.vars
+    }
+
+    /// Fills in any vars declared by the template pack that aren't set yet,
+    /// taking them from `answers` (a TOML file of `name = value` pairs) if
+    /// given, and otherwise using defaults in non-interactive mode and
+    /// prompting in interactive mode. Every var is converted to the type the
+    /// pack declares. If `save` is set, new values are written back to the
+    /// config file.
+    pub fn fill_vars(
+        &mut self,
+        specs: &[&VarSpec],
+        root_dir: &Path,
+        non_interactive: NonInteractive,
+        answers: Option<&Path>,
+        save: bool,
+    ) -> Result<(), VarsError> {
+        let answers = answers.map(load_answers).transpose()?;
+        let mut new = Vec::new();
+        for spec in specs {
+            let name = spec.name();
+            let coerce = |value: &toml::Value, source: String| {
+                spec.ty()
+                    .coerce(value)
+                    .ok_or_else(|| VarsError::VarInvalid {
+                        name: name.to_owned(),
+                        source,
+                        ty: spec.ty(),
+                        value: value.clone(),
+                    })
+            };
+            if let Some(value) = self.vars.get(name) {
+                let value = coerce(value, format!("`{}.vars.{}`", KEY, name))?;
+                self.vars.insert(name.to_owned(), value);
+                continue;
+            }
+            let value = if let Some((path, value)) = answers
+                .as_ref()
+                .and_then(|(path, answers)| answers.get(name).map(|value| (path, value)))
+            {
+                coerce(value, format!("the answer in {:?}", path))?
+            } else if non_interactive.yes() {
+                let default = spec.default().ok_or_else(|| VarsError::VarMissing {
+                    name: name.to_owned(),
+                })?;
+                coerce(default, "the template pack's default".to_owned())?
+            } else {
+                prompt_var(spec)?
+            };
+            log::info!("template var {:?} set to {}", name, value);
+            self.vars.insert(name.to_owned(), value.clone());
+            new.push((name.to_owned(), value));
+        }
+        if save && !new.is_empty() {
+            save_vars(root_dir, &new)?;
+        }
+        Ok(())
+    }
+}
+
+fn load_answers(path: &Path) -> Result<(PathBuf, toml::value::Table), VarsError> {
+    let contents = fs::read_to_string(path).map_err(|cause| VarsError::AnswersReadFailed {
+        path: path.to_owned(),
+        cause,
+    })?;
+    let answers = toml::from_str(&contents).map_err(|cause| VarsError::AnswersParseFailed {
+        path: path.to_owned(),
+        cause,
+    })?;
+    Ok((path.to_owned(), answers))
+}
+
+// Asks until we get something that converts to the var's type.
+fn prompt_var(spec: &VarSpec) -> Result<toml::Value, VarsError> {
+    if let Some(description) = spec.description() {
+        println!("{}", description);
+    }
+    // Strings are shown without quotes, since they're typed in without them.
+    let default = spec.default().map(|default| match default {
+        toml::Value::String(default) => default.clone(),
+        default => default.to_string(),
+    });
+    let msg = match spec.ty() {
+        VarType::String => spec.name().to_owned(),
+        ty => format!("{} ({})", spec.name(), ty),
+    };
+    loop {
+        let response =
+            prompt::default(&msg, default.as_deref(), None).map_err(VarsError::PromptFailed)?;
+        match spec.ty().coerce(&toml::Value::String(response)) {
+            Some(value) => return Ok(value),
+            None => println!("That isn't a valid {}; please try again.", spec.ty()),
+        }
+    }
+}
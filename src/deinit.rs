@@ -0,0 +1,195 @@
+use crate::{
+    config::{self, Config, LoadOrGenError, Overrides, Source},
+    opts,
+    templating::Lockfile,
+    util::{
+        cli::{self, Report, Reportable, TextWrapper},
+        prompt,
+    },
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    DiscoverFailed(io::Error),
+    NoConfig,
+    ConfirmationMissing,
+    ConfigFailed(LoadOrGenError),
+    PromptFailed(io::Error),
+    RemoveFailed { path: PathBuf, cause: io::Error },
+    GitignoreReadFailed { path: PathBuf, cause: io::Error },
+    GitignoreWriteFailed { path: PathBuf, cause: io::Error },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::DiscoverFailed(err) => Report::error("Failed to find config file", err),
+            Self::NoConfig => Report::action_request(
+                "There's nothing to deinit here!",
+                format!("No {} was found.", config::file_name()),
+            ),
+            Self::ConfirmationMissing => Report::action_request(
+                "Refusing to delete generated projects without confirmation",
+                "Pass `--yes` to deinit non-interactively.",
+            ),
+            Self::ConfigFailed(err) => err.report(),
+            Self::PromptFailed(err) => Report::error("Failed to prompt for confirmation", err),
+            Self::RemoveFailed { path, cause } => {
+                Report::error(format!("Failed to remove {:?}", path), cause)
+            }
+            Self::GitignoreReadFailed { path, cause } => {
+                Report::error(format!("Failed to read {:?}", path), cause)
+            }
+            Self::GitignoreWriteFailed { path, cause } => {
+                Report::error(format!("Failed to write {:?}", path), cause)
+            }
+        }
+    }
+}
+
+fn remove(path: &Path) -> Result<(), Error> {
+    let result = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else if path.exists() {
+        fs::remove_file(path)
+    } else {
+        log::info!("{:?} doesn't exist, so there's nothing to remove", path);
+        return Ok(());
+    };
+    cli::status(format!("Removing {:?}", path));
+    result.map_err(|cause| Error::RemoveFailed {
+        path: path.to_owned(),
+        cause,
+    })
+}
+
+// Removes directories left empty by deleting the projects, stopping at the app
+// root.
+fn remove_empty_parents(root_dir: &Path, path: &Path) -> Result<(), Error> {
+    let mut dir = path.parent();
+    while let Some(current) = dir.filter(|dir| *dir != root_dir && dir.starts_with(root_dir)) {
+        let empty = fs::read_dir(current)
+            .map(|mut entries| entries.next().is_none())
+            .unwrap_or(false);
+        if !empty {
+            break;
+        }
+        remove(current)?;
+        dir = current.parent();
+    }
+    Ok(())
+}
+
+fn gitignore_entry(root_dir: &Path, path: &Path) -> Option<String> {
+    path.strip_prefix(root_dir)
+        .ok()
+        .map(|rel| rel.to_string_lossy().replace('\\', "/"))
+        .filter(|rel| !rel.is_empty())
+}
+
+// Only drops entries that name exactly what we removed, so anything the user
+// added by hand stays put.
+fn clean_gitignore(root_dir: &Path, entries: &[String]) -> Result<(), Error> {
+    let path = root_dir.join(".gitignore");
+    if !path.is_file() {
+        return Ok(());
+    }
+    let contents = fs::read_to_string(&path).map_err(|cause| Error::GitignoreReadFailed {
+        path: path.clone(),
+        cause,
+    })?;
+    let mut removed = false;
+    let mut out = String::with_capacity(contents.len());
+    for line in contents.lines() {
+        let entry = line.trim().trim_start_matches('/').trim_end_matches('/');
+        if entries.iter().any(|candidate| candidate == entry) {
+            log::info!("removing {:?} from {:?}", line, path);
+            removed = true;
+        } else {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    if removed {
+        cli::status(format!(
+            "Removing generated project entries from {:?}",
+            path
+        ));
+        fs::write(&path, out).map_err(|cause| Error::GitignoreWriteFailed { path, cause })?;
+    }
+    Ok(())
+}
+
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    yes: bool,
+    remove_config: bool,
+    overrides: &Overrides,
+    cwd: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let (_, source) = config::discover(&cwd)
+        .map_err(Error::DiscoverFailed)?
+        .ok_or(Error::NoConfig)?;
+    if non_interactive.yes() && !yes {
+        return Err(Error::ConfirmationMissing);
+    }
+    let (config, _origin) = Config::load_or_gen(cwd, non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigFailed)?;
+    let root_dir = config.app().root_dir();
+    let project_dirs = [config.apple_project_dir(), config.android().project_dir()];
+    let gen_dir = config.app().gen_dir();
+
+    if !yes {
+        println!("This will permanently delete:");
+        for dir in &project_dirs {
+            println!("  {:?}", dir);
+        }
+        if remove_config {
+            println!("  {:?}", source.path(root_dir));
+        }
+        let answer = loop {
+            if let Some(answer) = prompt::yes_no("Continue?", Some(prompt::YesOrNo::No))
+                .map_err(Error::PromptFailed)?
+            {
+                break answer;
+            }
+        };
+        if answer.no() {
+            cli::status("Nothing was removed.");
+            return Ok(());
+        }
+    }
+
+    let mut entries = Vec::new();
+    for dir in &project_dirs {
+        remove(dir)?;
+        remove_empty_parents(root_dir, dir)?;
+        entries.extend(gitignore_entry(root_dir, dir));
+    }
+    if !gen_dir.exists() {
+        entries.extend(gitignore_entry(root_dir, &gen_dir));
+    }
+    clean_gitignore(root_dir, &entries)?;
+
+    if remove_config {
+        remove(&Lockfile::path(config.app()))?;
+        match source {
+            Source::File => remove(&source.path(root_dir))?,
+            Source::CargoMetadata => cli::status(format!(
+                "Your config lives in `Cargo.toml`, so you'll need to remove `[package.metadata.{}]` by hand.",
+                crate::NAME
+            )),
+        }
+    }
+    Report::victory(
+        "Generated projects removed successfully!",
+        "Run `cargo mobile init` whenever you want them back.",
+    )
+    .print(wrapper);
+    Ok(())
+}
@@ -0,0 +1,332 @@
+//! Serves the assets dir over HTTP with live reload, for WebView apps. Debug
+//! builds made while the server's running load their page from it instead of
+//! their bundled assets, so edits to HTML, CSS, and JS show up in the app
+//! without rebuilding it.
+//!
+//! This only needs to serve a handful of static files to one or two clients,
+//! so it's a minimal HTTP/1.1 server on top of `std::net`. Live reload is a
+//! long poll: the page asks for the current generation, and the server holds
+//! the request until the assets change.
+
+use crate::{
+    android,
+    config::{app::App, Config, LoadOrGenError, Overrides},
+    opts,
+    util::{
+        cli::{self, Report, Reportable, TextWrapper},
+        watch::Watcher,
+    },
+};
+use std::{
+    fs,
+    io::{self, BufRead as _, BufReader, Write as _},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// What debug builds see the server's URL as; `option_env!` this to find it.
+pub static URL_ENV_VAR: &str = "CARGO_MOBILE_DEV_URL";
+
+static URL_FILE_NAME: &str = ".dev-url";
+
+static RELOAD_PATH: &str = "/__cargo_mobile_reload";
+
+// Held requests are answered with the current generation after this long
+// anyway, so they don't get timed out by the WebView.
+static RELOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+static RELOAD_SCRIPT: &str = r#"<script>
+(function () {
+  var generation = null;
+  function poll() {
+    fetch("/__cargo_mobile_reload" + (generation === null ? "" : "?v=" + generation))
+      .then(function (response) { return response.text(); })
+      .then(function (current) {
+        if (generation !== null && current !== generation) {
+          location.reload();
+          return;
+        }
+        generation = current;
+        poll();
+      })
+      .catch(function () { setTimeout(poll, 1000); });
+  }
+  poll();
+})();
+</script>
+"#;
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadOrGenError),
+    BindFailed { addr: SocketAddr, cause: io::Error },
+    WatchFailed(io::Error),
+    UrlWriteFailed { path: PathBuf, cause: io::Error },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            Self::BindFailed { addr, cause } => Report::action_request(
+                "Use `--port` to pick a different port.",
+                format!("Failed to listen on {}: {}", addr, cause),
+            ),
+            Self::WatchFailed(err) => Report::error("Failed to watch assets for changes", err),
+            Self::UrlWriteFailed { path, cause } => Report::error(
+                format!("Failed to save dev server URL to {:?}", path),
+                cause,
+            ),
+        }
+    }
+}
+
+fn url_path(app: &App) -> PathBuf {
+    app.gen_dir().join(URL_FILE_NAME)
+}
+
+/// The URL of the dev server for this project, if one's running. The file it
+/// comes from outlives the server, so we check it's still listening; otherwise
+/// a debug build made after stopping it would load a blank page.
+pub fn url(app: &App) -> Option<String> {
+    let contents = fs::read_to_string(url_path(app)).ok()?;
+    let mut lines = contents.lines();
+    let url = lines.next()?.to_owned();
+    let port = lines.next()?.parse::<u16>().ok()?;
+    let local = SocketAddr::from(([127, 0, 0, 1], port));
+    TcpStream::connect_timeout(&local, Duration::from_millis(100))
+        .ok()
+        .map(|_| url)
+}
+
+#[derive(Debug, Default)]
+struct Reload {
+    generation: Mutex<u64>,
+    changed: Condvar,
+}
+
+impl Reload {
+    fn bump(&self) {
+        *self.generation.lock().unwrap() += 1;
+        self.changed.notify_all();
+    }
+
+    fn current(&self) -> u64 {
+        *self.generation.lock().unwrap()
+    }
+
+    /// Blocks until the generation isn't `seen`, or until the timeout.
+    fn wait(&self, seen: u64) -> u64 {
+        let generation = self.generation.lock().unwrap();
+        let (generation, _) = self
+            .changed
+            .wait_timeout_while(generation, RELOAD_TIMEOUT, |generation| *generation == seen)
+            .unwrap();
+        *generation
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("wasm") => "application/wasm",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+/// Maps a request path onto the assets dir, refusing anything that'd escape
+/// it.
+fn resolve(asset_dir: &Path, path: &str) -> Option<PathBuf> {
+    let rel = Path::new(path.trim_start_matches('/'));
+    if rel
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    let path = asset_dir.join(rel);
+    Some(if path.is_dir() {
+        path.join("index.html")
+    } else {
+        path
+    })
+}
+
+fn handle(mut stream: TcpStream, asset_dir: &Path, reload: &Reload) -> io::Result<()> {
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(stream.try_clone()?);
+    reader.read_line(&mut request_line)?;
+    // We don't care about any of the headers, but they need to be read.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let mut parts = target.splitn(2, '?');
+    let path = parts.next().unwrap_or("/");
+    let query = parts.next();
+    if path == RELOAD_PATH {
+        let seen = query
+            .and_then(|query| query.strip_prefix("v="))
+            .and_then(|seen| seen.parse().ok());
+        let generation = match seen {
+            Some(seen) => reload.wait(seen),
+            None => reload.current(),
+        };
+        return respond(
+            &mut stream,
+            "200 OK",
+            "text/plain",
+            generation.to_string().as_bytes(),
+        );
+    }
+    match resolve(asset_dir, path).map(|path| fs::read(&path).map(|body| (path, body))) {
+        Some(Ok((path, mut body))) => {
+            let content_type = content_type(&path);
+            if content_type.starts_with("text/html") {
+                body.extend_from_slice(RELOAD_SCRIPT.as_bytes());
+            }
+            log::info!("served {:?}", path);
+            respond(&mut stream, "200 OK", content_type, &body)
+        }
+        _ => {
+            log::warn!("nothing to serve for {:?}", path);
+            respond(&mut stream, "404 Not Found", "text/plain", b"Not Found")
+        }
+    }
+}
+
+/// `adb reverse` makes the server reachable as `localhost` from connected
+/// Android devices and emulators, so the same URL works everywhere. This is
+/// best effort, since the server's still useful without it.
+fn reverse_android_ports(wrapper: &TextWrapper, port: u16) {
+    let env = match android::env::Env::new() {
+        Ok(env) => env,
+        Err(err) => {
+            log::info!("not forwarding port to Android devices: {}", err);
+            return;
+        }
+    };
+    let devices = match android::adb::device_list(&env) {
+        Ok(devices) => devices,
+        Err(err) => {
+            err.report().print(wrapper);
+            return;
+        }
+    };
+    let spec = format!("tcp:{}", port);
+    for device in devices {
+        let result = android::adb::adb(&env, device.serial_no())
+            .with_args(&["reverse", &spec, &spec])
+            .run_and_wait_for_output();
+        match result {
+            Ok(_) => cli::status(format!("Forwarded port {} to {}", port, device)),
+            Err(err) => log::warn!("failed to forward port to {}: {}", device, err),
+        }
+    }
+}
+
+/// Serves the assets dir until interrupted.
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    overrides: &Overrides,
+    port: u16,
+    host: Option<String>,
+) -> Result<(), Error> {
+    let (config, _origin) = Config::load_or_gen(".", non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigFailed)?;
+    let app = config.app();
+    if !app.template_pack().webview() {
+        log::warn!("the template pack isn't a WebView pack, so the app won't load from the dev server on its own");
+    }
+    // Without `--host`, only this machine (and Android devices, through `adb
+    // reverse`) can reach the server, which is all the simulator needs.
+    let addr = SocketAddr::from(if host.is_some() {
+        ([0, 0, 0, 0], port)
+    } else {
+        ([127, 0, 0, 1], port)
+    });
+    let listener = TcpListener::bind(addr).map_err(|cause| Error::BindFailed { addr, cause })?;
+    let url = format!(
+        "http://{}:{}/",
+        host.as_deref().unwrap_or("localhost"),
+        port
+    );
+    let url_path = url_path(app);
+    fs::create_dir_all(app.gen_dir())
+        .and_then(|()| fs::write(&url_path, format!("{}\n{}\n", url, port)))
+        .map_err(|cause| Error::UrlWriteFailed {
+            path: url_path.clone(),
+            cause,
+        })?;
+    reverse_android_ports(wrapper, port);
+
+    let asset_dir = app.asset_dir();
+    let reload = Arc::new(Reload::default());
+    let mut watcher = Watcher::new(vec![asset_dir.clone()]).map_err(Error::WatchFailed)?;
+    {
+        let reload = Arc::clone(&reload);
+        thread::spawn(move || loop {
+            match watcher.wait() {
+                Ok(()) => {
+                    cli::status("Assets changed; reloading");
+                    reload.bump();
+                }
+                Err(err) => {
+                    log::error!("stopped watching assets: {}", err);
+                    break;
+                }
+            }
+        });
+    }
+
+    cli::status(format!(
+        "Serving {:?} at {}; debug builds made while this is running will load from it. Press Ctrl+C to stop.",
+        asset_dir, url
+    ));
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let asset_dir = asset_dir.clone();
+                let reload = Arc::clone(&reload);
+                // Reload requests are held open, so each gets a thread.
+                thread::spawn(move || {
+                    if let Err(err) = handle(stream, &asset_dir, &reload) {
+                        log::warn!("failed to handle request: {}", err);
+                    }
+                });
+            }
+            Err(err) => log::warn!("failed to accept connection: {}", err),
+        }
+    }
+    Ok(())
+}
@@ -1,11 +1,208 @@
-use crate::util::cli::{Report, Reportable};
-use std::{fmt::Debug, io};
+use crate::{
+    config::app::App,
+    util::{
+        self,
+        cli::{Report, Reportable, TextWrapper},
+    },
+};
+use std::{
+    fmt::{Debug, Display},
+    fs, io,
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+static LAST_DEVICE_FILE_NAME: &str = ".last-device";
+
+/// The UDID, serial number, or name of the device to use, for CI and anyone
+/// tired of being asked. This beats the last picked device, and on macOS it
+/// can name an iOS simulator too.
+pub static DEVICE_ENV_VAR: &str = "CARGO_MOBILE_DEVICE";
+
+static POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub static DEFAULT_WAIT: Duration = Duration::from_secs(60);
+
+/// Calls `detect` until `found` is satisfied or `wait` elapses, returning the
+/// last result either way. Without `wait`, this only detects once. Cached
+/// device lists are never used here.
+pub fn poll<T, E>(
+    wait: Option<Duration>,
+    mut detect: impl FnMut() -> Result<T, E>,
+    found: impl Fn(&T) -> bool,
+) -> Result<T, E> {
+    let deadline = wait.map(|wait| Instant::now() + wait);
+    let mut announced = false;
+    loop {
+        // Devices come and go while we wait, so only a fresh scan will do.
+        let result = util::cache::skipping(&mut detect)?;
+        match deadline {
+            Some(deadline) if !found(&result) && Instant::now() < deadline => {
+                if !announced {
+                    util::cli::status(format!(
+                        "Waiting up to {}s for a device to connect...",
+                        wait.unwrap_or_default().as_secs()
+                    ));
+                    announced = true;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            _ => return Ok(result),
+        }
+    }
+}
+
+fn last_device_path(app: &App) -> PathBuf {
+    app.gen_dir().join(match app.selected_app() {
+        Some(selected) => format!("{}-{}", LAST_DEVICE_FILE_NAME, selected),
+        None => LAST_DEVICE_FILE_NAME.to_owned(),
+    })
+}
+
+/// The id (UDID or serial) of the device most recently picked in this project.
+pub fn last_device(app: &App) -> Option<String> {
+    let path = last_device_path(app);
+    match fs::read_to_string(&path) {
+        Ok(id) => Some(id.trim().to_owned()).filter(|id| !id.is_empty()),
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                log::warn!("failed to read last device from {:?}: {}", path, err);
+            }
+            None
+        }
+    }
+}
+
+/// Failing to remember the device isn't worth failing the command over, so
+/// this only logs errors.
+pub fn remember_device(app: &App, id: &str) {
+    let path = last_device_path(app);
+    let result = fs::create_dir_all(app.gen_dir()).and_then(|()| fs::write(&path, id));
+    if let Err(err) = result {
+        log::warn!("failed to save last device to {:?}: {}", path, err);
+    }
+}
+
+fn version_components(version: &str) -> Vec<u32> {
+    version
+        .split('.')
+        .map(|component| {
+            component
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>()
+                .parse()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+// Compares dotted versions numerically, treating missing components as 0 (so
+// "15" and "15.0" are equal).
+fn version_at_least(version: &str, min: &str) -> bool {
+    let (mut version, mut min) = (version_components(version), version_components(min));
+    let len = version.len().max(min.len());
+    version.resize(len, 0);
+    min.resize(len, 0);
+    version >= min
+}
+
+/// Narrows down which devices are offered, i.e. when lots are connected.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    pub min_os: Option<String>,
+    pub arch: Option<String>,
+    /// Prompt even if a device was picked last time, so a different one can
+    /// be chosen.
+    pub pick: bool,
+}
+
+impl Filter {
+    /// Whether any devices are being filtered out; `pick` doesn't count.
+    pub fn is_empty(&self) -> bool {
+        self.min_os.is_none() && self.arch.is_none()
+    }
+
+    /// If `arch` isn't one of `arches`, this gives the closest one that is,
+    /// since `--arch arch64` is an easy typo to make.
+    pub fn suggest_arch<'a>(&self, arches: impl IntoIterator<Item = &'a str>) -> Option<String> {
+        let arch = self.arch.as_deref()?;
+        let arches = arches.into_iter().collect::<Vec<_>>();
+        if arches.contains(&arch) {
+            None
+        } else {
+            util::did_you_mean(arch, arches).map(ToOwned::to_owned)
+        }
+    }
+
+    /// Devices with an unknown OS version never satisfy `min_os`.
+    pub fn matches(&self, os_version: Option<&str>, arch: &str) -> bool {
+        let os_ok = match (&self.min_os, os_version) {
+            (Some(min), Some(version)) => version_at_least(version, min),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let arch_ok = self
+            .arch
+            .as_deref()
+            .map(|expected| expected == arch)
+            .unwrap_or(true);
+        os_ok && arch_ok
+    }
+}
+
+/// The device set in `CARGO_MOBILE_DEVICE`, if any.
+pub fn env_device() -> Option<String> {
+    std::env::var(DEVICE_ENV_VAR)
+        .ok()
+        .map(|device| device.trim().to_owned())
+        .filter(|device| !device.is_empty())
+}
+
+/// Finds `wanted` among `devices`, given as `(id, name)` pairs. Ids have to
+/// match exactly, but names are case-insensitive, since they're typed by hand.
+pub fn env_device_index<'a>(
+    wanted: &str,
+    devices: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Option<usize> {
+    let devices = devices.collect::<Vec<_>>();
+    devices
+        .iter()
+        .position(|(id, _)| *id == wanted)
+        .or_else(|| {
+            devices
+                .iter()
+                .position(|(_, name)| name.eq_ignore_ascii_case(wanted))
+        })
+}
+
+/// Finds the previously picked device among `ids`, so we can skip prompting.
+/// This is always `None` if `filter.pick` is set.
+pub fn last_device_index<'a>(
+    app: &App,
+    filter: &Filter,
+    ids: impl Iterator<Item = &'a str>,
+) -> Option<usize> {
+    if filter.pick {
+        return None;
+    }
+    let last = last_device(app)?;
+    let index = ids.into_iter().position(|id| id == last);
+    if index.is_none() {
+        log::info!("last device {:?} is no longer connected", last);
+    }
+    index
+}
 
 #[derive(Debug)]
 pub enum PromptErrorCause<T: Reportable> {
     DetectionFailed(T),
     PromptFailed(io::Error),
     NoneDetected,
+    NoneMatched { suggestion: Option<String> },
+    EnvDeviceMissing { device: String },
 }
 
 #[derive(Debug)]
@@ -25,6 +222,25 @@ impl<T: Reportable> Reportable for PromptError<T> {
                 format!("Failed to prompt for {} device", self.name),
                 format!("No connected {} devices detected", self.name),
             ),
+            PromptErrorCause::NoneMatched { suggestion } => Report::action_request(
+                match suggestion {
+                    Some(arch) => format!("Did you mean `--arch {}`?", arch),
+                    None => {
+                        "Connect a matching device, or loosen `--min-os` / `--arch`.".to_owned()
+                    }
+                },
+                format!("No connected {} devices match the filter", self.name),
+            ),
+            PromptErrorCause::EnvDeviceMissing { device } => Report::action_request(
+                format!(
+                    "Connect it, or unset `{}` to pick from the devices that are connected.",
+                    DEVICE_ENV_VAR
+                ),
+                format!(
+                    "`{}` is set to {:?}, but no connected {} device has that id or name",
+                    DEVICE_ENV_VAR, device, self.name
+                ),
+            ),
         }
     }
 }
@@ -45,42 +261,202 @@ impl<T: Reportable> PromptError<T> {
     pub fn none_detected(name: &'static str) -> Self {
         Self::new(name, PromptErrorCause::NoneDetected)
     }
+
+    pub fn none_matched(name: &'static str, suggestion: Option<String>) -> Self {
+        Self::new(name, PromptErrorCause::NoneMatched { suggestion })
+    }
+
+    pub fn env_device_missing(name: &'static str, device: String) -> Self {
+        Self::new(name, PromptErrorCause::EnvDeviceMissing { device })
+    }
+}
+
+/// Prints `(label, value)` pairs with the values lined up.
+pub fn print_info(info: &[(&str, String)]) {
+    let width = info.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+    for (label, value) in info {
+        println!("{:>width$}: {}", label, value, width = width);
+    }
+}
+
+#[derive(Debug)]
+pub struct DeployAllError {
+    failed: usize,
+    total: usize,
+}
+
+impl Reportable for DeployAllError {
+    fn report(&self) -> Report {
+        Report::error(
+            "Failed to deploy to all devices",
+            format!(
+                "{} of {} devices failed; see above for details",
+                self.failed, self.total
+            ),
+        )
+    }
+}
+
+/// Runs `deploy` on every device at once, and then reports how each one went.
+pub fn deploy_all<D, E>(
+    wrapper: &TextWrapper,
+    devices: Vec<D>,
+    deploy: impl Fn(&D) -> Result<(), E> + Send + Sync + 'static,
+) -> Result<(), DeployAllError>
+where
+    D: Display + Send + 'static,
+    E: Reportable + Send + 'static,
+{
+    let total = devices.len();
+    util::cli::status(format!("Deploying to {} devices...", total));
+    let deploy = Arc::new(deploy);
+    let handles = devices
+        .into_iter()
+        .map(|device| {
+            let deploy = Arc::clone(&deploy);
+            let name = device.to_string();
+            (name, thread::spawn(move || deploy(&device)))
+        })
+        .collect::<Vec<_>>();
+    let mut failed = 0;
+    for (name, handle) in handles {
+        match handle.join() {
+            Ok(Ok(())) => util::cli::status(format!("Deployed to {}", name)),
+            Ok(Err(err)) => {
+                failed += 1;
+                eprintln!("Failed to deploy to {}:", name);
+                err.report().print(wrapper);
+            }
+            Err(_) => {
+                failed += 1;
+                eprintln!("Deploying to {} panicked", name);
+            }
+        }
+    }
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(DeployAllError { failed, total })
+    }
 }
 
 #[macro_export]
 macro_rules! define_device_prompt {
-    ($func:path, $e:ty, $name:ident) => {
-        fn device_prompt<'a>(env: &'_ Env) -> Result<Device<'a>, $crate::device::PromptError<$e>> {
-            let device_list = $func(env).map_err(|cause| {
-                $crate::device::PromptError::detection_failed(stringify!($name), cause)
-            })?;
-            if device_list.len() > 0 {
-                let index = if device_list.len() > 1 {
-                    prompt::list(
-                        concat!("Detected ", stringify!($name), " devices"),
-                        device_list.iter(),
-                        "device",
-                        None,
-                        "Device",
+    // Any extra args are passed along to `$func` after `env`.
+    ($func:path, $e:ty, $name:ident $(, $arg:ident: $arg_ty:ty)*) => {
+        /// `CARGO_MOBILE_DEVICE` is used if it's set. Otherwise, if `app` is
+        /// specified, the last device picked for it is reused when it's still
+        /// connected (unless `filter.pick` is set), and the new pick is
+        /// remembered.
+        fn device_prompt<'a>(
+            env: &'_ Env,
+            $($arg: $arg_ty,)*
+            app: Option<&$crate::config::app::App>,
+            filter: &$crate::device::Filter,
+            wait: Option<std::time::Duration>,
+        ) -> Result<Device<'a>, $crate::device::PromptError<$e>> {
+            let env_device = $crate::device::env_device();
+            let find_env_device = |device_list: &[Device<'a>]| {
+                env_device.as_deref().and_then(|wanted| {
+                    $crate::device::env_device_index(
+                        wanted,
+                        device_list.iter().map(|device| (device.id(), device.name())),
                     )
-                    .map_err(|cause| {
-                        $crate::device::PromptError::prompt_failed(stringify!($name), cause)
-                    })?
-                } else {
-                    0
-                };
-                let device = device_list.into_iter().nth(index).unwrap();
-                println!(
-                    "Detected connected device: {} with target {:?}",
+                })
+            };
+            let (detected_arches, device_list) = $crate::device::poll(
+                wait,
+                || -> Result<_, $crate::device::PromptError<$e>> {
+                    let device_list = $func(env $(, $arg)*).map_err(|cause| {
+                        $crate::device::PromptError::detection_failed(stringify!($name), cause)
+                    })?;
+                    let detected_arches = device_list
+                        .iter()
+                        .map(|device| device.target().arch)
+                        .collect::<Vec<_>>();
+                    let device_list = device_list
+                        .into_iter()
+                        .filter(|device| filter.matches(device.os_version(), device.target().arch))
+                        .collect::<Vec<_>>();
+                    Ok((detected_arches, device_list))
+                },
+                // If a specific device was asked for, that's the one to wait
+                // for.
+                |(_, device_list)| match &env_device {
+                    Some(_) => find_env_device(device_list).is_some(),
+                    None => !device_list.is_empty(),
+                },
+            )?;
+            if detected_arches.is_empty() {
+                return Err($crate::device::PromptError::none_detected(stringify!(
+                    $name
+                )));
+            }
+            if device_list.is_empty() {
+                return Err($crate::device::PromptError::none_matched(
+                    stringify!($name),
+                    filter.suggest_arch(detected_arches),
+                ));
+            }
+            let env_index = match &env_device {
+                Some(wanted) => Some(find_env_device(&device_list).ok_or_else(|| {
+                    $crate::device::PromptError::env_device_missing(
+                        stringify!($name),
+                        wanted.clone(),
+                    )
+                })?),
+                None => None,
+            };
+            let last_index = app.filter(|_| env_index.is_none()).and_then(|app| {
+                $crate::device::last_device_index(
+                    app,
+                    filter,
+                    device_list.iter().map(|device| device.id()),
+                )
+            });
+            let index = if let Some(index) = env_index.or(last_index) {
+                index
+            } else if device_list.len() > 1 {
+                prompt::list(
+                    concat!("Detected ", stringify!($name), " devices"),
+                    device_list.iter(),
+                    "device",
+                    None,
+                    "Device",
+                )
+                .map_err(|cause| {
+                    $crate::device::PromptError::prompt_failed(stringify!($name), cause)
+                })?
+            } else {
+                0
+            };
+            let device = device_list.into_iter().nth(index).unwrap();
+            if env_index.is_some() {
+                $crate::util::cli::status(format!(
+                    "Using device from `{}`: {} with target {:?}",
+                    $crate::device::DEVICE_ENV_VAR,
                     device,
                     device.target().triple,
-                );
-                Ok(device)
+                ));
+            } else if last_index.is_some() {
+                $crate::util::cli::status(format!(
+                    "Using last selected device: {} with target {:?}",
+                    device,
+                    device.target().triple,
+                ));
             } else {
-                Err($crate::device::PromptError::none_detected(stringify!(
-                    $name
-                )))
+                $crate::util::cli::status(format!(
+                    "Detected connected device: {} with target {:?}",
+                    device,
+                    device.target().triple,
+                ));
+            }
+            // A device from the env var is a one-off, so it shouldn't replace
+            // the one that was picked.
+            if let Some(app) = app.filter(|_| env_index.is_none()) {
+                $crate::device::remember_device(app, device.id());
             }
+            Ok(device)
         }
     };
 }
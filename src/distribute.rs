@@ -0,0 +1,226 @@
+use crate::{
+    config::{firebase, Config, LoadOrGenError, Overrides},
+    opts,
+    util::{
+        self,
+        cli::{Report, Reportable, TextWrapper},
+    },
+};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Platform {
+    Android,
+    Apple,
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "android" => Ok(Self::Android),
+            "apple" => Ok(Self::Apple),
+            _ => Err(format!("{:?} isn't a supported platform", s)),
+        }
+    }
+}
+
+impl Platform {
+    pub const POSSIBLE_VALUES: &'static [&'static str] = &["android", "apple"];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Android => "android",
+            Self::Apple => "apple",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadOrGenError),
+    AppIdMissing {
+        platform: Platform,
+    },
+    GroupsMissing,
+    #[cfg(not(target_os = "macos"))]
+    AppleUnsupported,
+    ArtifactMissing {
+        path: PathBuf,
+        hint: String,
+    },
+    ReleaseNotesFailed(bossy::Error),
+    ReleaseNotesInvalid(std::str::Utf8Error),
+    FirebaseMissing,
+    FirebasePresentFailed(bossy::Error),
+    UploadFailed(bossy::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            Self::AppIdMissing { platform } => Report::action_request(
+                format!(
+                    "Please set `{}.{}-app-id` in your config and try again.",
+                    firebase::KEY,
+                    platform.as_str()
+                ),
+                "The Firebase app ID is listed under your Firebase project's settings.",
+            ),
+            Self::GroupsMissing => Report::action_request(
+                format!(
+                    "Please pass `--group`, or set `{}.groups` in your config, and try again.",
+                    firebase::KEY
+                ),
+                "There'd be nobody to distribute to.",
+            ),
+            #[cfg(not(target_os = "macos"))]
+            Self::AppleUnsupported => Report::error(
+                "Failed to distribute",
+                "Apple builds can only be distributed from macOS",
+            ),
+            Self::ArtifactMissing { path, hint } => {
+                Report::action_request(hint, format!("Nothing to distribute at {:?}", path))
+            }
+            Self::ReleaseNotesFailed(err) => {
+                Report::error("Failed to get release notes from `git log`", err)
+            }
+            Self::ReleaseNotesInvalid(err) => {
+                Report::error("`git log` output contained invalid UTF-8", err)
+            }
+            Self::FirebaseMissing => Report::action_request(
+                "`firebase` isn't installed; you can install it by running this:",
+                "npm install -g firebase-tools",
+            ),
+            Self::FirebasePresentFailed(err) => {
+                Report::error("Failed to check if `firebase` is installed", err)
+            }
+            Self::UploadFailed(err) => {
+                Report::error("Failed to upload to Firebase App Distribution", err)
+            }
+        }
+    }
+}
+
+fn artifact(config: &Config, platform: Platform) -> Result<PathBuf, Error> {
+    match platform {
+        // This is what Gradle's `assembleArm64Release` produces, which is
+        // what most teams hand to testers.
+        Platform::Android => {
+            let path = config
+                .android()
+                .project_dir()
+                .join("app/build/outputs/apk/arm64/release/app-arm64-release.apk");
+            if path.is_file() {
+                Ok(path)
+            } else {
+                Err(Error::ArtifactMissing {
+                    path,
+                    hint: "Build a signed release APK, or pass `--artifact`.".to_owned(),
+                })
+            }
+        }
+        #[cfg(target_os = "macos")]
+        Platform::Apple => config
+            .apple()
+            .ipa_path()
+            .map_err(|(path, _)| Error::ArtifactMissing {
+                path,
+                hint:
+                    "Run `cargo apple archive --release` and export an IPA, or pass `--artifact`."
+                        .to_owned(),
+            }),
+        #[cfg(not(target_os = "macos"))]
+        Platform::Apple => Err(Error::AppleUnsupported),
+    }
+}
+
+// Everything since the last tag, which is usually everything since the last
+// release; without tags, we settle for the most recent commits.
+fn git_log_notes(root_dir: &Path) -> Result<String, Error> {
+    let git = util::Git::new(root_dir);
+    let last_tag = git
+        .command()
+        .with_args(&["describe", "--tags", "--abbrev=0"])
+        .run_and_wait_for_output()
+        .ok()
+        .and_then(|output| output.stdout_str().ok().map(|tag| tag.trim().to_owned()))
+        .filter(|tag| !tag.is_empty());
+    let command = git.command().with_args(&["log", "--format=- %s"]);
+    let command = match last_tag {
+        Some(tag) => command.with_arg(format!("{}..HEAD", tag)),
+        None => command.with_args(&["-n", "20"]),
+    };
+    command
+        .run_and_wait_for_output()
+        .map_err(Error::ReleaseNotesFailed)?
+        .stdout_str()
+        .map(|notes| notes.trim().to_owned())
+        .map_err(Error::ReleaseNotesInvalid)
+}
+
+/// Uploads a signed APK or IPA to Firebase App Distribution, using the
+/// `firebase` CLI's own login (or `FIREBASE_TOKEN`).
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    overrides: &Overrides,
+    platform: Platform,
+    groups: Vec<String>,
+    artifact_override: Option<PathBuf>,
+    release_notes_file: Option<PathBuf>,
+) -> Result<(), Error> {
+    let (config, _origin) = Config::load_or_gen(".", non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigFailed)?;
+    let firebase = config.firebase();
+    let app_id = match platform {
+        Platform::Android => firebase.android_app_id(),
+        Platform::Apple => firebase.apple_app_id(),
+    }
+    .ok_or_else(|| Error::AppIdMissing { platform })?;
+    let groups = if groups.is_empty() {
+        firebase.groups().to_vec()
+    } else {
+        groups
+    };
+    if groups.is_empty() {
+        return Err(Error::GroupsMissing);
+    }
+    let artifact = match artifact_override {
+        Some(path) if path.is_file() => path,
+        Some(path) => {
+            return Err(Error::ArtifactMissing {
+                path,
+                hint: "Please check the path passed to `--artifact`.".to_owned(),
+            })
+        }
+        None => artifact(&config, platform)?,
+    };
+    if !util::command_present("firebase").map_err(Error::FirebasePresentFailed)? {
+        return Err(Error::FirebaseMissing);
+    }
+    let command = bossy::Command::impure("firebase")
+        .with_arg("appdistribution:distribute")
+        .with_arg(&artifact)
+        .with_args(&["--app", app_id])
+        .with_arg("--groups")
+        .with_arg(groups.join(","));
+    let command = match release_notes_file {
+        Some(path) => command.with_arg("--release-notes-file").with_arg(path),
+        None => command
+            .with_arg("--release-notes")
+            .with_arg(git_log_notes(config.app().root_dir())?),
+    };
+    println!(
+        "Distributing {:?} to {}",
+        artifact,
+        util::list_display(&groups)
+    );
+    command.run_and_wait().map_err(Error::UploadFailed)?;
+    Ok(())
+}
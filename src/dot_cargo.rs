@@ -74,29 +74,29 @@ impl Reportable for WriteError {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct DotCargoBuild {
-    target: String,
-}
-
-impl DotCargoBuild {
-    pub fn new(target: impl Into<String>) -> Self {
-        Self {
-            target: target.into(),
-        }
-    }
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rustflags: Vec<String>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct DotCargoTarget {
     pub ar: Option<String>,
     pub linker: Option<String>,
+    #[serde(default)]
     pub rustflags: Vec<String>,
+    pub runner: Option<String>,
 }
 
 impl DotCargoTarget {
     pub fn is_empty(&self) -> bool {
-        self.ar.is_none() && self.linker.is_none() && self.rustflags.is_empty()
+        self.ar.is_none()
+            && self.linker.is_none()
+            && self.rustflags.is_empty()
+            && self.runner.is_none()
     }
 }
 
@@ -141,7 +141,19 @@ impl DotCargo {
     }
 
     pub fn set_default_target(&mut self, target: impl Into<String>) {
-        self.build = Some(DotCargoBuild::new(target));
+        self.build.get_or_insert_with(Default::default).target = Some(target.into());
+    }
+
+    /// The flags cargo passes to rustc when building for `triple`, going by
+    /// this config: the target's own, or else the ones under `[build]`.
+    pub fn rustflags(&self, triple: &str) -> Vec<String> {
+        self.target
+            .get(triple)
+            .map(|target| &target.rustflags)
+            .filter(|rustflags| !rustflags.is_empty())
+            .or_else(|| self.build.as_ref().map(|build| &build.rustflags))
+            .cloned()
+            .unwrap_or_default()
     }
 
     pub fn insert_target(&mut self, name: impl Into<String>, target: DotCargoTarget) {
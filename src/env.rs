@@ -1,10 +1,24 @@
-use crate::util::cli::{Report, Reportable};
+use crate::{
+    config::Overrides,
+    opts::EntryPoint,
+    util::{
+        self,
+        cli::{Report, Reportable},
+        lock,
+        metrics::{self, Metrics},
+        reproducible,
+    },
+};
 use std::{
     ffi::OsStr,
     fmt::{self, Debug, Display},
-    path::Path,
+    fs, io,
+    path::{Path, PathBuf},
 };
 
+/// Loaded in this order, so `.env.local` can override values from `.env`.
+static DOTENV_FILE_NAMES: &[&str] = &[".env", ".env.local"];
+
 pub trait ExplicitEnv: Debug {
     fn explicit_env(&self) -> Vec<(&str, &OsStr)>;
 }
@@ -13,6 +27,8 @@ pub trait ExplicitEnv: Debug {
 pub enum Error {
     HomeNotSet(std::env::VarError),
     PathNotSet(std::env::VarError),
+    DotenvReadFailed { path: PathBuf, cause: io::Error },
+    DotenvLineInvalid { path: PathBuf, line: usize },
 }
 
 impl Display for Error {
@@ -28,6 +44,14 @@ impl Display for Error {
                 "The `PATH` environment variable isn't set, which is super weird: {}",
                 err
             ),
+            Self::DotenvReadFailed { path, cause } => {
+                write!(f, "Failed to read {:?}: {}", path, cause)
+            }
+            Self::DotenvLineInvalid { path, line } => write!(
+                f,
+                "Line {} of {:?} isn't a valid `KEY=value` pair",
+                line, path
+            ),
         }
     }
 }
@@ -38,12 +62,83 @@ impl Reportable for Error {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Env {
     home: String,
     path: String,
     term: Option<String>,
     ssh_auth_sock: Option<String>,
+    proxy: Vec<(&'static str, String)>,
+    dotenv: Vec<(String, String)>,
+    example: Option<String>,
+    metrics_file: Option<PathBuf>,
+    reproducible: Vec<(&'static str, String)>,
+    overrides: Vec<(&'static str, String)>,
+    lock_holders: String,
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    if value.len() >= 2
+        && (value.starts_with('"') && value.ends_with('"')
+            || value.starts_with('\'') && value.ends_with('\''))
+    {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+fn parse_dotenv(path: &Path, contents: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut vars = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.trim_start_matches("export ");
+        let mut parts = line.splitn(2, '=');
+        match (parts.next().map(str::trim), parts.next()) {
+            (Some(key), Some(value)) if !key.is_empty() && !key.contains(char::is_whitespace) => {
+                vars.push((key.to_owned(), unquote(value).to_owned()))
+            }
+            _ => {
+                return Err(Error::DotenvLineInvalid {
+                    path: path.to_owned(),
+                    line: index + 1,
+                })
+            }
+        }
+    }
+    Ok(vars)
+}
+
+// Values from the process environment win, so you can still override things
+// for a single invocation.
+fn load_dotenv(dir: &Path) -> Result<Vec<(String, String)>, Error> {
+    let mut vars = Vec::<(String, String)>::new();
+    for name in DOTENV_FILE_NAMES {
+        let path = dir.join(name);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(cause) => return Err(Error::DotenvReadFailed { path, cause }),
+        };
+        for (key, value) in parse_dotenv(&path, &contents)? {
+            if std::env::var_os(&key).is_some() {
+                log::info!(
+                    "{:?} from {:?} is already set, so it'll be ignored",
+                    key,
+                    path
+                );
+                continue;
+            }
+            log::info!("loaded {:?} from {:?}", key, path);
+            vars.retain(|(existing, _)| existing != &key);
+            vars.push((key, value));
+        }
+    }
+    Ok(vars)
 }
 
 impl Env {
@@ -52,18 +147,58 @@ impl Env {
         let path = std::env::var("PATH").map_err(Error::PathNotSet)?;
         let term = std::env::var("TERM").ok();
         let ssh_auth_sock = std::env::var("SSH_AUTH_SOCK").ok();
+        // Otherwise, `cargo` and `gradle` can't download anything behind a
+        // proxy.
+        let proxy = util::proxy::passthrough();
+        // Commands run from the project root, so that's where we look.
+        let dotenv = load_dotenv(".".as_ref())?;
+        let example = std::env::var(EntryPoint::ENV_VAR).ok();
+        let metrics_file = std::env::var_os(metrics::ENV_VAR).map(PathBuf::from);
+        let reproducible = reproducible::env_vars(".".as_ref());
+        let overrides = Overrides::forwarded();
+        // So builds that Gradle and Xcode run for us don't wait on our lock.
+        let lock_holders = lock::holders_env_value();
         Ok(Self {
             home,
             path,
             term,
             ssh_auth_sock,
+            proxy,
+            dotenv,
+            example,
+            metrics_file,
+            reproducible,
+            overrides,
+            lock_holders,
         })
     }
 
+    /// Variables loaded from the project's `.env` and `.env.local`, which are
+    /// passed to builds and to launched apps.
+    pub fn dotenv(&self) -> &[(String, String)] {
+        &self.dotenv
+    }
+
     pub fn path(&self) -> &str {
         &self.path
     }
 
+    /// Makes builds run by Gradle or Xcode build `entry_point`.
+    pub fn with_entry_point(mut self, entry_point: &EntryPoint) -> Self {
+        if let Some(example) = entry_point.example() {
+            self.example = Some(example.to_owned());
+        }
+        self
+    }
+
+    /// Makes builds run by Gradle or Xcode record to the same metrics file.
+    pub fn with_metrics(mut self, metrics: &Metrics) -> Self {
+        if let Some(path) = metrics.path() {
+            self.metrics_file = Some(path.to_owned());
+        }
+        self
+    }
+
     pub fn prepend_to_path(mut self, path: impl AsRef<Path>) -> Self {
         self.path = format!("{}:{}", path.as_ref().display(), self.path);
         self
@@ -79,6 +214,29 @@ impl ExplicitEnv for Env {
         if let Some(ssh_auth_sock) = self.ssh_auth_sock.as_ref() {
             env.push(("SSH_AUTH_SOCK", ssh_auth_sock.as_ref()));
         }
+        env.extend(self.proxy.iter().map(|(key, value)| (*key, value.as_ref())));
+        if let Some(example) = self.example.as_ref() {
+            env.push((EntryPoint::ENV_VAR, example.as_ref()));
+        }
+        if let Some(metrics_file) = self.metrics_file.as_ref() {
+            env.push((metrics::ENV_VAR, metrics_file.as_ref()));
+        }
+        env.extend(
+            self.reproducible
+                .iter()
+                .map(|(key, value)| (*key, value.as_ref())),
+        );
+        env.extend(
+            self.overrides
+                .iter()
+                .map(|(key, value)| (*key, value.as_ref())),
+        );
+        env.push((lock::HOLDERS_ENV_VAR, self.lock_holders.as_ref()));
+        env.extend(
+            self.dotenv
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_ref())),
+        );
         env
     }
 }
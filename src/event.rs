@@ -0,0 +1,73 @@
+use crate::util::cli::Reportable;
+use std::{fmt, sync::Arc};
+
+/// Progress notifications emitted while building and running.
+///
+/// These are emitted alongside the usual terminal output, so embedders can
+/// render their own progress UI without scraping stdout.
+#[derive(Clone, Debug)]
+pub enum Event<'a> {
+    BuildStarted,
+    CompilingTarget {
+        triple: &'a str,
+    },
+    Packaging,
+    /// On iOS, installation and launch happen in a single step, so no
+    /// `Launched` event follows this.
+    Deploying {
+        device: &'a str,
+    },
+    Launched,
+    /// The last event for a successful build or run.
+    Finished,
+    /// The last event for a failed build or run, with the same message and
+    /// details the CLI would print.
+    Failed {
+        msg: &'a str,
+        details: &'a str,
+    },
+}
+
+#[derive(Clone, Default)]
+pub struct Listener {
+    callback: Option<Arc<dyn Fn(&Event<'_>) + Send + Sync>>,
+}
+
+impl fmt::Debug for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Listener")
+            .field("callback", &self.callback.as_ref().map(|_| "Fn(&Event)"))
+            .finish()
+    }
+}
+
+impl Listener {
+    pub fn new(callback: impl Fn(&Event<'_>) + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Some(Arc::new(callback)),
+        }
+    }
+
+    pub fn emit(&self, event: Event<'_>) {
+        log::debug!("emitting event {:?}", event);
+        if let Some(callback) = &self.callback {
+            callback(&event);
+        }
+    }
+
+    /// Emits `Finished` or `Failed` depending on how things went, and passes
+    /// `result` along.
+    pub fn finish<T, E: Reportable>(&self, result: Result<T, E>) -> Result<T, E> {
+        match &result {
+            Ok(_) => self.emit(Event::Finished),
+            Err(err) => {
+                let report = err.report();
+                self.emit(Event::Failed {
+                    msg: report.msg(),
+                    details: report.details(),
+                });
+            }
+        }
+        result
+    }
+}
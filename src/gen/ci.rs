@@ -0,0 +1,298 @@
+use super::WriteError;
+use crate::{
+    config::{
+        app::App,
+        metadata::{self, Metadata},
+        Config, LoadOrGenError, Overrides,
+    },
+    opts,
+    templating::Review,
+    util::cli::{Report, Reportable, TextWrapper},
+};
+use std::{path::Path, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Provider {
+    GitHub,
+    GitLab,
+}
+
+impl FromStr for Provider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "github" => Ok(Self::GitHub),
+            "gitlab" => Ok(Self::GitLab),
+            _ => Err(format!("{:?} isn't a supported CI provider", s)),
+        }
+    }
+}
+
+impl Provider {
+    pub const POSSIBLE_VALUES: &'static [&'static str] = &["github", "gitlab"];
+
+    fn path(self) -> &'static str {
+        match self {
+            Self::GitHub => ".github/workflows/cargo-mobile.yml",
+            Self::GitLab => ".gitlab-ci.yml",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadOrGenError),
+    MetadataFailed(metadata::Error),
+    NothingToBuild,
+    WriteFailed(WriteError),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            Self::MetadataFailed(err) => err.report(),
+            Self::NothingToBuild => Report::error(
+                "Failed to generate CI config",
+                "Neither Apple nor Android are supported by this project",
+            ),
+            Self::WriteFailed(err) => Report::error("Failed to write CI config", err),
+        }
+    }
+}
+
+static INSTALL: &str = "cargo install --git https://github.com/BrainiumLLC/cargo-mobile";
+
+/// What each platform's job needs to know, independent of the provider.
+#[derive(Debug)]
+struct Job {
+    name: &'static str,
+    targets: Vec<&'static str>,
+    setup: Vec<String>,
+    script: Vec<String>,
+    artifacts: Vec<String>,
+}
+
+fn rel(app: &App, path: impl AsRef<Path>) -> String {
+    let path = path.as_ref();
+    app.unprefix_path(path)
+        .unwrap_or_else(|_| path.to_owned())
+        .display()
+        .to_string()
+}
+
+// Archiving needs signing certificates, which are better handled by
+// `cargo mobile gen fastlane`; this just makes sure everything builds. The job
+// runs on a macOS runner, so it's generated regardless of the host.
+fn apple_job(config: &Config) -> Job {
+    let app = config.app();
+    Job {
+        name: "apple",
+        targets: vec!["aarch64-apple-ios", "x86_64-apple-ios"],
+        setup: Vec::new(),
+        script: vec![
+            "cargo apple check aarch64 x86_64".to_owned(),
+            "cargo apple build --release aarch64".to_owned(),
+        ],
+        artifacts: vec![format!(
+            "{}/aarch64-apple-ios/release/*.a",
+            rel(app, app.target_dir())
+        )],
+    }
+}
+
+fn android_job(config: &Config) -> Job {
+    let android = config.android();
+    let project_dir = rel(config.app(), android.project_dir());
+    Job {
+        name: "android",
+        targets: vec![
+            "aarch64-linux-android",
+            "armv7-linux-androideabi",
+            "i686-linux-android",
+            "x86_64-linux-android",
+        ],
+        // The runner images ship with the SDK and an NDK, under different
+        // names than the ones cargo-mobile looks for.
+        setup: vec![
+            "export ANDROID_SDK_ROOT=\"${ANDROID_SDK_ROOT:-$ANDROID_HOME}\"".to_owned(),
+            "export NDK_HOME=\"${NDK_HOME:-${ANDROID_NDK_LATEST_HOME:-$ANDROID_NDK_HOME}}\""
+                .to_owned(),
+        ],
+        script: vec![
+            "cargo android check aarch64 armv7 i686 x86_64".to_owned(),
+            format!("(cd {} && ./gradlew assembleRelease)", project_dir),
+        ],
+        artifacts: vec![format!("{}/app/build/outputs/apk/**/*.apk", project_dir)],
+    }
+}
+
+fn jobs(config: &Config, metadata: &Metadata) -> Vec<Job> {
+    let mut jobs = Vec::new();
+    if metadata.apple_supported() {
+        jobs.push(apple_job(config));
+    }
+    if metadata.android().supported() {
+        jobs.push(android_job(config));
+    }
+    jobs
+}
+
+fn runner(provider: Provider, job: &Job) -> &'static str {
+    match (provider, job.name) {
+        (Provider::GitHub, "apple") => "macos-latest",
+        (Provider::GitHub, _) => "ubuntu-latest",
+        (Provider::GitLab, "apple") => "saas-macos-medium-m1",
+        (Provider::GitLab, _) => "saas-linux-medium-amd64",
+    }
+}
+
+fn github(app: &App, jobs: &[Job]) -> String {
+    let mut workflow = format!(
+        r#"# Generated by `cargo mobile gen ci`
+name: {name}
+
+on:
+  push:
+    branches: [main]
+  pull_request:
+
+jobs:"#,
+        name = app.name(),
+    );
+    for job in jobs {
+        let run = |lines: &[String]| {
+            lines
+                .iter()
+                .map(|line| format!("\n          {}", line))
+                .collect::<String>()
+        };
+        workflow.push_str(&format!(
+            r#"
+  {name}:
+    runs-on: {runner}
+    steps:
+      - uses: actions/checkout@v4
+        with:
+          submodules: recursive
+      - uses: dtolnay/rust-toolchain@stable
+        with:
+          targets: {targets}
+      - uses: actions/cache@v4
+        with:
+          path: |
+            ~/.cargo/bin
+            ~/.cargo/registry
+            ~/.cargo/git
+            {target_dir}
+          key: {name}-cargo-${{{{ hashFiles('**/Cargo.lock') }}}}
+          restore-keys: {name}-cargo-
+      - name: Install cargo-mobile
+        run: {install}
+      - name: Build
+        run: |{setup}{script}
+      - uses: actions/upload-artifact@v4
+        with:
+          name: {name}
+          path: |{artifacts}
+"#,
+            name = job.name,
+            runner = runner(Provider::GitHub, job),
+            targets = job.targets.join(","),
+            target_dir = rel(app, app.target_dir()),
+            install = INSTALL,
+            setup = run(&job.setup),
+            script = run(&job.script),
+            artifacts = job
+                .artifacts
+                .iter()
+                .map(|artifact| format!("\n            {}", artifact))
+                .collect::<String>(),
+        ));
+    }
+    workflow
+}
+
+fn gitlab(app: &App, jobs: &[Job]) -> String {
+    let mut pipeline = String::from(
+        r#"# Generated by `cargo mobile gen ci`
+variables:
+  GIT_SUBMODULE_STRATEGY: recursive
+  CARGO_HOME: $CI_PROJECT_DIR/.cargo
+
+stages:
+  - build
+"#,
+    );
+    for job in jobs {
+        let list = |lines: &[String], indent: &str| {
+            lines
+                .iter()
+                .map(|line| format!("\n{}- {}", indent, line))
+                .collect::<String>()
+        };
+        let mut script = vec![
+            "curl https://sh.rustup.rs -sSf | sh -s -- -y --profile minimal".to_owned(),
+            "source $CARGO_HOME/env".to_owned(),
+            format!("rustup target add {}", job.targets.join(" ")),
+            INSTALL.to_owned(),
+        ];
+        script.extend(job.setup.iter().cloned());
+        script.extend(job.script.iter().cloned());
+        pipeline.push_str(&format!(
+            r#"
+{name}:
+  stage: build
+  tags:
+    - {runner}
+  cache:
+    key: {name}-cargo
+    paths:
+      - .cargo/bin
+      - .cargo/registry
+      - .cargo/git
+      - {target_dir}
+  script:{script}
+  artifacts:
+    paths:{artifacts}
+"#,
+            name = job.name,
+            runner = runner(Provider::GitLab, job),
+            target_dir = rel(app, app.target_dir()),
+            script = list(&script, "    "),
+            artifacts = list(&job.artifacts, "      "),
+        ));
+    }
+    pipeline
+}
+
+/// Writes a CI pipeline that builds for every supported platform, with each
+/// platform on a runner that can actually build for it.
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    accept_changes: opts::AcceptChanges,
+    overrides: &Overrides,
+    provider: Provider,
+    cwd: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let (config, _origin) = Config::load_or_gen(cwd, non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigFailed)?;
+    let metadata =
+        Metadata::load(&config.app().crate_dir(), overrides).map_err(Error::MetadataFailed)?;
+    let jobs = jobs(&config, &metadata);
+    if jobs.is_empty() {
+        return Err(Error::NothingToBuild);
+    }
+    let contents = match provider {
+        Provider::GitHub => github(config.app(), &jobs),
+        Provider::GitLab => gitlab(config.app(), &jobs),
+    };
+    super::write_reviewed(
+        config.app().root_dir(),
+        &[(provider.path(), contents)],
+        Review::new(non_interactive, accept_changes),
+    )
+    .map_err(Error::WriteFailed)
+}
@@ -0,0 +1,195 @@
+use super::WriteError;
+use crate::{
+    config::{
+        app::App,
+        metadata::{self, Metadata},
+        Config, LoadOrGenError, Overrides,
+    },
+    opts,
+    templating::Review,
+    util::cli::{Report, Reportable, TextWrapper},
+};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadOrGenError),
+    MetadataFailed(metadata::Error),
+    WriteFailed(WriteError),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            Self::MetadataFailed(err) => err.report(),
+            Self::WriteFailed(err) => Report::error("Failed to write fastlane config", err),
+        }
+    }
+}
+
+// fastlane runs lanes from the project root, but `sh` from `fastlane/`.
+static HELPERS: &str = r#"# Generated by `cargo mobile gen fastlane`; regenerating it will show you a
+# diff before overwriting any changes you've made.
+
+# Runs `cargo mobile` from the project root.
+def cargo_mobile(*args)
+  Dir.chdir("..") { sh("cargo", "mobile", *args) }
+end
+"#;
+
+/// Paths in the Fastfile are relative to the project root, so the project
+/// can be moved (or checked out somewhere else on CI).
+fn rel(app: &App, path: impl AsRef<Path>) -> String {
+    let path = path.as_ref();
+    app.unprefix_path(path)
+        .unwrap_or_else(|_| path.to_owned())
+        .display()
+        .to_string()
+}
+
+// The lanes run on macOS, but can be generated anywhere, so the Xcode paths
+// are derived here; they need to match the ones in `apple::config::Config`.
+fn apple_lanes(config: &Config) -> String {
+    let app = config.app();
+    let project_dir = config.apple_project_dir();
+    let scheme = format!("{}_iOS", app.name());
+    let workspace = project_dir.join(format!("{}.xcodeproj/project.xcworkspace/", app.name()));
+    let build_dir = project_dir.join("build");
+    let archive = build_dir.join(format!("{}.xcarchive", scheme));
+    format!(
+        r#"
+platform :ios do
+  desc "Build the Rust library for a target (defaults to aarch64)"
+  lane :build do |options|
+    cargo_mobile("apple", "build", options[:target] || "aarch64", "--release")
+  end
+
+  desc "Build and archive the app"
+  lane :archive do
+    cargo_mobile("apple", "archive", "--release")
+  end
+
+  desc "Export the archive as an IPA, and upload it to TestFlight"
+  lane :beta do
+    archive
+    build_app(
+      workspace: "{workspace}",
+      scheme: "{scheme}",
+      skip_build_archive: true,
+      archive_path: "{archive}",
+      export_options: "{export_options}",
+      output_directory: "{export_dir}",
+    )
+    upload_to_testflight
+  end
+end
+"#,
+        workspace = rel(app, workspace),
+        scheme = scheme,
+        archive = rel(app, archive),
+        export_options = rel(app, project_dir.join("ExportOptions.plist")),
+        export_dir = rel(app, build_dir),
+    )
+}
+
+fn android_lanes(config: &crate::android::config::Config) -> String {
+    let app = config.app();
+    format!(
+        r#"
+platform :android do
+  desc "Build the Rust library for a target (defaults to aarch64)"
+  lane :build do |options|
+    cargo_mobile("android", "build", options[:target] || "aarch64", "--release")
+  end
+
+  # Gradle builds the Rust library itself. Release signing reads
+  # `CARGO_MOBILE_KEYSTORE_PASSWORD` and `CARGO_MOBILE_KEY_PASSWORD`.
+  desc "Assemble a release APK for an ABI flavor (defaults to {flavor})"
+  lane :apk do |options|
+    gradle(
+      project_dir: "{project_dir}",
+      task: "assemble",
+      flavor: options[:flavor] || "{flavor}",
+      build_type: "Release",
+    )
+  end
+
+  desc "Assemble a release APK, and upload it to the internal track"
+  lane :beta do |options|
+    apk(options)
+    upload_to_play_store(
+      package_name: "{package_name}",
+      track: "internal",
+      apk: lane_context[SharedValues::GRADLE_APK_OUTPUT_PATH],
+    )
+  end
+end
+"#,
+        project_dir = rel(app, config.project_dir()),
+        // Flavors are named after arches, i.e. `arm64` for aarch64.
+        flavor = "Arm64",
+        package_name = android_package_name(app),
+    )
+}
+
+// These need to match the identifiers in the platform template packs.
+fn apple_bundle_id(app: &App) -> String {
+    format!("{}.{}", app.reverse_domain(), app.name())
+}
+
+fn android_package_name(app: &App) -> String {
+    format!("{}.{}", app.reverse_domain(), app.name_snake())
+}
+
+fn appfile(config: &Config, metadata: &Metadata) -> String {
+    let mut appfile = String::from("# Generated by `cargo mobile gen fastlane`\n");
+    if metadata.apple_supported() {
+        appfile.push_str(&format!(
+            "\nfor_platform :ios do\n  app_identifier(\"{}\")\nend\n",
+            apple_bundle_id(config.app())
+        ));
+    }
+    if metadata.android().supported() {
+        appfile.push_str(&format!(
+            "\nfor_platform :android do\n  package_name(\"{}\")\nend\n",
+            android_package_name(config.app())
+        ));
+    }
+    appfile
+}
+
+fn fastfile(config: &Config, metadata: &Metadata) -> String {
+    let mut fastfile = HELPERS.to_owned();
+    if metadata.apple_supported() {
+        fastfile.push_str(&apple_lanes(config));
+    }
+    if metadata.android().supported() {
+        fastfile.push_str(&android_lanes(config.android()));
+    }
+    fastfile
+}
+
+/// Writes a `Fastfile` (and `Appfile`) whose lanes call into `cargo mobile`
+/// for building, and into fastlane's own actions for exporting and uploading.
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    accept_changes: opts::AcceptChanges,
+    overrides: &Overrides,
+    cwd: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let (config, _origin) = Config::load_or_gen(cwd, non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigFailed)?;
+    let metadata =
+        Metadata::load(&config.app().crate_dir(), overrides).map_err(Error::MetadataFailed)?;
+    super::write_reviewed(
+        config.app().root_dir(),
+        &[
+            ("fastlane/Appfile", appfile(&config, &metadata)),
+            ("fastlane/Fastfile", fastfile(&config, &metadata)),
+        ],
+        Review::new(non_interactive, accept_changes),
+    )
+    .map_err(Error::WriteFailed)
+}
@@ -0,0 +1,223 @@
+use super::{
+    native::{self, android_package_name},
+    WriteError,
+};
+use crate::{
+    android,
+    config::{app::App, Config, LoadOrGenError, Overrides},
+    opts,
+    templating::Review,
+    util::cli::{Report, Reportable, TextWrapper},
+};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadOrGenError),
+    WriteFailed(WriteError),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            Self::WriteFailed(err) => Report::error("Failed to write Flutter plugin", err),
+        }
+    }
+}
+
+/// Where the plugin package goes, relative to the project root.
+static PLUGIN_DIR: &str = "flutter";
+
+// The plugin is always `flutter/<name>/<platform>`, so the project root is
+// three levels up from either platform dir.
+static ROOT_FROM_PLATFORM: &str = "../../..";
+
+fn pubspec(app: &App) -> String {
+    format!(
+        r#"# Generated by `cargo mobile gen flutter`
+name: {name}
+description: Flutter FFI plugin wrapping the `{name}` Rust library.
+version: {version}
+publish_to: none
+
+environment:
+  sdk: ">=2.17.0 <4.0.0"
+  flutter: ">=3.0.0"
+
+dependencies:
+  flutter:
+    sdk: flutter
+
+flutter:
+  plugin:
+    platforms:
+      android:
+        ffiPlugin: true
+      ios:
+        ffiPlugin: true
+"#,
+        name = app.name_snake(),
+        version = app.version(),
+    )
+}
+
+fn dart_lib(config: &android::config::Config) -> String {
+    format!(
+        r#"// Generated by `cargo mobile gen flutter`
+
+import 'dart:ffi';
+import 'dart:io';
+
+/// The Rust library. Look up its `extern "C"` functions with
+/// `library.lookupFunction`, or pass this to `ffigen`'s generated bindings.
+///
+/// On iOS the library is statically linked into the app, so its symbols are
+/// already in the process.
+final DynamicLibrary library = Platform.isAndroid
+    ? DynamicLibrary.open('{so_name}')
+    : DynamicLibrary.process();
+"#,
+        so_name = config.so_name(),
+    )
+}
+
+fn podspec(app: &App) -> String {
+    let name = app.name_snake();
+    format!(
+        r#"# Generated by `cargo mobile gen flutter`
+Pod::Spec.new do |s|
+  s.name             = '{name}'
+  s.version          = '{version}'
+  s.summary          = 'Flutter FFI plugin wrapping the {name} Rust library.'
+  s.homepage         = 'https://github.com/BrainiumLLC/cargo-mobile'
+  s.license          = {{ :type => 'Proprietary' }}
+  s.author           = {{ '{name}' => '' }}
+  s.source           = {{ :path => '.' }}
+  s.source_files     = 'Classes/**/*'
+  s.dependency 'Flutter'
+  s.platform         = :ios, '9.0'
+  s.pod_target_xcconfig = {{
+    'DEFINES_MODULE' => 'YES',
+    'EXCLUDED_ARCHS[sdk=iphonesimulator*]' => 'i386',
+  }}
+
+{build}end
+"#,
+        name = name,
+        version = app.version(),
+        build = native::podspec_build(app, &name, ROOT_FROM_PLATFORM),
+    )
+}
+
+// CocoaPods won't build a pod without any sources.
+static IOS_CLASS: &str = "// Generated by `cargo mobile gen flutter`; the Rust library is linked in by\n// the podspec, but CocoaPods needs at least one source file.\n";
+
+fn gradle(config: &android::config::Config) -> String {
+    let app = config.app();
+    format!(
+        r#"// Generated by `cargo mobile gen flutter`
+
+group '{package}'
+version '{version}'
+
+buildscript {{
+    repositories {{
+        google()
+        mavenCentral()
+    }}
+    dependencies {{
+        classpath 'com.android.tools.build:gradle:7.3.0'
+    }}
+}}
+
+rootProject.allprojects {{
+    repositories {{
+        google()
+        mavenCentral()
+    }}
+}}
+
+apply plugin: 'com.android.library'
+
+android {{
+    namespace '{package}'
+    compileSdkVersion 33
+    defaultConfig {{
+        minSdkVersion {min_sdk}
+    }}
+    sourceSets {{
+{jni_libs}
+    }}
+}}
+
+{cargo_build}"#,
+        package = android_package_name(app),
+        version = app.version(),
+        min_sdk = config.min_sdk_version(),
+        jni_libs = native::gradle_jni_libs(config, ROOT_FROM_PLATFORM),
+        cargo_build = native::gradle_cargo_build(ROOT_FROM_PLATFORM),
+    )
+}
+
+fn android_manifest(app: &App) -> String {
+    format!(
+        "<manifest xmlns:android=\"http://schemas.android.com/apk/res/android\"\n  package=\"{}\">\n</manifest>\n",
+        android_package_name(app)
+    )
+}
+
+/// Writes a Flutter FFI plugin package whose platform builds call into
+/// `cargo mobile`, so a Flutter app can depend on it by path.
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    accept_changes: opts::AcceptChanges,
+    overrides: &Overrides,
+    cwd: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let (config, _origin) = Config::load_or_gen(cwd, non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigFailed)?;
+    let app = config.app();
+    let name = app.name_snake();
+    let dir = format!("{}/{}", PLUGIN_DIR, name);
+    let files = vec![
+        (format!("{}/pubspec.yaml", dir), pubspec(app)),
+        (
+            format!("{}/lib/{}.dart", dir, name),
+            dart_lib(config.android()),
+        ),
+        (format!("{}/ios/{}.podspec", dir, name), podspec(app)),
+        (
+            format!("{}/ios/Classes/{}.c", dir, name),
+            IOS_CLASS.to_owned(),
+        ),
+        (
+            format!("{}/android/build.gradle", dir),
+            gradle(config.android()),
+        ),
+        (
+            format!("{}/android/settings.gradle", dir),
+            format!("rootProject.name = '{}'\n", name),
+        ),
+        (
+            format!("{}/android/src/main/AndroidManifest.xml", dir),
+            android_manifest(app),
+        ),
+    ];
+    let files = files
+        .iter()
+        .map(|(path, contents)| (path.as_str(), contents.clone()))
+        .collect::<Vec<_>>();
+    super::write_reviewed(
+        app.root_dir(),
+        &files,
+        Review::new(non_interactive, accept_changes),
+    )
+    .map_err(Error::WriteFailed)?;
+    println!(
+        "Add `{}: {{ path: {} }}` to your Flutter app's `pubspec.yaml` dependencies to use it.",
+        name, dir
+    );
+    Ok(())
+}
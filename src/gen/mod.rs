@@ -0,0 +1,55 @@
+pub mod ci;
+pub mod fastlane;
+pub mod flutter;
+mod native;
+pub mod react_native;
+
+use crate::templating::{DryRun, DryRunError, Review, ReviewError};
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum WriteError {
+    StagingFailed(DryRunError),
+    WriteFailed { path: PathBuf, cause: io::Error },
+    ReviewFailed(ReviewError),
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StagingFailed(err) => write!(f, "{}", err),
+            Self::WriteFailed { path, cause } => {
+                write!(f, "Failed to write {:?}: {}", path, cause)
+            }
+            Self::ReviewFailed(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Writes generated files (relative to `root`) through a staging dir, so
+/// files that were edited since they were generated get the same
+/// diff-and-confirm treatment as they do in `init`.
+fn write_reviewed(root: &Path, files: &[(&str, String)], review: Review) -> Result<(), WriteError> {
+    let staging = DryRun::new(root).map_err(WriteError::StagingFailed)?;
+    for (rel, contents) in files {
+        let path = staging.stage(&root.join(rel));
+        let result = path
+            .parent()
+            .map(fs::create_dir_all)
+            .transpose()
+            .and_then(|_| fs::write(&path, contents));
+        result.map_err(|cause| WriteError::WriteFailed {
+            path: path.clone(),
+            cause,
+        })?;
+    }
+    review.apply(&staging).map_err(WriteError::ReviewFailed)?;
+    for (rel, _) in files {
+        println!("Generated {:?}", root.join(rel));
+    }
+    Ok(())
+}
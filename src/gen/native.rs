@@ -0,0 +1,99 @@
+//! Build steps shared by the packages that wrap the Rust library for another
+//! framework (Flutter, React Native), so that building the package builds the
+//! library with `cargo mobile` first.
+
+use crate::{
+    android::{self, target::Target},
+    config::app::App,
+    target::TargetTrait as _,
+};
+use std::path::Path;
+
+pub(super) fn rel(app: &App, path: impl AsRef<Path>) -> String {
+    let path = path.as_ref();
+    app.unprefix_path(path)
+        .unwrap_or_else(|_| path.to_owned())
+        .display()
+        .to_string()
+}
+
+pub(super) fn android_package_name(app: &App) -> String {
+    format!("{}.{}", app.reverse_domain(), app.name_snake())
+}
+
+/// Podspec lines that build the static library with `cargo apple` before the
+/// pod is compiled, and force load it into the app (nothing references its
+/// symbols at link time, so they'd otherwise be stripped). `root` is the
+/// project root relative to the podspec.
+pub(super) fn podspec_build(app: &App, pod_name: &str, root: &str) -> String {
+    let lib_name = app.lib_name();
+    format!(
+        r#"  s.script_phase = {{
+    :name => 'Build Rust library',
+    :execution_position => :before_compile,
+    :output_files => ['${{BUILT_PRODUCTS_DIR}}/lib{lib_name}.a'],
+    :script => <<~SCRIPT
+      set -e
+      case "$CONFIGURATION" in
+        Debug) PROFILE=debug ;;
+        *) PROFILE=release ;;
+      esac
+      cd "$PODS_TARGET_SRCROOT/{root}"
+      "$HOME/.cargo/bin/cargo-apple" xcode-script -v --platform "$PLATFORM_DISPLAY_NAME" --sdk-root "$SDKROOT" --configuration "$PROFILE" $ARCHS
+      LIBS=""
+      for ARCH in $ARCHS; do
+        case "$ARCH" in
+          arm64) TRIPLE=aarch64-apple-ios ;;
+          x86_64) TRIPLE=x86_64-apple-ios ;;
+          *) echo "unsupported arch $ARCH" >&2; exit 1 ;;
+        esac
+        LIBS="$LIBS {target_dir}/$TRIPLE/$PROFILE/lib{lib_name}.a"
+      done
+      lipo -create $LIBS -output "$BUILT_PRODUCTS_DIR/lib{lib_name}.a"
+    SCRIPT
+  }}
+  s.user_target_xcconfig = {{
+    'OTHER_LDFLAGS' => '$(inherited) -force_load "${{PODS_CONFIGURATION_BUILD_DIR}}/{pod_name}/lib{lib_name}.a"',
+  }}
+"#,
+        lib_name = lib_name,
+        pod_name = pod_name,
+        root = root,
+        target_dir = rel(app, app.target_dir()),
+    )
+}
+
+/// Adds the Android project's `jniLibs` dir, which `cargo android build`
+/// links the libraries it builds into, to the module's sources. Goes in the
+/// `android` block; `root` is the project root relative to the module.
+pub(super) fn gradle_jni_libs(config: &android::config::Config, root: &str) -> String {
+    format!(
+        "        main.jniLibs.srcDirs += '{}/{}'",
+        root,
+        rel(
+            config.app(),
+            config.project_dir().join("app/src/main/jniLibs")
+        )
+    )
+}
+
+/// A task that builds release libraries for every Android target before the
+/// module is built.
+pub(super) fn gradle_cargo_build(root: &str) -> String {
+    let targets = Target::all()
+        .keys()
+        .map(|key| format!("'{}'", key))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        r#"task cargoBuild(type: Exec) {{
+    workingDir '{root}'
+    commandLine 'cargo', 'android', 'build', '--release', {targets}
+}}
+
+preBuild.dependsOn cargoBuild
+"#,
+        root = root,
+        targets = targets,
+    )
+}
@@ -0,0 +1,355 @@
+use super::{
+    native::{self, android_package_name},
+    WriteError,
+};
+use crate::{
+    android,
+    config::{app::App, Config, LoadOrGenError, Overrides},
+    opts,
+    templating::Review,
+    util::cli::{Report, Reportable, TextWrapper},
+};
+use heck::CamelCase as _;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadOrGenError),
+    WriteFailed(WriteError),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            Self::WriteFailed(err) => {
+                Report::error("Failed to write React Native native module", err)
+            }
+        }
+    }
+}
+
+/// Where the module package goes, relative to the project root.
+static MODULE_DIR: &str = "react-native";
+
+// The podspec has to be at the package root for autolinking to find it, so
+// it's two levels down, while the Gradle module is three.
+static ROOT_FROM_PACKAGE: &str = "../..";
+static ROOT_FROM_ANDROID: &str = "../../..";
+
+/// What the module's registered as, on both platforms.
+fn module_name(app: &App) -> String {
+    app.name().to_camel_case()
+}
+
+fn package_json(app: &App) -> String {
+    format!(
+        r#"{{
+  "name": "{name}",
+  "version": "{version}",
+  "description": "React Native native module wrapping the {name} Rust library. Generated by `cargo mobile gen react-native`.",
+  "main": "src/index.ts",
+  "react-native": "src/index.ts",
+  "private": true,
+  "peerDependencies": {{
+    "react-native": "*"
+  }},
+  "codegenConfig": {{
+    "name": "{module}Spec",
+    "type": "modules",
+    "jsSrcsDir": "src",
+    "android": {{
+      "javaPackageName": "{package}"
+    }}
+  }}
+}}
+"#,
+        name = app.name(),
+        version = app.version(),
+        module = module_name(app),
+        package = android_package_name(app),
+    )
+}
+
+fn spec(app: &App) -> String {
+    format!(
+        r#"// Generated by `cargo mobile gen react-native`
+
+import type {{ TurboModule }} from 'react-native';
+import {{ TurboModuleRegistry }} from 'react-native';
+
+// Add the methods that call into the Rust library here, then implement them in
+// `ios/{module}.mm` and `android/.../{module}Module.java`. `get` falls back to
+// the legacy bridge when the new architecture isn't enabled.
+export interface Spec extends TurboModule {{}}
+
+export default TurboModuleRegistry.get<Spec>('{module}');
+"#,
+        module = module_name(app),
+    )
+}
+
+fn index(app: &App) -> String {
+    format!(
+        "// Generated by `cargo mobile gen react-native`\n\nexport {{ default }} from './Native{}';\n",
+        module_name(app)
+    )
+}
+
+fn podspec(app: &App) -> String {
+    let name = app.name();
+    format!(
+        r#"# Generated by `cargo mobile gen react-native`
+Pod::Spec.new do |s|
+  s.name             = '{name}'
+  s.version          = '{version}'
+  s.summary          = 'React Native native module wrapping the {name} Rust library.'
+  s.homepage         = 'https://github.com/BrainiumLLC/cargo-mobile'
+  s.license          = {{ :type => 'Proprietary' }}
+  s.author           = {{ '{name}' => '' }}
+  s.source           = {{ :path => '.' }}
+  s.source_files     = 'ios/**/*.{{h,m,mm}}'
+  s.platform         = :ios, '12.4'
+
+  # Pulls in the codegen'd TurboModule specs when the new architecture is
+  # enabled, and just `React-Core` otherwise.
+  if respond_to?(:install_modules_dependencies, true)
+    install_modules_dependencies(s)
+  else
+    s.dependency 'React-Core'
+  end
+
+{build}end
+"#,
+        name = name,
+        version = app.version(),
+        build = native::podspec_build(app, name, ROOT_FROM_PACKAGE),
+    )
+}
+
+fn ios_header(app: &App) -> String {
+    format!(
+        r#"// Generated by `cargo mobile gen react-native`
+
+#ifdef RCT_NEW_ARCH_ENABLED
+#import "{module}Spec.h"
+
+@interface {module} : NSObject <Native{module}Spec>
+#else
+#import <React/RCTBridgeModule.h>
+
+@interface {module} : NSObject <RCTBridgeModule>
+#endif
+
+@end
+"#,
+        module = module_name(app),
+    )
+}
+
+fn ios_impl(app: &App) -> String {
+    format!(
+        r#"// Generated by `cargo mobile gen react-native`
+
+#import "{module}.h"
+
+// The Rust library is statically linked into the app, so its `extern "C"`
+// functions can be declared and called directly from here.
+@implementation {module}
+
+RCT_EXPORT_MODULE()
+
+#ifdef RCT_NEW_ARCH_ENABLED
+- (std::shared_ptr<facebook::react::TurboModule>)getTurboModule:
+    (const facebook::react::ObjCTurboModule::InitParams &)params
+{{
+    return std::make_shared<facebook::react::Native{module}SpecJSI>(params);
+}}
+#endif
+
+@end
+"#,
+        module = module_name(app),
+    )
+}
+
+fn gradle(config: &android::config::Config) -> String {
+    let app = config.app();
+    format!(
+        r#"// Generated by `cargo mobile gen react-native`
+
+buildscript {{
+    repositories {{
+        google()
+        mavenCentral()
+    }}
+    dependencies {{
+        classpath 'com.android.tools.build:gradle:7.3.0'
+    }}
+}}
+
+apply plugin: 'com.android.library'
+
+android {{
+    namespace '{package}'
+    compileSdkVersion 33
+    defaultConfig {{
+        minSdkVersion {min_sdk}
+    }}
+    sourceSets {{
+{jni_libs}
+    }}
+}}
+
+repositories {{
+    google()
+    mavenCentral()
+}}
+
+dependencies {{
+    // Provided by the app, which picks the React Native version.
+    implementation 'com.facebook.react:react-native:+'
+}}
+
+{cargo_build}"#,
+        package = android_package_name(app),
+        min_sdk = config.min_sdk_version(),
+        jni_libs = native::gradle_jni_libs(config, ROOT_FROM_ANDROID),
+        cargo_build = native::gradle_cargo_build(ROOT_FROM_ANDROID),
+    )
+}
+
+fn android_manifest(app: &App) -> String {
+    format!(
+        "<manifest xmlns:android=\"http://schemas.android.com/apk/res/android\"\n  package=\"{}\">\n</manifest>\n",
+        android_package_name(app)
+    )
+}
+
+// This is a legacy module, which the new architecture's interop layer runs as
+// is, so the same class works under both.
+fn java_module(config: &android::config::Config) -> String {
+    let app = config.app();
+    format!(
+        r#"// Generated by `cargo mobile gen react-native`
+
+package {package};
+
+import com.facebook.react.bridge.ReactApplicationContext;
+import com.facebook.react.bridge.ReactContextBaseJavaModule;
+
+public class {module}Module extends ReactContextBaseJavaModule {{
+    static {{
+        System.loadLibrary("{lib_name}");
+    }}
+
+    {module}Module(ReactApplicationContext context) {{
+        super(context);
+    }}
+
+    @Override
+    public String getName() {{
+        return "{module}";
+    }}
+
+    // Declare the Rust library's JNI functions as `native` methods here, and
+    // expose them to JS with `@ReactMethod`.
+}}
+"#,
+        package = android_package_name(app),
+        module = module_name(app),
+        lib_name = app.lib_name(),
+    )
+}
+
+fn java_package(app: &App) -> String {
+    format!(
+        r#"// Generated by `cargo mobile gen react-native`
+
+package {package};
+
+import com.facebook.react.ReactPackage;
+import com.facebook.react.bridge.NativeModule;
+import com.facebook.react.bridge.ReactApplicationContext;
+import com.facebook.react.uimanager.ViewManager;
+import java.util.Collections;
+import java.util.List;
+
+public class {module}Package implements ReactPackage {{
+    @Override
+    public List<NativeModule> createNativeModules(ReactApplicationContext context) {{
+        return Collections.<NativeModule>singletonList(new {module}Module(context));
+    }}
+
+    @Override
+    public List<ViewManager> createViewManagers(ReactApplicationContext context) {{
+        return Collections.emptyList();
+    }}
+}}
+"#,
+        package = android_package_name(app),
+        module = module_name(app),
+    )
+}
+
+/// Writes a React Native native module package whose platform builds call
+/// into `cargo mobile`, so an app can depend on it by path and have it
+/// autolinked into its Xcode and Gradle projects.
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    accept_changes: opts::AcceptChanges,
+    overrides: &Overrides,
+    cwd: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let (config, _origin) = Config::load_or_gen(cwd, non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigFailed)?;
+    let app = config.app();
+    let module = module_name(app);
+    let dir = format!("{}/{}", MODULE_DIR, app.name());
+    let java_dir = format!(
+        "{}/android/src/main/java/{}",
+        dir,
+        android_package_name(app).replace('.', "/")
+    );
+    let files = vec![
+        (format!("{}/package.json", dir), package_json(app)),
+        (format!("{}/src/index.ts", dir), index(app)),
+        (format!("{}/src/Native{}.ts", dir, module), spec(app)),
+        (format!("{}/{}.podspec", dir, app.name()), podspec(app)),
+        (format!("{}/ios/{}.h", dir, module), ios_header(app)),
+        (format!("{}/ios/{}.mm", dir, module), ios_impl(app)),
+        (
+            format!("{}/android/build.gradle", dir),
+            gradle(config.android()),
+        ),
+        (
+            format!("{}/android/src/main/AndroidManifest.xml", dir),
+            android_manifest(app),
+        ),
+        (
+            format!("{}/{}Module.java", java_dir, module),
+            java_module(config.android()),
+        ),
+        (
+            format!("{}/{}Package.java", java_dir, module),
+            java_package(app),
+        ),
+    ];
+    let files = files
+        .iter()
+        .map(|(path, contents)| (path.as_str(), contents.clone()))
+        .collect::<Vec<_>>();
+    super::write_reviewed(
+        app.root_dir(),
+        &files,
+        Review::new(non_interactive, accept_changes),
+    )
+    .map_err(Error::WriteFailed)?;
+    println!(
+        "Add `\"{}\": \"file:{}\"` to your React Native app's `package.json` dependencies, and reinstall pods, to use it.",
+        app.name(),
+        dir
+    );
+    Ok(())
+}
@@ -0,0 +1,342 @@
+use crate::android;
+#[cfg(target_os = "macos")]
+use crate::apple;
+use crate::{
+    config::{
+        app::App,
+        metadata::{self, Metadata},
+        Config, LoadOrGenError, Overrides,
+    },
+    opts,
+    util::cli::{self, Report, Reportable, TextWrapper},
+};
+use once_cell_regex::regex;
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// Each locale gets a flat `key = "string"` TOML file in here, which is synced
+// into the platform-specific formats whenever we build.
+static DIR_NAME: &str = "i18n";
+
+// The generated Android project already uses this for `android.label`, so a
+// translation of it would silently replace the configured display name.
+static RESERVED_KEY: &str = "app_name";
+
+#[derive(Debug)]
+pub enum Error {
+    LocaleInvalid {
+        locale: String,
+    },
+    DirReadFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ReadFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ParseFailed {
+        path: PathBuf,
+        cause: toml::de::Error,
+    },
+    ValueInvalid {
+        path: PathBuf,
+        key: String,
+    },
+    KeyInvalid {
+        path: PathBuf,
+        key: String,
+    },
+    KeyReserved {
+        path: PathBuf,
+        key: String,
+    },
+    DirCreationFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    WriteFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LocaleInvalid { locale } => write!(
+                f,
+                "{:?} isn't a valid locale; locales should look like \"fr\" or \"pt-BR\".",
+                locale
+            ),
+            Self::DirReadFailed { path, cause } => {
+                write!(f, "Failed to read strings directory {:?}: {}", path, cause)
+            }
+            Self::ReadFailed { path, cause } => {
+                write!(f, "Failed to read strings file {:?}: {}", path, cause)
+            }
+            Self::ParseFailed { path, cause } => {
+                write!(f, "Failed to parse strings file {:?}: {}", path, cause)
+            }
+            Self::ValueInvalid { path, key } => write!(
+                f,
+                "Value for {:?} in strings file {:?} isn't a string.",
+                key, path
+            ),
+            Self::KeyInvalid { path, key } => write!(
+                f,
+                "Key {:?} in strings file {:?} isn't a valid Android resource name; keys can only contain letters, digits, and underscores, and can't start with a digit.",
+                key, path
+            ),
+            Self::KeyReserved { path, key } => write!(
+                f,
+                "Key {:?} in strings file {:?} is reserved for the app's display name; set `android.label` and `apple.name` instead.",
+                key, path
+            ),
+            Self::DirCreationFailed { path, cause } => {
+                write!(f, "Failed to create directory at {:?}: {}", path, cause)
+            }
+            Self::WriteFailed { path, cause } => {
+                write!(f, "Failed to write strings to {:?}: {}", path, cause)
+            }
+        }
+    }
+}
+
+pub fn dir(app: &App) -> PathBuf {
+    app.root_dir().join(DIR_NAME)
+}
+
+fn check_locale(locale: &str) -> Result<(), Error> {
+    if regex!(r"^[a-zA-Z]{2,3}([-_][a-zA-Z0-9]{2,4})?$").is_match(locale) {
+        Ok(())
+    } else {
+        Err(Error::LocaleInvalid {
+            locale: locale.to_owned(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Strings {
+    locale: String,
+    strings: Vec<(String, String)>,
+}
+
+impl Strings {
+    fn load(path: &Path) -> Result<Self, Error> {
+        let locale = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_owned();
+        check_locale(&locale)?;
+        let contents = fs::read_to_string(path).map_err(|cause| Error::ReadFailed {
+            path: path.to_owned(),
+            cause,
+        })?;
+        let table = toml::from_str::<toml::value::Table>(&contents).map_err(|cause| {
+            Error::ParseFailed {
+                path: path.to_owned(),
+                cause,
+            }
+        })?;
+        // Keys are shared between platforms, so they're held to Android's
+        // stricter rules up front.
+        let strings = table
+            .into_iter()
+            .map(|(key, value)| {
+                if key == RESERVED_KEY {
+                    return Err(Error::KeyReserved {
+                        path: path.to_owned(),
+                        key,
+                    });
+                }
+                if !regex!(r"^[a-zA-Z_][a-zA-Z0-9_]*$").is_match(&key) {
+                    return Err(Error::KeyInvalid {
+                        path: path.to_owned(),
+                        key,
+                    });
+                }
+                match value {
+                    toml::Value::String(value) => Ok((key, value)),
+                    _ => Err(Error::ValueInvalid {
+                        path: path.to_owned(),
+                        key,
+                    }),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { locale, strings })
+    }
+
+    fn load_all(app: &App) -> Result<Vec<Self>, Error> {
+        let dir = dir(app);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut paths = fs::read_dir(&dir)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .map_err(|cause| Error::DirReadFailed { path: dir, cause })?;
+        paths.retain(|path| {
+            path.extension()
+                .map(|ext| ext == "toml")
+                .unwrap_or_default()
+        });
+        paths.sort();
+        paths.iter().map(|path| Self::load(path)).collect()
+    }
+
+    #[cfg(target_os = "macos")]
+    fn render_apple(&self) -> String {
+        fn escape(s: &str) -> String {
+            s.replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        }
+        self.strings
+            .iter()
+            .map(|(key, value)| format!("\"{}\" = \"{}\";\n", escape(key), escape(value)))
+            .collect()
+    }
+
+    fn render_android(&self) -> String {
+        fn escape(s: &str) -> String {
+            s.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('\\', "\\\\")
+                .replace('\'', "\\'")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        }
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<resources>\n");
+        for (key, value) in &self.strings {
+            out.push_str(&format!(
+                "    <string name=\"{}\">{}</string>\n",
+                key,
+                escape(value)
+            ));
+        }
+        out.push_str("</resources>\n");
+        out
+    }
+}
+
+fn write(path: PathBuf, contents: String) -> Result<(), Error> {
+    let parent = path
+        .parent()
+        .expect("developer error: strings path had no parent");
+    fs::create_dir_all(parent).map_err(|cause| Error::DirCreationFailed {
+        path: parent.to_owned(),
+        cause,
+    })?;
+    log::info!("writing strings to {:?}", path);
+    fs::write(&path, contents).map_err(|cause| Error::WriteFailed { path, cause })
+}
+
+#[cfg(target_os = "macos")]
+pub fn sync_apple(config: &apple::config::Config) -> Result<(), Error> {
+    for strings in Strings::load_all(config.app())? {
+        let lproj = format!("{}.lproj", strings.locale.replace('_', "-"));
+        write(
+            config
+                .project_dir()
+                .join("Sources")
+                .join(lproj)
+                .join("Localizable.strings"),
+            strings.render_apple(),
+        )?;
+    }
+    Ok(())
+}
+
+pub fn sync_android(config: &android::config::Config) -> Result<(), Error> {
+    for strings in Strings::load_all(config.app())? {
+        // Android wants region qualifiers prefixed with an `r`, i.e. `pt-rBR`
+        let locale = strings.locale.replace('_', "-");
+        let mut parts = locale.splitn(2, '-');
+        let language = parts.next().unwrap_or_default();
+        let qualifier = match parts.next() {
+            Some(region) => format!("{}-r{}", language, region),
+            None => language.to_owned(),
+        };
+        write(
+            config
+                .project_dir()
+                .join("app/src/main/res")
+                .join(format!("values-{}", qualifier))
+                .join("strings.xml"),
+            strings.render_android(),
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum AddError {
+    ConfigFailed(LoadOrGenError),
+    MetadataFailed(metadata::Error),
+    AddFailed(Error),
+    #[cfg(target_os = "macos")]
+    XcodegenFailed(bossy::Error),
+}
+
+impl Reportable for AddError {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            Self::MetadataFailed(err) => err.report(),
+            Self::AddFailed(err) => Report::error("Failed to add locale", err),
+            #[cfg(target_os = "macos")]
+            Self::XcodegenFailed(err) => {
+                Report::error("Failed to regenerate Xcode project with new locale", err)
+            }
+        }
+    }
+}
+
+/// Creates the shared strings file for `locale` and syncs it into any
+/// generated projects. The Xcode project is regenerated, since it only picks
+/// up new `.lproj` dirs then.
+pub fn add(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    overrides: &Overrides,
+    locale: &str,
+) -> Result<(), AddError> {
+    let (config, _origin) = Config::load_or_gen(".", non_interactive, overrides, wrapper)
+        .map_err(AddError::ConfigFailed)?;
+    let metadata =
+        Metadata::load(&config.app().crate_dir(), overrides).map_err(AddError::MetadataFailed)?;
+    check_locale(locale).map_err(AddError::AddFailed)?;
+    let path = dir(config.app()).join(format!("{}.toml", locale));
+    if path.is_file() {
+        cli::status(format!(
+            "Strings for {:?} already exist at {:?}",
+            locale, path
+        ));
+    } else {
+        write(
+            path,
+            "# Strings for this locale, i.e.:\n# greeting = \"Bonjour\"\n".to_owned(),
+        )
+        .map_err(AddError::AddFailed)?;
+    }
+    #[cfg(target_os = "macos")]
+    if metadata.apple().supported() && config.apple().project_dir_exists() {
+        sync_apple(config.apple()).map_err(AddError::AddFailed)?;
+        apple::project::xcodegen(config.apple()).map_err(AddError::XcodegenFailed)?;
+    }
+    if metadata.android().supported() && config.android().project_dir_exists() {
+        sync_android(config.android()).map_err(AddError::AddFailed)?;
+    }
+    Ok(())
+}
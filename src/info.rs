@@ -0,0 +1,182 @@
+//! Collects what we usually end up asking for in bug reports, so it can be
+//! pasted into an issue in one go. Nothing here is allowed to fail outright;
+//! anything we can't find out is reported as such, since that's often the
+//! bug in the first place.
+
+use crate::{
+    android,
+    templating::Lockfile,
+    util::{self, cli::VERSION_LONG},
+};
+use std::{
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Anything starting with `CARGO_MOBILE_` is included too.
+static ENV_VARS: &[&str] = &[
+    "ANDROID_SDK_ROOT",
+    "ANDROID_HOME",
+    "NDK_HOME",
+    "JAVA_HOME",
+    "DEVELOPER_DIR",
+    "RUSTUP_TOOLCHAIN",
+    "CARGO_HOME",
+    "CARGO_TARGET_DIR",
+    "RUSTFLAGS",
+    "CARGO_ENCODED_RUSTFLAGS",
+];
+
+fn or_unknown<T: Display, E: Display>(result: Result<T, E>) -> String {
+    result
+        .map(|value| value.to_string())
+        .unwrap_or_else(|err| format!("unknown ({})", err))
+}
+
+fn field(name: &str, value: impl Display) {
+    println!("{:<20}{}", format!("{}:", name), value);
+}
+
+// These are both directories of versioned subdirectories, like
+// `platforms/android-30`.
+fn installed(sdk_root: &Path, dir: &str) -> String {
+    let mut versions = fs::read_dir(sdk_root.join(dir))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if versions.is_empty() {
+        "none".to_owned()
+    } else {
+        versions.sort();
+        versions.join(", ")
+    }
+}
+
+fn rust() {
+    field("rustc", or_unknown(util::RustVersion::check()));
+    match util::rustup::Toolchain::active() {
+        Ok(toolchain) => {
+            field("toolchain", &toolchain);
+            field(
+                "targets",
+                toolchain
+                    .installed_targets()
+                    .map(|targets| targets.join(", "))
+                    .unwrap_or_else(|_| "unknown".to_owned()),
+            );
+        }
+        Err(_) => field("toolchain", "unknown (is `rustup` installed?)"),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn apple() {
+    use crate::apple::system_profile::DeveloperTools;
+
+    field(
+        "xcode",
+        or_unknown(
+            DeveloperTools::new().map(|tools| format!("{}.{}", tools.version.0, tools.version.1)),
+        ),
+    );
+    for sdk in &["iphoneos", "iphonesimulator", "macosx"] {
+        let version = bossy::Command::impure("xcrun")
+            .with_args(&["--sdk", sdk, "--show-sdk-version"])
+            .run_and_wait_for_output()
+            .ok()
+            .and_then(|output| output.stdout_str().ok().map(|s| s.trim().to_owned()))
+            .unwrap_or_else(|| "unknown".to_owned());
+        field(&format!("{} sdk", sdk), version);
+    }
+}
+
+fn android() {
+    match android::env::Env::new() {
+        Ok(env) => {
+            field("android sdk", env.sdk_root().display());
+            field("build-tools", installed(env.sdk_root(), "build-tools"));
+            field("platforms", installed(env.sdk_root(), "platforms"));
+            field(
+                "android ndk",
+                format!(
+                    "{} ({})",
+                    or_unknown(env.ndk.version()),
+                    env.ndk.home().display()
+                ),
+            );
+        }
+        Err(err) => field("android sdk", format!("unknown ({})", err)),
+    }
+}
+
+fn template_packs(cwd: &Path) {
+    let paths = Lockfile::paths_in(cwd);
+    if paths.is_empty() {
+        field("template packs", "no lockfile in this directory");
+    }
+    for path in paths {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        match Lockfile::load_from(path) {
+            Ok(Some(lockfile)) => {
+                for (path, commit) in lockfile.packs() {
+                    field(
+                        "template pack",
+                        format!(
+                            "{} @ {} ({})",
+                            path.display(),
+                            commit.unwrap_or("unknown"),
+                            name
+                        ),
+                    );
+                }
+            }
+            Ok(None) => (),
+            Err(err) => field("template packs", format!("unknown ({})", err)),
+        }
+    }
+}
+
+fn env_vars() {
+    let mut vars = ENV_VARS
+        .iter()
+        .filter_map(|name| env::var(name).ok().map(|value| (name.to_string(), value)))
+        .chain(env::vars().filter(|(name, _)| name.starts_with("CARGO_MOBILE_")))
+        .collect::<Vec<_>>();
+    vars.sort();
+    for (name, value) in vars {
+        println!("{}={}", name, value);
+    }
+}
+
+/// Prints a report about cargo-mobile and the toolchains it uses, fenced so
+/// that it renders as-is when pasted into an issue.
+pub fn exec(cwd: impl AsRef<Path>) {
+    let cwd = cwd.as_ref();
+    let cwd = fs::canonicalize(cwd).unwrap_or_else(|_| PathBuf::from(cwd));
+    println!("```");
+    for (i, line) in VERSION_LONG.lines().enumerate() {
+        field(if i == 0 { "cargo-mobile" } else { "" }, line);
+    }
+    field(
+        "host",
+        format!("{} ({})", env::consts::OS, env::consts::ARCH),
+    );
+    rust();
+    #[cfg(target_os = "macos")]
+    apple();
+    android();
+    template_packs(&cwd);
+    println!();
+    env_vars();
+    println!("```");
+}
@@ -5,12 +5,14 @@ use crate::{
     config::{
         self,
         metadata::{self, Metadata},
-        Config,
+        Config, Origin, Overrides,
     },
-    dot_cargo, opts, project, templating,
+    dot_cargo, opts, project,
+    templating::{self, DryRun, Review},
     util::{
         self,
         cli::{Report, Reportable, TextWrapper},
+        watch::Watcher,
     },
 };
 use std::{
@@ -63,6 +65,14 @@ pub enum Error {
         cause: io::Error,
     },
     OpenInEditorFailed(util::OpenInEditorError),
+    TemplatePackListFailed(templating::ListError),
+    DryRunFailed(templating::DryRunError),
+    TemplateVarsFailed(config::template::VarsError),
+    WatchFailed(io::Error),
+    HookFailed {
+        name: String,
+        cause: bossy::Error,
+    },
 }
 
 impl Reportable for Error {
@@ -85,8 +95,120 @@ impl Reportable for Error {
             Self::DotCargoWriteFailed(err) => err.report(),
             Self::DotFirstInitDeleteFailed { path, cause } => Report::action_request(format!("Failed to delete first init dot file {:?}; the project generated successfully, but `cargo mobile init` will have unexpected results unless you manually delete this file!", path), cause),
             Self::OpenInEditorFailed(err) => Report::error("Failed to open project in editor (your project generated successfully though, so no worries!)", err),
+            Self::TemplatePackListFailed(err) => Report::error("Failed to list template packs", err),
+            Self::DryRunFailed(err) => Report::error("Failed to preview project generation", err),
+            Self::TemplateVarsFailed(err) => err.report(),
+            Self::WatchFailed(err) => Report::error("Failed to watch template pack for changes", err),
+            Self::HookFailed { name, cause } => Report::action_request(format!("Post-init hook `{}` failed; the project generated successfully, so you can fix the issue and re-run it by hand, or pass `--skip-hooks` to skip it", name), cause),
+        }
+    }
+}
+
+pub fn list_template_packs() -> Result<(), Error> {
+    let packs = templating::list_app_packs().map_err(Error::TemplatePackListFailed)?;
+    if packs.is_empty() {
+        println!("No template packs are installed.");
+    }
+    for pack in packs {
+        match templating::app_pack_description(&pack) {
+            Some(description) => println!("{} - {}", pack, description),
+            None => println!("{}", pack),
         }
     }
+    Ok(())
+}
+
+fn run_hooks(config: &Config, skip_hooks: opts::SkipHooks) -> Result<(), Error> {
+    let hooks = config.app().template_pack().hooks();
+    if hooks.is_empty() {
+        return Ok(());
+    }
+    if skip_hooks.yes() {
+        util::cli::status(format!(
+            "Skipping {} post-init hook(s) declared by the template pack",
+            hooks.len()
+        ));
+        return Ok(());
+    }
+    for (index, hook) in hooks.iter().enumerate() {
+        util::cli::status(format!(
+            "Running post-init hook {}/{}: {}",
+            index + 1,
+            hooks.len(),
+            hook.name()
+        ));
+        let dir = hook
+            .dir()
+            .map(|dir| config.app().root_dir().join(dir))
+            .unwrap_or_else(|| config.app().root_dir().to_owned());
+        bossy::Command::impure(hook.program())
+            .with_args(hook.args())
+            .with_current_dir(&dir)
+            .run_and_wait()
+            .map_err(|cause| Error::HookFailed {
+                name: hook.name(),
+                cause,
+            })?;
+    }
+    Ok(())
+}
+
+// Renders everything into a staging directory and reports what would change,
+// skipping all of the steps that install things or run external tools.
+fn preview(config: &Config, config_origin: Origin, overrides: &Overrides) -> Result<(), Error> {
+    let dot_first_init_exists = config
+        .app()
+        .root_dir()
+        .join(DOT_FIRST_INIT_FILE_NAME)
+        .exists();
+    let bike = config.build_a_bike();
+    let filter = templating::Filter::new(config, config_origin, dot_first_init_exists)
+        .map_err(Error::FilterConfigureFailed)?;
+    let dry_run = DryRun::new(config.app().root_dir()).map_err(Error::DryRunFailed)?;
+
+    project::gen(
+        config,
+        &bike,
+        &filter,
+        None,
+        Some(&dry_run),
+        Review::accept_all(),
+        opts::AllowUnverified::No,
+    )
+    .map_err(Error::ProjectInitFailed)?;
+
+    let metadata =
+        Metadata::load(&config.app().crate_dir(), overrides).map_err(Error::MetadataFailed)?;
+
+    #[cfg(target_os = "macos")]
+    if metadata.apple().supported() {
+        apple::project::process_templates(
+            config.apple(),
+            metadata.apple(),
+            config.app().template_pack().submodule_path(),
+            &bike,
+            &dry_run.stage(&config.apple().project_dir()),
+            dry_run.filter(&filter),
+        )
+        .map_err(Error::AppleInitFailed)?;
+    }
+
+    if metadata.android().supported() {
+        android::project::process_templates(
+            config.android(),
+            metadata.android(),
+            &bike,
+            &dry_run.stage(&config.android().project_dir()),
+            dry_run.filter(&filter),
+        )
+        .map_err(Error::AndroidInitFailed)?;
+    }
+
+    dry_run.report().map_err(Error::DryRunFailed)?;
+    if config_origin.freshly_minted() {
+        println!("A new {} would also be created.", config::file_name());
+    }
+    Ok(())
 }
 
 pub fn exec(
@@ -94,13 +216,36 @@ pub fn exec(
     non_interactive: opts::NonInteractive,
     skip_dev_tools: opts::SkipDevTools,
     reinstall_deps: opts::ReinstallDeps,
+    skip_hooks: opts::SkipHooks,
+    accept_changes: opts::AcceptChanges,
     open_in_editor: opts::OpenInEditor,
+    dry_run: opts::DryRun,
+    allow_unverified: opts::AllowUnverified,
     submodule_commit: Option<String>,
+    overrides: &Overrides,
     cwd: impl AsRef<Path>,
 ) -> Result<Config, Error> {
     let cwd = cwd.as_ref();
-    let (config, config_origin) =
-        Config::load_or_gen(cwd, non_interactive, wrapper).map_err(Error::ConfigLoadOrGenFailed)?;
+    if dry_run.yes() {
+        let (mut config, config_origin) =
+            Config::load_or_preview(cwd, non_interactive, overrides, wrapper)
+                .map_err(Error::ConfigLoadOrGenFailed)?;
+        config
+            .fill_template_vars(
+                non_interactive,
+                overrides.template_answers.as_deref(),
+                false,
+            )
+            .map_err(Error::TemplateVarsFailed)?;
+        preview(&config, config_origin, overrides)?;
+        return Ok(config);
+    }
+    let (mut config, config_origin) = Config::load_or_gen(cwd, non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigLoadOrGenFailed)?;
+    config
+        .fill_template_vars(non_interactive, overrides.template_answers.as_deref(), true)
+        .map_err(Error::TemplateVarsFailed)?;
+    let _lock = util::lock::ProjectLock::acquire(config.app().gen_dir());
     let dot_first_init_path = config.app().root_dir().join(DOT_FIRST_INIT_FILE_NAME);
     let dot_first_init_exists = {
         let dot_first_init_exists = dot_first_init_path.exists();
@@ -122,9 +267,19 @@ pub fn exec(
     let bike = config.build_a_bike();
     let filter = templating::Filter::new(&config, config_origin, dot_first_init_exists)
         .map_err(Error::FilterConfigureFailed)?;
+    let review = Review::new(non_interactive, accept_changes);
 
     // Generate the base project
-    project::gen(&config, &bike, &filter, submodule_commit).map_err(Error::ProjectInitFailed)?;
+    project::gen(
+        &config,
+        &bike,
+        &filter,
+        submodule_commit,
+        None,
+        review,
+        allow_unverified,
+    )
+    .map_err(Error::ProjectInitFailed)?;
 
     let asset_dir = config.app().asset_dir();
     if !asset_dir.is_dir() {
@@ -159,7 +314,8 @@ pub fn exec(
         util::host_target_triple().map_err(Error::HostTargetTripleDetectionFailed)?,
     );
 
-    let metadata = Metadata::load(&config.app().root_dir()).map_err(Error::MetadataFailed)?;
+    let metadata =
+        Metadata::load(&config.app().crate_dir(), overrides).map_err(Error::MetadataFailed)?;
 
     // Generate Xcode project
     #[cfg(target_os = "macos")]
@@ -174,19 +330,28 @@ pub fn exec(
             skip_dev_tools,
             reinstall_deps,
             &filter,
+            review,
         )
         .map_err(Error::AppleInitFailed)?;
     } else {
-        println!("Skipping iOS init, since it's marked as unsupported in your Cargo.toml metadata");
+        util::cli::status(
+            "Skipping iOS init, since it's marked as unsupported in your Cargo.toml metadata",
+        );
     }
 
     // Generate Android Studio project
     if metadata.android().supported() {
         match android::env::Env::new() {
-            Ok(env) => {
-                android::project::gen(config.android(), &env, &bike, &filter, &mut dot_cargo)
-                    .map_err(Error::AndroidInitFailed)?
-            }
+            Ok(env) => android::project::gen(
+                config.android(),
+                metadata.android(),
+                &env,
+                &bike,
+                &filter,
+                review,
+                &mut dot_cargo,
+            )
+            .map_err(Error::AndroidInitFailed)?,
             Err(err) => {
                 if err.sdk_or_ndk_issue() {
                     Report::action_request(
@@ -200,14 +365,15 @@ pub fn exec(
             }
         }
     } else {
-        println!(
-            "Skipping Android init, since it's marked as unsupported in your Cargo.toml metadata"
+        util::cli::status(
+            "Skipping Android init, since it's marked as unsupported in your Cargo.toml metadata",
         );
     }
 
     dot_cargo
         .write(config.app())
         .map_err(Error::DotCargoWriteFailed)?;
+    run_hooks(&config, skip_hooks)?;
     if dot_first_init_exists {
         log::info!("deleting first init dot file at {:?}", dot_first_init_path);
         fs::remove_file(&dot_first_init_path).map_err(|cause| Error::DotFirstInitDeleteFailed {
@@ -225,3 +391,248 @@ pub fn exec(
     }
     Ok(config)
 }
+
+// Regenerates everything the template pack produces, without installing
+// anything. This uses `WildWest` filtering, since the whole point is to see
+// the pack's latest output.
+fn rerender(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    overrides: &Overrides,
+    cwd: &Path,
+) -> Result<Config, Error> {
+    // Reloading picks up changes to the pack's spec, like new vars.
+    let (mut config, _) = Config::load_or_gen(cwd, non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigLoadOrGenFailed)?;
+    config
+        .fill_template_vars(non_interactive, overrides.template_answers.as_deref(), true)
+        .map_err(Error::TemplateVarsFailed)?;
+    let _lock = util::lock::ProjectLock::acquire(config.app().gen_dir());
+    let bike = config.build_a_bike();
+    let filter = templating::Filter::WildWest;
+    // The pack's being edited, so of course it doesn't match the lockfile.
+    project::gen(
+        &config,
+        &bike,
+        &filter,
+        None,
+        None,
+        Review::accept_all(),
+        opts::AllowUnverified::Yes,
+    )
+    .map_err(Error::ProjectInitFailed)?;
+
+    let metadata =
+        Metadata::load(&config.app().crate_dir(), overrides).map_err(Error::MetadataFailed)?;
+
+    #[cfg(target_os = "macos")]
+    if metadata.apple().supported() {
+        apple::project::process_templates(
+            config.apple(),
+            metadata.apple(),
+            config.app().template_pack().submodule_path(),
+            &bike,
+            &config.apple().project_dir(),
+            filter.fun(),
+        )
+        .map_err(Error::AppleInitFailed)?;
+    }
+
+    if metadata.android().supported() {
+        android::project::process_templates(
+            config.android(),
+            metadata.android(),
+            &bike,
+            &config.android().project_dir(),
+            filter.fun(),
+        )
+        .map_err(Error::AndroidInitFailed)?;
+    }
+    Ok(config)
+}
+
+/// Runs a normal init, and then re-renders the project every time the template
+/// pack changes until interrupted. Files generated from the pack will be
+/// overwritten, so this is only meant for developing template packs.
+pub fn watch_template(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    skip_dev_tools: opts::SkipDevTools,
+    reinstall_deps: opts::ReinstallDeps,
+    skip_hooks: opts::SkipHooks,
+    accept_changes: opts::AcceptChanges,
+    allow_unverified: opts::AllowUnverified,
+    submodule_commit: Option<String>,
+    overrides: &Overrides,
+    cwd: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let cwd = cwd.as_ref();
+    let mut config = exec(
+        wrapper,
+        non_interactive,
+        skip_dev_tools,
+        reinstall_deps,
+        skip_hooks,
+        accept_changes,
+        opts::OpenInEditor::No,
+        opts::DryRun::No,
+        allow_unverified,
+        submodule_commit,
+        overrides,
+        cwd,
+    )?;
+    loop {
+        let paths = config
+            .app()
+            .template_pack()
+            .local_chain()
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+        let mut watcher = Watcher::new(paths).map_err(Error::WatchFailed)?;
+        util::cli::status("Watching for template pack changes; press Ctrl+C to stop.");
+        for path in watcher.paths() {
+            log::info!("watching {:?}", path);
+        }
+        watcher.wait().map_err(Error::WatchFailed)?;
+        util::cli::status("Template pack changed, re-rendering...");
+        match rerender(wrapper, non_interactive, overrides, cwd) {
+            Ok(new_config) => {
+                config = new_config;
+                util::cli::status("Re-rendered successfully!");
+            }
+            // Broken templates are expected while editing them, so we just
+            // report the error and wait for the next change.
+            Err(err) => err.report().print(wrapper),
+        }
+    }
+}
+
+/// Generates a project without going through the CLI.
+///
+/// This is non-interactive by default, and skips dev tool installation, since
+/// embedders generally can't answer prompts or want to manage tooling
+/// themselves.
+#[derive(Clone, Debug)]
+pub struct Builder {
+    cwd: PathBuf,
+    non_interactive: opts::NonInteractive,
+    skip_dev_tools: opts::SkipDevTools,
+    reinstall_deps: opts::ReinstallDeps,
+    skip_hooks: opts::SkipHooks,
+    accept_changes: opts::AcceptChanges,
+    open_in_editor: opts::OpenInEditor,
+    dry_run: opts::DryRun,
+    allow_unverified: opts::AllowUnverified,
+    submodule_commit: Option<String>,
+    overrides: Overrides,
+}
+
+impl Builder {
+    pub fn new(cwd: impl Into<PathBuf>) -> Self {
+        Self {
+            cwd: cwd.into(),
+            non_interactive: opts::NonInteractive::Yes,
+            skip_dev_tools: opts::SkipDevTools::Yes,
+            reinstall_deps: opts::ReinstallDeps::No,
+            skip_hooks: opts::SkipHooks::No,
+            accept_changes: opts::AcceptChanges::No,
+            open_in_editor: opts::OpenInEditor::No,
+            dry_run: opts::DryRun::No,
+            allow_unverified: opts::AllowUnverified::No,
+            submodule_commit: None,
+            overrides: Default::default(),
+        }
+    }
+
+    pub fn non_interactive(mut self, non_interactive: opts::NonInteractive) -> Self {
+        self.non_interactive = non_interactive;
+        self
+    }
+
+    pub fn skip_dev_tools(mut self, skip_dev_tools: opts::SkipDevTools) -> Self {
+        self.skip_dev_tools = skip_dev_tools;
+        self
+    }
+
+    pub fn reinstall_deps(mut self, reinstall_deps: opts::ReinstallDeps) -> Self {
+        self.reinstall_deps = reinstall_deps;
+        self
+    }
+
+    pub fn skip_hooks(mut self, skip_hooks: opts::SkipHooks) -> Self {
+        self.skip_hooks = skip_hooks;
+        self
+    }
+
+    /// Since builders are non-interactive by default, files that would be
+    /// changed are left alone unless this is set.
+    pub fn accept_changes(mut self, accept_changes: opts::AcceptChanges) -> Self {
+        self.accept_changes = accept_changes;
+        self
+    }
+
+    pub fn open_in_editor(mut self, open_in_editor: opts::OpenInEditor) -> Self {
+        self.open_in_editor = open_in_editor;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: opts::DryRun) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Generates from template packs that don't match the lockfile's
+    /// checksums and signatures, instead of refusing to.
+    pub fn allow_unverified(mut self, allow_unverified: opts::AllowUnverified) -> Self {
+        self.allow_unverified = allow_unverified;
+        self
+    }
+
+    pub fn submodule_commit(mut self, submodule_commit: impl Into<String>) -> Self {
+        self.submodule_commit = Some(submodule_commit.into());
+        self
+    }
+
+    pub fn gen_dir(mut self, gen_dir: impl Into<PathBuf>) -> Self {
+        self.overrides.gen_dir = Some(gen_dir.into());
+        self
+    }
+
+    pub fn config_profile(mut self, profile: impl Into<String>) -> Self {
+        self.overrides.profile = Some(profile.into());
+        self
+    }
+
+    pub fn app(mut self, app: impl Into<String>) -> Self {
+        self.overrides.app = Some(app.into());
+        self
+    }
+
+    pub fn template_pack(mut self, template_pack: impl Into<String>) -> Self {
+        self.overrides.template_pack = Some(template_pack.into());
+        self
+    }
+
+    pub fn template_answers(mut self, template_answers: impl Into<PathBuf>) -> Self {
+        self.overrides.template_answers = Some(template_answers.into());
+        self
+    }
+
+    pub fn exec(self) -> Result<Config, Error> {
+        exec(
+            &util::cli::default_wrapper(),
+            self.non_interactive,
+            self.skip_dev_tools,
+            self.reinstall_deps,
+            self.skip_hooks,
+            self.accept_changes,
+            self.open_in_editor,
+            self.dry_run,
+            self.allow_unverified,
+            self.submodule_commit,
+            &self.overrides,
+            self.cwd,
+        )
+    }
+}
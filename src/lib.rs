@@ -3,17 +3,35 @@
 pub mod android;
 #[cfg(target_os = "macos")]
 pub mod apple;
+pub mod bench;
+pub mod collect;
 pub mod config;
+pub mod deinit;
+pub mod dev;
 pub mod device;
+pub mod distribute;
 mod dot_cargo;
 pub mod env;
+pub mod event;
+pub mod gen;
+pub mod i18n;
+pub mod info;
 pub mod init;
 pub mod opts;
 pub mod os;
 mod project;
+pub mod run;
+pub mod runner;
+pub mod sbom;
+pub mod secret;
 pub mod target;
 mod templating;
+pub mod test_lab;
+#[cfg(feature = "test-support")]
+pub mod test_support;
 pub mod update;
 pub mod util;
+pub mod validate;
+pub mod watch;
 
 pub static NAME: &str = "mobile";
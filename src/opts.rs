@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use yes_or_no::yes_or_no;
 
 yes_or_no!(NonInteractive);
@@ -46,6 +47,18 @@ yes_or_no!(ReinstallDeps);
 
 yes_or_no!(OpenInEditor);
 
+yes_or_no!(DryRun);
+
+yes_or_no!(SkipHooks);
+
+yes_or_no!(AllowUnverified);
+
+yes_or_no!(AcceptChanges);
+
+yes_or_no!(Force);
+
+yes_or_no!(Coverage);
+
 #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum Profile {
     Debug,
@@ -76,3 +89,73 @@ impl Profile {
         }
     }
 }
+
+/// Which of the crate's targets gets built into the app. Examples need the
+/// same `crate-type` as the lib, and to expose the same entry point.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EntryPoint {
+    Lib,
+    Example(String),
+}
+
+impl Default for EntryPoint {
+    fn default() -> Self {
+        Self::Lib
+    }
+}
+
+impl EntryPoint {
+    /// Passed along to the builds that Gradle and Xcode run on our behalf, so
+    /// they build the same thing we were asked to.
+    pub const ENV_VAR: &'static str = "CARGO_MOBILE_EXAMPLE";
+
+    pub fn from_example(example: Option<String>) -> Self {
+        example.map(Self::Example).unwrap_or_default()
+    }
+
+    pub fn example(&self) -> Option<&str> {
+        match self {
+            Self::Lib => None,
+            Self::Example(name) => Some(name),
+        }
+    }
+
+    /// The file name cargo gives the library built for this entry point,
+    /// relative to the profile's output dir.
+    pub fn artifact(&self, lib_file_name: &str, extension: &str) -> String {
+        match self {
+            Self::Lib => lib_file_name.to_owned(),
+            Self::Example(name) => {
+                format!("examples/lib{}.{}", name.replace('-', "_"), extension)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Timings {
+    Text,
+    Json,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self::Text
+    }
+}
+
+impl FromStr for Timings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("{:?} isn't a timings format", s)),
+        }
+    }
+}
+
+impl Timings {
+    pub const POSSIBLE_VALUES: &'static [&'static str] = &["text", "json"];
+}
@@ -1,7 +1,12 @@
 use crate::{
     config::Config,
-    templating::{self, FancyPackResolveError},
+    opts,
+    templating::{
+        self, DryRun, DryRunError, FancyPackResolveError, LockError, Lockfile, PinError,
+        PinnedPack, Review, ReviewError,
+    },
     util::{
+        self,
         cli::{Report, Reportable},
         Git,
     },
@@ -11,7 +16,14 @@ use std::path::PathBuf;
 #[derive(Debug)]
 pub enum Error {
     GitInitFailed(bossy::Error),
+    LockfileLoadFailed(LockError),
+    LockfileWriteFailed(LockError),
+    TemplatePackPinFailed(PinError),
+    Unverified(Vec<String>),
     TemplatePackResolveFailed(FancyPackResolveError),
+    ConditionDataFailed(serde_json::Error),
+    StagingFailed(DryRunError),
+    ReviewFailed(ReviewError),
     ProcessingFailed {
         src: PathBuf,
         dest: PathBuf,
@@ -23,9 +35,31 @@ impl Reportable for Error {
     fn report(&self) -> Report {
         match self {
             Self::GitInitFailed(err) => Report::error("Failed to initialize git", err),
+            Self::LockfileLoadFailed(err) => Report::error("Failed to load lockfile", err),
+            Self::LockfileWriteFailed(err) => Report::error("Failed to write lockfile", err),
+            Self::TemplatePackPinFailed(err) => Report::action_request(
+                "Update the template pack so it has the locked commit, or pass `--allow-unverified` to generate from the pack as it is and update the lockfile.",
+                err,
+            ),
+            Self::Unverified(problems) => Report::action_request(
+                "If these changes are expected, run again with `--allow-unverified` to accept them and update the lockfile.",
+                format!(
+                    "Template packs don't match the lockfile:\n{}",
+                    problems
+                        .iter()
+                        .map(|problem| format!("  {}", problem))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                ),
+            ),
             Self::TemplatePackResolveFailed(err) => {
                 Report::error("Failed to resolve template pack", err)
             }
+            Self::ConditionDataFailed(err) => {
+                Report::error("Failed to evaluate template pack conditions", err)
+            }
+            Self::StagingFailed(err) => Report::error("Failed to stage base project", err),
+            Self::ReviewFailed(err) => Report::error("Failed to update base project", err),
             Self::ProcessingFailed { src, dest, cause } => Report::error(
                 format!(
                     "Base project template processing from src {:?} to dest {:?} failed",
@@ -42,25 +76,112 @@ pub fn gen(
     bike: &bicycle::Bicycle,
     filter: &templating::Filter,
     submodule_commit: Option<String>,
+    dry_run: Option<&DryRun>,
+    review: Review,
+    allow_unverified: opts::AllowUnverified,
 ) -> Result<(), Error> {
-    println!("Generating base project...");
+    util::cli::status("Generating base project...");
     let root = config.app().root_dir();
+    let excluded = config
+        .app()
+        .template_pack()
+        .excluded(&serde_json::to_value(config).map_err(Error::ConditionDataFailed)?);
+    if let Some(dry_run) = dry_run {
+        // Submodules and the lockfile would both need writing to, so we stick
+        // to what's already present.
+        for pack in config.app().template_pack().local_chain() {
+            log::info!("traversing template pack {:#?}", pack);
+            bike.filter_and_process(
+                &pack,
+                &dry_run.stage(root),
+                |_| (),
+                templating::excluding(&excluded, dry_run.filter(filter)),
+            )
+            .map_err(|cause| Error::ProcessingFailed {
+                src: pack.to_owned(),
+                dest: root.to_owned(),
+                cause,
+            })?;
+        }
+        return Ok(());
+    }
     let git = Git::new(&root);
     git.init().map_err(Error::GitInitFailed)?;
+    let locked = Lockfile::load(config.app()).map_err(Error::LockfileLoadFailed)?;
+    // An explicitly specified commit wins over the locked one.
+    let submodule_commit = submodule_commit.or_else(|| {
+        locked
+            .as_ref()
+            .and_then(|locked| locked.submodule_commit())
+            .map(|commit| {
+                log::info!("using locked submodule commit {:?}", commit);
+                commit.to_owned()
+            })
+    });
     let pack_chain = config
         .app()
         .template_pack()
         .resolve(git, submodule_commit.as_deref())
         .map_err(Error::TemplatePackResolveFailed)?;
     log::info!("template pack chain: {:#?}", pack_chain);
-    for pack in pack_chain {
+    // Packs are rendered at the commits they're locked to, unless you've said
+    // you want to move off of them.
+    let pack_chain = match &locked {
+        Some(locked) if !allow_unverified.yes() => locked
+            .pin(&pack_chain)
+            .map_err(Error::TemplatePackPinFailed)?,
+        _ => pack_chain
+            .into_iter()
+            .map(PinnedPack::unpinned)
+            .collect::<Vec<_>>(),
+    };
+    let current = Lockfile::new(config.app(), &pack_chain);
+    if let Some(locked) = &locked {
+        let drift = locked.drift(&current);
+        if !drift.is_empty() {
+            println!(
+                "Template packs have drifted from {:?}, so the generated project may differ from your teammates':",
+                Lockfile::path(config.app())
+            );
+            for drift in drift {
+                println!("  {}", drift);
+            }
+        }
+        let problems = locked.verify(&current);
+        if !problems.is_empty() {
+            if allow_unverified.yes() {
+                println!(
+                    "Template packs don't match the lockfile, but `--allow-unverified` was passed:"
+                );
+                for problem in &problems {
+                    println!("  {}", problem);
+                }
+            } else {
+                return Err(Error::Unverified(problems));
+            }
+        }
+    }
+    // Everything's rendered into a staging directory first, so we can see
+    // what it'd change before touching the user's files.
+    let staging = DryRun::new(&root).map_err(Error::StagingFailed)?;
+    for pack in &pack_chain {
+        let pack = pack.render_from();
         log::info!("traversing template pack {:#?}", pack);
-        bike.filter_and_process(&pack, &root, |_| (), filter.fun())
-            .map_err(|cause| Error::ProcessingFailed {
-                src: pack.to_owned(),
-                dest: root.to_owned(),
-                cause,
-            })?;
+        bike.filter_and_process(
+            pack,
+            &staging.stage(&root),
+            |_| (),
+            templating::excluding(&excluded, staging.filter(filter)),
+        )
+        .map_err(|cause| Error::ProcessingFailed {
+            src: pack.to_owned(),
+            dest: root.to_owned(),
+            cause,
+        })?;
     }
+    review.apply(&staging).map_err(Error::ReviewFailed)?;
+    current
+        .write(config.app())
+        .map_err(Error::LockfileWriteFailed)?;
     Ok(())
 }
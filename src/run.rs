@@ -0,0 +1,369 @@
+use crate::android;
+#[cfg(target_os = "macos")]
+use crate::{apple, env};
+use crate::{
+    config::{
+        metadata::{self, Metadata},
+        Config, LoadOrGenError, Overrides,
+    },
+    device, opts,
+    util::{
+        cli::{self, Report, Reportable, TextWrapper},
+        prompt,
+    },
+};
+use std::{
+    fmt::{self, Display},
+    io,
+    path::PathBuf,
+    time::Duration,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadOrGenError),
+    MetadataFailed(metadata::Error),
+    #[cfg(target_os = "macos")]
+    RustVersionCheckFailed(crate::util::RustVersionError),
+    #[cfg(target_os = "macos")]
+    AppleEnvFailed(env::Error),
+    #[cfg(target_os = "macos")]
+    AppleListFailed(apple::device::DeviceListError),
+    AndroidListFailed(android::adb::device_list::Error),
+    NoneDetected,
+    NoneMatched {
+        suggestion: Option<String>,
+    },
+    EnvDeviceMissing {
+        device: String,
+    },
+    PromptFailed(io::Error),
+    ProjectDirAbsent {
+        project_dir: PathBuf,
+    },
+    #[cfg(target_os = "macos")]
+    AppleRunFailed(apple::device::RunError),
+    AndroidRunFailed(android::device::RunError),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            Self::MetadataFailed(err) => err.report(),
+            #[cfg(target_os = "macos")]
+            Self::RustVersionCheckFailed(err) => err.report(),
+            #[cfg(target_os = "macos")]
+            Self::AppleEnvFailed(err) => err.report(),
+            #[cfg(target_os = "macos")]
+            Self::AppleListFailed(err) => err.report(),
+            Self::AndroidListFailed(err) => err.report(),
+            Self::NoneDetected => Report::error(
+                "Failed to prompt for device",
+                "No connected iOS or Android devices detected",
+            ),
+            Self::NoneMatched { suggestion } => Report::action_request(
+                match suggestion {
+                    Some(arch) => format!("Did you mean `--arch {}`?", arch),
+                    None => {
+                        "Connect a matching device, or loosen `--min-os` / `--arch`.".to_owned()
+                    }
+                },
+                "No connected devices match the filter",
+            ),
+            Self::EnvDeviceMissing { device } => Report::action_request(
+                format!(
+                    "Connect it, or unset `{}` to pick from the devices that are connected.",
+                    device::DEVICE_ENV_VAR
+                ),
+                format!(
+                    "`{}` is set to {:?}, but no connected device has that id or name",
+                    device::DEVICE_ENV_VAR,
+                    device
+                ),
+            ),
+            Self::PromptFailed(err) => Report::error("Failed to prompt for device", err),
+            Self::ProjectDirAbsent { project_dir } => Report::action_request(
+                "Please run `cargo mobile init` and try again!",
+                format!("Project directory {:?} doesn't exist.", project_dir),
+            ),
+            #[cfg(target_os = "macos")]
+            Self::AppleRunFailed(err) => err.report(),
+            Self::AndroidRunFailed(err) => err.report(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Device<'a> {
+    #[cfg(target_os = "macos")]
+    Apple(apple::device::Device<'a>),
+    Android(android::device::Device<'a>),
+}
+
+impl<'a> Display for Device<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(target_os = "macos")]
+            Self::Apple(device) => write!(f, "{} [iOS]", device),
+            Self::Android(device) => write!(f, "{} [Android]", device),
+        }
+    }
+}
+
+impl<'a> Device<'a> {
+    fn id(&self) -> &str {
+        match self {
+            #[cfg(target_os = "macos")]
+            Self::Apple(device) => device.id(),
+            Self::Android(device) => device.id(),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            #[cfg(target_os = "macos")]
+            Self::Apple(device) => device.name(),
+            Self::Android(device) => device.name(),
+        }
+    }
+
+    fn arch(&self) -> &'a str {
+        match self {
+            #[cfg(target_os = "macos")]
+            Self::Apple(device) => device.target().arch,
+            Self::Android(device) => device.target().arch,
+        }
+    }
+
+    fn matches(&self, filter: &device::Filter) -> bool {
+        match self {
+            #[cfg(target_os = "macos")]
+            Self::Apple(device) => filter.matches(device.os_version(), device.target().arch),
+            Self::Android(device) => filter.matches(device.os_version(), device.target().arch),
+        }
+    }
+}
+
+fn ensure_init(project_dir: PathBuf) -> Result<(), Error> {
+    if project_dir.is_dir() {
+        Ok(())
+    } else {
+        Err(Error::ProjectDirAbsent { project_dir })
+    }
+}
+
+/// A device picked by `pick_device`, along with what's needed to build for
+/// and deploy to it.
+#[derive(Debug)]
+pub(crate) enum Picked {
+    #[cfg(target_os = "macos")]
+    Apple {
+        device: apple::device::Device<'static>,
+        env: env::Env,
+    },
+    Android {
+        device: android::device::Device<'static>,
+        env: android::env::Env,
+    },
+}
+
+/// Detects connected iOS and Android devices, and picks one the same way for
+/// every command that needs one: the device in `CARGO_MOBILE_DEVICE`, the last
+/// device used, the only device, or whichever the user chooses.
+pub(crate) fn pick_device(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    filter: &device::Filter,
+    wait: Option<Duration>,
+    overrides: &Overrides,
+) -> Result<(Config, Metadata, Picked), Error> {
+    let (config, _origin) = Config::load_or_gen(".", non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigFailed)?;
+    let metadata =
+        Metadata::load(&config.app().crate_dir(), overrides).map_err(Error::MetadataFailed)?;
+
+    #[cfg(target_os = "macos")]
+    let apple_env = if metadata.apple().supported() {
+        Some(env::Env::new().map_err(Error::AppleEnvFailed)?)
+    } else {
+        log::info!("skipping iOS device detection, since iOS is marked as unsupported");
+        None
+    };
+
+    let android_env = if metadata.android().supported() {
+        match android::env::Env::new() {
+            Ok(env) => Some(env),
+            Err(err) => {
+                // Not being able to use Android shouldn't prevent running on
+                // iOS, so we only bail if there's nothing else to run on.
+                log::warn!("skipping Android device detection: {}", err);
+                None
+            }
+        }
+    } else {
+        log::info!("skipping Android device detection, since Android is marked as unsupported");
+        None
+    };
+
+    // Detecting which backend's installed takes a few processes, so it's only
+    // done once rather than each time we poll.
+    #[cfg(target_os = "macos")]
+    let apple_backend = config.apple().deploy_backend();
+
+    // A platform that can't list its devices shouldn't keep us from running
+    // on the other one, so its error only matters if it was the only one we
+    // tried. Polling calls this repeatedly, so each platform only warns once.
+    let mut warned = (false, false);
+    let detect = || -> Result<(Vec<&'static str>, Vec<Device<'static>>), Error> {
+        let mut devices = Vec::new();
+        let mut listed = false;
+        let mut failure = None;
+        #[cfg(target_os = "macos")]
+        if let Some(env) = &apple_env {
+            match apple::device::device_list(env, apple_backend) {
+                Ok(list) => {
+                    listed = true;
+                    devices.extend(list.into_iter().map(Device::Apple));
+                }
+                Err(err) => {
+                    if !warned.0 {
+                        log::warn!("failed to list iOS devices: {:?}", err);
+                        warned.0 = true;
+                    }
+                    failure = Some(Error::AppleListFailed(err));
+                }
+            }
+        }
+        if let Some(env) = &android_env {
+            match android::adb::device_list(env) {
+                Ok(list) => {
+                    listed = true;
+                    devices.extend(list.into_iter().map(Device::Android));
+                }
+                Err(err) => {
+                    if !warned.1 {
+                        log::warn!("failed to list Android devices: {:?}", err);
+                        warned.1 = true;
+                    }
+                    failure.get_or_insert(Error::AndroidListFailed(err));
+                }
+            }
+        }
+        if let (false, Some(err)) = (listed, failure) {
+            return Err(err);
+        }
+        let detected_arches = devices.iter().map(Device::arch).collect::<Vec<_>>();
+        devices.retain(|device| device.matches(filter));
+        Ok((detected_arches, devices))
+    };
+    let env_device = device::env_device();
+    let find_env_device = |devices: &[Device<'static>]| {
+        env_device.as_deref().and_then(|wanted| {
+            device::env_device_index(
+                wanted,
+                devices.iter().map(|device| (device.id(), device.name())),
+            )
+        })
+    };
+    let (detected_arches, devices) =
+        device::poll(wait, detect, |(_, devices)| match &env_device {
+            Some(_) => find_env_device(devices).is_some(),
+            None => !devices.is_empty(),
+        })?;
+    if devices.is_empty() {
+        return Err(if detected_arches.is_empty() {
+            Error::NoneDetected
+        } else {
+            Error::NoneMatched {
+                suggestion: filter.suggest_arch(detected_arches),
+            }
+        });
+    }
+    let env_index = match &env_device {
+        Some(device) => Some(
+            find_env_device(&devices).ok_or_else(|| Error::EnvDeviceMissing {
+                device: device.clone(),
+            })?,
+        ),
+        None => None,
+    };
+    let last_index = match env_index {
+        Some(_) => None,
+        None => device::last_device_index(config.app(), filter, devices.iter().map(Device::id)),
+    };
+    let index = match (env_index.or(last_index), devices.len()) {
+        (Some(index), _) => index,
+        (None, 1) => 0,
+        (None, _) => prompt::list("Detected devices", devices.iter(), "device", None, "Device")
+            .map_err(Error::PromptFailed)?,
+    };
+    let device = devices.into_iter().nth(index).unwrap();
+    if env_index.is_some() {
+        cli::status(format!(
+            "Using device from `{}`: {}",
+            device::DEVICE_ENV_VAR,
+            device
+        ));
+    } else if last_index.is_some() {
+        cli::status(format!("Using last selected device: {}", device));
+    } else {
+        cli::status(format!("Detected connected device: {}", device));
+    }
+    // A device from the env var is a one-off, so it shouldn't replace the one
+    // that was picked.
+    if env_index.is_none() {
+        device::remember_device(config.app(), device.id());
+    }
+
+    let picked = match device {
+        #[cfg(target_os = "macos")]
+        Device::Apple(device) => {
+            let env = apple_env.expect("developer error: iOS device detected without an env");
+            apple::rust_version_check(wrapper).map_err(Error::RustVersionCheckFailed)?;
+            ensure_init(config.apple().project_dir())?;
+            Picked::Apple { device, env }
+        }
+        Device::Android(device) => {
+            let env = android_env.expect("developer error: Android device detected without an env");
+            ensure_init(config.android().project_dir())?;
+            Picked::Android { device, env }
+        }
+    };
+    Ok((config, metadata, picked))
+}
+
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    noise_level: opts::NoiseLevel,
+    profile: opts::Profile,
+    filter: &device::Filter,
+    wait: Option<Duration>,
+    overrides: &Overrides,
+) -> Result<(), Error> {
+    let (config, _metadata, picked) =
+        pick_device(wrapper, non_interactive, filter, wait, overrides)?;
+    match picked {
+        #[cfg(target_os = "macos")]
+        Picked::Apple { device, env } => device
+            .run(
+                config.apple(),
+                &env,
+                noise_level,
+                non_interactive,
+                profile,
+                &Default::default(),
+            )
+            .map_err(Error::AppleRunFailed),
+        Picked::Android { device, env } => device
+            .run(
+                config.android(),
+                &env,
+                noise_level,
+                profile,
+                &Default::default(),
+            )
+            .map_err(Error::AndroidRunFailed),
+    }
+}
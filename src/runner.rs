@@ -0,0 +1,159 @@
+//! Lets us act as a cargo target runner, so that `cargo test` and
+//! `cargo nextest run` with `--target` set run test binaries on a device or
+//! simulator instead of failing to run them on the host.
+//!
+//! Cargo only hands us the path to the binary and its arguments, so the target
+//! is worked out from the path, and whatever device we pick can't be prompted
+//! for. Since runners like nextest parse the binary's output, nothing else
+//! goes to stdout.
+
+use crate::{
+    android::{self, target::Target},
+    device,
+    target::TargetTrait as _,
+    util::cli::{Report, Reportable},
+};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// What to set `target.<triple>.runner` to in `.cargo/config.toml`.
+pub static RUNNER: &str = "cargo mobile test-runner";
+
+// Test binaries for these are plain executables that `simctl spawn` can run.
+static SIMULATOR_TRIPLES: &[&str] = &["aarch64-apple-ios-sim", "x86_64-apple-ios"];
+
+// These need to be bundled into an app and signed, which `cargo apple test`
+// does, but is far too slow to do per test.
+static IOS_DEVICE_TRIPLES: &[&str] = &["aarch64-apple-ios"];
+
+// Anything the harness would've gotten from the environment had it been run
+// locally; the device doesn't see our environment.
+static FORWARDED_ENV_PREFIXES: &[&str] = &["RUST_", "NEXTEST"];
+
+#[derive(Debug)]
+pub enum Error {
+    TargetUnknown {
+        executable: PathBuf,
+    },
+    DeviceUnsupported {
+        triple: String,
+    },
+    AndroidEnvFailed(android::env::Error),
+    AndroidListFailed(android::adb::device_list::Error),
+    NoneDetected {
+        triple: String,
+    },
+    HarnessFailed(android::target::TestBuildError),
+    AndroidRunFailed(android::device::TestError),
+    #[cfg(target_os = "macos")]
+    SimulatorRunFailed(crate::apple::simctl::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::TargetUnknown { executable } => Report::error(
+                "Failed to run test binary",
+                format!(
+                    "Couldn't tell which target {:?} was built for; it needs to be under `target/<triple>`",
+                    executable
+                ),
+            ),
+            Self::DeviceUnsupported { triple } => Report::action_request(
+                "Test binaries can only be run on iOS simulators from macOS; build for `aarch64-apple-ios-sim` instead, or use `cargo apple test` to run on a device.",
+                format!("Can't run {:?} test binaries here", triple),
+            ),
+            Self::AndroidEnvFailed(err) => err.report(),
+            Self::AndroidListFailed(err) => err.report(),
+            Self::NoneDetected { triple } => Report::action_request(
+                "Connect a device or start an emulator, i.e. with `cargo android emulator`.",
+                format!("No connected Android devices can run {:?}", triple),
+            ),
+            Self::HarnessFailed(err) => err.report(),
+            Self::AndroidRunFailed(err) => err.report(),
+            #[cfg(target_os = "macos")]
+            Self::SimulatorRunFailed(err) => err.report(),
+        }
+    }
+}
+
+fn is_android(triple: &str) -> bool {
+    Target::all().values().any(|target| target.triple == triple)
+}
+
+// Cargo puts these under `<target-dir>/<triple>/<profile>/deps`.
+fn triple_for(executable: &Path) -> Option<String> {
+    executable
+        .components()
+        .filter_map(|component| component.as_os_str().to_str())
+        .find(|component| {
+            is_android(component)
+                || SIMULATOR_TRIPLES.contains(component)
+                || IOS_DEVICE_TRIPLES.contains(component)
+        })
+        .map(ToOwned::to_owned)
+}
+
+fn forwarded_env() -> Vec<(String, String)> {
+    env::vars()
+        .filter(|(key, _)| {
+            FORWARDED_ENV_PREFIXES
+                .iter()
+                .any(|prefix| key.starts_with(prefix))
+        })
+        .collect()
+}
+
+fn run_on_android(triple: &str, executable: PathBuf, args: &[String]) -> Result<i32, Error> {
+    let env = android::env::Env::new().map_err(Error::AndroidEnvFailed)?;
+    let mut devices = android::adb::device_list(&env)
+        .map_err(Error::AndroidListFailed)?
+        .into_iter()
+        .filter(|device| device.target().triple == triple)
+        .collect::<Vec<_>>();
+    // There's no prompting here, so `CARGO_MOBILE_DEVICE` is the only way to
+    // choose between devices; otherwise, any that can run it will do.
+    let index = device::env_device()
+        .and_then(|wanted| {
+            device::env_device_index(
+                &wanted,
+                devices.iter().map(|device| (device.id(), device.name())),
+            )
+        })
+        .unwrap_or(0);
+    if index >= devices.len() {
+        return Err(Error::NoneDetected {
+            triple: triple.to_owned(),
+        });
+    }
+    let device = devices.swap_remove(index);
+    log::info!("running {:?} on {}", executable, device);
+    let harness = device
+        .target()
+        .harness(&env, executable)
+        .map_err(Error::HarnessFailed)?;
+    device
+        .run_harness(&env, &harness, args, &forwarded_env())
+        .map_err(Error::AndroidRunFailed)
+}
+
+/// Runs `executable` on whatever it was built for, and returns its exit code.
+pub fn exec(executable: PathBuf, args: &[String]) -> Result<i32, Error> {
+    let triple = triple_for(&executable).ok_or_else(|| Error::TargetUnknown {
+        executable: executable.clone(),
+    })?;
+    if is_android(&triple) {
+        return run_on_android(&triple, executable, args);
+    }
+    #[cfg(target_os = "macos")]
+    if SIMULATOR_TRIPLES.contains(&triple.as_str()) {
+        use crate::apple::simctl;
+        let simulator = simctl::env_simulator().unwrap_or_else(|| "booted".to_owned());
+        simctl::boot(&simulator).map_err(Error::SimulatorRunFailed)?;
+        return simctl::spawn(&simulator, &executable, args, &forwarded_env())
+            .map_err(Error::SimulatorRunFailed);
+    }
+    Err(Error::DeviceUnsupported { triple })
+}
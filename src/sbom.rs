@@ -0,0 +1,705 @@
+//! Software bills of materials, for supply-chain compliance. Components are
+//! collected from everything that ends up linked into a release build: the
+//! crate's dependency graph, plus Gradle's runtime classpath on Android, or
+//! the resolved CocoaPods and Swift packages on Apple platforms. Dev and build
+//! dependencies are left out, since they never ship.
+
+use crate::{
+    android,
+    config::{Config, LoadOrGenError, Overrides},
+    distribute::Platform,
+    env::ExplicitEnv as _,
+    opts,
+    util::{
+        self,
+        cli::{Report, Reportable, TextWrapper},
+        CargoCommand,
+    },
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs, io,
+    path::PathBuf,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    CycloneDx,
+    Spdx,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cyclonedx" => Ok(Self::CycloneDx),
+            "spdx" => Ok(Self::Spdx),
+            _ => Err(format!("{:?} isn't a supported SBOM format", s)),
+        }
+    }
+}
+
+impl Format {
+    pub const POSSIBLE_VALUES: &'static [&'static str] = &["cyclonedx", "spdx"];
+}
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadOrGenError),
+    #[cfg(not(target_os = "macos"))]
+    AppleUnsupported,
+    MetadataFailed(bossy::Error),
+    MetadataInvalid(serde_json::Error),
+    AndroidEnvFailed(android::env::Error),
+    GradleFailed(bossy::Error),
+    GradleOutputInvalid(std::str::Utf8Error),
+    #[cfg(target_os = "macos")]
+    ReadFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    #[cfg(target_os = "macos")]
+    PackageResolvedInvalid {
+        path: PathBuf,
+        cause: serde_json::Error,
+    },
+    SerializeFailed(serde_json::Error),
+    WriteFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            #[cfg(not(target_os = "macos"))]
+            Self::AppleUnsupported => Report::error(
+                "Failed to generate SBOM",
+                "Apple SBOMs can only be generated from macOS",
+            ),
+            Self::MetadataFailed(err) => Report::error("Failed to get crate metadata", err),
+            Self::MetadataInvalid(err) => Report::error("Failed to parse crate metadata", err),
+            Self::AndroidEnvFailed(err) => err.report(),
+            Self::GradleFailed(err) => Report::error("Failed to list Gradle dependencies", err),
+            Self::GradleOutputInvalid(err) => {
+                Report::error("Gradle dependency list contained invalid UTF-8", err)
+            }
+            #[cfg(target_os = "macos")]
+            Self::ReadFailed { path, cause } => {
+                Report::error(format!("Failed to read {:?}", path), cause)
+            }
+            #[cfg(target_os = "macos")]
+            Self::PackageResolvedInvalid { path, cause } => Report::error(
+                format!("Failed to parse Swift packages in {:?}", path),
+                cause,
+            ),
+            Self::SerializeFailed(err) => Report::error("Failed to serialize SBOM", err),
+            Self::WriteFailed { path, cause } => {
+                Report::error(format!("Failed to write SBOM to {:?}", path), cause)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Component {
+    name: String,
+    version: String,
+    purl: String,
+    license: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    resolve: Option<Resolve>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    id: String,
+    name: String,
+    version: String,
+    license: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Resolve {
+    root: Option<String>,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Node {
+    id: String,
+    deps: Vec<NodeDep>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeDep {
+    pkg: String,
+    dep_kinds: Vec<DepKind>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DepKind {
+    kind: Option<String>,
+}
+
+/// Older crates use `/` to separate licenses, which isn't valid SPDX.
+fn license_expression(license: &str) -> String {
+    license.replace('/', " OR ")
+}
+
+/// A triple that's representative of the platform, so platform-specific
+/// dependencies are filtered the way they would be for a real build.
+fn representative_triple(platform: Platform) -> &'static str {
+    match platform {
+        Platform::Android => "aarch64-linux-android",
+        Platform::Apple => "aarch64-apple-ios",
+    }
+}
+
+/// Returns the root crate, along with everything it depends on at runtime.
+fn crate_components(
+    config: &Config,
+    platform: Platform,
+) -> Result<(Component, Vec<Component>), Error> {
+    let output = CargoCommand::new("metadata")
+        .with_manifest_path(Some(config.app().manifest_path()))
+        .into_command_impure()
+        .with_args(&["--format-version", "1", "--filter-platform"])
+        .with_arg(representative_triple(platform))
+        .run_and_wait_for_output()
+        .map_err(Error::MetadataFailed)?;
+    let metadata =
+        serde_json::from_slice::<Metadata>(output.stdout()).map_err(Error::MetadataInvalid)?;
+    let packages = metadata
+        .packages
+        .iter()
+        .map(|package| (package.id.as_str(), package))
+        .collect::<HashMap<_, _>>();
+    let component = |package: &Package| Component {
+        name: package.name.clone(),
+        version: package.version.clone(),
+        purl: format!("pkg:cargo/{}@{}", package.name, package.version),
+        license: package.license.as_deref().map(license_expression),
+    };
+    let resolve = metadata.resolve.as_ref();
+    let root = resolve
+        .and_then(|resolve| resolve.root.as_deref())
+        .and_then(|root| packages.get(root))
+        .copied()
+        .unwrap_or_else(|| {
+            // Virtual workspaces don't have a root, so we fall back to the
+            // package named after the app.
+            metadata
+                .packages
+                .iter()
+                .find(|package| package.name == config.app().name())
+                .unwrap_or(&metadata.packages[0])
+        });
+    let nodes = resolve
+        .map(|resolve| {
+            resolve
+                .nodes
+                .iter()
+                .map(|node| (node.id.as_str(), node))
+                .collect::<HashMap<_, _>>()
+        })
+        .unwrap_or_default();
+    let mut seen = HashSet::new();
+    let mut pending = vec![root.id.as_str()];
+    while let Some(id) = pending.pop() {
+        if let Some(node) = nodes.get(id) {
+            for dep in &node.deps {
+                // A `null` kind is a normal dependency; dev and build
+                // dependencies don't end up in the artifact.
+                let runtime = dep.dep_kinds.iter().any(|kind| kind.kind.is_none());
+                if runtime && seen.insert(dep.pkg.as_str()) {
+                    pending.push(&dep.pkg);
+                }
+            }
+        }
+    }
+    let mut components = seen
+        .into_iter()
+        .filter(|id| *id != root.id)
+        .filter_map(|id| packages.get(id))
+        .map(|package| component(package))
+        .collect::<Vec<_>>();
+    components.sort_by(|a, b| a.purl.cmp(&b.purl));
+    Ok((component(root), components))
+}
+
+/// Parses the tree printed by `gradle dependencies`, which has lines like
+/// `+--- androidx.appcompat:appcompat:1.2.0 -> 1.3.0 (*)`.
+fn parse_gradle_dependencies(stdout: &str) -> Vec<Component> {
+    let mut components = BTreeMap::new();
+    for line in stdout.lines() {
+        let dep = match line.find("--- ") {
+            Some(index) => &line[index + 4..],
+            None => continue,
+        };
+        // Project dependencies are built from source alongside the app, and
+        // `(n)` marks dependencies that weren't resolved.
+        if dep.starts_with("project ") || dep.ends_with("(n)") {
+            continue;
+        }
+        let dep = dep.trim_end_matches(" (*)").trim_end_matches(" (c)").trim();
+        let (requested, resolved) = match dep.find(" -> ") {
+            Some(index) => (&dep[..index], Some(&dep[index + 4..])),
+            None => (dep, None),
+        };
+        // Substitutions resolve to a full coordinate rather than a version.
+        let coordinate = resolved.filter(|resolved| resolved.contains(':'));
+        let mut parts = coordinate.unwrap_or(requested).split(':');
+        let (group, artifact) = match (parts.next(), parts.next()) {
+            (Some(group), Some(artifact)) => (group, artifact),
+            _ => continue,
+        };
+        let version = match resolved.filter(|_| coordinate.is_none()) {
+            Some(version) => Some(version),
+            None => parts.next(),
+        };
+        if let Some(version) = version {
+            let purl = format!("pkg:maven/{}/{}@{}", group, artifact, version);
+            components.entry(purl.clone()).or_insert_with(|| Component {
+                name: format!("{}:{}", group, artifact),
+                version: version.to_owned(),
+                purl,
+                license: None,
+            });
+        }
+    }
+    components.into_values().collect()
+}
+
+fn gradle_components(config: &Config) -> Result<Vec<Component>, Error> {
+    let env = android::env::Env::new().map_err(Error::AndroidEnvFailed)?;
+    let project_dir = config.android().project_dir();
+    let output = bossy::Command::pure(project_dir.join("gradlew"))
+        .with_env_vars(env.explicit_env())
+        .with_arg("--project-dir")
+        .with_arg(&project_dir)
+        .with_args(util::proxy::gradle_args())
+        // The arch doesn't matter, since all flavors have the same
+        // dependencies; this is just what `validate` checks too.
+        .with_args(&[
+            "--quiet",
+            ":app:dependencies",
+            "--configuration",
+            "arm64ReleaseRuntimeClasspath",
+        ])
+        .run_and_wait_for_output()
+        .map_err(Error::GradleFailed)?;
+    output
+        .stdout_str()
+        .map(parse_gradle_dependencies)
+        .map_err(Error::GradleOutputInvalid)
+}
+
+#[cfg(target_os = "macos")]
+/// Parses the `PODS` section of a `Podfile.lock`, which has lines like
+/// `  - Alamofire (5.4.0)`. Subspecs are folded into their pod.
+fn parse_podfile_lock(contents: &str) -> Vec<Component> {
+    let mut components = BTreeMap::new();
+    let pods = contents
+        .lines()
+        .skip_while(|line| *line != "PODS:")
+        .skip(1)
+        .take_while(|line| line.starts_with(' '));
+    for line in pods {
+        let pod = match line.strip_prefix("  - ") {
+            Some(pod) => pod.trim_end_matches(':'),
+            None => continue,
+        };
+        let (name, version) = match (pod.find(" ("), pod.strip_suffix(')')) {
+            (Some(index), Some(pod)) => (&pod[..index], &pod[index + 2..]),
+            _ => continue,
+        };
+        let name = name.split('/').next().unwrap_or(name);
+        components
+            .entry(name.to_owned())
+            .or_insert_with(|| Component {
+                name: name.to_owned(),
+                version: version.to_owned(),
+                purl: format!("pkg:cocoapods/{}@{}", name, version),
+                license: None,
+            });
+    }
+    components.into_values().collect()
+}
+
+#[cfg(target_os = "macos")]
+#[derive(Debug, Deserialize)]
+struct PinState {
+    version: Option<String>,
+    revision: Option<String>,
+}
+
+#[cfg(target_os = "macos")]
+#[derive(Debug, Deserialize)]
+struct Pin {
+    // Version 1 uses `repositoryURL`, and version 2 uses `location`.
+    #[serde(alias = "repositoryURL")]
+    location: String,
+    state: PinState,
+}
+
+#[cfg(target_os = "macos")]
+#[derive(Debug, Deserialize)]
+struct PinsV1 {
+    pins: Vec<Pin>,
+}
+
+#[cfg(target_os = "macos")]
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PackageResolved {
+    V1 { object: PinsV1 },
+    V2 { pins: Vec<Pin> },
+}
+
+#[cfg(target_os = "macos")]
+/// Parses the `Package.resolved` Xcode writes when resolving Swift packages.
+fn parse_package_resolved(contents: &str) -> Result<Vec<Component>, serde_json::Error> {
+    let pins = match serde_json::from_str(contents)? {
+        PackageResolved::V1 { object } => object.pins,
+        PackageResolved::V2 { pins } => pins,
+    };
+    Ok(pins
+        .into_iter()
+        .filter_map(|pin| {
+            let path = pin
+                .location
+                .splitn(2, "://")
+                .last()
+                .unwrap_or(&pin.location)
+                .trim_end_matches('/')
+                .trim_end_matches(".git")
+                .to_owned();
+            let name = path.rsplit('/').next().unwrap_or(&path).to_owned();
+            // Branch pins only have a revision.
+            let version = pin.state.version.or(pin.state.revision)?;
+            Some(Component {
+                purl: format!("pkg:swift/{}@{}", path, version),
+                name,
+                version,
+                license: None,
+            })
+        })
+        .collect())
+}
+
+#[cfg(target_os = "macos")]
+fn read_optional(path: &std::path::Path) -> Result<Option<String>, Error> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            log::info!("{:?} doesn't exist; skipping", path);
+            Ok(None)
+        }
+        Err(cause) => Err(Error::ReadFailed {
+            path: path.to_owned(),
+            cause,
+        }),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn apple_components(config: &Config) -> Result<Vec<Component>, Error> {
+    let mut components = Vec::new();
+    let podfile_lock = config.apple().project_dir().join("Podfile.lock");
+    if let Some(contents) = read_optional(&podfile_lock)? {
+        components.extend(parse_podfile_lock(&contents));
+    }
+    let package_resolved = config
+        .apple()
+        .workspace_path()
+        .join("xcshareddata/swiftpm/Package.resolved");
+    if let Some(contents) = read_optional(&package_resolved)? {
+        components.extend(parse_package_resolved(&contents).map_err(|cause| {
+            Error::PackageResolvedInvalid {
+                path: package_resolved,
+                cause,
+            }
+        })?);
+    }
+    Ok(components)
+}
+
+/// Formats seconds since the epoch as an RFC 3339 UTC timestamp, which both
+/// formats require.
+fn timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // Howard Hinnant's `civil_from_days`.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+fn cyclonedx(root: &Component, components: &[Component], created: &str) -> serde_json::Value {
+    let component = |component: &Component, ty: &str| {
+        let mut value = serde_json::json!({
+            "type": ty,
+            "bom-ref": component.purl,
+            "name": component.name,
+            "version": component.version,
+            "purl": component.purl,
+        });
+        if let Some(license) = &component.license {
+            value["licenses"] = serde_json::json!([{ "expression": license }]);
+        }
+        value
+    };
+    serde_json::json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.4",
+        "version": 1,
+        "metadata": {
+            "timestamp": created,
+            "tools": [{ "name": crate::NAME, "version": env!("CARGO_PKG_VERSION") }],
+            "component": component(root, "application"),
+        },
+        "components": components
+            .iter()
+            .map(|c| component(c, "library"))
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn spdx(root: &Component, components: &[Component], created: &str) -> serde_json::Value {
+    let package = |component: &Component, index: usize| {
+        serde_json::json!({
+            "name": component.name,
+            "SPDXID": format!("SPDXRef-Package-{}", index),
+            "versionInfo": component.version,
+            "downloadLocation": "NOASSERTION",
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": component.license.as_deref().unwrap_or("NOASSERTION"),
+            "externalRefs": [{
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": component.purl,
+            }],
+        })
+    };
+    let mut packages = vec![package(root, 0)];
+    let mut relationships = vec![serde_json::json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": "SPDXRef-Package-0",
+    })];
+    for (index, component) in components.iter().enumerate() {
+        packages.push(package(component, index + 1));
+        relationships.push(serde_json::json!({
+            "spdxElementId": "SPDXRef-Package-0",
+            "relationshipType": "CONTAINS",
+            "relatedSpdxElement": format!("SPDXRef-Package-{}", index + 1),
+        }));
+    }
+    serde_json::json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{}-{}", root.name, root.version),
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/{}-{}-{}",
+            root.name,
+            root.version,
+            created.replace(':', "")
+        ),
+        "creationInfo": {
+            "created": created,
+            "creators": [format!("Tool: {}-{}", crate::NAME, env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
+
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    overrides: &Overrides,
+    platform: Platform,
+    format: Format,
+    output: Option<PathBuf>,
+) -> Result<(), Error> {
+    let (config, _origin) = Config::load_or_gen(".", non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigFailed)?;
+    let (root, mut components) = crate_components(&config, platform)?;
+    components.extend(match platform {
+        Platform::Android => gradle_components(&config)?,
+        #[cfg(target_os = "macos")]
+        Platform::Apple => apple_components(&config)?,
+        #[cfg(not(target_os = "macos"))]
+        Platform::Apple => return Err(Error::AppleUnsupported),
+    });
+    let created = timestamp(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default(),
+    );
+    let document = match format {
+        Format::CycloneDx => cyclonedx(&root, &components, &created),
+        Format::Spdx => spdx(&root, &components, &created),
+    };
+    let document = serde_json::to_string_pretty(&document).map_err(Error::SerializeFailed)?;
+    match output {
+        Some(path) => {
+            fs::write(&path, document).map_err(|cause| Error::WriteFailed {
+                path: path.clone(),
+                cause,
+            })?;
+            println!(
+                "Wrote SBOM with {} components to {:?}",
+                components.len() + 1,
+                path
+            );
+        }
+        None => println!("{}", document),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn purls(components: &[Component]) -> Vec<&str> {
+        components
+            .iter()
+            .map(|component| component.purl.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn gradle_dependencies() {
+        let stdout = "\
+arm64ReleaseRuntimeClasspath - Runtime classpath of compilation 'arm64Release'.
++--- androidx.appcompat:appcompat:1.2.0 -> 1.3.0
+|    +--- androidx.annotation:annotation:1.1.0
+|    \\--- androidx.core:core:1.3.0 (*)
++--- project :library
++--- com.example:old:1.0 -> com.example:new:2.0 (c)
++--- org.jetbrains.kotlin:kotlin-stdlib:{strictly 1.5.0} -> 1.5.0 (n)
+\\--- androidx.annotation:annotation:1.1.0 (*)
+";
+        let components = parse_gradle_dependencies(stdout);
+        assert_eq!(
+            purls(&components),
+            [
+                "pkg:maven/androidx.annotation/annotation@1.1.0",
+                "pkg:maven/androidx.appcompat/appcompat@1.3.0",
+                "pkg:maven/androidx.core/core@1.3.0",
+                "pkg:maven/com.example/new@2.0",
+            ]
+        );
+        assert_eq!(components[1].name, "androidx.appcompat:appcompat");
+        assert_eq!(components[1].version, "1.3.0");
+    }
+
+    #[test]
+    fn gradle_dependencies_empty() {
+        assert!(parse_gradle_dependencies("No dependencies\n").is_empty());
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn podfile_lock() {
+        let contents = "\
+PODS:
+  - Alamofire (5.4.0)
+  - Firebase/Core (8.0.0):
+    - Firebase/CoreOnly
+  - Firebase/CoreOnly (8.0.0)
+
+DEPENDENCIES:
+  - Alamofire
+";
+        let components = parse_podfile_lock(contents);
+        assert_eq!(
+            purls(&components),
+            [
+                "pkg:cocoapods/Alamofire@5.4.0",
+                "pkg:cocoapods/Firebase@8.0.0"
+            ]
+        );
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn package_resolved_v1() {
+        let contents = r#"{
+  "object": {
+    "pins": [
+      {
+        "package": "Foo",
+        "repositoryURL": "https://github.com/example/foo.git",
+        "state": { "branch": "main", "revision": "deadbeef", "version": null }
+      }
+    ]
+  },
+  "version": 1
+}"#;
+        let components = parse_package_resolved(contents).unwrap();
+        assert_eq!(
+            purls(&components),
+            ["pkg:swift/github.com/example/foo@deadbeef"]
+        );
+        assert_eq!(components[0].name, "foo");
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn package_resolved_v2() {
+        let contents = r#"{
+  "pins": [
+    {
+      "identity": "swift-log",
+      "kind": "remoteSourceControl",
+      "location": "https://github.com/apple/swift-log",
+      "state": { "revision": "abc123", "version": "1.4.2" }
+    }
+  ],
+  "version": 2
+}"#;
+        let components = parse_package_resolved(contents).unwrap();
+        assert_eq!(
+            purls(&components),
+            ["pkg:swift/github.com/apple/swift-log@1.4.2"]
+        );
+        assert_eq!(components[0].version, "1.4.2");
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn package_resolved_invalid() {
+        assert!(parse_package_resolved("{}").is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    str::{self, FromStr},
+};
+
+/// Env var holding the passphrase used to decrypt `file:` secrets.
+pub static FILE_KEY_VAR: &str = "CARGO_MOBILE_SECRETS_KEY";
+
+#[derive(Debug)]
+pub enum ParseError {
+    SchemeMissing,
+    SchemeInvalid(String),
+    Empty,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SchemeMissing => write!(
+                f,
+                "secrets can't be stored in your config directly; use `env:VAR`, `keychain:SERVICE[/ACCOUNT]`, or `file:PATH` to refer to one instead"
+            ),
+            Self::SchemeInvalid(scheme) => write!(
+                f,
+                "{:?} isn't a known secret source; the possible sources are \"env\", \"keychain\", and \"file\"",
+                scheme
+            ),
+            Self::Empty => write!(f, "secret reference is missing a name or path"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ResolveError {
+    EnvVarMissing(String),
+    KeychainUnsupported,
+    KeychainLookupFailed(bossy::Error),
+    FileKeyMissing,
+    DecryptFailed { path: PathBuf, cause: bossy::Error },
+    InvalidUtf8(str::Utf8Error),
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EnvVarMissing(var) => write!(f, "Env var {:?} isn't set.", var),
+            Self::KeychainUnsupported => {
+                write!(f, "The keychain is only available on macOS.")
+            }
+            Self::KeychainLookupFailed(err) => {
+                write!(f, "Failed to look up secret in keychain: {}", err)
+            }
+            Self::FileKeyMissing => write!(
+                f,
+                "Env var {:?} must be set to decrypt secrets files.",
+                FILE_KEY_VAR
+            ),
+            Self::DecryptFailed { path, cause } => {
+                write!(f, "Failed to decrypt secrets file {:?}: {}", path, cause)
+            }
+            Self::InvalidUtf8(err) => write!(f, "Secret contained invalid UTF-8: {}", err),
+        }
+    }
+}
+
+/// A reference to a secret stored somewhere other than `mobile.toml`.
+///
+/// In the config, these are written as `env:VAR`, `keychain:SERVICE[/ACCOUNT]`
+/// (macOS only), or `file:PATH`; `file:` secrets are decrypted with
+/// `openssl enc -aes-256-cbc -pbkdf2` using the passphrase in
+/// `CARGO_MOBILE_SECRETS_KEY`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Secret {
+    Env(String),
+    Keychain {
+        service: String,
+        account: Option<String>,
+    },
+    File(PathBuf),
+}
+
+impl Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Env(var) => write!(f, "env:{}", var),
+            Self::Keychain { service, account } => match account {
+                Some(account) => write!(f, "keychain:{}/{}", service, account),
+                None => write!(f, "keychain:{}", service),
+            },
+            Self::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for Secret {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let scheme = parts.next().unwrap_or_default();
+        let rest = parts.next().ok_or(ParseError::SchemeMissing)?;
+        if rest.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        match scheme {
+            "env" => Ok(Self::Env(rest.to_owned())),
+            "keychain" => {
+                let mut parts = rest.splitn(2, '/');
+                let service = parts.next().unwrap_or_default().to_owned();
+                let account = parts.next().map(ToOwned::to_owned);
+                Ok(Self::Keychain { service, account })
+            }
+            "file" => Ok(Self::File(rest.into())),
+            _ => Err(ParseError::SchemeInvalid(scheme.to_owned())),
+        }
+    }
+}
+
+impl Secret {
+    /// Fetches the secret's value. `file:` paths are relative to `root_dir`.
+    pub fn resolve(&self, root_dir: &Path) -> Result<String, ResolveError> {
+        log::info!("resolving secret {}", self);
+        match self {
+            Self::Env(var) => {
+                std::env::var(var).map_err(|_| ResolveError::EnvVarMissing(var.clone()))
+            }
+            Self::Keychain { service, account } => {
+                if cfg!(target_os = "macos") {
+                    let output = bossy::Command::impure("security")
+                        .with_args(&["find-generic-password", "-w", "-s", service.as_str()])
+                        .with_args(
+                            account
+                                .iter()
+                                .flat_map(|account| vec!["-a", account.as_str()]),
+                        )
+                        .run_and_wait_for_output()
+                        .map_err(ResolveError::KeychainLookupFailed)?;
+                    output
+                        .stdout_str()
+                        .map(|value| value.trim_end_matches('\n').to_owned())
+                        .map_err(ResolveError::InvalidUtf8)
+                } else {
+                    Err(ResolveError::KeychainUnsupported)
+                }
+            }
+            Self::File(path) => {
+                if std::env::var_os(FILE_KEY_VAR).is_none() {
+                    return Err(ResolveError::FileKeyMissing);
+                }
+                let path = root_dir.join(path);
+                let output = bossy::Command::impure("openssl")
+                    .with_args(&["enc", "-d", "-aes-256-cbc", "-pbkdf2", "-in"])
+                    .with_arg(&path)
+                    .with_args(&["-pass", &format!("env:{}", FILE_KEY_VAR)])
+                    .run_and_wait_for_output()
+                    .map_err(|cause| ResolveError::DecryptFailed {
+                        path: path.clone(),
+                        cause,
+                    })?;
+                output
+                    .stdout_str()
+                    .map(|value| value.trim_end_matches('\n').to_owned())
+                    .map_err(ResolveError::InvalidUtf8)
+            }
+        }
+    }
+}
@@ -40,14 +40,11 @@ pub trait TargetTrait<'a>: Debug + Sized {
         util::rustup_add(self.triple())
     }
 
-    fn install_all() -> bossy::Result<()>
+    fn install_all() -> Result<(), util::rustup::Error>
     where
         Self: 'a,
     {
-        for target in Self::all().values() {
-            target.install()?;
-        }
-        Ok(())
+        util::rustup::install_missing(Self::all().values().map(|target| target.triple()))
     }
 }
 
@@ -59,11 +56,13 @@ pub struct TargetInvalid {
 
 impl Display for TargetInvalid {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "Target {:?} is invalid; the possible targets are {:?}",
-            self.name, self.possible,
-        )
+        write!(f, "Target {:?} is invalid; ", self.name)?;
+        if let Some(suggestion) =
+            util::did_you_mean(&self.name, self.possible.iter().map(String::as_str))
+        {
+            write!(f, "did you mean {:?}? ", suggestion)?;
+        }
+        write!(f, "The possible targets are {:?}", self.possible)
     }
 }
 
@@ -0,0 +1,209 @@
+use bicycle::Action;
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+};
+
+#[derive(Debug)]
+pub struct ConditionParseError {
+    expr: String,
+    reason: &'static str,
+}
+
+impl Display for ConditionParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Condition {:?} is invalid: {}", self.expr, self.reason)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    Truthy,
+    Falsy,
+    Eq(String),
+    Ne(String),
+}
+
+/// A tiny expression evaluated against the template data, which supports
+/// `key`, `!key`, `key == value`, and `key != value`, where `key` is a dotted
+/// path like `template.vars.compose`.
+#[derive(Clone, Debug)]
+pub struct Condition {
+    key: Vec<String>,
+    op: Op,
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    if value.len() >= 2
+        && (value.starts_with('"') && value.ends_with('"')
+            || value.starts_with('\'') && value.ends_with('\''))
+    {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+fn is_truthy(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => false,
+        serde_json::Value::Bool(value) => *value,
+        serde_json::Value::String(value) => !matches!(value.as_str(), "" | "false" | "no" | "0"),
+        serde_json::Value::Number(value) => value.as_f64() != Some(0.0),
+        serde_json::Value::Array(value) => !value.is_empty(),
+        serde_json::Value::Object(_) => true,
+    }
+}
+
+fn to_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(value) => value.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl Condition {
+    pub fn parse(expr: &str) -> Result<Self, ConditionParseError> {
+        let err = |reason| ConditionParseError {
+            expr: expr.to_owned(),
+            reason,
+        };
+        let trimmed = expr.trim();
+        let (key, op) = if let Some(index) = trimmed.find("!=") {
+            (
+                &trimmed[..index],
+                Op::Ne(unquote(&trimmed[index + 2..]).to_owned()),
+            )
+        } else if let Some(index) = trimmed.find("==") {
+            (
+                &trimmed[..index],
+                Op::Eq(unquote(&trimmed[index + 2..]).to_owned()),
+            )
+        } else if trimmed.starts_with('!') {
+            (&trimmed[1..], Op::Falsy)
+        } else {
+            (trimmed, Op::Truthy)
+        };
+        let key = key
+            .trim()
+            .split('.')
+            .map(|component| component.trim().to_owned())
+            .collect::<Vec<_>>();
+        if key.iter().any(|component| component.is_empty()) {
+            return Err(err("expected a key like `template.vars.name`"));
+        }
+        if key.iter().any(|component| {
+            !component
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        }) {
+            return Err(err(
+                "keys may only contain letters, numbers, dashes, and underscores",
+            ));
+        }
+        Ok(Self { key, op })
+    }
+
+    /// Missing keys are treated as falsy, so packs can test for optional vars.
+    pub fn eval(&self, data: &serde_json::Value) -> bool {
+        let value = self
+            .key
+            .iter()
+            .try_fold(data, |value, component| value.get(component.as_str()));
+        match &self.op {
+            Op::Truthy => value.map(is_truthy).unwrap_or(false),
+            Op::Falsy => !value.map(is_truthy).unwrap_or(false),
+            Op::Eq(expected) => value.map(to_string).as_deref() == Some(expected.as_str()),
+            Op::Ne(expected) => value.map(to_string).as_deref() != Some(expected.as_str()),
+        }
+    }
+}
+
+/// Wraps `fun` so that files from excluded paths in the pack are skipped.
+pub fn excluding<'a>(
+    excluded: &'a [PathBuf],
+    mut fun: impl FnMut(&Action) -> bool + 'a,
+) -> impl FnMut(&Action) -> bool + 'a {
+    move |action| {
+        let src = match action {
+            Action::CreateDirectory { .. } => None,
+            Action::CopyFile { src, .. } | Action::WriteTemplate { src, .. } => Some(src),
+        };
+        if let Some(src) = src.filter(|src| excluded.iter().any(|path| src.starts_with(path))) {
+            log::info!("skipping {:?}, since its condition wasn't met", src);
+            false
+        } else {
+            fun(action)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn eval(expr: &str, data: &serde_json::Value) -> bool {
+        Condition::parse(expr).unwrap().eval(data)
+    }
+
+    #[test]
+    fn truthiness() {
+        let data = json!({ "template": { "vars": {
+            "on": true,
+            "off": false,
+            "yes": "yes",
+            "no": "no",
+            "empty": "",
+            "zero": 0,
+            "count": 2
+        } } });
+        assert!(eval("template.vars.on", &data));
+        assert!(!eval("template.vars.off", &data));
+        assert!(eval("template.vars.yes", &data));
+        assert!(!eval("template.vars.no", &data));
+        assert!(!eval("template.vars.empty", &data));
+        assert!(!eval("template.vars.zero", &data));
+        assert!(eval("template.vars.count", &data));
+        assert!(eval("!template.vars.off", &data));
+        assert!(!eval(" ! template.vars.on ", &data));
+    }
+
+    #[test]
+    fn missing_keys_are_falsy() {
+        let data = json!({ "template": { "vars": {} } });
+        assert!(!eval("template.vars.compose", &data));
+        assert!(eval("!template.vars.compose", &data));
+        assert!(!eval("template.vars.compose == true", &data));
+        assert!(eval("template.vars.compose != true", &data));
+        assert!(!eval("nothing.here", &data));
+    }
+
+    #[test]
+    fn comparisons() {
+        let data = json!({ "template": { "vars": { "ui": "compose", "on": true, "n": 3 } } });
+        assert!(eval("template.vars.ui == compose", &data));
+        assert!(eval("template.vars.ui == \"compose\"", &data));
+        assert!(eval("template.vars.ui=='compose'", &data));
+        assert!(!eval("template.vars.ui == views", &data));
+        assert!(eval("template.vars.ui != views", &data));
+        assert!(eval("template.vars.on == true", &data));
+        assert!(eval("template.vars.n == 3", &data));
+    }
+
+    #[test]
+    fn invalid() {
+        for expr in &[
+            "",
+            "!",
+            "template..vars",
+            "template.vars.",
+            "== x",
+            "a b",
+            "a.$b",
+        ] {
+            assert!(Condition::parse(expr).is_err(), "{:?} parsed", expr);
+        }
+    }
+}
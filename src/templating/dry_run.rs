@@ -0,0 +1,315 @@
+use super::Filter;
+use bicycle::Action;
+use colored::Colorize as _;
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum DryRunError {
+    StagingFailed { path: PathBuf, cause: io::Error },
+    ReadFailed { path: PathBuf, cause: io::Error },
+}
+
+impl Display for DryRunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StagingFailed { path, cause } => write!(
+                f,
+                "Failed to create staging directory at {:?}: {}",
+                path, cause
+            ),
+            Self::ReadFailed { path, cause } => write!(f, "Failed to read {:?}: {}", path, cause),
+        }
+    }
+}
+
+static CONTEXT: usize = 3;
+
+// Paths in the project and paths outside of it are staged in separate dirs,
+// so they can't collide, and can each be mapped back to where they came from.
+static IN_ROOT_DIR: &str = "root";
+static OUTSIDE_ROOT_DIR: &str = "absolute";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Op {
+    Keep,
+    Remove,
+    Add,
+}
+
+// A minimal line diff, which is plenty for the handful of lines that usually
+// change between template revisions.
+fn ops<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    // lcs[i][j] is the length of the longest common subsequence of old[i..]
+    // and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push((Op::Keep, old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out.push((Op::Add, new[j]));
+            j += 1;
+        } else {
+            out.push((Op::Remove, old[i]));
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Produces the lines of a unified diff (without the file headers), with
+/// `CONTEXT` lines of context around each hunk.
+pub(super) fn diff(old: &str, new: &str) -> Vec<String> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let ops = ops(&old, &new);
+    let mut out = Vec::new();
+    let mut index = 0;
+    while let Some(first_change) = ops[index..]
+        .iter()
+        .position(|(op, _)| *op != Op::Keep)
+        .map(|offset| index + offset)
+    {
+        // Extend the hunk until we see more than twice the context's worth of
+        // unchanged lines, since then the next change gets its own hunk.
+        let start = first_change.saturating_sub(CONTEXT);
+        let mut end = first_change;
+        let mut unchanged = 0;
+        for (offset, (op, _)) in ops[first_change..].iter().enumerate() {
+            if *op == Op::Keep {
+                unchanged += 1;
+                if unchanged > CONTEXT * 2 {
+                    break;
+                }
+            } else {
+                unchanged = 0;
+                end = first_change + offset;
+            }
+        }
+        let end = (end + CONTEXT + 1).min(ops.len());
+        let (old_start, new_start) = ops[..start]
+            .iter()
+            .fold((1, 1), |(o, n), (op, _)| match op {
+                Op::Keep => (o + 1, n + 1),
+                Op::Remove => (o + 1, n),
+                Op::Add => (o, n + 1),
+            });
+        let old_len = ops[start..end]
+            .iter()
+            .filter(|(op, _)| *op != Op::Add)
+            .count();
+        let new_len = ops[start..end]
+            .iter()
+            .filter(|(op, _)| *op != Op::Remove)
+            .count();
+        out.push(format!(
+            "@@ -{},{} +{},{} @@",
+            old_start, old_len, new_start, new_len
+        ));
+        for (op, line) in &ops[start..end] {
+            let prefix = match op {
+                Op::Keep => ' ',
+                Op::Remove => '-',
+                Op::Add => '+',
+            };
+            out.push(format!("{}{}", prefix, line));
+        }
+        index = end;
+    }
+    out
+}
+
+/// Prints a diff between two versions of `path`, colored if the terminal
+/// supports it.
+pub(super) fn print_diff(path: &Path, old: &[u8], new: &[u8]) {
+    match (std::str::from_utf8(old), std::str::from_utf8(new)) {
+        (Ok(old), Ok(new)) => {
+            println!("{}", format!("--- {}", path.display()).bold());
+            println!("{}", format!("+++ {}", path.display()).bold());
+            for line in diff(old, new) {
+                if line.starts_with("@@") {
+                    println!("{}", line.cyan());
+                } else if line.starts_with('+') {
+                    println!("{}", line.green());
+                } else if line.starts_with('-') {
+                    println!("{}", line.red());
+                } else {
+                    println!("{}", line);
+                }
+            }
+        }
+        _ => println!("Binary file {:?} differs", path),
+    }
+}
+
+/// Generates into a scratch directory instead of the project, so we can show
+/// what would change without touching anything.
+#[derive(Debug)]
+pub struct DryRun {
+    root: PathBuf,
+    staging: PathBuf,
+}
+
+impl Drop for DryRun {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.staging) {
+            log::error!(
+                "failed to delete staging directory {:?}: {}",
+                self.staging,
+                err
+            );
+        }
+    }
+}
+
+impl DryRun {
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self, DryRunError> {
+        let staging =
+            std::env::temp_dir().join(format!("cargo-mobile-dry-run-{}", std::process::id()));
+        if staging.exists() {
+            fs::remove_dir_all(&staging).map_err(|cause| DryRunError::StagingFailed {
+                path: staging.clone(),
+                cause,
+            })?;
+        }
+        fs::create_dir_all(&staging).map_err(|cause| DryRunError::StagingFailed {
+            path: staging.clone(),
+            cause,
+        })?;
+        log::info!("staging dry run in {:?}", staging);
+        Ok(Self {
+            root: root.into(),
+            staging,
+        })
+    }
+
+    /// Maps a path in the project to where it should be staged.
+    pub fn stage(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.root) {
+            Ok(rel) => self.staging.join(IN_ROOT_DIR).join(rel),
+            Err(_) => {
+                log::warn!(
+                    "{:?} is outside of the project root, so it'll be staged by its absolute path",
+                    path
+                );
+                self.staging
+                    .join(OUTSIDE_ROOT_DIR)
+                    .join(path.strip_prefix("/").unwrap_or(path))
+            }
+        }
+    }
+
+    pub(super) fn staging_dir(&self) -> &Path {
+        &self.staging
+    }
+
+    /// The inverse of `stage`.
+    pub(super) fn unstage(&self, path: &Path) -> PathBuf {
+        let staged = |dir: &str| path.strip_prefix(self.staging.join(dir)).ok();
+        if let Some(rel) = staged(IN_ROOT_DIR) {
+            self.root.join(rel)
+        } else if let Some(rel) = staged(OUTSIDE_ROOT_DIR) {
+            Path::new("/").join(rel)
+        } else {
+            path.to_owned()
+        }
+    }
+
+    /// Wraps `filter` so it sees the paths actions would have in the project,
+    /// rather than their staged paths.
+    pub fn filter<'a>(&'a self, filter: &'a Filter) -> impl FnMut(&Action) -> bool + 'a {
+        let mut fun = filter.fun();
+        move |action| {
+            let action = match action {
+                Action::CreateDirectory { dest } => Action::CreateDirectory {
+                    dest: self.unstage(dest),
+                },
+                Action::CopyFile { src, dest } => Action::CopyFile {
+                    src: src.clone(),
+                    dest: self.unstage(dest),
+                },
+                Action::WriteTemplate { src, dest } => Action::WriteTemplate {
+                    src: src.clone(),
+                    dest: self.unstage(dest),
+                },
+            };
+            fun(&action)
+        }
+    }
+
+    pub(super) fn staged_files(
+        &self,
+        dir: &Path,
+        files: &mut Vec<PathBuf>,
+    ) -> Result<(), DryRunError> {
+        let entries = fs::read_dir(dir).map_err(|cause| DryRunError::ReadFailed {
+            path: dir.to_owned(),
+            cause,
+        })?;
+        for entry in entries {
+            let path = entry
+                .map_err(|cause| DryRunError::ReadFailed {
+                    path: dir.to_owned(),
+                    cause,
+                })?
+                .path();
+            if path.is_dir() {
+                self.staged_files(&path, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints every file that would be created or modified, along with diffs
+    /// for the latter.
+    pub fn report(&self) -> Result<(), DryRunError> {
+        let mut files = Vec::new();
+        self.staged_files(&self.staging, &mut files)?;
+        files.sort();
+        let (mut created, mut modified) = (0, 0);
+        for staged in files {
+            let dest = self.unstage(&staged);
+            let new = fs::read(&staged).map_err(|cause| DryRunError::ReadFailed {
+                path: staged.clone(),
+                cause,
+            })?;
+            if !dest.exists() {
+                created += 1;
+                println!("create {:?}", dest);
+                continue;
+            }
+            let old = fs::read(&dest).map_err(|cause| DryRunError::ReadFailed {
+                path: dest.clone(),
+                cause,
+            })?;
+            if old != new {
+                modified += 1;
+                println!("modify {:?}", dest);
+                print_diff(&dest, &old, &new);
+            }
+        }
+        println!(
+            "Dry run complete: {} file(s) would be created and {} modified; nothing was written.",
+            created, modified
+        );
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use super::{LookupError, Pack};
+use super::{Condition, ConditionParseError, LookupError, Pack};
 use crate::util::{
     self,
     submodule::{self, Submodule},
@@ -23,6 +23,13 @@ pub enum FancyPackParseError {
     },
     NoHomeDir(util::NoHomeDir),
     BaseFailed(Box<LookupError>),
+    ConditionInvalid {
+        path: PathBuf,
+        cause: ConditionParseError,
+    },
+    HookEmpty {
+        path: PathBuf,
+    },
 }
 
 impl Display for FancyPackParseError {
@@ -40,6 +47,14 @@ impl Display for FancyPackParseError {
             ),
             Self::NoHomeDir(err) => write!(f, "{}", err),
             Self::BaseFailed(err) => write!(f, "Failed to lookup base template pack: {}", err),
+            Self::ConditionInvalid { path, cause } => {
+                write!(f, "Template pack spec {:?} is invalid: {}", path, cause)
+            }
+            Self::HookEmpty { path } => write!(
+                f,
+                "Template pack spec {:?} is invalid: hooks must have a non-empty `command`",
+                path
+            ),
         }
     }
 }
@@ -59,20 +74,152 @@ impl Display for FancyPackResolveError {
     }
 }
 
+/// What kind of value a var holds, so templates and `when` conditions see a
+/// real bool or number rather than a string.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum VarType {
+    String,
+    Bool,
+    Integer,
+}
+
+impl Default for VarType {
+    fn default() -> Self {
+        Self::String
+    }
+}
+
+impl Display for VarType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String => write!(f, "string"),
+            Self::Bool => write!(f, "bool"),
+            Self::Integer => write!(f, "integer"),
+        }
+    }
+}
+
+impl VarType {
+    /// Converts `value` to this type, parsing it if it's a string (i.e. if it
+    /// was typed in at a prompt). Returns `None` if it can't be converted.
+    pub fn coerce(self, value: &toml::Value) -> Option<toml::Value> {
+        match (self, value) {
+            (Self::String, toml::Value::String(_))
+            | (Self::Bool, toml::Value::Boolean(_))
+            | (Self::Integer, toml::Value::Integer(_)) => Some(value.clone()),
+            (Self::Bool, toml::Value::String(s)) => match s.trim().to_lowercase().as_str() {
+                "true" | "yes" | "y" => Some(toml::Value::Boolean(true)),
+                "false" | "no" | "n" => Some(toml::Value::Boolean(false)),
+                _ => None,
+            },
+            (Self::Integer, toml::Value::String(s)) => {
+                s.trim().parse().ok().map(toml::Value::Integer)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A variable the pack's templates need, which is read from `template.vars`
+/// in the config (or prompted for if it's missing there).
+#[derive(Clone, Debug, Deserialize)]
+pub struct VarSpec {
+    name: String,
+    description: Option<String>,
+    #[serde(default, rename = "type")]
+    ty: VarType,
+    default: Option<toml::Value>,
+}
+
+impl VarSpec {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn ty(&self) -> VarType {
+        self.ty
+    }
+
+    pub fn default(&self) -> Option<&toml::Value> {
+        self.default.as_ref()
+    }
+}
+
+/// A command to run in the project after it's generated, i.e. `pod install`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Hook {
+    name: Option<String>,
+    command: Vec<String>,
+    dir: Option<PathBuf>,
+}
+
+impl Hook {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.command.join(" "))
+    }
+
+    pub fn program(&self) -> &str {
+        &self.command[0]
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.command[1..]
+    }
+
+    /// Relative to the app root.
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+}
+
+/// Files in the pack (relative to its root) that are only generated when
+/// `when` holds.
+#[derive(Clone, Debug)]
+struct Conditional {
+    path: PathBuf,
+    when: Condition,
+}
+
 #[derive(Clone, Debug)]
 pub struct FancyPack {
     path: PathBuf,
     base: Option<Box<Pack>>,
     submodule: Option<Submodule>,
+    vars: Vec<VarSpec>,
+    conditionals: Vec<Conditional>,
+    hooks: Vec<Hook>,
+    webview: bool,
 }
 
 impl FancyPack {
-    pub fn parse(path: impl AsRef<Path>) -> Result<Self, FancyPackParseError> {
+    /// `local` packs are ones referenced by path rather than installed, in
+    /// which case a relative `path` in the spec is relative to the spec;
+    /// otherwise, it's relative to the app root, like a submodule's is.
+    pub fn parse(path: impl AsRef<Path>, local: bool) -> Result<Self, FancyPackParseError> {
+        #[derive(Deserialize)]
+        struct RawConditional {
+            path: PathBuf,
+            when: String,
+        }
+
         #[derive(Deserialize)]
         struct Raw {
             path: PathBuf,
             base: Option<String>,
             submodule: Option<Submodule>,
+            #[serde(default, rename = "var")]
+            vars: Vec<VarSpec>,
+            #[serde(default, rename = "conditional")]
+            conditionals: Vec<RawConditional>,
+            #[serde(default, rename = "hook")]
+            hooks: Vec<Hook>,
+            #[serde(default)]
+            webview: bool,
         }
 
         let path = path.as_ref();
@@ -87,6 +234,35 @@ impl FancyPack {
             })?
         };
         let real_path = util::expand_home(&raw.path).map_err(FancyPackParseError::NoHomeDir)?;
+        // Being relative to the spec is what makes it possible to develop a
+        // pack without installing it.
+        let real_path = if local {
+            path.parent()
+                .expect("developer error: template pack spec had no parent")
+                .join(real_path)
+        } else {
+            real_path
+        };
+        let conditionals = raw
+            .conditionals
+            .into_iter()
+            .map(|conditional| {
+                Condition::parse(&conditional.when)
+                    .map(|when| Conditional {
+                        path: conditional.path,
+                        when,
+                    })
+                    .map_err(|cause| FancyPackParseError::ConditionInvalid {
+                        path: path.to_owned(),
+                        cause,
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if raw.hooks.iter().any(|hook| hook.command.is_empty()) {
+            return Err(FancyPackParseError::HookEmpty {
+                path: path.to_owned(),
+            });
+        }
         let this = Self {
             path: real_path,
             base: raw
@@ -96,6 +272,7 @@ impl FancyPack {
                         path.parent()
                             .expect("developer error: templates dir had no parent"),
                         &name,
+                        local,
                     )
                 })
                 .transpose()
@@ -103,6 +280,10 @@ impl FancyPack {
                 .map_err(FancyPackParseError::BaseFailed)?
                 .map(Box::new),
             submodule: raw.submodule,
+            vars: raw.vars,
+            conditionals,
+            hooks: raw.hooks,
+            webview: raw.webview,
         };
         log::info!("template pack {:#?}", this);
         Ok(this)
@@ -112,6 +293,68 @@ impl FancyPack {
         self.submodule.as_ref().map(|submodule| submodule.path())
     }
 
+    /// Variables declared by this pack and everything it's based on.
+    pub fn vars(&self) -> Vec<&VarSpec> {
+        let mut vars = self
+            .base
+            .as_ref()
+            .map(|base| base.vars())
+            .unwrap_or_default();
+        vars.extend(&self.vars);
+        vars
+    }
+
+    /// Whether the app is a WebView shell, which the platform projects need to
+    /// be set up differently for. This is inherited from the base pack.
+    pub fn webview(&self) -> bool {
+        self.webview || self.base.as_ref().map_or(false, |base| base.webview())
+    }
+
+    /// Hooks declared by this pack and everything it's based on, in the order
+    /// they should run.
+    pub fn hooks(&self) -> Vec<&Hook> {
+        let mut hooks = self
+            .base
+            .as_ref()
+            .map(|base| base.hooks())
+            .unwrap_or_default();
+        hooks.extend(&self.hooks);
+        hooks
+    }
+
+    /// Paths in this pack and everything it's based on whose conditions don't
+    /// hold for `data`.
+    pub fn excluded(&self, data: &serde_json::Value) -> Vec<PathBuf> {
+        let mut excluded = self
+            .base
+            .as_ref()
+            .map(|base| base.excluded(data))
+            .unwrap_or_default();
+        excluded.extend(
+            self.conditionals
+                .iter()
+                .filter(|conditional| !conditional.when.eval(data))
+                .map(|conditional| self.path.join(&conditional.path)),
+        );
+        excluded
+    }
+
+    /// Like `resolve`, but without initializing submodules; anything that isn't
+    /// present locally is skipped.
+    pub fn local_chain(&self) -> Vec<&Path> {
+        let mut paths = self
+            .base
+            .as_ref()
+            .map(|base| base.local_chain())
+            .unwrap_or_default();
+        if self.path.exists() {
+            paths.push(&self.path);
+        } else {
+            log::warn!("template pack {:?} isn't present locally", self.path);
+        }
+        paths
+    }
+
     pub fn resolve(
         &self,
         git: Git<'_>,
@@ -94,6 +94,35 @@ fn reverse_domain(
         .map_err(Into::into)
 }
 
+// JNI symbol names escape underscores as `_1`, since a plain underscore
+// separates package components.
+fn jni_escape(s: &str) -> String {
+    s.replace('_', "_1").replace('.', "_")
+}
+
+fn reverse_domain_jni(
+    helper: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    out.write(&jni_escape(&util::reverse_domain(get_str(helper))))
+        .map_err(Into::into)
+}
+
+fn snake_case_jni(
+    helper: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    use heck::SnekCase as _;
+    out.write(&jni_escape(&get_str(helper).to_snek_case()))
+        .map_err(Into::into)
+}
+
 fn app_root<'a>(ctx: &'a Context) -> Result<&'a str, RenderError> {
     let app_root = ctx
         .data()
@@ -172,6 +201,8 @@ pub fn init(config: Option<&Config>) -> Bicycle {
             helpers.insert("quote-and-join", Box::new(quote_and_join));
             helpers.insert("snake-case", Box::new(snake_case));
             helpers.insert("reverse-domain", Box::new(reverse_domain));
+            helpers.insert("reverse-domain-jni", Box::new(reverse_domain_jni));
+            helpers.insert("snake-case-jni", Box::new(snake_case_jni));
             if config.is_some() {
                 // don't mix these up or very bad things will happen to all of us
                 helpers.insert("prefix-path", Box::new(prefix_path));
@@ -187,6 +218,9 @@ pub fn init(config: Option<&Config>) -> Bicycle {
                 #[cfg(target_os = "macos")]
                 map.insert(crate::apple::NAME, config.apple());
                 map.insert(crate::android::NAME, config.android());
+                map.insert(crate::config::template::KEY, config.template());
+                map.insert("webview", config.app().template_pack().webview());
+                map.insert("manifest-path", config.app().manifest_path());
             }
             map
         },
@@ -0,0 +1,547 @@
+use crate::{
+    config::app::App,
+    util::{self, Git},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+static HEADER: &str = "# This file is generated by `cargo mobile init`; commit it so that everyone\n# generates identical projects.\n\n";
+
+#[derive(Debug)]
+pub enum LockError {
+    ReadFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ParseFailed {
+        path: PathBuf,
+        cause: toml::de::Error,
+    },
+    SerializeFailed(toml::ser::Error),
+    WriteFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+}
+
+#[derive(Debug)]
+pub enum PinError {
+    DirCreationFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ExportFailed {
+        name: String,
+        commit: String,
+        cause: bossy::Error,
+    },
+    RenameFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+}
+
+impl Display for PinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DirCreationFailed { path, cause } => {
+                write!(f, "Failed to create directory at {:?}: {}", path, cause)
+            }
+            Self::ExportFailed {
+                name,
+                commit,
+                cause,
+            } => write!(
+                f,
+                "Failed to export locked commit {} of template pack {:?}: {}",
+                commit, name, cause
+            ),
+            Self::RenameFailed { path, cause } => {
+                write!(f, "Failed to move exported pack to {:?}: {}", path, cause)
+            }
+        }
+    }
+}
+
+impl Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadFailed { path, cause } => {
+                write!(f, "Failed to read lockfile at {:?}: {}", path, cause)
+            }
+            Self::ParseFailed { path, cause } => {
+                write!(f, "Failed to parse lockfile at {:?}: {}", path, cause)
+            }
+            Self::SerializeFailed(err) => write!(f, "Failed to serialize lockfile: {}", err),
+            Self::WriteFailed { path, cause } => {
+                write!(f, "Failed to write lockfile to {:?}: {}", path, cause)
+            }
+        }
+    }
+}
+
+fn commit(path: &Path) -> Option<String> {
+    Git::new(path)
+        .command()
+        .with_args(&["rev-parse", "HEAD"])
+        .run_and_wait_for_output()
+        .ok()
+        .and_then(|output| {
+            output
+                .stdout_str()
+                .ok()
+                .map(|commit| commit.trim().to_owned())
+        })
+        .filter(|commit| !commit.is_empty())
+}
+
+// Whether `commit` has a signature that `git` can verify with the keys you
+// trust.
+fn signed(path: &Path, commit: &str) -> bool {
+    Git::new(path)
+        .command()
+        .with_args(&["verify-commit", commit])
+        .run_and_wait_for_output()
+        .is_ok()
+}
+
+fn pack_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Pack paths are recorded relative to the app root, or else to your home
+/// directory, so that the lockfile means the same thing on every machine.
+fn portable_path(root_dir: &Path, path: &Path) -> PathBuf {
+    if let Ok(rel) = path.strip_prefix(root_dir) {
+        return rel.to_owned();
+    }
+    match util::home_dir().map(|home| path.strip_prefix(home).map(|rel| Path::new("~").join(rel))) {
+        Ok(Ok(path)) => path,
+        _ => path.to_owned(),
+    }
+}
+
+/// The inverse of `portable_path`.
+fn local_path(root_dir: &Path, path: &Path) -> PathBuf {
+    let path = util::expand_home(path).unwrap_or_else(|_| path.to_owned());
+    if path.is_relative() {
+        root_dir.join(path)
+    } else {
+        path
+    }
+}
+
+/// Writes the contents of `path` as of `commit` to `dest`. `path` can be
+/// anywhere in its repo, since packs are often subdirectories.
+fn export(name: &str, path: &Path, commit: &str, dest: &Path) -> Result<(), PinError> {
+    let export_failed = |cause| PinError::ExportFailed {
+        name: name.to_owned(),
+        commit: commit.to_owned(),
+        cause,
+    };
+    let git = Git::new(path);
+    let prefix = git
+        .command()
+        .with_args(&["rev-parse", "--show-prefix"])
+        .run_and_wait_for_output()
+        .map_err(export_failed)?;
+    let prefix = prefix.stdout_str().unwrap_or_default().trim();
+    let partial = dest.with_file_name(format!("{}.partial", pack_name(dest)));
+    if partial.exists() {
+        fs::remove_dir_all(&partial).map_err(|cause| PinError::DirCreationFailed {
+            path: partial.clone(),
+            cause,
+        })?;
+    }
+    fs::create_dir_all(&partial).map_err(|cause| PinError::DirCreationFailed {
+        path: partial.clone(),
+        cause,
+    })?;
+    let archive = partial.join(".pack.tar");
+    git.command()
+        .with_args(&["archive", "--format=tar", "-o"])
+        .with_arg(&archive)
+        .with_arg(format!("{}:{}", commit, prefix))
+        .run_and_wait_for_output()
+        .map_err(export_failed)?;
+    bossy::Command::impure("tar")
+        .with_arg("-xf")
+        .with_arg(&archive)
+        .with_arg("-C")
+        .with_arg(&partial)
+        .run_and_wait_for_output()
+        .map_err(export_failed)?;
+    if let Err(err) = fs::remove_file(&archive) {
+        log::warn!("failed to remove {:?}: {}", archive, err);
+    }
+    fs::rename(&partial, dest).map_err(|cause| PinError::RenameFailed {
+        path: dest.to_owned(),
+        cause,
+    })
+}
+
+/// A SHA-256 of every file in the pack (other than in `.git`), along with
+/// their paths, so renames and deletions count too.
+fn checksum(path: &Path) -> Option<String> {
+    let mut files = Vec::new();
+    for entry in ignore::WalkBuilder::new(path)
+        .standard_filters(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build()
+    {
+        match entry {
+            Ok(entry) if entry.file_type().map_or(false, |ty| ty.is_file()) => {
+                files.push(entry.into_path())
+            }
+            Ok(_) => (),
+            Err(err) => {
+                log::warn!("failed to checksum template pack {:?}: {}", path, err);
+                return None;
+            }
+        }
+    }
+    files.sort();
+    let mut hasher = Sha256::new();
+    for file in files {
+        let contents = match fs::read(&file) {
+            Ok(contents) => contents,
+            Err(err) => {
+                log::warn!("failed to checksum {:?}: {}", file, err);
+                return None;
+            }
+        };
+        let rel = file.strip_prefix(path).unwrap_or(&file);
+        hasher.update(rel.to_string_lossy().as_bytes());
+        hasher.update(&[0]);
+        hasher.update(&(contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+    Some(format!("sha256:{:x}", hasher.finalize()))
+}
+
+/// A pack in the chain, along with where to render it from: its working copy,
+/// or an export of the commit it's locked to.
+#[derive(Debug)]
+pub struct PinnedPack {
+    path: PathBuf,
+    render_from: PathBuf,
+    commit: Option<String>,
+}
+
+impl PinnedPack {
+    pub fn unpinned(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            render_from: path.to_owned(),
+            commit: commit(path),
+        }
+    }
+
+    pub fn render_from(&self) -> &Path {
+        &self.render_from
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct LockedPack {
+    // Missing from lockfiles written by older versions, in which case it's
+    // taken from the path.
+    #[serde(default)]
+    name: String,
+    path: PathBuf,
+    commit: Option<String>,
+    // Both of these are missing from lockfiles written by older versions, in
+    // which case there's nothing to verify against until they're rewritten.
+    #[serde(default)]
+    checksum: Option<String>,
+    #[serde(default)]
+    signed: bool,
+}
+
+impl LockedPack {
+    fn new(root_dir: &Path, pack: &PinnedPack) -> Self {
+        Self {
+            name: pack_name(&pack.path),
+            path: portable_path(root_dir, &pack.path),
+            commit: pack.commit.clone(),
+            checksum: checksum(&pack.render_from),
+            signed: pack
+                .commit
+                .as_deref()
+                .map_or(false, |commit| signed(&pack.path, commit)),
+        }
+    }
+
+    fn name(&self) -> String {
+        if self.name.is_empty() {
+            pack_name(&self.path)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+fn cargo_mobile_version() -> Option<String> {
+    crate::util::installed_commit_msg()
+        .ok()
+        .flatten()
+        .and_then(|msg| msg.lines().next().map(ToOwned::to_owned))
+}
+
+/// Records exactly which template packs (and which commits of them) were used
+/// to generate the project, so regenerating elsewhere gives the same result.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Lockfile {
+    cargo_mobile: Option<String>,
+    submodule_commit: Option<String>,
+    #[serde(default)]
+    packs: Vec<LockedPack>,
+}
+
+impl Lockfile {
+    /// Each of the config's `[apps.<name>]` sections generates its own
+    /// project, so each gets its own lockfile.
+    pub fn path(app: &App) -> PathBuf {
+        app.root_dir().join(match app.selected_app() {
+            Some(selected) => format!("{}.{}.lock", crate::NAME, selected),
+            None => format!("{}.lock", crate::NAME),
+        })
+    }
+
+    /// Every lockfile in `dir`, for when there's no config to tell us which
+    /// app we're looking at.
+    pub fn paths_in(dir: &Path) -> Vec<PathBuf> {
+        let mut paths = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| {
+                        path.file_name()
+                            .and_then(|name| name.to_str())
+                            .map_or(false, |name| {
+                                name.starts_with(crate::NAME) && name.ends_with(".lock")
+                            })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        paths.sort();
+        paths
+    }
+
+    pub fn load(app: &App) -> Result<Option<Self>, LockError> {
+        Self::load_from(Self::path(app))
+    }
+
+    /// Like `load`, but for when there's no config to find the lockfile with.
+    pub fn load_from(path: PathBuf) -> Result<Option<Self>, LockError> {
+        if !path.is_file() {
+            log::info!("no lockfile present at {:?}", path);
+            return Ok(None);
+        }
+        let bytes = fs::read(&path).map_err(|cause| LockError::ReadFailed {
+            path: path.clone(),
+            cause,
+        })?;
+        toml::from_slice(&bytes)
+            .map(Some)
+            .map_err(|cause| LockError::ParseFailed { path, cause })
+    }
+
+    pub fn new(app: &App, pack_chain: &[PinnedPack]) -> Self {
+        Self {
+            cargo_mobile: cargo_mobile_version(),
+            submodule_commit: app
+                .template_pack()
+                .submodule_path()
+                .and_then(|path| commit(&app.root_dir().join(path))),
+            packs: pack_chain
+                .iter()
+                .map(|pack| LockedPack::new(app.root_dir(), pack))
+                .collect(),
+        }
+    }
+
+    fn locked_commit(&self, name: &str) -> Option<&str> {
+        self.packs
+            .iter()
+            .find(|pack| pack.name() == name)
+            .and_then(|pack| pack.commit.as_deref())
+    }
+
+    /// Exports the locked commit of each pack in `chain` that's since moved
+    /// off of it, so the project's generated from exactly what was locked.
+    /// Packs that aren't in the lockfile are rendered as they are.
+    pub fn pin(&self, chain: &[&Path]) -> Result<Vec<PinnedPack>, PinError> {
+        chain
+            .iter()
+            .map(|path| {
+                let pack = PinnedPack::unpinned(path);
+                let name = pack_name(path);
+                match self.locked_commit(&name) {
+                    Some(locked) if pack.commit.as_deref() != Some(locked) => {
+                        // Commits never change, so an export can be reused.
+                        let dest = util::temp_dir()
+                            .join("pinned")
+                            .join(format!("{}-{}", name, locked));
+                        if !dest.is_dir() {
+                            export(&name, path, locked, &dest)?;
+                        }
+                        util::cli::status(format!(
+                            "Using template pack {:?} at its locked commit {}",
+                            name, locked
+                        ));
+                        Ok(PinnedPack {
+                            render_from: dest,
+                            commit: Some(locked.to_owned()),
+                            ..pack
+                        })
+                    }
+                    _ => Ok(pack),
+                }
+            })
+            .collect()
+    }
+
+    /// Describes how the packs on disk differ from what was locked, for
+    /// `doctor`. Unlike `drift`, this doesn't need a config, so it only knows
+    /// about the packs in the lockfile.
+    pub fn check(&self, root_dir: &Path) -> Vec<String> {
+        let mut problems = Vec::new();
+        let packs = self
+            .packs
+            .iter()
+            .filter_map(|locked| {
+                let path = local_path(root_dir, &locked.path);
+                if path.is_dir() {
+                    Some(LockedPack::new(root_dir, &PinnedPack::unpinned(&path)))
+                } else {
+                    problems.push(format!(
+                        "template pack {:?} isn't present at {:?}",
+                        locked.name(),
+                        path
+                    ));
+                    None
+                }
+            })
+            .collect();
+        let current = Self {
+            cargo_mobile: cargo_mobile_version(),
+            submodule_commit: self.submodule_commit.clone(),
+            packs,
+        };
+        if problems.is_empty() {
+            problems.extend(self.drift(&current));
+        }
+        problems
+    }
+
+    pub fn submodule_commit(&self) -> Option<&str> {
+        self.submodule_commit.as_deref()
+    }
+
+    /// Each pack in the chain, along with the commit it was at.
+    pub fn packs(&self) -> impl Iterator<Item = (&Path, Option<&str>)> {
+        self.packs
+            .iter()
+            .map(|pack| (pack.path.as_path(), pack.commit.as_deref()))
+    }
+
+    /// Describes how `current` differs from what was locked.
+    pub fn drift(&self, current: &Self) -> Vec<String> {
+        let mut drift = Vec::new();
+        if self.cargo_mobile != current.cargo_mobile {
+            drift.push(format!(
+                "`cargo-mobile` was {}, but is now {}",
+                self.cargo_mobile.as_deref().unwrap_or("unknown"),
+                current.cargo_mobile.as_deref().unwrap_or("unknown"),
+            ));
+        }
+        if self.packs.len() != current.packs.len()
+            || self
+                .packs
+                .iter()
+                .zip(&current.packs)
+                .any(|(locked, current)| locked.name() != current.name())
+        {
+            drift.push("the template pack chain changed".to_owned());
+        } else {
+            for (locked, current) in self.packs.iter().zip(&current.packs) {
+                if locked.commit != current.commit {
+                    drift.push(format!(
+                        "template pack {:?} was at commit {}, but is now at {}",
+                        locked.name(),
+                        locked.commit.as_deref().unwrap_or("unknown"),
+                        current.commit.as_deref().unwrap_or("unknown"),
+                    ));
+                }
+            }
+        }
+        drift
+    }
+
+    /// Describes how the contents of the packs in `current` differ from what
+    /// was locked, which we refuse to generate from without
+    /// `--allow-unverified`. Unlike drift, a pack changing without its commit
+    /// changing (or at all, if it isn't in a git repo) is caught here.
+    pub fn verify(&self, current: &Self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for locked in &self.packs {
+            let current = match current
+                .packs
+                .iter()
+                .find(|pack| pack.name() == locked.name())
+            {
+                Some(current) => current,
+                None => continue,
+            };
+            if let Some(checksum) = &locked.checksum {
+                if current.checksum.as_ref() != Some(checksum) {
+                    problems.push(format!(
+                        "template pack {:?} was locked with checksum {}, but now has {}{}",
+                        locked.name(),
+                        checksum,
+                        current.checksum.as_deref().unwrap_or("an unknown checksum"),
+                        if locked.commit.is_some() && locked.commit == current.commit {
+                            " without its commit changing"
+                        } else {
+                            ""
+                        },
+                    ));
+                }
+            }
+            if locked.signed && !current.signed {
+                problems.push(format!(
+                    "template pack {:?} was at a signed commit, but commit {} couldn't be verified",
+                    locked.name(),
+                    current.commit.as_deref().unwrap_or("unknown"),
+                ));
+            }
+        }
+        let is_locked =
+            |pack: &LockedPack| self.packs.iter().any(|locked| locked.name() == pack.name());
+        for pack in current.packs.iter().filter(|pack| !is_locked(pack)) {
+            problems.push(format!(
+                "template pack {:?} isn't in the lockfile",
+                pack.name()
+            ));
+        }
+        problems
+    }
+
+    pub fn write(&self, app: &App) -> Result<(), LockError> {
+        let path = Self::path(app);
+        let contents = toml::to_string(self).map_err(LockError::SerializeFailed)?;
+        log::info!("writing lockfile to {:?}", path);
+        fs::write(&path, format!("{}{}", HEADER, contents))
+            .map_err(|cause| LockError::WriteFailed { path, cause })
+    }
+}
@@ -1,8 +1,12 @@
+mod condition;
+mod dry_run;
 mod fancy;
 mod filter;
 mod init;
+mod lock;
+mod review;
 
-pub use self::{fancy::*, filter::*, init::*};
+pub use self::{condition::*, dry_run::*, fancy::*, filter::*, init::*, lock::*, review::*};
 
 use crate::util::{self, Git};
 use std::{
@@ -15,6 +19,28 @@ use std::{
 // always be at the top of the list.
 static BRAINIUM: &[&str] = &["brainstorm", "brainstorm-demo"];
 
+// Descriptions for the packs we ship; anything else installed alongside them
+// is still listed, just without a description.
+static DESCRIPTIONS: &[(&str, &str)] = &[
+    ("bevy", "Bevy sprite example"),
+    ("bevy-demo", "Bevy breakout example"),
+    (
+        "bevy-game",
+        "Bevy game with mobile assets, audio, and lifecycle set up",
+    ),
+    ("egui", "Touch-ready `egui` UI on `winit` and `wgpu`"),
+    ("wgpu", "`wgpu` triangle example"),
+    ("winit", "`winit` window example"),
+    ("wry", "`wry` WebView shell, loading the assets dir"),
+];
+
+pub fn app_pack_description(name: &str) -> Option<&'static str> {
+    DESCRIPTIONS
+        .iter()
+        .find(|(pack, _)| *pack == name)
+        .map(|(_, description)| *description)
+}
+
 fn platform_pack_dir() -> Result<PathBuf, util::NoHomeDir> {
     util::install_dir().map(|dir| dir.join("templates/platforms"))
 }
@@ -62,6 +88,7 @@ impl Pack {
     pub(super) fn lookup(
         dir: impl AsRef<Path>,
         name: impl AsRef<str>,
+        local: bool,
     ) -> Result<Self, LookupError> {
         fn check_path(name: &str, path: &Path) -> Option<PathBuf> {
             log::info!("checking for template pack \"{}\" at {:?}", name, path);
@@ -87,7 +114,7 @@ impl Pack {
                 })
         }?;
         if path.extension() == Some("toml".as_ref()) {
-            let pack = FancyPack::parse(path).map_err(LookupError::FancyPackParseFailed)?;
+            let pack = FancyPack::parse(path, local).map_err(LookupError::FancyPackParseFailed)?;
             Ok(Pack::Fancy(pack))
         } else {
             Ok(Pack::Simple(path))
@@ -97,13 +124,31 @@ impl Pack {
     pub fn lookup_platform(name: &str) -> Result<Self, LookupError> {
         platform_pack_dir()
             .map_err(LookupError::NoHomeDir)
-            .and_then(|dir| Self::lookup(dir, name))
+            .and_then(|dir| Self::lookup(dir, name, false))
     }
 
     pub fn lookup_app(name: &str) -> Result<Self, LookupError> {
         app_pack_dir()
             .map_err(LookupError::NoHomeDir)
-            .and_then(|dir| Self::lookup(dir, name))
+            .and_then(|dir| Self::lookup(dir, name, false))
+    }
+
+    /// Packs can also be referenced by path (relative to the app root), which
+    /// is handy when developing them.
+    pub fn is_local(name: &str) -> bool {
+        name.starts_with('.') || name.starts_with('~') || name.contains('/')
+    }
+
+    pub fn lookup_local(root_dir: &Path, path: &str) -> Result<Self, LookupError> {
+        let path = root_dir.join(util::expand_home(path).map_err(LookupError::NoHomeDir)?);
+        match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) => Self::lookup(dir, name.to_string_lossy(), true),
+            _ => Err(LookupError::MissingPack {
+                name: path.display().to_string(),
+                tried_toml: path.clone(),
+                tried: path,
+            }),
+        }
     }
 
     pub fn expect_local(self) -> PathBuf {
@@ -122,6 +167,41 @@ impl Pack {
         }
     }
 
+    pub fn vars(&self) -> Vec<&VarSpec> {
+        match self {
+            Self::Simple(_) => Vec::new(),
+            Self::Fancy(pack) => pack.vars(),
+        }
+    }
+
+    pub fn hooks(&self) -> Vec<&Hook> {
+        match self {
+            Self::Simple(_) => Vec::new(),
+            Self::Fancy(pack) => pack.hooks(),
+        }
+    }
+
+    pub fn webview(&self) -> bool {
+        match self {
+            Self::Simple(_) => false,
+            Self::Fancy(pack) => pack.webview(),
+        }
+    }
+
+    pub fn excluded(&self, data: &serde_json::Value) -> Vec<PathBuf> {
+        match self {
+            Self::Simple(_) => Vec::new(),
+            Self::Fancy(pack) => pack.excluded(data),
+        }
+    }
+
+    pub fn local_chain(&self) -> Vec<&Path> {
+        match self {
+            Self::Simple(path) => vec![path.as_path()],
+            Self::Fancy(pack) => pack.local_chain(),
+        }
+    }
+
     pub fn resolve(
         &self,
         git: Git<'_>,
@@ -0,0 +1,148 @@
+use super::{dry_run::print_diff, DryRun};
+use crate::{opts, util::prompt};
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum ReviewError {
+    ReadFailed { path: PathBuf, cause: io::Error },
+    WriteFailed { path: PathBuf, cause: io::Error },
+    PromptFailed(io::Error),
+}
+
+impl Display for ReviewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadFailed { path, cause } => write!(f, "Failed to read {:?}: {}", path, cause),
+            Self::WriteFailed { path, cause } => {
+                write!(f, "Failed to write {:?}: {}", path, cause)
+            }
+            Self::PromptFailed(err) => write!(f, "Failed to prompt for confirmation: {}", err),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Answer {
+    Yes,
+    No,
+    All,
+}
+
+fn ask(path: &Path) -> Result<Answer, ReviewError> {
+    loop {
+        let response = prompt::minimal(format!("Overwrite {:?}? [y/n/a]", path))
+            .map_err(ReviewError::PromptFailed)?;
+        match response.to_ascii_lowercase().as_str() {
+            "y" | "yes" => return Ok(Answer::Yes),
+            "n" | "no" => return Ok(Answer::No),
+            "a" | "all" => return Ok(Answer::All),
+            _ => println!("Please answer \"y\" to overwrite, \"n\" to keep your version, or \"a\" to overwrite this and everything after it."),
+        }
+    }
+}
+
+/// Decides what happens when regenerating would change a file that already
+/// exists: interactively, we show a diff and ask; otherwise, we only overwrite
+/// if changes were explicitly accepted.
+#[derive(Clone, Copy, Debug)]
+pub struct Review {
+    non_interactive: opts::NonInteractive,
+    accept_changes: opts::AcceptChanges,
+}
+
+impl Review {
+    pub fn new(non_interactive: opts::NonInteractive, accept_changes: opts::AcceptChanges) -> Self {
+        Self {
+            non_interactive,
+            accept_changes,
+        }
+    }
+
+    /// Overwrites without asking, which is only appropriate when the user
+    /// already opted into that, i.e. when watching a template pack.
+    pub fn accept_all() -> Self {
+        Self::new(opts::NonInteractive::Yes, opts::AcceptChanges::Yes)
+    }
+
+    fn walk(dir: &Path, entries: &mut Vec<(PathBuf, bool)>) -> Result<(), ReviewError> {
+        let read_failed = |cause| ReviewError::ReadFailed {
+            path: dir.to_owned(),
+            cause,
+        };
+        for entry in fs::read_dir(dir).map_err(read_failed)? {
+            let path = entry.map_err(read_failed)?.path();
+            let is_dir = path.is_dir();
+            entries.push((path.clone(), is_dir));
+            if is_dir {
+                Self::walk(&path, entries)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves everything rendered into `dry_run` into place.
+    pub fn apply(&self, dry_run: &DryRun) -> Result<(), ReviewError> {
+        let mut entries = Vec::new();
+        Self::walk(dry_run.staging_dir(), &mut entries)?;
+        entries.sort();
+        let mut accept_all = self.accept_changes.yes();
+        let mut kept = 0;
+        for (staged, is_dir) in entries {
+            let dest = dry_run.unstage(&staged);
+            if is_dir {
+                fs::create_dir_all(&dest).map_err(|cause| ReviewError::WriteFailed {
+                    path: dest.clone(),
+                    cause,
+                })?;
+                continue;
+            }
+            if dest.exists() && !accept_all {
+                let read = |path: &Path| {
+                    fs::read(path).map_err(|cause| ReviewError::ReadFailed {
+                        path: path.to_owned(),
+                        cause,
+                    })
+                };
+                let (old, new) = (read(&dest)?, read(&staged)?);
+                if old == new {
+                    continue;
+                }
+                if self.non_interactive.yes() {
+                    log::warn!("keeping modified file {:?}", dest);
+                    kept += 1;
+                    continue;
+                }
+                print_diff(&dest, &old, &new);
+                match ask(&dest)? {
+                    Answer::Yes => (),
+                    Answer::No => {
+                        kept += 1;
+                        continue;
+                    }
+                    Answer::All => accept_all = true,
+                }
+            }
+            log::info!("writing {:?}", dest);
+            fs::copy(&staged, &dest).map_err(|cause| ReviewError::WriteFailed {
+                path: dest.clone(),
+                cause,
+            })?;
+        }
+        if kept > 0 {
+            println!(
+                "Kept your version of {} file(s) that would've been changed{}.",
+                kept,
+                if self.non_interactive.yes() {
+                    "; pass `--accept-changes` to overwrite them"
+                } else {
+                    ""
+                }
+            );
+        }
+        Ok(())
+    }
+}
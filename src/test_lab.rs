@@ -0,0 +1,121 @@
+//! Runs test bundles on Firebase Test Lab's device farm through `gcloud`,
+//! which uploads them, waits for the matrix to finish, and reports an outcome
+//! per device.
+
+use crate::util::{
+    self,
+    cli::{Report, Reportable},
+};
+use colored::Colorize as _;
+use serde::Deserialize;
+
+#[derive(Debug)]
+pub enum Error {
+    GcloudMissing,
+    GcloudPresentFailed(bossy::Error),
+    RunFailed(bossy::Error),
+    OutputInvalid(serde_json::Error),
+    TestsFailed { failed: usize, total: usize },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::GcloudMissing => Report::action_request(
+                "`gcloud` isn't installed; please install the Google Cloud SDK, run `gcloud auth login`, and try again.",
+                "https://cloud.google.com/sdk/docs/install",
+            ),
+            Self::GcloudPresentFailed(err) => {
+                Report::error("Failed to check if `gcloud` is installed", err)
+            }
+            Self::RunFailed(err) => Report::error("Failed to run tests on Firebase Test Lab", err),
+            Self::OutputInvalid(err) => {
+                Report::error("Failed to parse Firebase Test Lab results", err)
+            }
+            Self::TestsFailed { failed, total } => Report::error(
+                "Tests failed on Firebase Test Lab",
+                format!("{} of {} device(s) didn't pass", failed, total),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct Outcome {
+    axis_value: String,
+    outcome: String,
+    #[serde(default)]
+    test_details: String,
+}
+
+impl Outcome {
+    fn passed(&self) -> bool {
+        self.outcome == "Passed" || self.outcome == "Flaky"
+    }
+}
+
+/// The `gcloud firebase test <platform> run` command, before any platform
+/// specific args are added.
+pub fn command(platform: &str, devices: &[String], timeout: Option<&str>) -> bossy::Command {
+    let mut command = bossy::Command::impure("gcloud")
+        .with_args(&["firebase", "test", platform, "run"])
+        .with_arg("--format=json");
+    for device in devices {
+        command.add_arg("--device");
+        command.add_arg(device);
+    }
+    if let Some(timeout) = timeout {
+        command.add_arg("--timeout");
+        command.add_arg(timeout);
+    }
+    command
+}
+
+fn render(outcomes: &[Outcome]) {
+    for outcome in outcomes {
+        let status = match outcome.outcome.as_str() {
+            "Passed" => outcome.outcome.green(),
+            "Flaky" => outcome.outcome.yellow(),
+            _ => outcome.outcome.red(),
+        };
+        if outcome.test_details.is_empty() {
+            println!("{:>9}  {}", status.bold(), outcome.axis_value);
+        } else {
+            println!(
+                "{:>9}  {} ({})",
+                status.bold(),
+                outcome.axis_value,
+                outcome.test_details
+            );
+        }
+    }
+}
+
+/// Runs `command` (from `command`, with the platform's bundles added), and
+/// prints the outcome for each device.
+pub fn run(command: bossy::Command) -> Result<(), Error> {
+    if !util::command_present("gcloud").map_err(Error::GcloudPresentFailed)? {
+        return Err(Error::GcloudMissing);
+    }
+    // `gcloud` exits with an error if any tests failed, but still prints the
+    // results.
+    let stdout = match command.run_and_wait_for_output() {
+        Ok(output) => output.stdout().to_owned(),
+        Err(err) => match err.output() {
+            Some(output) if !output.stdout().is_empty() => output.stdout().to_owned(),
+            _ => return Err(Error::RunFailed(err)),
+        },
+    };
+    let outcomes: Vec<Outcome> = serde_json::from_slice(&stdout).map_err(Error::OutputInvalid)?;
+    render(&outcomes);
+    let failed = outcomes.iter().filter(|outcome| !outcome.passed()).count();
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(Error::TestsFailed {
+            failed,
+            total: outcomes.len(),
+        })
+    }
+}
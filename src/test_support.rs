@@ -0,0 +1,293 @@
+//! Helpers for testing template packs against the same generation engine
+//! `cargo mobile init` uses. Packs are rendered into a temp dir, which can
+//! then be asserted on directly or compared to a golden directory:
+//!
+//! ```ignore
+//! let project = test_support::Project::new("[app]\nname = \"hello\"\n...")?;
+//! let rendered = project.render("path/to/pack")?;
+//! rendered.assert_contains("Cargo.toml", "name = \"hello\"");
+//! rendered.assert_matches_golden("tests/golden/hello");
+//! ```
+//!
+//! Set `CARGO_MOBILE_UPDATE_GOLDEN=1` to overwrite the golden files with
+//! whatever was rendered, rather than comparing against them.
+
+use crate::{
+    config::{self, Config, LoadOrGenError, Overrides},
+    opts,
+    templating::{self, Pack},
+    util::cli,
+};
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static UPDATE_GOLDEN_VAR: &str = "CARGO_MOBILE_UPDATE_GOLDEN";
+
+// Tests run concurrently, so each temp dir needs its own name.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+pub enum Error {
+    TempDirFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ConfigWriteFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ConfigLoadFailed(LoadOrGenError),
+    ConditionDataFailed(serde_json::Error),
+    ProcessingFailed {
+        pack: PathBuf,
+        cause: bicycle::ProcessingError,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TempDirFailed { path, cause } => {
+                write!(f, "Failed to create temp dir {:?}: {}", path, cause)
+            }
+            Self::ConfigWriteFailed { path, cause } => {
+                write!(f, "Failed to write config to {:?}: {}", path, cause)
+            }
+            Self::ConfigLoadFailed(err) => write!(f, "Failed to load config: {:?}", err),
+            Self::ConditionDataFailed(err) => {
+                write!(f, "Failed to evaluate template pack conditions: {}", err)
+            }
+            Self::ProcessingFailed { pack, cause } => {
+                write!(f, "Failed to render template pack {:?}: {}", pack, cause)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A directory that's deleted when dropped.
+#[derive(Debug)]
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(label: &str) -> Result<Self, Error> {
+        let path = std::env::temp_dir().join(format!(
+            "cargo-mobile-test-{}-{}-{}",
+            label,
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+        if path.exists() {
+            fs::remove_dir_all(&path).map_err(|cause| Error::TempDirFailed {
+                path: path.clone(),
+                cause,
+            })?;
+        }
+        fs::create_dir_all(&path).map_err(|cause| Error::TempDirFailed {
+            path: path.clone(),
+            cause,
+        })?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.0) {
+            log::warn!("failed to remove temp dir {:?}: {}", self.0, err);
+        }
+    }
+}
+
+/// A throwaway project root with a config, which is what packs get rendered
+/// against.
+#[derive(Debug)]
+pub struct Project {
+    config: Config,
+    root: TempDir,
+}
+
+impl Project {
+    /// `config_toml` is the contents of the project's `mobile.toml`.
+    pub fn new(config_toml: &str) -> Result<Self, Error> {
+        let root = TempDir::new("project")?;
+        let config_path = root.0.join(config::file_name());
+        fs::write(&config_path, config_toml).map_err(|cause| Error::ConfigWriteFailed {
+            path: config_path,
+            cause,
+        })?;
+        let (config, _origin) = Config::load_or_gen(
+            &root.0,
+            opts::NonInteractive::Yes,
+            &Overrides::default(),
+            &cli::default_wrapper(),
+        )
+        .map_err(Error::ConfigLoadFailed)?;
+        Ok(Self { config, root })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn root_dir(&self) -> &Path {
+        &self.root.0
+    }
+
+    /// Renders the pack at `pack_path` (a directory, or a fancy pack's
+    /// `.toml`), honoring its conditions. Fancy packs are rendered from their
+    /// local chain, so submodule-based packs need their submodule checked out.
+    pub fn render(&self, pack_path: impl AsRef<Path>) -> Result<Rendered, Error> {
+        let pack_path = pack_path.as_ref();
+        let pack = match (pack_path.parent(), pack_path.file_stem()) {
+            (Some(dir), Some(name)) => Pack::lookup(dir, name.to_string_lossy(), true).ok(),
+            _ => None,
+        }
+        .unwrap_or_else(|| Pack::Simple(pack_path.to_owned()));
+        let excluded =
+            pack.excluded(&serde_json::to_value(&self.config).map_err(Error::ConditionDataFailed)?);
+        let out = TempDir::new("rendered")?;
+        let bike = self.config.build_a_bike();
+        for src in pack.local_chain() {
+            bike.filter_and_process(
+                src,
+                &out.0,
+                |_| (),
+                templating::excluding(&excluded, |_| true),
+            )
+            .map_err(|cause| Error::ProcessingFailed {
+                pack: src.to_owned(),
+                cause,
+            })?;
+        }
+        Ok(Rendered { dir: out })
+    }
+}
+
+fn walk(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries =
+        fs::read_dir(dir).unwrap_or_else(|err| panic!("failed to read {:?}: {}", dir, err));
+    for entry in entries {
+        let path = entry
+            .unwrap_or_else(|err| panic!("failed to read entry in {:?}: {}", dir, err))
+            .path();
+        if path.is_dir() {
+            walk(root, &path, files);
+        } else {
+            files.push(path.strip_prefix(root).unwrap().to_owned());
+        }
+    }
+}
+
+/// The output of rendering a pack. The assertions panic with a description of
+/// what didn't match, so they can be used directly in tests.
+#[derive(Debug)]
+pub struct Rendered {
+    dir: TempDir,
+}
+
+impl Rendered {
+    pub fn dir(&self) -> &Path {
+        &self.dir.0
+    }
+
+    /// Every rendered file, relative to the output dir, in sorted order.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        walk(self.dir(), self.dir(), &mut files);
+        files.sort();
+        files
+    }
+
+    pub fn read(&self, path: impl AsRef<Path>) -> String {
+        let path = self.dir().join(path);
+        fs::read_to_string(&path).unwrap_or_else(|err| panic!("failed to read {:?}: {}", path, err))
+    }
+
+    pub fn assert_exists(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        assert!(
+            self.dir().join(path).exists(),
+            "expected {:?} to be rendered, but it wasn't; rendered files: {:#?}",
+            path,
+            self.files()
+        );
+    }
+
+    pub fn assert_missing(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        assert!(
+            !self.dir().join(path).exists(),
+            "expected {:?} not to be rendered, but it was",
+            path
+        );
+    }
+
+    pub fn assert_contains(&self, path: impl AsRef<Path>, needle: &str) {
+        let path = path.as_ref();
+        let contents = self.read(path);
+        assert!(
+            contents.contains(needle),
+            "expected {:?} to contain {:?}, but it was:\n{}",
+            path,
+            needle,
+            contents
+        );
+    }
+
+    /// Checks that the rendered files are exactly those in `golden_dir`, with
+    /// the same contents. Any files that reference the temp project root
+    /// should be avoided or asserted on separately, since it differs per run.
+    pub fn assert_matches_golden(&self, golden_dir: impl AsRef<Path>) {
+        let golden_dir = golden_dir.as_ref();
+        if std::env::var(UPDATE_GOLDEN_VAR).map_or(false, |value| value == "1") {
+            self.update_golden(golden_dir);
+            return;
+        }
+        let mut expected = Vec::new();
+        walk(golden_dir, golden_dir, &mut expected);
+        expected.sort();
+        let actual = self.files();
+        assert_eq!(
+            actual, expected,
+            "rendered files didn't match those in {:?} (set `{}=1` to update)",
+            golden_dir, UPDATE_GOLDEN_VAR
+        );
+        for path in actual {
+            let read = |path: &Path| {
+                fs::read(path).unwrap_or_else(|err| panic!("failed to read {:?}: {}", path, err))
+            };
+            let (actual, expected) = (read(&self.dir().join(&path)), read(&golden_dir.join(&path)));
+            assert!(
+                actual == expected,
+                "{:?} didn't match its golden version (set `{}=1` to update):\n--- expected\n{}\n--- actual\n{}",
+                path,
+                UPDATE_GOLDEN_VAR,
+                String::from_utf8_lossy(&expected),
+                String::from_utf8_lossy(&actual)
+            );
+        }
+    }
+
+    fn update_golden(&self, golden_dir: &Path) {
+        if golden_dir.exists() {
+            fs::remove_dir_all(golden_dir)
+                .unwrap_or_else(|err| panic!("failed to clear {:?}: {}", golden_dir, err));
+        }
+        for path in self.files() {
+            let dest = golden_dir.join(&path);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)
+                    .unwrap_or_else(|err| panic!("failed to create {:?}: {}", parent, err));
+            }
+            fs::copy(self.dir().join(&path), &dest)
+                .unwrap_or_else(|err| panic!("failed to write {:?}: {}", dest, err));
+        }
+        println!("updated golden files in {:?}", golden_dir);
+    }
+}
@@ -0,0 +1,94 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    cell::Cell,
+    fs, io,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+static BYPASS: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static SKIP: Cell<bool> = Cell::new(false);
+}
+
+/// Ignores anything cached for the rest of the process, i.e. for `--refresh`.
+/// Fresh results are still cached for next time.
+pub fn bypass() {
+    BYPASS.store(true, Ordering::SeqCst);
+}
+
+/// Runs `f` without reading anything cached, i.e. while polling for devices
+/// to come and go. Fresh results are still cached for next time.
+pub fn skipping<T>(f: impl FnOnce() -> T) -> T {
+    let skipped = SKIP.with(|skip| skip.replace(true));
+    let result = f();
+    SKIP.with(|skip| skip.set(skipped));
+    result
+}
+
+fn path(name: &str) -> PathBuf {
+    super::temp_dir()
+        .join("cache")
+        .join(format!("{}.json", name))
+}
+
+/// Loads what was cached under `name`, as long as it's younger than `max_age`.
+/// Anything going wrong here just counts as a miss.
+pub fn load<T: DeserializeOwned>(name: &str, max_age: Duration) -> Option<T> {
+    if BYPASS.load(Ordering::SeqCst) {
+        log::info!("ignoring cached {:?}, since a refresh was requested", name);
+        return None;
+    }
+    if SKIP.with(Cell::get) {
+        log::info!("ignoring cached {:?}, since we're polling", name);
+        return None;
+    }
+    let path = path(name);
+    let age = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())?;
+    if age > max_age {
+        log::info!("cached {:?} is stale ({:?} old)", name, age);
+        return None;
+    }
+    let bytes = fs::read(&path).ok()?;
+    match serde_json::from_slice(&bytes) {
+        Ok(value) => {
+            log::info!("using cached {:?} ({:?} old)", name, age);
+            Some(value)
+        }
+        Err(err) => {
+            log::info!("cached {:?} couldn't be parsed: {}", name, err);
+            None
+        }
+    }
+}
+
+pub fn store<T: Serialize>(name: &str, value: &T) {
+    let path = path(name);
+    let result = path
+        .parent()
+        .map(fs::create_dir_all)
+        .transpose()
+        .and_then(|_| {
+            let bytes = serde_json::to_vec(value).map_err(io::Error::from)?;
+            fs::write(&path, bytes)
+        });
+    if let Err(err) = result {
+        log::info!("failed to cache {:?} at {:?}: {}", name, path, err);
+    }
+}
+
+/// Throws away what was cached under `name`, i.e. once something suggests
+/// it's wrong.
+pub fn clear(name: &str) {
+    let path = path(name);
+    if let Err(err) = fs::remove_file(&path) {
+        if err.kind() != io::ErrorKind::NotFound {
+            log::info!("failed to clear cached {:?} at {:?}: {}", name, path, err);
+        }
+    }
+}
@@ -1,16 +1,75 @@
 use crate::env::ExplicitEnv;
+use serde::Deserialize;
 use std::path::PathBuf;
 
+/// Overrides for a cargo profile's settings, which are passed to cargo as
+/// `CARGO_PROFILE_<NAME>_*` env vars so they only apply to our builds.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProfileSettings {
+    #[serde(default)]
+    split_debuginfo: Option<String>,
+    /// `true`, `false`, `"symbols"`, `"debuginfo"`, or `"none"`.
+    #[serde(default)]
+    strip: Option<toml::Value>,
+    /// `true`, `false`, `"thin"`, `"fat"`, or `"off"`.
+    #[serde(default)]
+    lto: Option<toml::Value>,
+    #[serde(default)]
+    panic: Option<String>,
+}
+
+impl ProfileSettings {
+    fn env_vars(&self, profile: &str) -> Vec<(String, String)> {
+        fn value_str(value: &toml::Value) -> String {
+            match value {
+                toml::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            }
+        }
+
+        let var = |key: &str| format!("CARGO_PROFILE_{}_{}", profile, key);
+        let mut vars = Vec::new();
+        if let Some(split_debuginfo) = &self.split_debuginfo {
+            vars.push((var("SPLIT_DEBUGINFO"), split_debuginfo.clone()));
+        }
+        if let Some(strip) = &self.strip {
+            vars.push((var("STRIP"), value_str(strip)));
+        }
+        if let Some(lto) = &self.lto {
+            vars.push((var("LTO"), value_str(lto)));
+        }
+        if let Some(panic) = &self.panic {
+            vars.push((var("PANIC"), panic.clone()));
+        }
+        vars
+    }
+}
+
+/// Profile settings for debug and release builds, i.e.
+/// `[package.metadata.cargo-android.release]`.
+#[derive(Debug, Default, Deserialize)]
+pub struct Profiles {
+    #[serde(default)]
+    debug: ProfileSettings,
+    #[serde(default)]
+    release: ProfileSettings,
+}
+
 #[derive(Debug)]
 pub struct CargoCommand<'a> {
     subcommand: &'a str,
     verbose: bool,
+    quiet: bool,
     package: Option<&'a str>,
     manifest_path: Option<PathBuf>,
+    target_dir: Option<PathBuf>,
     target: Option<&'a str>,
     no_default_features: bool,
-    features: Option<&'a [String]>,
+    features: Vec<&'a str>,
+    example: Option<&'a str>,
     release: bool,
+    profiles: Option<&'a Profiles>,
 }
 
 impl<'a> CargoCommand<'a> {
@@ -18,12 +77,16 @@ impl<'a> CargoCommand<'a> {
         Self {
             subcommand,
             verbose: Default::default(),
+            quiet: super::cli::quiet(),
             package: Default::default(),
             manifest_path: Default::default(),
+            target_dir: Default::default(),
             target: Default::default(),
             no_default_features: Default::default(),
             features: Default::default(),
+            example: Default::default(),
             release: Default::default(),
+            profiles: Default::default(),
         }
     }
 
@@ -42,6 +105,11 @@ impl<'a> CargoCommand<'a> {
         self
     }
 
+    pub fn with_target_dir(mut self, target_dir: Option<PathBuf>) -> Self {
+        self.target_dir = target_dir;
+        self
+    }
+
     pub fn with_target(mut self, target: Option<&'a str>) -> Self {
         self.target = target;
         self
@@ -52,8 +120,16 @@ impl<'a> CargoCommand<'a> {
         self
     }
 
+    /// Features from each call are added to those from previous ones, so
+    /// platform-wide and target-specific features can be combined.
     pub fn with_features(mut self, features: Option<&'a [String]>) -> Self {
-        self.features = features;
+        self.features
+            .extend(features.into_iter().flatten().map(String::as_str));
+        self
+    }
+
+    pub fn with_example(mut self, example: Option<&'a str>) -> Self {
+        self.example = example;
         self
     }
 
@@ -62,10 +138,32 @@ impl<'a> CargoCommand<'a> {
         self
     }
 
+    pub fn with_profiles(mut self, profiles: Option<&'a Profiles>) -> Self {
+        self.profiles = profiles;
+        self
+    }
+
+    /// The env vars the command gets for the profile settings, which cargo
+    /// only takes that way.
+    pub fn env_vars(&self) -> Vec<(String, String)> {
+        self.profiles
+            .map(|profiles| {
+                if self.release {
+                    profiles.release.env_vars("RELEASE")
+                } else {
+                    profiles.debug.env_vars("DEV")
+                }
+            })
+            .unwrap_or_default()
+    }
+
     fn into_command_inner(self, mut command: bossy::Command) -> bossy::Command {
+        let env_vars = self.env_vars();
         command.add_arg(self.subcommand);
         if self.verbose {
             command.add_arg("-vv");
+        } else if self.quiet {
+            command.add_arg("--quiet");
         }
         if let Some(package) = self.package {
             command.add_args(&["--package", package]);
@@ -76,6 +174,9 @@ impl<'a> CargoCommand<'a> {
             }
             command.add_arg("--manifest-path").add_arg(manifest_path);
         }
+        if let Some(target_dir) = self.target_dir {
+            command.add_arg("--target-dir").add_arg(target_dir);
+        }
         if let Some(target) = self.target {
             // We used to use `util::host_target_triple` to avoid explicitly
             // specifying the default target triple here, since specifying it
@@ -90,12 +191,18 @@ impl<'a> CargoCommand<'a> {
         if self.no_default_features {
             command.add_arg("--no-default-features");
         }
-        if let Some(features) = self.features {
-            command.add_args(&["--features", &features.join(" ")]);
+        if !self.features.is_empty() {
+            command.add_args(&["--features", &self.features.join(" ")]);
+        }
+        if let Some(example) = self.example {
+            command.add_args(&["--example", example]);
         }
         if self.release {
             command.add_arg("--release");
         }
+        for (key, value) in env_vars {
+            command.add_env_var(key, value);
+        }
         command
     }
 
@@ -107,3 +214,34 @@ impl<'a> CargoCommand<'a> {
         self.into_command_inner(bossy::Command::pure("cargo").with_env_vars(env.explicit_env()))
     }
 }
+
+#[derive(Debug, Deserialize)]
+struct ArtifactProfile {
+    test: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtifactMessage {
+    reason: String,
+    #[serde(default)]
+    profile: Option<ArtifactProfile>,
+    #[serde(default)]
+    executable: Option<PathBuf>,
+}
+
+/// Picks the test harnesses out of the output of
+/// `cargo test --no-run --message-format=json`.
+pub fn test_executables(stdout: &str) -> Vec<PathBuf> {
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<ArtifactMessage>(line).ok())
+        .filter(|message| {
+            message.reason == "compiler-artifact"
+                && message
+                    .profile
+                    .as_ref()
+                    .map_or(false, |profile| profile.test)
+        })
+        .filter_map(|message| message.executable)
+        .collect()
+}
@@ -1,7 +1,11 @@
-use crate::{opts, util};
+use crate::{config, opts, util};
 use colored::Colorize as _;
 use once_cell_regex::exports::once_cell::sync::Lazy;
-use std::fmt::{Debug, Display};
+use std::{
+    fmt::{Debug, Display},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
 use structopt::{
     clap::{self, AppSettings},
     StructOpt,
@@ -30,7 +34,7 @@ pub static VERSION_LONG: Lazy<String> = Lazy::new(|| match util::installed_commi
     }
 });
 
-#[derive(Clone, Copy, Debug, StructOpt)]
+#[derive(Clone, Debug, StructOpt)]
 pub struct GlobalFlags {
     #[structopt(
         short = "v",
@@ -41,14 +45,134 @@ pub struct GlobalFlags {
         parse(from_occurrences = opts::NoiseLevel::from_occurrences),
     )]
     pub noise_level: opts::NoiseLevel,
+    #[structopt(
+        short = "q",
+        long = "quiet",
+        help = "Only print errors and the final result",
+        global = true,
+        conflicts_with = "noise-level"
+    )]
+    pub quiet: bool,
     #[structopt(
         short = "y",
         long = "non-interactive",
-        help = "Never prompt for input",
+        help = "Never prompt for input (implied when stdin or stdout isn't a terminal)",
         global = true,
-        parse(from_flag = opts::NonInteractive::from_bool),
+        parse(from_flag = non_interactive_from_flag),
     )]
     pub non_interactive: opts::NonInteractive,
+    #[structopt(
+        long = "refresh",
+        help = "Scan for devices again instead of using the list from the last few seconds",
+        global = true
+    )]
+    pub refresh: bool,
+    #[structopt(
+        long = "reproducible",
+        help = "Build bit-identical native libraries from the same commit, by normalizing timestamps and paths",
+        global = true
+    )]
+    pub reproducible: bool,
+    #[structopt(
+        long = "full-install",
+        help = "Always do a regular install, instead of skipping unchanged apps and patching or streaming changed ones",
+        global = true
+    )]
+    pub full_install: bool,
+    #[structopt(
+        long = "timings",
+        help = "How to report how long each step took",
+        global = true,
+        default_value = "text",
+        possible_values = opts::Timings::POSSIBLE_VALUES
+    )]
+    pub timings: opts::Timings,
+    #[structopt(
+        long = "manifest-path",
+        help = "Run as if started in the project containing this `Cargo.toml` or `mobile.toml` (or directory)",
+        global = true,
+        parse(from_os_str)
+    )]
+    pub manifest_path: Option<PathBuf>,
+    #[structopt(
+        long = "project-dir",
+        help = "Directory to generate projects in (overrides `app.gen-dir`)",
+        global = true,
+        env = "CARGO_MOBILE_PROJECT_DIR",
+        parse(from_os_str)
+    )]
+    pub gen_dir: Option<PathBuf>,
+    #[structopt(
+        long = "out-dir",
+        help = "Copy final artifacts here, with a `manifest.json` describing them (overrides `app.out-dir`)",
+        global = true,
+        parse(from_os_str)
+    )]
+    pub out_dir: Option<PathBuf>,
+    #[structopt(
+        long = "config-profile",
+        help = "Config profile to apply on top of the base config",
+        global = true,
+        env = "CARGO_MOBILE_CONFIG_PROFILE"
+    )]
+    pub config_profile: Option<String>,
+    #[structopt(
+        long = "app",
+        help = "Which of the config's `[apps.<name>]` sections to use",
+        global = true
+    )]
+    pub app: Option<String>,
+    #[structopt(
+        long = "set",
+        help = "Override a config value for this invocation, i.e. `apple.development-team=XYZ` (prefix with `metadata.` to override `Cargo.toml` metadata, and use i.e. `key:int=24` for a number)",
+        global = true,
+        number_of_values = 1,
+        multiple = true
+    )]
+    pub sets: Vec<config::Set>,
+}
+
+fn non_interactive_from_flag(flag: bool) -> opts::NonInteractive {
+    opts::NonInteractive::from_bool(flag || !util::prompt::interactive())
+}
+
+impl GlobalFlags {
+    /// The directory commands should run in, if `--manifest-path` was passed.
+    pub fn root_dir(&self) -> Option<PathBuf> {
+        self.manifest_path.as_ref().map(|path| {
+            if path.is_dir() {
+                path.clone()
+            } else {
+                path.parent()
+                    .filter(|parent| !parent.as_os_str().is_empty())
+                    .map(ToOwned::to_owned)
+                    .unwrap_or_else(|| PathBuf::from("."))
+            }
+        })
+    }
+
+    pub fn config_overrides(&self) -> config::Overrides {
+        // Forwarded sets come first, so that any passed to us directly win.
+        let forwarded = std::env::var(config::SETS_ENV_VAR).unwrap_or_default();
+        let sets = forwarded
+            .lines()
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| {
+                line.parse::<config::Set>()
+                    .map_err(|err| log::warn!("ignoring forwarded override: {}", err))
+                    .ok()
+            })
+            .chain(self.sets.iter().cloned())
+            .collect();
+        config::Overrides {
+            app: self.app.clone(),
+            gen_dir: self.gen_dir.clone(),
+            out_dir: self.out_dir.clone(),
+            profile: self.config_profile.clone(),
+            sets,
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, StructOpt)]
@@ -71,6 +195,102 @@ pub struct ReinstallDeps {
     pub reinstall_deps: opts::ReinstallDeps,
 }
 
+#[derive(Clone, Debug, StructOpt)]
+pub struct TestLab {
+    #[structopt(
+        long = "device",
+        help = "Device to test on, as `model=MODEL,version=VERSION` (see `gcloud firebase test <platform> models list`)",
+        number_of_values = 1
+    )]
+    pub devices: Vec<String>,
+    #[structopt(long = "timeout", help = "Max time to run the tests for, i.e. `15m`")]
+    pub timeout: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, StructOpt)]
+pub struct AcceptChanges {
+    #[structopt(
+        long = "accept-changes",
+        help = "Overwrite existing files that would be changed, without asking",
+        parse(from_flag = opts::AcceptChanges::from_bool),
+    )]
+    pub accept_changes: opts::AcceptChanges,
+}
+
+#[derive(Clone, Copy, Debug, StructOpt)]
+pub struct Force {
+    #[structopt(
+        long = "force",
+        help = "Do it even if nothing's changed since the last time",
+        parse(from_flag = opts::Force::from_bool),
+    )]
+    pub force: opts::Force,
+}
+
+#[derive(Clone, Copy, Debug, StructOpt)]
+pub struct SkipHooks {
+    #[structopt(
+        long = "skip-hooks",
+        help = "Skip commands the template pack wants to run after generation",
+        parse(from_flag = opts::SkipHooks::from_bool),
+    )]
+    pub skip_hooks: opts::SkipHooks,
+}
+
+#[derive(Clone, Copy, Debug, StructOpt)]
+pub struct AllowUnverified {
+    #[structopt(
+        long = "allow-unverified",
+        help = "Generate from template packs even if they don't match the checksums and signatures in the lockfile",
+        parse(from_flag = opts::AllowUnverified::from_bool),
+    )]
+    pub allow_unverified: opts::AllowUnverified,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct DeviceFilter {
+    #[structopt(
+        long = "min-os",
+        help = "Only consider devices running at least this OS version"
+    )]
+    pub min_os: Option<String>,
+    #[structopt(
+        long = "arch",
+        help = "Only consider devices with this arch, i.e. arm64"
+    )]
+    pub arch: Option<String>,
+    #[structopt(
+        long = "pick-device",
+        help = "Prompt for a device, instead of reusing the one picked last time"
+    )]
+    pub pick: bool,
+}
+
+#[derive(Clone, Copy, Debug, StructOpt)]
+pub struct Wait {
+    #[structopt(
+        long = "wait",
+        value_name = "SECS",
+        help = "Wait for a matching device to connect, giving up after SECS (60 by default)"
+    )]
+    pub wait: Option<Option<u64>>,
+}
+
+impl Wait {
+    pub fn timeout(self) -> Option<std::time::Duration> {
+        self.wait.map(|secs| {
+            secs.map(std::time::Duration::from_secs)
+                .unwrap_or(crate::device::DEFAULT_WAIT)
+        })
+    }
+}
+
+impl From<DeviceFilter> for crate::device::Filter {
+    fn from(DeviceFilter { min_os, arch, pick }: DeviceFilter) -> Self {
+        Self { min_os, arch, pick }
+    }
+}
+
 #[derive(Clone, Copy, Debug, StructOpt)]
 pub struct Profile {
     #[structopt(
@@ -81,8 +301,154 @@ pub struct Profile {
     pub profile: opts::Profile,
 }
 
+#[derive(Clone, Debug, StructOpt)]
+pub struct EntryPoint {
+    #[structopt(
+        long = "example",
+        help = "Build the given example into the app instead of the lib",
+        env = "CARGO_MOBILE_EXAMPLE"
+    )]
+    pub example: Option<String>,
+    #[structopt(
+        long = "bin",
+        help = "Build the given binary into the app instead of the lib",
+        conflicts_with = "example"
+    )]
+    pub bin: Option<String>,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct MetricsFile {
+    #[structopt(
+        long = "metrics-file",
+        value_name = "PATH",
+        help = "Append a JSON line per build to PATH (i.e. `builds.jsonl`), for charting build times",
+        env = "CARGO_MOBILE_METRICS_FILE",
+        parse(from_os_str)
+    )]
+    pub metrics_file: Option<PathBuf>,
+}
+
+impl MetricsFile {
+    pub fn metrics(self) -> util::metrics::Metrics {
+        util::metrics::Metrics::new(self.metrics_file)
+    }
+}
+
+#[derive(Debug)]
+pub struct BinUnsupported {
+    bin: String,
+}
+
+impl Reportable for BinUnsupported {
+    fn report(&self) -> Report {
+        Report::action_request(
+            format!(
+                "Please make `{}` an `[[example]]` with `crate-type = [\"staticlib\", \"cdylib\"]`, and pass `--example {}` instead.",
+                self.bin, self.bin
+            ),
+            "Apps can only load libraries, and cargo can't build binaries as libraries.",
+        )
+    }
+}
+
+impl EntryPoint {
+    pub fn entry_point(self) -> Result<opts::EntryPoint, BinUnsupported> {
+        match self.bin {
+            Some(bin) => Err(BinUnsupported { bin }),
+            None => Ok(opts::EntryPoint::from_example(self.example)),
+        }
+    }
+}
+
+fn parse_extra(s: &str) -> Result<(String, String), String> {
+    let mut parts = s.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(key), Some(value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(format!("{:?} isn't of the form `KEY=VALUE`", s)),
+    }
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct RunArgs {
+    #[structopt(
+        long = "extra",
+        value_name = "KEY=VALUE",
+        help = "Launch the app with this extra, on top of `app.run-args.extras`",
+        number_of_values = 1,
+        multiple = true,
+        parse(try_from_str = parse_extra)
+    )]
+    pub extras: Vec<(String, String)>,
+    #[structopt(
+        name = "ARGS",
+        help = "Launch the app with these arguments instead of `app.run-args.args`",
+        last = true
+    )]
+    pub args: Vec<String>,
+}
+
+impl RunArgs {
+    /// Layers these over `app.run-args`, so whatever launches the app only
+    /// has to look at the config.
+    pub fn apply(&self, overrides: &config::Overrides) -> config::Overrides {
+        let mut overrides = overrides.clone();
+        let key = |last: &str| -> Vec<String> {
+            config::app::run_args::KEY
+                .split('.')
+                .chain(Some(last))
+                .map(ToOwned::to_owned)
+                .collect()
+        };
+        if !self.args.is_empty() {
+            overrides.sets.push(config::Set::new(
+                key("args"),
+                toml::Value::Array(self.args.iter().cloned().map(toml::Value::String).collect()),
+            ));
+        }
+        for (name, value) in &self.extras {
+            let mut path = key("extras");
+            path.push(name.clone());
+            overrides
+                .sets
+                .push(config::Set::new(path, toml::Value::String(value.clone())));
+        }
+        overrides
+    }
+}
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Whether `--quiet` was passed, in which case only errors and final results
+/// should be printed.
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::SeqCst)
+}
+
+/// Prints a progress message, unless we're being quiet. Anything that isn't
+/// an error or a final result should go through this.
+pub fn status(msg: impl Display) {
+    if !quiet() {
+        println!("{}", msg);
+    }
+}
+
+/// Prints the path of something a command produced. This is the one thing
+/// that's still printed in quiet mode, so scripts can pick it up.
+pub fn print_artifact(path: &Path) {
+    if quiet() {
+        println!("{}", path.display());
+    } else {
+        log::info!("produced {:?}", path);
+    }
+}
+
 pub type TextWrapper = textwrap::Wrapper<'static, textwrap::NoHyphenation>;
 
+pub fn default_wrapper() -> TextWrapper {
+    TextWrapper::with_splitter(textwrap::termwidth(), textwrap::NoHyphenation)
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum Label {
     Error,
@@ -147,6 +513,14 @@ impl Report {
         self.label.exit_code()
     }
 
+    pub fn msg(&self) -> &str {
+        &self.msg
+    }
+
+    pub fn details(&self) -> &str {
+        &self.details
+    }
+
     fn format(&self, wrapper: &TextWrapper) -> String {
         static INDENT: &str = "    ";
         let head = if colored::control::SHOULD_COLORIZE.should_colorize() {
@@ -168,6 +542,9 @@ impl Report {
     }
 
     pub fn print(&self, wrapper: &TextWrapper) {
+        if quiet() && !matches!(self.label, Label::Error) {
+            return;
+        }
         let s = self.format(wrapper);
         if matches!(self.label, Label::Error) {
             eprint!("{}", s)
@@ -199,9 +576,10 @@ fn get_args(name: &str) -> Vec<String> {
     args
 }
 
-fn init_logging(noise_level: opts::NoiseLevel) {
+fn init_logging(noise_level: opts::NoiseLevel, quiet: bool) {
     use env_logger::{Builder, Env};
     let default_level = match noise_level {
+        _ if quiet => "error",
         opts::NoiseLevel::Polite => "warn",
         opts::NoiseLevel::LoudAndProud => {
             "cargo_mobile=info,cargo_android=info,cargo_apple=info,bossy=info,hit=info"
@@ -237,7 +615,7 @@ impl Exit {
     }
 
     fn main(inner: impl FnOnce(&TextWrapper) -> Result<(), Self>) {
-        let wrapper = TextWrapper::with_splitter(textwrap::termwidth(), textwrap::NoHyphenation);
+        let wrapper = default_wrapper();
         if let Err(exit) = inner(&wrapper) {
             exit.do_the_thing(wrapper)
         }
@@ -248,8 +626,43 @@ pub fn exec<E: Exec>(name: &str) {
     Exit::main(|wrapper| {
         let args = get_args(name);
         let input = E::from_iter_safe(&args).map_err(Exit::Clap)?;
-        init_logging(input.global_flags().noise_level);
+        let global_flags = input.global_flags();
+        init_logging(global_flags.noise_level, global_flags.quiet);
+        if global_flags.quiet {
+            QUIET.store(true, Ordering::SeqCst);
+            util::progress::disable();
+        } else {
+            util::progress::configure(global_flags.noise_level, global_flags.non_interactive);
+        }
         log::debug!("raw args: {:#?}", args);
-        input.exec(wrapper).map_err(Exit::report)
+        if global_flags.refresh {
+            util::cache::bypass();
+        }
+        if global_flags.reproducible {
+            util::reproducible::enable();
+        }
+        if global_flags.full_install {
+            util::install::bypass();
+        }
+        // Everything else resolves paths relative to the working directory,
+        // so changing it here is all it takes for them to find the project.
+        if let Some(root_dir) = global_flags.root_dir() {
+            log::info!("changing working directory to {:?}", root_dir);
+            std::env::set_current_dir(&root_dir).map_err(|cause| {
+                Exit::Report(Report::error(
+                    format!("Failed to change working directory to {:?}", root_dir),
+                    cause,
+                ))
+            })?;
+        }
+        config::network::configure(".", &global_flags.config_overrides());
+        config::retry::configure(".", &global_flags.config_overrides());
+        global_flags.config_overrides().forward();
+        if global_flags.non_interactive.yes() && !util::prompt::interactive() {
+            log::info!("stdin or stdout isn't a terminal, so we're running non-interactively");
+        }
+        let result = input.exec(wrapper);
+        util::progress::print_timings(global_flags.timings);
+        result.map_err(Exit::report)
     })
 }
@@ -0,0 +1,252 @@
+//! `cc` logs every compiler invocation it makes to the build script's output
+//! as `running: "clang" "-O0" ...`, which is enough to reconstruct a
+//! `compile_commands.json` for clangd and friends without having to wrap the
+//! compiler.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+pub static FILE_NAME: &str = "compile_commands.json";
+
+#[derive(Debug)]
+pub enum Error {
+    MetadataFailed(bossy::Error),
+    MetadataInvalid(serde_json::Error),
+    ReadFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ExistingInvalid {
+        path: PathBuf,
+        cause: serde_json::Error,
+    },
+    WriteFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MetadataFailed(err) => write!(f, "Failed to run `cargo metadata`: {}", err),
+            Self::MetadataInvalid(err) => {
+                write!(f, "`cargo metadata` output was invalid: {}", err)
+            }
+            Self::ReadFailed { path, cause } => write!(f, "Failed to read {:?}: {}", path, cause),
+            Self::ExistingInvalid { path, cause } => {
+                write!(f, "Existing {:?} couldn't be parsed: {}", path, cause)
+            }
+            Self::WriteFailed { path, cause } => {
+                write!(f, "Failed to write {:?}: {}", path, cause)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Entry {
+    directory: PathBuf,
+    arguments: Vec<String>,
+    file: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<PathBuf>,
+}
+
+impl Entry {
+    fn key(&self) -> PathBuf {
+        self.directory.join(&self.file)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    name: String,
+    manifest_path: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+}
+
+// Build scripts run in their package's root, which is what relative sources
+// are relative to.
+fn package_dirs(root_dir: &Path) -> Result<HashMap<String, PathBuf>, Error> {
+    let output = bossy::Command::impure("cargo")
+        .with_args(&["metadata", "--format-version", "1"])
+        .with_arg("--manifest-path")
+        .with_arg(root_dir.join("Cargo.toml"))
+        .run_and_wait_for_output()
+        .map_err(Error::MetadataFailed)?;
+    let metadata: Metadata =
+        serde_json::from_slice(output.stdout()).map_err(Error::MetadataInvalid)?;
+    Ok(metadata
+        .packages
+        .into_iter()
+        .filter_map(|package| {
+            package
+                .manifest_path
+                .parent()
+                .map(|dir| (package.name, dir.to_owned()))
+        })
+        .collect())
+}
+
+// Splits a `{:?}`-formatted command line, keeping only the quoted arguments;
+// newer versions of `cc` prefix the command with unquoted `env` overrides.
+fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars();
+    let mut prev = ' ';
+    while let Some(c) = chars.next() {
+        if c == '"' && prev == ' ' {
+            let mut arg = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => match chars.next() {
+                        Some('n') => arg.push('\n'),
+                        Some('t') => arg.push('\t'),
+                        Some(c) => arg.push(c),
+                        None => break,
+                    },
+                    c => arg.push(c),
+                }
+            }
+            args.push(arg);
+            prev = '"';
+        } else {
+            prev = c;
+        }
+    }
+    args
+}
+
+fn parse_output(contents: &str, directory: &Path) -> Vec<Entry> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let arguments = split_args(line.strip_prefix("running: ")?);
+            let after = |flag: &str| {
+                arguments
+                    .iter()
+                    .position(|arg| arg == flag)
+                    .and_then(|i| arguments.get(i + 1))
+                    .map(PathBuf::from)
+            };
+            // Archiver invocations get logged too, but they don't have `-c`.
+            let file = after("-c")?;
+            let output = after("-o");
+            Some(Entry {
+                directory: directory.to_owned(),
+                arguments,
+                file,
+                output,
+            })
+        })
+        .collect()
+}
+
+fn build_outputs(build_dir: &Path) -> Vec<(SystemTime, String, PathBuf)> {
+    let entries = match fs::read_dir(build_dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| {
+            let dir = entry.ok()?.path();
+            let output = dir.join("output");
+            let modified = output.metadata().and_then(|meta| meta.modified()).ok()?;
+            // Build dirs are named `<package>-<hash>`.
+            let name = dir.file_name()?.to_str()?;
+            let package = name.rsplitn(2, '-').nth(1)?.to_owned();
+            Some((modified, package, output))
+        })
+        .collect()
+}
+
+/// Collects the C/C++/ObjC compiler invocations from the build scripts that
+/// ran for `triples`, and merges them into `compile_commands.json` in the
+/// project root. Entries for files that weren't rebuilt are left alone, so
+/// building one target at a time still ends up with everything.
+pub fn update(
+    root_dir: &Path,
+    target_dir: &Path,
+    triples: &[&str],
+    profile: crate::opts::Profile,
+) -> Result<(), Error> {
+    let mut outputs = triples
+        .iter()
+        .flat_map(|triple| {
+            build_outputs(&target_dir.join(triple).join(profile.as_str()).join("build"))
+        })
+        .collect::<Vec<_>>();
+    if outputs.is_empty() {
+        return Ok(());
+    }
+    // Stale build dirs from older hashes stick around, so newer ones win.
+    outputs.sort();
+    let mut logs = Vec::new();
+    for (_, package, output) in outputs {
+        let contents = fs::read_to_string(&output).map_err(|cause| Error::ReadFailed {
+            path: output.clone(),
+            cause,
+        })?;
+        if contents.contains("running: ") {
+            logs.push((package, contents));
+        }
+    }
+    // Most build scripts don't compile anything, so it's not worth asking
+    // `cargo metadata` where packages live unless some did.
+    if logs.is_empty() {
+        return Ok(());
+    }
+    let package_dirs = package_dirs(root_dir)?;
+    let mut fresh = BTreeMap::new();
+    for (package, contents) in logs {
+        let directory = package_dirs
+            .get(&package)
+            .cloned()
+            .unwrap_or_else(|| root_dir.to_owned());
+        for entry in parse_output(&contents, &directory) {
+            fresh.insert(entry.key(), entry);
+        }
+    }
+    if fresh.is_empty() {
+        return Ok(());
+    }
+    let path = root_dir.join(FILE_NAME);
+    let mut merged = if path.is_file() {
+        let contents = fs::read(&path).map_err(|cause| Error::ReadFailed {
+            path: path.clone(),
+            cause,
+        })?;
+        serde_json::from_slice::<Vec<Entry>>(&contents)
+            .map_err(|cause| Error::ExistingInvalid {
+                path: path.clone(),
+                cause,
+            })?
+            .into_iter()
+            .map(|entry| (entry.key(), entry))
+            .collect::<BTreeMap<_, _>>()
+    } else {
+        BTreeMap::new()
+    };
+    let count = fresh.len();
+    merged.extend(fresh);
+    let json = serde_json::to_vec_pretty(&merged.values().collect::<Vec<_>>())
+        .expect("developer error: failed to serialize compile commands");
+    fs::write(&path, json).map_err(|cause| Error::WriteFailed {
+        path: path.clone(),
+        cause,
+    })?;
+    log::info!("wrote {} compile commands to {:?}", count, path);
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use super::{
+    cli::{self, Report, Reportable},
+    HostTargetTripleError,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Makes `rustc` instrument everything it compiles.
+pub static RUSTFLAG: &str = "-Cinstrument-coverage";
+
+/// What each test process names its raw profile, relative to wherever it's
+/// written on the device. `%p` and `%m` keep concurrent processes and
+/// different binaries from clobbering each other.
+pub static PROFILE_FILE: &str = "cargo-mobile-%p-%m.profraw";
+
+#[derive(Debug)]
+pub enum Error {
+    HostTripleFailed(HostTargetTripleError),
+    SysrootFailed(bossy::Error),
+    OutputInvalid(std::str::Utf8Error),
+    ToolMissing { tool: PathBuf },
+    ReadFailed { path: PathBuf, cause: io::Error },
+    ProfilesMissing { dir: PathBuf },
+    MergeFailed(bossy::Error),
+    LcovFailed(bossy::Error),
+    WriteFailed { path: PathBuf, cause: io::Error },
+    HtmlFailed(bossy::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::HostTripleFailed(err) => err.report("Failed to detect host target triple"),
+            Self::SysrootFailed(err) => Report::error("Failed to find Rust sysroot", err),
+            Self::OutputInvalid(err) => {
+                Report::error("`rustc` output contained invalid UTF-8", err)
+            }
+            Self::ToolMissing { tool } => Report::action_request(
+                "Coverage reports need LLVM's tools; install them by running this:",
+                format!(
+                    "rustup component add llvm-tools-preview\n(looked for {:?})",
+                    tool
+                ),
+            ),
+            Self::ReadFailed { path, cause } => {
+                Report::error(format!("Failed to read {:?}", path), cause)
+            }
+            Self::ProfilesMissing { dir } => Report::error(
+                "Failed to generate coverage report",
+                format!("No coverage profiles were found in {:?}", dir),
+            ),
+            Self::MergeFailed(err) => Report::error("Failed to merge coverage profiles", err),
+            Self::LcovFailed(err) => Report::error("Failed to export lcov report", err),
+            Self::WriteFailed { path, cause } => {
+                Report::error(format!("Failed to write {:?}", path), cause)
+            }
+            Self::HtmlFailed(err) => Report::error("Failed to generate HTML report", err),
+        }
+    }
+}
+
+/// Where raw profiles pulled off of a device go, and where the reports end up.
+pub fn output_dir(target_dir: &Path, triple: &str) -> PathBuf {
+    target_dir.join("coverage").join(triple)
+}
+
+pub fn profraw_dir(output_dir: &Path) -> PathBuf {
+    output_dir.join("profraw")
+}
+
+/// Clears out profiles from previous runs, so they don't get merged in.
+pub fn prepare(output_dir: &Path) -> Result<(), Error> {
+    let dir = profraw_dir(output_dir);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).map_err(|cause| Error::WriteFailed {
+            path: dir.clone(),
+            cause,
+        })?;
+    }
+    fs::create_dir_all(&dir).map_err(|cause| Error::WriteFailed { path: dir, cause })
+}
+
+fn llvm_tool(name: &str) -> Result<PathBuf, Error> {
+    let sysroot = bossy::Command::impure("rustc")
+        .with_args(&["--print", "sysroot"])
+        .run_and_wait_for_output()
+        .map_err(Error::SysrootFailed)?
+        .stdout_str()
+        .map(|s| s.trim().to_owned())
+        .map_err(Error::OutputInvalid)?;
+    let tool = Path::new(&sysroot)
+        .join("lib/rustlib")
+        .join(super::host_target_triple().map_err(Error::HostTripleFailed)?)
+        .join("bin")
+        .join(name);
+    if tool.is_file() {
+        Ok(tool)
+    } else {
+        Err(Error::ToolMissing { tool })
+    }
+}
+
+fn find_profiles(dir: &Path, profiles: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let read_failed = |cause| Error::ReadFailed {
+        path: dir.to_owned(),
+        cause,
+    };
+    for entry in fs::read_dir(dir).map_err(read_failed)? {
+        let path = entry.map_err(read_failed)?.path();
+        if path.is_dir() {
+            find_profiles(&path, profiles)?;
+        } else if path.extension().map_or(false, |ext| ext == "profraw") {
+            profiles.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Merges the raw profiles in `output_dir` and renders them as both an lcov
+/// file and an HTML report for the instrumented `executables`.
+pub fn report(output_dir: &Path, executables: &[PathBuf]) -> Result<(), Error> {
+    let mut profiles = Vec::new();
+    find_profiles(&profraw_dir(output_dir), &mut profiles)?;
+    if profiles.is_empty() {
+        return Err(Error::ProfilesMissing {
+            dir: profraw_dir(output_dir),
+        });
+    }
+    let profdata = output_dir.join("merged.profdata");
+    bossy::Command::impure(llvm_tool("llvm-profdata")?)
+        .with_args(&["merge", "-sparse"])
+        .with_args(&profiles)
+        .with_arg("-o")
+        .with_arg(&profdata)
+        .run_and_wait()
+        .map_err(Error::MergeFailed)?;
+    let llvm_cov = llvm_tool("llvm-cov")?;
+    let cov_command = |subcommand: &str| {
+        let mut command = bossy::Command::impure(&llvm_cov)
+            .with_arg(subcommand)
+            .with_arg(format!("-instr-profile={}", profdata.display()))
+            // Nobody wants coverage for the standard library or dependencies.
+            .with_arg("-ignore-filename-regex=/.cargo/registry|/rustc/");
+        for (i, executable) in executables.iter().enumerate() {
+            if i > 0 {
+                command.add_arg("-object");
+            }
+            command.add_arg(executable);
+        }
+        command
+    };
+    let lcov = cov_command("export")
+        .with_arg("-format=lcov")
+        .run_and_wait_for_output()
+        .map_err(Error::LcovFailed)?;
+    let lcov_path = output_dir.join("lcov.info");
+    fs::write(&lcov_path, lcov.stdout()).map_err(|cause| Error::WriteFailed {
+        path: lcov_path.clone(),
+        cause,
+    })?;
+    let html_dir = output_dir.join("html");
+    cov_command("show")
+        .with_arg("-format=html")
+        .with_arg(format!("-output-dir={}", html_dir.display()))
+        .run_and_wait()
+        .map_err(Error::HtmlFailed)?;
+    cli::print_artifact(&lcov_path);
+    cli::print_artifact(&html_dir.join("index.html"));
+    Ok(())
+}
@@ -0,0 +1,258 @@
+use crate::{config::app::App, opts, util::reproducible};
+use serde::Deserialize;
+use std::{
+    collections::hash_map::DefaultHasher,
+    env,
+    fmt::Debug,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+fn fingerprints_dir(app: &App) -> PathBuf {
+    app.target_dir()
+        .join(concat!(".", env!("CARGO_PKG_NAME"), "/fingerprints"))
+}
+
+#[derive(Debug, Deserialize)]
+struct CargoTarget {
+    src_path: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    source: Option<String>,
+    manifest_path: PathBuf,
+    targets: Vec<CargoTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    workspace_root: PathBuf,
+}
+
+fn metadata(app: &App) -> Option<Metadata> {
+    let output = bossy::Command::impure("cargo")
+        .with_args(&["metadata", "--format-version", "1", "--manifest-path"])
+        .with_arg(app.manifest_path())
+        .run_and_wait_for_output()
+        .map_err(|err| log::info!("failed to get cargo metadata: {}", err))
+        .ok()?;
+    serde_json::from_slice(output.stdout())
+        .map_err(|err| log::info!("cargo metadata was invalid: {}", err))
+        .ok()
+}
+
+fn rustc_version() -> Option<String> {
+    bossy::Command::impure_parse("rustc -vV")
+        .run_and_wait_for_output()
+        .and_then(|output| output.stdout_str().map(ToOwned::to_owned))
+        .map_err(|err| log::info!("failed to get rustc version: {}", err))
+        .ok()
+}
+
+fn source_paths(dir: &Path, paths: &mut Vec<PathBuf>) -> Option<()> {
+    for entry in ignore::WalkBuilder::new(dir).build() {
+        match entry {
+            Ok(entry) if entry.file_type().map_or(false, |ty| ty.is_file()) => {
+                paths.push(entry.into_path())
+            }
+            Ok(_) => (),
+            Err(err) => {
+                log::info!("failed to fingerprint {:?}: {}", dir, err);
+                return None;
+            }
+        }
+    }
+    Some(())
+}
+
+// The files cargo reads to build the app's crate and the local packages it
+// uses (workspace members and path dependencies, which are the packages
+// without a `source`): their manifests, the dirs their targets are in, and
+// `Cargo.lock`, which pins everything else. A target right next to its
+// manifest (i.e. `build.rs`) is taken on its own, since the rest of the
+// package dir is assets and platform projects. The config and `.env` files
+// are already covered by the inputs.
+fn input_paths(app: &App) -> Option<Vec<PathBuf>> {
+    let metadata = metadata(app)?;
+    let mut paths = vec![metadata.workspace_root.join("Cargo.lock")];
+    let dot_cargo_config = app.prefix_path(".cargo/config.toml");
+    if dot_cargo_config.is_file() {
+        paths.push(dot_cargo_config);
+    }
+    let mut dirs = Vec::new();
+    for package in metadata
+        .packages
+        .into_iter()
+        .filter(|package| package.source.is_none())
+    {
+        let package_dir = package.manifest_path.parent().map(Path::to_owned);
+        paths.push(package.manifest_path);
+        for target in package.targets {
+            match target.src_path.parent() {
+                Some(dir) if Some(dir) != package_dir.as_deref() => dirs.push(dir.to_owned()),
+                _ => paths.push(target.src_path),
+            }
+        }
+    }
+    // Sorted, a dir comes right before any that are inside it, which don't
+    // need walking again.
+    dirs.sort();
+    let mut walked = Vec::<PathBuf>::new();
+    for dir in dirs {
+        if !walked.iter().any(|walked| dir.starts_with(walked)) {
+            source_paths(&dir, &mut paths)?;
+            walked.push(dir);
+        }
+    }
+    // The walk order isn't guaranteed, but the hash needs to be stable.
+    paths.sort();
+    paths.dedup();
+    Some(paths)
+}
+
+/// A hash of everything a check or build depends on: the sources and
+/// manifests of the app's crate and any local packages it uses (minus
+/// anything gitignored), `Cargo.lock`, the config it's being done with, and
+/// the toolchain and environment cargo will see. Files are compared by size
+/// and mtime first, and only read if those changed.
+#[derive(Debug)]
+pub struct Fingerprint {
+    path: PathBuf,
+    inputs: DefaultHasher,
+    paths: Vec<PathBuf>,
+    stats: String,
+    contents: Option<String>,
+}
+
+impl Fingerprint {
+    /// Returns `None` if hashing fails, in which case whatever we're
+    /// fingerprinting should just be treated as stale.
+    pub fn compute(app: &App, name: &str, inputs: &impl Debug) -> Option<Self> {
+        let mut hasher = DefaultHasher::new();
+        format!("{:?}", inputs).hash(&mut hasher);
+        rustc_version()?.hash(&mut hasher);
+        reproducible::enabled().hash(&mut hasher);
+        let mut rustflags = env::vars()
+            .filter(|(key, _)| key.ends_with("RUSTFLAGS"))
+            .collect::<Vec<_>>();
+        rustflags.sort();
+        rustflags.hash(&mut hasher);
+        let paths = input_paths(app)?;
+        let mut stats = hasher.clone();
+        for path in &paths {
+            let metadata = fs::metadata(path)
+                .map_err(|err| log::info!("failed to fingerprint {:?}: {}", path, err))
+                .ok()?;
+            path.hash(&mut stats);
+            metadata.len().hash(&mut stats);
+            metadata.modified().ok()?.hash(&mut stats);
+        }
+        Some(Self {
+            path: fingerprints_dir(app).join(name),
+            inputs: hasher,
+            paths,
+            stats: format!("{:016x}", stats.finish()),
+            contents: None,
+        })
+    }
+
+    // Reads every file, so it's only done when the sizes and mtimes alone
+    // don't settle it.
+    fn contents(&mut self) -> Option<&str> {
+        if self.contents.is_none() {
+            let mut hasher = self.inputs.clone();
+            for path in &self.paths {
+                match fs::read(path) {
+                    Ok(contents) => {
+                        path.hash(&mut hasher);
+                        contents.hash(&mut hasher);
+                    }
+                    Err(err) => {
+                        log::info!("failed to fingerprint {:?}: {}", path, err);
+                        return None;
+                    }
+                }
+            }
+            self.contents = Some(format!("{:016x}", hasher.finish()));
+        }
+        self.contents.as_deref()
+    }
+
+    /// Touching a file without changing it only costs reading everything
+    /// once, since the contents are compared when the mtimes don't match.
+    pub fn fresh(&mut self) -> bool {
+        let stored = match fs::read_to_string(&self.path) {
+            Ok(stored) => stored,
+            Err(_) => return false,
+        };
+        let mut lines = stored.lines();
+        let (stats, contents) = (lines.next(), lines.next());
+        if stats == Some(self.stats.as_str()) {
+            return true;
+        }
+        let fresh = contents.is_some() && self.contents() == contents;
+        if fresh {
+            // So that next time, the mtimes are enough.
+            self.save();
+        }
+        fresh
+    }
+
+    pub fn save(&mut self) {
+        let contents = match self.contents() {
+            Some(contents) => contents.to_owned(),
+            None => return,
+        };
+        let result = self
+            .path
+            .parent()
+            .map(fs::create_dir_all)
+            .transpose()
+            .and_then(|_| fs::write(&self.path, format!("{}\n{}\n", self.stats, contents)));
+        if let Err(err) = result {
+            log::info!("failed to save fingerprint to {:?}: {}", self.path, err);
+        }
+    }
+}
+
+/// Runs `f` unless nothing's changed since the last time it succeeded (or
+/// `force` is set). Returns whether it was skipped.
+pub fn run_unless_fresh<E>(
+    app: &App,
+    action: &str,
+    triple: &str,
+    inputs: &impl Debug,
+    force: opts::Force,
+    f: impl FnOnce() -> Result<(), E>,
+) -> Result<bool, E> {
+    // Apps in the same repo share a root, so their fingerprints would
+    // otherwise clobber each other.
+    let name = match app.selected_app() {
+        Some(selected) => format!("{}-{}-{}", selected, action.replace(' ', "-"), triple),
+        None => format!("{}-{}", action.replace(' ', "-"), triple),
+    };
+    let mut fingerprint = Fingerprint::compute(app, &name, inputs);
+    if let Some(fingerprint) = fingerprint.as_mut().filter(|_| force.no()) {
+        if fingerprint.fresh() {
+            super::cli::status(format!(
+                "Skipping {} for {}, since nothing's changed (pass `--force` to do it anyway)",
+                action, triple
+            ));
+            return Ok(true);
+        }
+    }
+    // The contents are hashed before `f` runs, so that anything edited while
+    // it's running still counts as changed next time.
+    let fingerprint = fingerprint.and_then(|mut fingerprint| {
+        fingerprint.contents()?;
+        Some(fingerprint)
+    });
+    f()?;
+    if let Some(mut fingerprint) = fingerprint {
+        fingerprint.save();
+    }
+    Ok(false)
+}
@@ -138,7 +138,7 @@ impl Repo {
                 .run_and_wait()
                 .map_err(Error::CloneFailed)?;
         } else {
-            println!(
+            util::cli::status(format!(
                 "Updating `{}` repo...",
                 Path::new(
                     self.path()
@@ -146,7 +146,7 @@ impl Repo {
                         .expect("developer error: `Repo` path had no file name")
                 )
                 .display()
-            );
+            ));
             self.git()
                 .command_parse("fetch --depth 1")
                 .run_and_wait()
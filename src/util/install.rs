@@ -0,0 +1,155 @@
+//! Faster installs, for when rebuilding only changed the native lib or
+//! assets. We keep a record of what was last installed on each device, so the
+//! next deploy can skip installing altogether if nothing changed. Otherwise,
+//! simulators get only the changed files copied into the installed app, while
+//! Android devices still get the whole APK, just streamed.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static BYPASS: AtomicBool = AtomicBool::new(false);
+
+/// Always does a full install for the rest of the process, i.e. for
+/// `--full-install`. What gets installed is still recorded for next time.
+pub fn bypass() {
+    BYPASS.store(true, Ordering::SeqCst);
+}
+
+/// Paths in an app, relative to its root, mapped to something that changes
+/// whenever their contents do.
+pub type Contents = BTreeMap<String, String>;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Plan {
+    /// Nothing changed since the last install.
+    Skip,
+    /// Only paths that can be updated in place changed.
+    Patch {
+        changed: Vec<String>,
+        removed: Vec<String>,
+    },
+    Full,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Record {
+    /// Whatever identifies the specific install, like a device's serial
+    /// number, so a record for one never gets used for another.
+    target: String,
+    contents: Contents,
+}
+
+fn path(target_dir: &Path, key: &str) -> PathBuf {
+    target_dir
+        .join(concat!(".", env!("CARGO_PKG_NAME"), "/installs"))
+        .join(format!("{}.json", key))
+}
+
+/// Compares `contents` against what was last installed for `key`. `patchable`
+/// says which paths can change without needing a full install.
+pub fn plan(
+    target_dir: &Path,
+    key: &str,
+    target: &str,
+    contents: &Contents,
+    patchable: impl Fn(&str) -> bool,
+) -> Plan {
+    if BYPASS.load(Ordering::SeqCst) {
+        log::info!("doing a full install, since one was requested");
+        return Plan::Full;
+    }
+    let record = match fs::read(path(target_dir, key))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Record>(&bytes).ok())
+    {
+        Some(record) if record.target == target => record,
+        _ => {
+            log::info!("no record of a previous install for {:?}", key);
+            return Plan::Full;
+        }
+    };
+    let changed = contents
+        .iter()
+        .filter(|(path, digest)| record.contents.get(*path) != Some(digest))
+        .map(|(path, _)| path.clone())
+        .collect::<Vec<_>>();
+    let removed = record
+        .contents
+        .keys()
+        .filter(|path| !contents.contains_key(*path))
+        .cloned()
+        .collect::<Vec<_>>();
+    if changed.is_empty() && removed.is_empty() {
+        Plan::Skip
+    } else if let Some(path) = changed
+        .iter()
+        .chain(removed.iter())
+        .find(|path| !patchable(path))
+    {
+        log::info!("{:?} changed, so a full install is needed", path);
+        Plan::Full
+    } else {
+        Plan::Patch { changed, removed }
+    }
+}
+
+/// Records that `contents` was just installed for `key`.
+pub fn record(target_dir: &Path, key: &str, target: &str, contents: Contents) {
+    let path = path(target_dir, key);
+    let record = Record {
+        target: target.to_owned(),
+        contents,
+    };
+    let result = path
+        .parent()
+        .map(fs::create_dir_all)
+        .transpose()
+        .and_then(|_| {
+            let bytes = serde_json::to_vec(&record).map_err(io::Error::from)?;
+            fs::write(&path, bytes)
+        });
+    if let Err(err) = result {
+        log::info!("failed to record install at {:?}: {}", path, err);
+    }
+}
+
+/// Forgets what was installed for `key`, so the next install is a full one.
+/// This is for when an install fails partway and we can't know what's there.
+pub fn forget(target_dir: &Path, key: &str) {
+    let path = path(target_dir, key);
+    if let Err(err) = fs::remove_file(&path) {
+        if err.kind() != io::ErrorKind::NotFound {
+            log::info!("failed to remove install record {:?}: {}", path, err);
+        }
+    }
+}
+
+/// Hashes every file in `dir`, for apps that are installed as a directory.
+pub fn dir_contents(dir: &Path) -> io::Result<Contents> {
+    fn visit(root: &Path, dir: &Path, contents: &mut Contents) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                visit(root, &path, contents)?;
+            } else {
+                let digest = Sha256::digest(&fs::read(&path)?);
+                let rel = path
+                    .strip_prefix(root)
+                    .expect("developer error: visited path wasn't in root")
+                    .display()
+                    .to_string();
+                contents.insert(rel, format!("{:x}", digest));
+            }
+        }
+        Ok(())
+    }
+    let mut contents = Contents::new();
+    visit(dir, dir, &mut contents)?;
+    Ok(contents)
+}
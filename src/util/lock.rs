@@ -0,0 +1,178 @@
+//! An advisory lock on an app's gen dir, so that two of us (i.e. parallel CI
+//! jobs, or an IDE plugin and a terminal) can't regenerate or build into the
+//! same projects at once and leave them half-written.
+//!
+//! The lock is a file holding the PID of whoever has it. If that process is
+//! gone, the lock's stale and gets taken over. Taking a lock we already hold
+//! just bumps a count, so nested steps don't deadlock on themselves.
+//!
+//! Gradle and Xcode call back into us to build the lib while we're waiting on
+//! them with the lock held, so a lock held by one of the processes we were
+//! run by counts as ours too. Those are passed down in `HOLDERS_ENV_VAR`.
+
+use once_cell_regex::exports::once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    process,
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+pub static FILE_NAME: &str = ".cargo-mobile.lock";
+
+/// The PIDs of every cargo-mobile process we were (transitively) run by,
+/// separated by `:`.
+pub static HOLDERS_ENV_VAR: &str = "CARGO_MOBILE_LOCK_HOLDERS";
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+static HELD: Lazy<Mutex<HashMap<PathBuf, usize>>> = Lazy::new(Default::default);
+
+/// What to set `HOLDERS_ENV_VAR` to for anything we run: the processes we
+/// were run by, plus us.
+pub fn holders_env_value() -> String {
+    let mut holders = std::env::var(HOLDERS_ENV_VAR).unwrap_or_default();
+    if !holders.is_empty() {
+        holders.push(':');
+    }
+    holders.push_str(&process::id().to_string());
+    holders
+}
+
+fn held_by_ancestor(pid: u32) -> bool {
+    std::env::var(HOLDERS_ENV_VAR)
+        .map(|holders| holders.split(':').any(|holder| holder.parse() == Ok(pid)))
+        .unwrap_or(false)
+}
+
+fn holder(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn alive(pid: u32) -> bool {
+    bossy::Command::impure("kill")
+        .with_args(&["-0", &pid.to_string()])
+        .run_and_wait_for_output()
+        .is_ok()
+}
+
+fn try_create(path: &Path) -> io::Result<bool> {
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+    {
+        Ok(mut file) => {
+            write!(file, "{}", process::id())?;
+            Ok(true)
+        }
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns `false` if the lock's held by one of the processes we were run by,
+/// in which case it isn't ours to release.
+fn acquire(path: &Path) -> io::Result<bool> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut waiting = false;
+    while !try_create(path)? {
+        match holder(path) {
+            Some(pid) if held_by_ancestor(pid) && alive(pid) => {
+                log::info!("lock at {:?} is held by PID {}, which ran us", path, pid);
+                return Ok(false);
+            }
+            Some(pid) if alive(pid) => {
+                if !waiting {
+                    super::cli::status(format!(
+                        "Waiting for another cargo-mobile process (PID {}) to finish with {:?}...",
+                        pid,
+                        path.parent().unwrap_or(path)
+                    ));
+                    waiting = true;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            // The file's written right after it's created, so give whoever
+            // created it a moment before deciding it's stale.
+            None if !waiting => {
+                waiting = true;
+                thread::sleep(POLL_INTERVAL);
+            }
+            holder => {
+                log::warn!(
+                    "removing stale lock at {:?} held by {:?}, which isn't running",
+                    path,
+                    holder
+                );
+                fs::remove_file(path).or_else(|err| {
+                    if err.kind() == io::ErrorKind::NotFound {
+                        Ok(())
+                    } else {
+                        Err(err)
+                    }
+                })?;
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Held for as long as the gen dir is being written to; the lock's released
+/// when this is dropped.
+#[derive(Debug)]
+pub struct ProjectLock {
+    path: Option<PathBuf>,
+}
+
+impl ProjectLock {
+    /// Takes the lock on `gen_dir`, waiting for whoever holds it to finish.
+    /// Since the lock's only advisory, failing to take it is logged rather
+    /// than treated as an error.
+    pub fn acquire(gen_dir: impl AsRef<Path>) -> Self {
+        let path = gen_dir.as_ref().join(FILE_NAME);
+        let mut held = match HELD.lock() {
+            Ok(held) => held,
+            Err(_) => return Self { path: None },
+        };
+        if let Some(count) = held.get_mut(&path) {
+            *count += 1;
+            return Self { path: Some(path) };
+        }
+        match acquire(&path) {
+            Ok(false) => Self { path: None },
+            Ok(true) => {
+                log::info!("took lock at {:?}", path);
+                held.insert(path.clone(), 1);
+                Self { path: Some(path) }
+            }
+            Err(err) => {
+                log::warn!("failed to take lock at {:?}: {}", path, err);
+                Self { path: None }
+            }
+        }
+    }
+}
+
+impl Drop for ProjectLock {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            if let Ok(mut held) = HELD.lock() {
+                let count = held.entry(path.clone()).or_insert(1);
+                *count -= 1;
+                if *count == 0 {
+                    held.remove(&path);
+                    log::info!("releasing lock at {:?}", path);
+                    if let Err(err) = fs::remove_file(&path) {
+                        log::warn!("failed to release lock at {:?}: {}", path, err);
+                    }
+                }
+            }
+        }
+    }
+}
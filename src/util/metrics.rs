@@ -0,0 +1,102 @@
+use crate::opts::Profile;
+use serde::Serialize;
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Builds run by Gradle or Xcode see this, so they record to the same file.
+pub static ENV_VAR: &str = "CARGO_MOBILE_METRICS_FILE";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Record<'a> {
+    timestamp: u64,
+    platform: &'a str,
+    target: &'a str,
+    profile: &'a str,
+    duration_secs: f64,
+    success: bool,
+    cache_hit: bool,
+    artifact: Option<&'a Path>,
+    artifact_size: Option<u64>,
+}
+
+/// Appends a JSON line per build to the file passed as `--metrics-file`, if
+/// any. Failing to record is only ever logged, since it shouldn't fail the
+/// build it's recording.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    path: Option<PathBuf>,
+}
+
+impl Metrics {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        // Inner builds don't necessarily run from the same directory.
+        let path = path.map(|path| {
+            env::current_dir()
+                .map(|cwd| cwd.join(&path))
+                .unwrap_or(path)
+        });
+        Self { path }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Times `f`, which returns whether the build was skipped as fresh, and
+    /// records how it went.
+    pub fn record<E>(
+        &self,
+        platform: &str,
+        target: &str,
+        profile: Profile,
+        artifact: &Path,
+        f: impl FnOnce() -> Result<bool, E>,
+    ) -> Result<bool, E> {
+        let started = Instant::now();
+        let result = f();
+        if let Some(path) = &self.path {
+            let record = Record {
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|time| time.as_secs())
+                    .unwrap_or_default(),
+                platform,
+                target,
+                profile: profile.as_str(),
+                duration_secs: started.elapsed().as_secs_f64(),
+                success: result.is_ok(),
+                cache_hit: result.as_ref().map(|fresh| *fresh).unwrap_or_default(),
+                artifact: Some(artifact).filter(|_| result.is_ok()),
+                artifact_size: fs::metadata(artifact)
+                    .ok()
+                    .filter(|_| result.is_ok())
+                    .map(|metadata| metadata.len()),
+            };
+            if let Err(err) = Self::append(path, &record) {
+                log::warn!("failed to record build metrics to {:?}: {}", path, err);
+            }
+        }
+        result
+    }
+
+    fn append(path: &Path, record: &Record<'_>) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // A single write with `O_APPEND` keeps lines from concurrent builds
+        // from interleaving.
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(line.as_bytes())
+    }
+}
@@ -1,9 +1,24 @@
+pub mod cache;
 mod cargo;
 pub mod cli;
+pub mod compile_commands;
+pub mod coverage;
+pub mod fingerprint;
 mod git;
+pub mod install;
 pub mod ln;
+pub mod lock;
+pub mod metrics;
+pub mod out_dir;
 mod path;
+pub mod progress;
 pub mod prompt;
+pub mod proxy;
+pub mod repro;
+pub mod reproducible;
+pub mod retry;
+pub mod rustup;
+pub mod watch;
 
 pub use self::{cargo::*, git::*, path::*};
 
@@ -37,6 +52,36 @@ pub fn list_display(list: &[impl Display]) -> String {
     }
 }
 
+// Levenshtein distance, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let cost = if a_char == *b_char { 0 } else { 1 };
+            current[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+/// Finds the candidate closest to `name`, as long as it's close enough to
+/// plausibly be a typo.
+pub fn did_you_mean<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let threshold = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
 pub fn reverse_domain(domain: &str) -> String {
     domain.split('.').rev().collect::<Vec<_>>().join(".")
 }
@@ -248,6 +293,16 @@ impl Display for PipeError {
     }
 }
 
+/// Discards a child's stdout when running with `--quiet`. Stderr is left
+/// alone, so errors still make it through.
+pub fn quiet_stdout(command: bossy::Command) -> bossy::Command {
+    if cli::quiet() {
+        command.with_stdout(bossy::Stdio::null())
+    } else {
+        command
+    }
+}
+
 pub fn pipe(mut tx_command: bossy::Command, rx_command: bossy::Command) -> Result<bool, PipeError> {
     let tx_output = tx_command
         .run_and_wait_for_output()
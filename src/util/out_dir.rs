@@ -0,0 +1,325 @@
+//! `--out-dir`, which copies final artifacts into a layout that doesn't depend
+//! on where each tool happens to put things:
+//!
+//! ```text
+//! <out-dir>/<platform>/<profile>/<kind>/[<arch>/]<file>
+//! ```
+//!
+//! `manifest.json` in the out dir lists everything that's been collected, so
+//! CI can find artifacts without globbing. Collecting again replaces entries
+//! for the same paths, so one out dir can be shared by several builds.
+
+use super::cli::{Report, Reportable};
+use crate::{config::app::App, opts::Profile};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+static MANIFEST_FILE_NAME: &str = "manifest.json";
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    Apk,
+    Aab,
+    Aar,
+    Ipa,
+    Dsym,
+    Mapping,
+    NativeSymbols,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Apk => "apk",
+            Self::Aab => "aab",
+            Self::Aar => "aar",
+            Self::Ipa => "ipa",
+            Self::Dsym => "dsym",
+            Self::Mapping => "mapping",
+            Self::NativeSymbols => "native-symbols",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Artifact {
+    kind: Kind,
+    arch: Option<String>,
+    path: PathBuf,
+}
+
+impl Artifact {
+    pub fn new(kind: Kind, path: impl Into<PathBuf>) -> Self {
+        Self {
+            kind,
+            arch: None,
+            path: path.into(),
+        }
+    }
+
+    pub fn with_arch(mut self, arch: impl Into<String>) -> Self {
+        self.arch = Some(arch.into());
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    CopyFailed {
+        from: PathBuf,
+        to: PathBuf,
+        cause: io::Error,
+    },
+    ManifestReadFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ManifestInvalid {
+        path: PathBuf,
+        cause: serde_json::Error,
+    },
+    ManifestWriteFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::CopyFailed { from, to, cause } => Report::error(
+                format!("Failed to copy {:?} to out dir at {:?}", from, to),
+                cause,
+            ),
+            Self::ManifestReadFailed { path, cause } => {
+                Report::error(format!("Failed to read out dir manifest {:?}", path), cause)
+            }
+            Self::ManifestInvalid { path, cause } => Report::error(
+                format!("Out dir manifest {:?} is invalid", path),
+                format!("{}; delete it to start over", cause),
+            ),
+            Self::ManifestWriteFailed { path, cause } => Report::error(
+                format!("Failed to write out dir manifest {:?}", path),
+                cause,
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Entry {
+    /// Relative to the out dir.
+    path: String,
+    kind: Kind,
+    platform: String,
+    profile: String,
+    arch: Option<String>,
+    /// Only set for files, since dSYMs are directories.
+    size: Option<u64>,
+    /// Where the tool that built it put it.
+    source: PathBuf,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Manifest {
+    version: u32,
+    artifacts: Vec<Entry>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            artifacts: Vec::new(),
+        }
+    }
+}
+
+fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    if from.is_dir() {
+        // Replaced rather than merged, so nothing stale is left over.
+        if to.exists() {
+            fs::remove_dir_all(to)?;
+        }
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Copies `artifacts` into the out dir and records them in its manifest. Does
+/// nothing if no out dir is set.
+pub fn collect(
+    app: &App,
+    platform: &str,
+    profile: Profile,
+    artifacts: &[Artifact],
+) -> Result<(), Error> {
+    let out_dir = match app.out_dir() {
+        Some(out_dir) => out_dir,
+        None => return Ok(()),
+    };
+    let manifest_path = out_dir.join(MANIFEST_FILE_NAME);
+    fs::create_dir_all(out_dir).map_err(|cause| Error::ManifestWriteFailed {
+        path: manifest_path.clone(),
+        cause,
+    })?;
+    let mut manifest = match fs::read(&manifest_path) {
+        Ok(bytes) => {
+            serde_json::from_slice::<Manifest>(&bytes).map_err(|cause| Error::ManifestInvalid {
+                path: manifest_path.clone(),
+                cause,
+            })?
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Manifest::default(),
+        Err(cause) => {
+            return Err(Error::ManifestReadFailed {
+                path: manifest_path,
+                cause,
+            })
+        }
+    };
+    for artifact in artifacts {
+        let file_name = match artifact.path.file_name() {
+            Some(file_name) => file_name,
+            None => continue,
+        };
+        let mut rel = PathBuf::from(platform)
+            .join(profile.as_str())
+            .join(artifact.kind.as_str());
+        if let Some(arch) = &artifact.arch {
+            rel.push(arch);
+        }
+        rel.push(file_name);
+        let to = out_dir.join(&rel);
+        copy(&artifact.path, &to).map_err(|cause| Error::CopyFailed {
+            from: artifact.path.clone(),
+            to: to.clone(),
+            cause,
+        })?;
+        log::info!("copied {:?} to {:?}", artifact.path, to);
+        let rel = rel.display().to_string();
+        manifest.artifacts.retain(|entry| entry.path != rel);
+        manifest.artifacts.push(Entry {
+            path: rel,
+            kind: artifact.kind,
+            platform: platform.to_owned(),
+            profile: profile.as_str().to_owned(),
+            arch: artifact.arch.clone(),
+            size: fs::metadata(&to)
+                .ok()
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len()),
+            source: artifact.path.clone(),
+        });
+    }
+    manifest.artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+    let contents = serde_json::to_string_pretty(&manifest)
+        .expect("developer error: failed to serialize out dir manifest");
+    fs::write(&manifest_path, contents).map_err(|cause| Error::ManifestWriteFailed {
+        path: manifest_path,
+        cause,
+    })?;
+    super::cli::print_artifact(out_dir);
+    Ok(())
+}
+
+/// Whether `path` was modified no earlier than `than`, for telling whether an
+/// artifact came from the build that just happened.
+pub fn newer(path: &Path, than: &Path) -> bool {
+    let modified = |path: &Path| -> Option<SystemTime> { fs::metadata(path).ok()?.modified().ok() };
+    match (modified(path), modified(than)) {
+        (Some(path), Some(than)) => path >= than,
+        _ => false,
+    }
+}
+
+fn dir_entries(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Everything Gradle put in `app/build/outputs` for `profile`: APKs, bundles,
+/// R8 mapping files, and native debug symbols. Flavors are named after
+/// arches, and variant dirs are named `<flavor><Profile>`, i.e.
+/// `arm64Release`.
+pub fn gradle_outputs(project_dir: &Path, profile: Profile) -> Vec<Artifact> {
+    use heck::CamelCase as _;
+    let outputs = project_dir.join("app/build/outputs");
+    let build_ty = profile.as_str();
+    let suffix = build_ty.to_camel_case();
+    let mut artifacts = Vec::new();
+    // `apk/<flavor>/<profile>/*.apk`
+    for flavor_dir in dir_entries(&outputs.join("apk")) {
+        let flavor = match flavor_dir.file_name() {
+            Some(flavor) => flavor.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        for path in dir_entries(&flavor_dir.join(build_ty)) {
+            if path.extension().map_or(false, |ext| ext == "apk") {
+                artifacts.push(Artifact::new(Kind::Apk, path).with_arch(flavor.clone()));
+            }
+        }
+    }
+    let variants = |dir: &str, f: &mut dyn FnMut(String, PathBuf)| {
+        for variant_dir in dir_entries(&outputs.join(dir)) {
+            let flavor = variant_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .and_then(|name| name.strip_suffix(&suffix).map(ToOwned::to_owned));
+            if let Some(flavor) = flavor {
+                f(flavor, variant_dir);
+            }
+        }
+    };
+    variants("bundle", &mut |flavor, dir| {
+        for path in dir_entries(&dir) {
+            if path.extension().map_or(false, |ext| ext == "aab") {
+                artifacts.push(Artifact::new(Kind::Aab, path).with_arch(flavor.clone()));
+            }
+        }
+    });
+    variants("mapping", &mut |flavor, dir| {
+        let path = dir.join("mapping.txt");
+        if path.is_file() {
+            artifacts.push(Artifact::new(Kind::Mapping, path).with_arch(flavor));
+        }
+    });
+    variants("native-debug-symbols", &mut |flavor, dir| {
+        let path = dir.join("native-debug-symbols.zip");
+        if path.is_file() {
+            artifacts.push(Artifact::new(Kind::NativeSymbols, path).with_arch(flavor));
+        }
+    });
+    artifacts
+}
+
+/// The dSYMs in an archive.
+pub fn archive_dsyms(archive_path: &Path) -> Vec<Artifact> {
+    dir_entries(&archive_path.join("dSYMs"))
+        .into_iter()
+        .filter(|path| path.extension().map_or(false, |ext| ext == "dSYM"))
+        .map(|path| Artifact::new(Kind::Dsym, path))
+        .collect()
+}
@@ -161,3 +161,18 @@ pub fn under_root(
 ) -> Result<bool, NormalizationError> {
     normalize_path(root.as_ref().join(path)).map(|norm| norm.starts_with(root))
 }
+
+/// The total size of the files under `path`, not following symlinks.
+pub fn dir_size(path: impl AsRef<Path>) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
@@ -0,0 +1,236 @@
+use super::repro;
+use crate::opts::{NoiseLevel, NonInteractive, Timings};
+use once_cell_regex::exports::once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    io::{self, Write as _},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+static FRAMES: &[char] = &['|', '/', '-', '\\'];
+static FRAME_INTERVAL: Duration = Duration::from_millis(120);
+static HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+const MODE_SPINNER: u8 = 0;
+const MODE_HEARTBEAT: u8 = 1;
+const MODE_OFF: u8 = 2;
+
+static MODE: AtomicU8 = AtomicU8::new(MODE_HEARTBEAT);
+
+/// How long a finished step took, which is kept even when progress is hidden.
+#[derive(Debug, Serialize)]
+pub struct Timing {
+    step: String,
+    seconds: f64,
+    succeeded: bool,
+}
+
+static TIMINGS: Lazy<Mutex<Vec<Timing>>> = Lazy::new(Default::default);
+
+/// Picks how progress is shown for the rest of the process.
+///
+/// Spinners are only drawn when we're polite, interactive, and attached to a
+/// terminal; otherwise we fall back to printing a line every so often, since
+/// carriage returns just make a mess of CI logs and verbose output.
+pub fn configure(noise_level: NoiseLevel, non_interactive: NonInteractive) {
+    let mode = if noise_level.polite() && non_interactive.no() && atty::is(atty::Stream::Stderr) {
+        MODE_SPINNER
+    } else {
+        MODE_HEARTBEAT
+    };
+    MODE.store(mode, Ordering::SeqCst);
+}
+
+/// Hides progress output entirely, i.e. for `--quiet`.
+pub fn disable() {
+    MODE.store(MODE_OFF, Ordering::SeqCst);
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs >= 60 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}.{}s", secs, elapsed.subsec_millis() / 100)
+    }
+}
+
+/// A long-running step, which shows progress until it's dropped.
+#[derive(Debug)]
+pub struct Step {
+    msg: String,
+    started: Instant,
+    mode: u8,
+    succeeded: bool,
+    repro_mark: usize,
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Step {
+    pub fn start(msg: impl Into<String>) -> Self {
+        let msg = msg.into();
+        let started = Instant::now();
+        let mode = MODE.load(Ordering::SeqCst);
+        let (stop, handle) = if mode == MODE_OFF {
+            (None, None)
+        } else {
+            if mode == MODE_HEARTBEAT {
+                eprintln!("{}...", msg);
+            }
+            let (tx, rx) = mpsc::channel();
+            let thread_msg = msg.clone();
+            let handle = thread::spawn(move || {
+                let interval = if mode == MODE_SPINNER {
+                    FRAME_INTERVAL
+                } else {
+                    HEARTBEAT_INTERVAL
+                };
+                let mut frame = 0;
+                while let Err(mpsc::RecvTimeoutError::Timeout) = rx.recv_timeout(interval) {
+                    let elapsed = format_elapsed(started.elapsed());
+                    if mode == MODE_SPINNER {
+                        eprint!(
+                            "\r\x1b[2K{} {}... ({})",
+                            FRAMES[frame % FRAMES.len()],
+                            thread_msg,
+                            elapsed
+                        );
+                        let _ = io::stderr().flush();
+                        frame += 1;
+                    } else {
+                        eprintln!(
+                            "Still {}... ({} elapsed)",
+                            thread_msg.to_lowercase(),
+                            elapsed
+                        );
+                    }
+                }
+            });
+            (Some(tx), Some(handle))
+        };
+        Self {
+            msg,
+            started,
+            mode,
+            succeeded: false,
+            repro_mark: repro::mark(),
+            stop,
+            handle,
+        }
+    }
+
+    /// Marks the step as successful; steps dropped without this are reported
+    /// as failed.
+    pub fn finish(mut self) {
+        self.succeeded = true;
+    }
+
+    /// Runs `f` as a step, finishing it only if `f` succeeds.
+    pub fn run<T, E>(msg: impl Into<String>, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let step = Self::start(msg);
+        let result = f();
+        if result.is_ok() {
+            step.finish();
+        }
+        result
+    }
+}
+
+impl Drop for Step {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        let elapsed = self.started.elapsed();
+        if let Ok(mut timings) = TIMINGS.lock() {
+            timings.push(Timing {
+                step: self.msg.clone(),
+                seconds: elapsed.as_secs_f64(),
+                succeeded: self.succeeded,
+            });
+        }
+        // The repro file is most useful on CI, which is exactly where progress
+        // tends to be off, so it's written either way.
+        let repro = if self.succeeded {
+            None
+        } else {
+            match repro::write(self.repro_mark, &self.msg) {
+                Ok(path) => path,
+                Err(err) => {
+                    log::error!("failed to write {}: {}", repro::FILE_NAME, err);
+                    None
+                }
+            }
+        };
+        if self.mode == MODE_OFF {
+            if let Some(path) = repro {
+                log::info!("wrote the commands {:?} ran to {:?}", self.msg, path);
+            }
+            return;
+        }
+        if self.mode == MODE_SPINNER {
+            eprint!("\r\x1b[2K");
+        }
+        let elapsed = format_elapsed(elapsed);
+        if self.succeeded {
+            eprintln!("{} finished in {}", self.msg, elapsed);
+        } else {
+            eprintln!("{} failed after {}", self.msg, elapsed);
+            if let Some(path) = repro {
+                eprintln!(
+                    "The commands it ran were written to {:?}, which you can attach when reporting the failure.",
+                    path
+                );
+            }
+        }
+    }
+}
+
+/// Prints how long each step took, if there were any. The text summary is
+/// skipped when progress is hidden, but JSON is always printed, since it was
+/// explicitly asked for.
+pub fn print_timings(format: Timings) {
+    let timings = match TIMINGS.lock() {
+        Ok(mut timings) => std::mem::take(&mut *timings),
+        Err(_) => return,
+    };
+    if timings.is_empty() {
+        return;
+    }
+    match format {
+        Timings::Text => {
+            // A single step already said how long it took when it finished.
+            if timings.len() < 2 || MODE.load(Ordering::SeqCst) == MODE_OFF {
+                return;
+            }
+            let width = timings
+                .iter()
+                .map(|timing| timing.step.chars().count())
+                .max()
+                .unwrap_or_default();
+            eprintln!("Timings:");
+            for timing in &timings {
+                eprintln!(
+                    "  {:width$}  {}{}",
+                    timing.step,
+                    format_elapsed(Duration::from_secs_f64(timing.seconds)),
+                    if timing.succeeded { "" } else { " (failed)" },
+                    width = width
+                );
+            }
+        }
+        Timings::Json => match serde_json::to_string(&timings) {
+            Ok(json) => println!("{}", json),
+            Err(err) => log::error!("failed to serialize timings: {}", err),
+        },
+    }
+}
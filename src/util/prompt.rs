@@ -5,7 +5,23 @@ use std::{
 };
 use yes_or_no::yes_or_no;
 
+/// Whether we can actually ask the user anything.
+pub fn interactive() -> bool {
+    atty::is(atty::Stream::Stdin) && atty::is(atty::Stream::Stdout)
+}
+
 pub fn minimal(msg: impl Display) -> io::Result<String> {
+    // Otherwise, we'd just sit there waiting for input that's never coming,
+    // which is a great way to burn CI minutes.
+    if !atty::is(atty::Stream::Stdin) {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "input was needed for {:?}, but stdin isn't a terminal; specify the value up front (i.e. in your config or via a flag) instead",
+                msg.to_string()
+            ),
+        ));
+    }
     let mut input = String::new();
     print!("{}: ", msg);
     io::stdout().flush()?;
@@ -0,0 +1,123 @@
+// Tools disagree on the case, so we check (and set) both.
+static PROXY_VARS: &[&str] = &[
+    "HTTPS_PROXY",
+    "https_proxy",
+    "HTTP_PROXY",
+    "http_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+];
+static NO_PROXY_VARS: &[&str] = &["NO_PROXY", "no_proxy"];
+
+fn first_set(vars: &[&str]) -> Option<String> {
+    vars.iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// The proxy everything should go through, if there is one.
+pub fn proxy() -> Option<String> {
+    first_set(PROXY_VARS)
+}
+
+/// Comma-separated hosts that shouldn't go through the proxy.
+pub fn no_proxy() -> Option<String> {
+    first_set(NO_PROXY_VARS)
+}
+
+/// Uses the proxy from the config, unless one's already set in the
+/// environment. Since this sets the process environment, anything we spawn
+/// afterwards (`git`, `brew`, etc.) picks it up.
+pub fn configure(proxy: Option<&str>, no_proxy: Option<&str>) {
+    if let Some(proxy) = proxy {
+        if let Some(existing) = self::proxy() {
+            log::info!(
+                "ignoring proxy {:?} from config, since {:?} is set in the environment",
+                proxy,
+                existing
+            );
+        } else {
+            log::info!("using proxy {:?} from config", proxy);
+            for var in &PROXY_VARS[..4] {
+                std::env::set_var(var, proxy);
+            }
+        }
+    }
+    if let Some(no_proxy) = no_proxy {
+        if self::no_proxy().is_none() {
+            for var in NO_PROXY_VARS {
+                std::env::set_var(var, no_proxy);
+            }
+        }
+    }
+}
+
+/// The proxy variables that are set, which need to be passed explicitly to
+/// pure commands.
+pub fn passthrough() -> Vec<(&'static str, String)> {
+    PROXY_VARS
+        .iter()
+        .chain(NO_PROXY_VARS)
+        .filter_map(|var| std::env::var(var).ok().map(|value| (*var, value)))
+        .collect()
+}
+
+fn host_and_port(proxy: &str) -> Option<(String, String)> {
+    let (scheme, rest) = {
+        let mut parts = proxy.splitn(2, "://");
+        match (parts.next(), parts.next()) {
+            (Some(scheme), Some(rest)) => (scheme, rest),
+            _ => ("http", proxy),
+        }
+    };
+    // Credentials aren't supported by either of the tools this is for.
+    let authority = rest.split('/').next()?.rsplit('@').next()?;
+    let mut parts = authority.rsplitn(2, ':');
+    let (port, host) = match (parts.next(), parts.next()) {
+        (Some(port), Some(host)) if port.parse::<u16>().is_ok() => (port, host),
+        _ => (if scheme == "https" { "443" } else { "80" }, authority),
+    };
+    if host.is_empty() {
+        None
+    } else {
+        Some((host.to_owned(), port.to_owned()))
+    }
+}
+
+/// `sdkmanager` ignores the proxy environment variables, and has its own
+/// flags instead.
+pub fn sdkmanager_args() -> Vec<String> {
+    proxy()
+        .and_then(|proxy| host_and_port(&proxy))
+        .map(|(host, port)| {
+            vec![
+                "--proxy=http".to_owned(),
+                format!("--proxy_host={}", host),
+                format!("--proxy_port={}", port),
+            ]
+        })
+        .unwrap_or_default()
+}
+
+/// Gradle runs on the JVM, which also ignores the proxy environment
+/// variables, so we pass them as system properties.
+pub fn gradle_args() -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some((host, port)) = proxy().and_then(|proxy| host_and_port(&proxy)) {
+        for protocol in &["http", "https"] {
+            args.push(format!("-D{}.proxyHost={}", protocol, host));
+            args.push(format!("-D{}.proxyPort={}", protocol, port));
+        }
+        if let Some(no_proxy) = no_proxy() {
+            let hosts = no_proxy
+                .split(',')
+                .map(|host| host.trim().trim_start_matches('.'))
+                .filter(|host| !host.is_empty())
+                .map(|host| format!("*{}", host))
+                .collect::<Vec<_>>()
+                .join("|");
+            args.push(format!("-Dhttp.nonProxyHosts={}", hosts));
+        }
+    }
+    args
+}
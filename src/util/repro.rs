@@ -0,0 +1,194 @@
+//! Keeps track of the external commands each step runs, so that when a step
+//! fails, they can be written to a `repro.sh` that replays them without us.
+//! That's what makes issues filed against Xcode or Gradle behavior
+//! reproducible by the people who maintain them.
+
+use crate::env::ExplicitEnv;
+use once_cell_regex::exports::once_cell::sync::Lazy;
+use std::{
+    ffi::{OsStr, OsString},
+    fs, io,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+pub static FILE_NAME: &str = "repro.sh";
+
+#[derive(Debug)]
+struct Record {
+    command: String,
+    cwd: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    // Whether the command was made with `impure`, and so also got everything
+    // in our environment that isn't in `env`.
+    inherits: bool,
+}
+
+static RECORDS: Lazy<Mutex<Vec<Record>>> = Lazy::new(Default::default);
+
+// The script is meant to be attached to issues, so anything that looks like a
+// credential is left out.
+fn is_sensitive(key: &str) -> bool {
+    let key = key.to_ascii_uppercase();
+    ["PASSWORD", "SECRET", "TOKEN", "CREDENTIAL"]
+        .iter()
+        .any(|word| key.contains(word))
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Env vars for a single command, on top of the ones it starts out with.
+/// bossy can't tell us what a command's been given, so anything a recorded
+/// command needs set goes through here instead of `with_env_var`.
+#[derive(Debug, Default)]
+pub struct Vars(Vec<(String, OsString)>);
+
+impl Vars {
+    /// Sets `key`, replacing whatever it was set to before.
+    pub fn add(&mut self, key: impl Into<String>, value: impl AsRef<OsStr>) -> &mut Self {
+        let key = key.into();
+        let value = value.as_ref().to_owned();
+        match self.0.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, existing)) => *existing = value,
+            None => self.0.push((key, value)),
+        }
+        self
+    }
+
+    pub fn with(mut self, key: impl Into<String>, value: impl AsRef<OsStr>) -> Self {
+        self.add(key, value);
+        self
+    }
+}
+
+impl<K: Into<String>, V: AsRef<OsStr>> Extend<(K, V)> for Vars {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.add(key, value);
+        }
+    }
+}
+
+fn record<'a>(
+    command: &bossy::Command,
+    env: impl Iterator<Item = (&'a str, &'a OsStr)>,
+    vars: &'a Vars,
+    inherits: bool,
+) {
+    let mut env = env.collect::<Vec<_>>();
+    for (key, value) in &vars.0 {
+        env.retain(|(existing, _)| *existing != key.as_str());
+        env.push((key.as_str(), value.as_os_str()));
+    }
+    let record = Record {
+        command: command.display().to_owned(),
+        cwd: std::env::current_dir().ok(),
+        env: env
+            .into_iter()
+            .filter(|(key, _)| !is_sensitive(key))
+            .map(|(key, value)| (key.to_owned(), value.to_string_lossy().into_owned()))
+            .collect(),
+        inherits,
+    };
+    if let Ok(mut records) = RECORDS.lock() {
+        records.push(record);
+    }
+}
+
+pub trait Recorded {
+    /// Sets `vars` on this command made with `pure`, and remembers it, along
+    /// with the environment it's run with, in case the step running it fails.
+    fn recorded(self, env: &impl ExplicitEnv, vars: Vars) -> Self;
+
+    /// Like `recorded`, but for commands made with `impure`, which get our
+    /// whole environment.
+    fn recorded_impure(self, vars: Vars) -> Self;
+}
+
+impl Recorded for bossy::Command {
+    fn recorded(mut self, env: &impl ExplicitEnv, vars: Vars) -> Self {
+        for (key, value) in &vars.0 {
+            self.add_env_var(key, value);
+        }
+        let explicit_env = env.explicit_env();
+        record(&self, explicit_env.into_iter(), &vars, false);
+        self
+    }
+
+    fn recorded_impure(mut self, vars: Vars) -> Self {
+        for (key, value) in &vars.0 {
+            self.add_env_var(key, value);
+        }
+        let inherited = std::env::vars_os()
+            .filter_map(|(key, value)| Some((key.into_string().ok()?, value)))
+            .collect::<Vec<_>>();
+        record(
+            &self,
+            inherited
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_os_str())),
+            &vars,
+            true,
+        );
+        self
+    }
+}
+
+/// Where we are in the list of recorded commands, so that a step knows which
+/// ones it ran.
+pub(super) fn mark() -> usize {
+    RECORDS
+        .lock()
+        .map(|records| records.len())
+        .unwrap_or_default()
+}
+
+fn script(step: &str, records: &[Record]) -> String {
+    let mut script = format!(
+        "#!/bin/sh\n# Replays the commands cargo-mobile ran for {:?}, which failed.\n# Secrets passed through the environment aren't included.\nset -e\n",
+        step
+    );
+    for record in records {
+        script.push('\n');
+        if let Some(cwd) = &record.cwd {
+            script.push_str(&format!("cd {}\n", quote(&cwd.to_string_lossy())));
+        }
+        // A pure command starts with nothing but what it's given, but an
+        // impure one would've had the rest of our environment too.
+        script.push_str(if record.inherits {
+            "env \\\n"
+        } else {
+            "env -i \\\n"
+        });
+        for (key, value) in &record.env {
+            script.push_str(&format!("  {} \\\n", quote(&format!("{}={}", key, value))));
+        }
+        script.push_str(&format!("  {}\n", record.command));
+    }
+    script
+}
+
+/// Writes whatever was recorded since `mark` to `repro.sh` in our temp dir,
+/// returning its path. Nothing's written if no commands were recorded.
+pub(super) fn write(mark: usize, step: &str) -> io::Result<Option<PathBuf>> {
+    let records = match RECORDS.lock() {
+        Ok(records) => records,
+        Err(_) => return Ok(None),
+    };
+    let records = records.get(mark..).unwrap_or_default();
+    if records.is_empty() {
+        return Ok(None);
+    }
+    let dir = super::temp_dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(FILE_NAME);
+    fs::write(&path, script(step, records))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(Some(path))
+}
@@ -0,0 +1,144 @@
+//! `--reproducible` builds, where two builds of the same commit produce
+//! bit-identical native libraries. That takes a few things: paths baked into
+//! debug info and panic messages are remapped to be machine-independent,
+//! timestamps come from the commit instead of the clock, and archives are
+//! written without mtimes. Since Gradle and Xcode run cargo on our behalf, the
+//! mode is passed along to those builds through the environment.
+
+use super::git::Git;
+use crate::{config::app::App, dot_cargo::DotCargo};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Set for builds run by Gradle and Xcode, so they know to be reproducible
+/// too.
+pub static ENV_VAR: &str = "CARGO_MOBILE_REPRODUCIBLE";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst) || std::env::var_os(ENV_VAR).is_some()
+}
+
+/// The commit time of `HEAD`, unless `SOURCE_DATE_EPOCH` is already set.
+fn source_date_epoch(root_dir: &Path) -> Option<String> {
+    if let Ok(epoch) = std::env::var("SOURCE_DATE_EPOCH") {
+        return Some(epoch);
+    }
+    Git::new(root_dir)
+        .command_parse("log -1 --format=%ct")
+        .run_and_wait_for_output()
+        .ok()
+        .and_then(|output| output.stdout_str().ok().map(|s| s.trim().to_owned()))
+        .filter(|epoch| !epoch.is_empty())
+        .or_else(|| {
+            log::warn!(
+                "couldn't get the commit time of `HEAD`, so `SOURCE_DATE_EPOCH` won't be set"
+            );
+            None
+        })
+}
+
+/// Variables that make the tools involved in a build deterministic, if
+/// `--reproducible` was passed.
+pub fn env_vars(root_dir: &Path) -> Vec<(&'static str, String)> {
+    if !enabled() {
+        return Vec::new();
+    }
+    let mut vars = vec![
+        (ENV_VAR, "1".to_owned()),
+        // Makes `ar` and `ld64` write zeroed timestamps, since iOS libs are
+        // static archives.
+        ("ZERO_AR_DATE", "1".to_owned()),
+    ];
+    if let Some(epoch) = source_date_epoch(root_dir) {
+        vars.push(("SOURCE_DATE_EPOCH", epoch));
+    }
+    vars
+}
+
+/// What `rustflags` goes in. Unlike `RUSTFLAGS`, it isn't split on spaces,
+/// so paths with spaces in them survive.
+pub static RUSTFLAGS_ENV_VAR: &str = "CARGO_ENCODED_RUSTFLAGS";
+
+fn sysroot(root_dir: &Path) -> Option<PathBuf> {
+    // Run from the project, so a `rust-toolchain` file there is respected.
+    bossy::Command::impure("rustc")
+        .with_current_dir(root_dir)
+        .with_args(&["--print", "sysroot"])
+        .run_and_wait_for_output()
+        .ok()
+        .and_then(|output| output.stdout_str().ok().map(|s| PathBuf::from(s.trim())))
+        .filter(|sysroot| !sysroot.as_os_str().is_empty())
+}
+
+// Paths that end up in the build and differ between machines, along with
+// what they're remapped to.
+fn remaps(root_dir: &Path) -> Vec<(PathBuf, &'static str)> {
+    let mut remaps = Vec::new();
+    // Dependencies come from the registry and git checkouts in cargo's home.
+    // Our own `CARGO_HOME` doesn't make it to pure commands, so the default
+    // location is remapped too.
+    let cargo_homes = home::cargo_home()
+        .ok()
+        .into_iter()
+        .chain(home::home_dir().map(|home| home.join(".cargo")));
+    for cargo_home in cargo_homes {
+        remaps.push((cargo_home, "/cargo"));
+    }
+    // `std`'s sources, whenever they're referred to, are in the toolchain's
+    // sysroot.
+    if let Some(sysroot) = sysroot(root_dir) {
+        remaps.push((sysroot, "/rustc/sysroot"));
+    }
+    // rustc uses the last prefix that matches, so the project goes last, in
+    // case it's inside one of the above.
+    remaps.push((root_dir.to_owned(), "."));
+    // The remapping only applies to paths that start with the prefix, so
+    // they have to be absolute, and symlinks need resolving.
+    let mut resolved = Vec::new();
+    for (from, to) in remaps {
+        let canonical = from.canonicalize().ok();
+        for from in canonical.into_iter().chain(Some(from)) {
+            if !resolved.contains(&(from.clone(), to)) {
+                resolved.push((from, to));
+            }
+        }
+    }
+    resolved
+}
+
+/// The flags for building `triple`, encoded for `RUSTFLAGS_ENV_VAR`. That
+/// takes precedence over `.cargo/config`, so the flags the target gets from
+/// there come first, then `extra_flags`, and then, if `--reproducible` was
+/// passed, remappings for the paths that would otherwise differ between
+/// machines.
+pub fn rustflags<'a>(
+    app: &App,
+    triple: &str,
+    extra_flags: impl IntoIterator<Item = &'a str>,
+) -> String {
+    let mut flags = match DotCargo::load(app) {
+        Ok(dot_cargo) => dot_cargo.rustflags(triple),
+        Err(err) => {
+            log::warn!(
+                "couldn't load cargo config, so its rustflags won't be used: {:?}",
+                err
+            );
+            Vec::new()
+        }
+    };
+    flags.extend(extra_flags.into_iter().map(ToOwned::to_owned));
+    if enabled() {
+        for (from, to) in remaps(app.root_dir()) {
+            flags.push(format!("--remap-path-prefix={}={}", from.display(), to));
+        }
+    }
+    flags.join("\x1f")
+}
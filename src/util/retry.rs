@@ -0,0 +1,75 @@
+//! Retries for device operations that fail every so often for no good reason,
+//! like `adb install` or the `ios-deploy` handshake, so that a USB hiccup
+//! doesn't fail a whole CI run.
+
+use std::{
+    fmt::Debug,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    thread,
+    time::Duration,
+};
+
+pub const DEFAULT_ATTEMPTS: u32 = 3;
+pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(2);
+// Doubling gets out of hand quickly with a generous `retry.attempts`.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+static ATTEMPTS: AtomicU32 = AtomicU32::new(DEFAULT_ATTEMPTS);
+static BACKOFF_MILLIS: AtomicU64 = AtomicU64::new(DEFAULT_BACKOFF.as_millis() as u64);
+
+/// Sets the retry policy for the rest of the process.
+pub fn configure(attempts: u32, backoff: Duration) {
+    ATTEMPTS.store(attempts.max(1), Ordering::SeqCst);
+    BACKOFF_MILLIS.store(backoff.as_millis() as u64, Ordering::SeqCst);
+}
+
+/// Whether a failed command is worth retrying, going by whether its output
+/// mentions any of `permanent`, i.e. failures that'll just happen again. If
+/// the output wasn't captured, there's no telling, so it's assumed to be
+/// transient.
+pub fn unless_output_contains(
+    permanent: &'static [&'static str],
+) -> impl Fn(&bossy::Error) -> bool {
+    move |err| {
+        err.output().map_or(true, |output| {
+            let stdout = String::from_utf8_lossy(output.stdout());
+            let stderr = String::from_utf8_lossy(output.stderr());
+            !permanent
+                .iter()
+                .any(|failure| stdout.contains(failure) || stderr.contains(failure))
+        })
+    }
+}
+
+/// Runs `f` until it succeeds, fails in a way `transient` says isn't worth
+/// retrying, or we're out of attempts, doubling the wait between attempts
+/// each time.
+pub fn retry<T, E: Debug>(
+    what: &str,
+    transient: impl Fn(&E) -> bool,
+    mut f: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let attempts = ATTEMPTS.load(Ordering::SeqCst);
+    let mut backoff = Duration::from_millis(BACKOFF_MILLIS.load(Ordering::SeqCst));
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Err(err) if attempt < attempts && transient(&err) => {
+                log::info!("{} failed on attempt {}: {:?}", what, attempt, err);
+                attempt += 1;
+                eprintln!(
+                    "{} failed; retrying ({}/{}) in {}s...",
+                    what,
+                    attempt,
+                    attempts,
+                    backoff.as_secs_f32()
+                );
+                thread::sleep(backoff);
+                backoff = backoff
+                    .checked_mul(2)
+                    .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF));
+            }
+            result => return result,
+        }
+    }
+}
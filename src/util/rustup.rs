@@ -0,0 +1,243 @@
+use super::{
+    cli::{Report, Reportable},
+    prompt,
+};
+use crate::opts;
+use once_cell_regex::exports::once_cell::sync::Lazy;
+use std::{
+    collections::HashSet,
+    env,
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+static TOOLCHAIN_FILES: &[&str] = &["rust-toolchain.toml", "rust-toolchain"];
+
+// Targets don't get uninstalled out from under us, so there's no sense in
+// asking `rustup` about the same one twice.
+static VERIFIED: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+
+#[derive(Debug)]
+pub enum Error {
+    ActiveToolchainFailed(bossy::Error),
+    ListFailed(bossy::Error),
+    OutputInvalid(std::str::Utf8Error),
+    TargetsMissing {
+        toolchain: Toolchain,
+        missing: Vec<String>,
+    },
+    PromptFailed(io::Error),
+    AddFailed(bossy::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ActiveToolchainFailed(err) => {
+                Report::error("Failed to detect active Rust toolchain", err)
+            }
+            Self::ListFailed(err) => Report::error("Failed to list installed Rust targets", err),
+            Self::OutputInvalid(err) => {
+                Report::error("`rustup` output contained invalid UTF-8", err)
+            }
+            Self::TargetsMissing { toolchain, missing } => Report::action_request(
+                format!(
+                    "Rust targets are missing for the {} toolchain; install them by running this:",
+                    toolchain
+                ),
+                add_command(toolchain, missing),
+            ),
+            Self::PromptFailed(err) => {
+                Report::error("Failed to prompt for installing Rust targets", err)
+            }
+            Self::AddFailed(err) => Report::error("Failed to install Rust targets", err),
+        }
+    }
+}
+
+fn add_command(toolchain: &Toolchain, missing: &[String]) -> String {
+    format!(
+        "rustup target add --toolchain {} {}",
+        toolchain.name,
+        missing.join(" ")
+    )
+}
+
+/// The toolchain `rustup` would use in the current directory, which takes
+/// `rust-toolchain.toml` overrides into account.
+#[derive(Clone, Debug)]
+pub struct Toolchain {
+    name: String,
+    override_file: Option<PathBuf>,
+}
+
+impl Display for Toolchain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`", self.name)?;
+        if let Some(override_file) = &self.override_file {
+            write!(f, " (from {:?})", override_file)?;
+        }
+        Ok(())
+    }
+}
+
+fn find_override_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .flat_map(|dir| TOOLCHAIN_FILES.iter().map(move |name| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+impl Toolchain {
+    pub fn active() -> Result<Self, Error> {
+        let output = bossy::Command::impure("rustup")
+            .with_args(&["show", "active-toolchain"])
+            .run_and_wait_for_output()
+            .map_err(Error::ActiveToolchainFailed)?;
+        // This looks like `stable-x86_64-apple-darwin (default)`, or has
+        // `(overridden by '...')` at the end instead.
+        let name = output
+            .stdout_str()
+            .map_err(Error::OutputInvalid)?
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        let override_file = env::current_dir()
+            .ok()
+            .and_then(|cwd| find_override_file(&cwd));
+        if let Some(override_file) = &override_file {
+            log::info!("toolchain {:?} is overridden by {:?}", name, override_file);
+        } else {
+            log::info!("active toolchain is {:?}", name);
+        }
+        Ok(Self {
+            name,
+            override_file,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn installed_targets(&self) -> Result<Vec<String>, Error> {
+        let output = bossy::Command::impure("rustup")
+            .with_args(&["target", "list", "--installed", "--toolchain", &self.name])
+            .run_and_wait_for_output()
+            .map_err(Error::ListFailed)?;
+        Ok(output
+            .stdout_str()
+            .map_err(Error::OutputInvalid)?
+            .lines()
+            .map(|line| line.trim().to_owned())
+            .filter(|line| !line.is_empty())
+            .collect())
+    }
+
+    /// Which of `triples` still need to be installed for this toolchain.
+    pub fn missing_targets<'a>(
+        &self,
+        triples: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<String>, Error> {
+        let installed = self.installed_targets()?;
+        Ok(triples
+            .into_iter()
+            .filter(|triple| {
+                !installed
+                    .iter()
+                    .any(|installed| installed.as_str() == *triple)
+            })
+            .map(ToOwned::to_owned)
+            .collect())
+    }
+
+    pub fn add_targets(&self, triples: &[String]) -> Result<(), Error> {
+        bossy::Command::impure("rustup")
+            .with_args(&["target", "add", "--toolchain", &self.name])
+            .with_args(triples)
+            .run_and_wait()
+            .map_err(Error::AddFailed)?;
+        Ok(())
+    }
+}
+
+/// Installs whichever of `triples` are missing from the active toolchain
+/// without asking, which is what `init` does.
+pub fn install_missing<'a>(triples: impl IntoIterator<Item = &'a str>) -> Result<(), Error> {
+    let toolchain = Toolchain::active()?;
+    let missing = toolchain.missing_targets(triples)?;
+    if !missing.is_empty() {
+        toolchain.add_targets(&missing)?;
+    }
+    Ok(())
+}
+
+/// Makes sure `triples` are installed for the active toolchain, offering to
+/// install any that are missing when we're able to ask. Not everyone uses
+/// `rustup`, so if we can't ask it, we just let cargo be the judge.
+pub fn ensure_targets<'a>(
+    triples: impl IntoIterator<Item = &'a str>,
+    non_interactive: opts::NonInteractive,
+) -> Result<(), Error> {
+    let triples = {
+        let verified = VERIFIED.lock().unwrap();
+        triples
+            .into_iter()
+            .filter(|triple| !verified.contains(*triple))
+            .collect::<Vec<_>>()
+    };
+    if triples.is_empty() {
+        return Ok(());
+    }
+    let verified = || {
+        VERIFIED
+            .lock()
+            .unwrap()
+            .extend(triples.iter().map(|triple| triple.to_string()))
+    };
+    let checked = Toolchain::active().and_then(|toolchain| {
+        toolchain
+            .missing_targets(triples.iter().copied())
+            .map(|missing| (toolchain, missing))
+    });
+    let (toolchain, missing) = match checked {
+        Ok(checked) => checked,
+        Err(err) => {
+            log::warn!(
+                "can't verify that Rust targets {} are installed: {:?}",
+                super::list_display(&triples),
+                err
+            );
+            verified();
+            return Ok(());
+        }
+    };
+    if missing.is_empty() {
+        verified();
+        return Ok(());
+    }
+    if non_interactive.no() {
+        let answer = loop {
+            if let Some(answer) = prompt::yes_no(
+                format!(
+                    "Rust targets {} aren't installed for the {} toolchain; install them now?",
+                    super::list_display(&missing),
+                    toolchain
+                ),
+                Some(prompt::YesOrNo::Yes),
+            )
+            .map_err(Error::PromptFailed)?
+            {
+                break answer;
+            }
+        };
+        if answer.yes() {
+            toolchain.add_targets(&missing)?;
+            verified();
+            return Ok(());
+        }
+    }
+    Err(Error::TargetsMissing { toolchain, missing })
+}
@@ -0,0 +1,83 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+static POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn hash_dir(dir: &Path, hasher: &mut DefaultHasher) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        let metadata = fs::metadata(&path)?;
+        path.hash(hasher);
+        if metadata.is_dir() {
+            hash_dir(&path, hasher)?;
+        } else {
+            metadata.len().hash(hasher);
+            metadata.modified()?.hash(hasher);
+        }
+    }
+    Ok(())
+}
+
+fn fingerprint(paths: &[PathBuf]) -> io::Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    for path in paths {
+        if path.is_dir() {
+            hash_dir(path, &mut hasher)?;
+        } else {
+            path.hash(&mut hasher);
+            fs::metadata(path)?.modified()?.hash(&mut hasher);
+        }
+    }
+    Ok(hasher.finish())
+}
+
+/// Polls paths for changes. We'd rather not pull in a whole file watching
+/// library for this, and template packs and source dirs are small enough to
+/// walk cheaply.
+#[derive(Debug)]
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    last: u64,
+}
+
+impl Watcher {
+    pub fn new(paths: Vec<PathBuf>) -> io::Result<Self> {
+        let last = fingerprint(&paths)?;
+        Ok(Self { paths, last })
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Blocks until anything in the watched paths is added, removed, or
+    /// modified.
+    pub fn wait(&mut self) -> io::Result<()> {
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            // Editors often replace files by deleting and recreating them, so
+            // we might catch things mid-save; we'll just check again.
+            match fingerprint(&self.paths) {
+                Ok(current) if current != self.last => {
+                    self.last = current;
+                    return Ok(());
+                }
+                Ok(_) => (),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    log::info!("file vanished while watching: {}", err)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! Catches the problems that get builds rejected by the stores, before
+//! spending the time uploading them. The stores' own validators are run when
+//! they're available; on top of that, we check for the usual suspects
+//! ourselves, since those checks work offline and without credentials.
+
+use crate::{
+    config::{Config, LoadOrGenError, Overrides},
+    distribute::Platform,
+    opts,
+    util::{
+        self,
+        cli::{Report, Reportable, TextWrapper},
+    },
+};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+/// The ABIs Google Play accepts native code for.
+static SUPPORTED_ABIS: &[&str] = &["armeabi-v7a", "arm64-v8a", "x86", "x86_64"];
+
+/// `altool` is only run with App Store Connect API credentials, which come
+/// from `apple.app-store-connect`, or else from these (in which case `altool`
+/// looks for the private key itself).
+static API_KEY_ENV_VAR: &str = "APP_STORE_CONNECT_API_KEY_ID";
+static API_ISSUER_ENV_VAR: &str = "APP_STORE_CONNECT_API_ISSUER_ID";
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadOrGenError),
+    #[cfg(not(target_os = "macos"))]
+    AppleUnsupported,
+    ArtifactMissing {
+        path: PathBuf,
+        hint: String,
+    },
+    ListFailed(bossy::Error),
+    ListInvalid(std::str::Utf8Error),
+    ExtractFailed(bossy::Error),
+    PresentFailed(bossy::Error),
+    IssuesFound(usize),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            #[cfg(not(target_os = "macos"))]
+            Self::AppleUnsupported => Report::error(
+                "Failed to validate",
+                "Apple builds can only be validated from macOS",
+            ),
+            Self::ArtifactMissing { path, hint } => {
+                Report::action_request(hint, format!("Nothing to validate at {:?}", path))
+            }
+            Self::ListFailed(err) => Report::error("Failed to list artifact contents", err),
+            Self::ListInvalid(err) => {
+                Report::error("Artifact contents contained invalid UTF-8", err)
+            }
+            Self::ExtractFailed(err) => Report::error("Failed to extract artifact", err),
+            Self::PresentFailed(err) => {
+                Report::error("Failed to check which validators are installed", err)
+            }
+            Self::IssuesFound(count) => Report::error(
+                "Validation failed",
+                format!(
+                    "Found {} issue{} that would get this build rejected",
+                    count,
+                    if *count == 1 { "" } else { "s" }
+                ),
+            ),
+        }
+    }
+}
+
+fn artifact(config: &Config, platform: Platform) -> Result<PathBuf, Error> {
+    match platform {
+        // Play only takes bundles, which is what `bundleArm64Release`
+        // produces.
+        Platform::Android => {
+            let path = config
+                .android()
+                .project_dir()
+                .join("app/build/outputs/bundle/arm64Release/app-arm64-release.aab");
+            if path.is_file() {
+                Ok(path)
+            } else {
+                Err(Error::ArtifactMissing {
+                    path,
+                    hint: "Build a signed release bundle, or pass `--artifact`.".to_owned(),
+                })
+            }
+        }
+        #[cfg(target_os = "macos")]
+        Platform::Apple => config
+            .apple()
+            .ipa_path()
+            .map_err(|(path, _)| Error::ArtifactMissing {
+                path,
+                hint:
+                    "Run `cargo apple archive --release` and export an IPA, or pass `--artifact`."
+                        .to_owned(),
+            }),
+        #[cfg(not(target_os = "macos"))]
+        Platform::Apple => Err(Error::AppleUnsupported),
+    }
+}
+
+/// Lists the paths in a zip, which both AABs and IPAs are.
+fn entries(artifact: &Path) -> Result<Vec<String>, Error> {
+    bossy::Command::impure("unzip")
+        .with_arg("-Z1")
+        .with_arg(artifact)
+        .run_and_wait_for_output()
+        .map_err(Error::ListFailed)?
+        .stdout_str()
+        .map(|entries| entries.lines().map(ToOwned::to_owned).collect())
+        .map_err(Error::ListInvalid)
+}
+
+// Validators explain themselves on stderr, which is more useful in the issue
+// than the exit status.
+fn failure_output(err: &bossy::Error) -> String {
+    err.output()
+        .map(|output| {
+            let stderr = String::from_utf8_lossy(output.stderr());
+            if stderr.trim().is_empty() {
+                String::from_utf8_lossy(output.stdout()).trim().to_owned()
+            } else {
+                stderr.trim().to_owned()
+            }
+        })
+        .unwrap_or_else(|| err.to_string())
+}
+
+fn bundletool_manifest_value(aab: &Path, xpath: &str) -> Option<String> {
+    bossy::Command::impure("bundletool")
+        .with_args(&["dump", "manifest", "--bundle"])
+        .with_arg(aab)
+        .with_args(&["--xpath", xpath])
+        .run_and_wait_for_output()
+        .ok()
+        .and_then(|output| {
+            output
+                .stdout_str()
+                .ok()
+                .map(|value| value.trim().to_owned())
+        })
+}
+
+fn validate_aab(aab: &Path) -> Result<Vec<String>, Error> {
+    let mut issues = Vec::new();
+    let entries = entries(aab)?;
+
+    let abis = entries
+        .iter()
+        .filter_map(|entry| entry.strip_prefix("base/lib/"))
+        .filter_map(|rest| rest.split('/').next())
+        .filter(|abi| !abi.is_empty())
+        .collect::<BTreeSet<_>>();
+    for abi in &abis {
+        if !SUPPORTED_ABIS.contains(abi) {
+            issues.push(format!("Native code for unsupported ABI {:?}", abi));
+        }
+    }
+    if abis.is_empty() {
+        issues.push("No native code; the Rust lib wasn't packaged".to_owned());
+    } else if !abis.contains(&"arm64-v8a") {
+        issues.push("No native code for `arm64-v8a`; Play requires 64-bit support".to_owned());
+    }
+
+    let has_icon = entries
+        .iter()
+        .any(|entry| entry.starts_with("base/res/mipmap-") && entry.contains("/ic_launcher"));
+    if !has_icon {
+        issues.push("No launcher icon (`mipmap-*/ic_launcher`)".to_owned());
+    }
+
+    if util::command_present("bundletool").map_err(Error::PresentFailed)? {
+        println!("Running `bundletool validate`...");
+        if let Err(err) = bossy::Command::impure("bundletool")
+            .with_args(&["validate", "--bundle"])
+            .with_arg(aab)
+            .run_and_wait_for_output()
+        {
+            issues.push(format!(
+                "`bundletool validate` failed: {}",
+                failure_output(&err)
+            ));
+        }
+        if bundletool_manifest_value(aab, "/manifest/application/@android:debuggable").as_deref()
+            == Some("true")
+        {
+            issues.push("The app is debuggable; build it in release mode".to_owned());
+        }
+    } else {
+        println!("`bundletool` isn't installed, so only our own checks were run; install it with `brew install bundletool` for a more thorough validation.");
+    }
+    Ok(issues)
+}
+
+#[cfg(target_os = "macos")]
+fn validate_ipa(config: &Config, ipa: &Path) -> Result<Vec<String>, Error> {
+    let mut issues = Vec::new();
+    let entries = entries(ipa)?;
+    // Zips don't always have entries for directories, so this goes by what's
+    // in it.
+    let app_dir = entries
+        .iter()
+        .filter(|entry| entry.starts_with("Payload/"))
+        .find_map(|entry| {
+            entry
+                .find(".app/")
+                .map(|index| entry[..index + ".app".len()].to_owned())
+        });
+    let app_dir = match app_dir {
+        Some(app_dir) => app_dir,
+        None => {
+            issues.push("No app bundle under `Payload/`".to_owned());
+            return Ok(issues);
+        }
+    };
+    let in_app = |name: &str| entries.contains(&format!("{}/{}", app_dir, name));
+    if !in_app("Assets.car") && !entries.iter().any(|entry| entry.contains("/AppIcon")) {
+        issues.push("No app icon; add an `AppIcon` set to the asset catalog".to_owned());
+    }
+    if !in_app("embedded.mobileprovision") {
+        issues.push(
+            "No provisioning profile embedded; the IPA wasn't exported for distribution".to_owned(),
+        );
+    }
+
+    // The rest needs the bundle on disk.
+    let extract_dir = util::temp_dir().join(format!("validate-{}", config.app().name()));
+    bossy::Command::impure("unzip")
+        .with_args(&["-q", "-o"])
+        .with_arg(ipa)
+        .with_arg("-d")
+        .with_arg(&extract_dir)
+        .run_and_wait()
+        .map_err(Error::ExtractFailed)?;
+    let app_path = extract_dir.join(&app_dir);
+
+    match bossy::Command::impure("codesign")
+        .with_args(&["-d", "--entitlements", ":-"])
+        .with_arg(&app_path)
+        .run_and_wait_for_output()
+    {
+        Ok(output) => {
+            let entitlements = String::from_utf8_lossy(output.stdout());
+            if plist_value(&entitlements, "get-task-allow") == Some("<true/>") {
+                issues.push("`get-task-allow` is set, so this was signed for development; export it with a distribution profile".to_owned());
+            }
+            let aps_environment = plist_value(&entitlements, "aps-environment");
+            if aps_environment.is_some() && aps_environment != Some("<string>production</string>") {
+                issues.push("`aps-environment` isn't `production`; push notifications won't work in the released app".to_owned());
+            }
+        }
+        Err(err) => issues.push(format!(
+            "The app isn't signed properly: {}",
+            failure_output(&err)
+        )),
+    }
+
+    let executable = bossy::Command::impure("/usr/libexec/PlistBuddy")
+        .with_args(&["-c", "Print :CFBundleExecutable"])
+        .with_arg(app_path.join("Info.plist"))
+        .run_and_wait_for_output()
+        .ok()
+        .and_then(|output| output.stdout_str().ok().map(|name| name.trim().to_owned()));
+    match executable {
+        Some(executable) => {
+            let archs = bossy::Command::impure("lipo")
+                .with_arg("-archs")
+                .with_arg(app_path.join(&executable))
+                .run_and_wait_for_output()
+                .ok()
+                .and_then(|output| {
+                    output
+                        .stdout_str()
+                        .ok()
+                        .map(|archs| archs.trim().to_owned())
+                })
+                .unwrap_or_default();
+            let archs = archs.split_whitespace().collect::<Vec<_>>();
+            if !archs.contains(&"arm64") {
+                issues.push("The executable has no `arm64` slice".to_owned());
+            }
+            for arch in archs
+                .iter()
+                .filter(|arch| ["x86_64", "i386"].contains(arch))
+            {
+                issues.push(format!(
+                    "The executable has a simulator slice ({}), which App Store Connect rejects",
+                    arch
+                ));
+            }
+        }
+        None => issues.push("`Info.plist` has no `CFBundleExecutable`".to_owned()),
+    }
+
+    match api_credentials(config) {
+        Ok(Some((api_key, api_issuer, private_key))) => {
+            println!("Running `altool --validate-app`...");
+            let mut command = bossy::Command::impure("xcrun")
+                .with_args(&["altool", "--validate-app", "-t", "ios", "-f"])
+                .with_arg(ipa)
+                .with_args(&["--apiKey", &api_key, "--apiIssuer", &api_issuer]);
+            let keys_dir = match private_key {
+                Some(private_key) => match write_private_key(&api_key, &private_key) {
+                    Ok(keys_dir) => {
+                        command.add_env_var("API_PRIVATE_KEYS_DIR", &keys_dir);
+                        Some(keys_dir)
+                    }
+                    Err(err) => {
+                        issues.push(format!(
+                            "Failed to write App Store Connect private key: {}",
+                            err
+                        ));
+                        return Ok(issues);
+                    }
+                },
+                None => None,
+            };
+            let result = command.run_and_wait_for_output();
+            if let Some(keys_dir) = keys_dir {
+                if let Err(err) = std::fs::remove_dir_all(&keys_dir) {
+                    log::warn!("failed to remove {:?}: {}", keys_dir, err);
+                }
+            }
+            if let Err(err) = result {
+                issues.push(format!(
+                    "`altool --validate-app` failed: {}",
+                    failure_output(&err)
+                ));
+            }
+        }
+        Ok(None) => println!(
+            "Set `{}.app-store-connect` (or `{}` and `{}`) to also validate with App Store Connect using `altool`.",
+            crate::apple::NAME, API_KEY_ENV_VAR, API_ISSUER_ENV_VAR
+        ),
+        Err(err) => issues.push(format!(
+            "Failed to get App Store Connect credentials: {}",
+            err
+        )),
+    }
+    Ok(issues)
+}
+
+/// The API key ID, issuer ID, and private key (if it's not somewhere `altool`
+/// already looks) to validate with.
+#[cfg(target_os = "macos")]
+fn api_credentials(
+    config: &Config,
+) -> Result<Option<(String, String, Option<String>)>, crate::secret::ResolveError> {
+    match config.apple().app_store_connect() {
+        Some(credentials) => {
+            let root_dir = config.app().root_dir();
+            Ok(Some((
+                credentials.key_id.resolve(root_dir)?,
+                credentials.issuer_id.resolve(root_dir)?,
+                Some(credentials.private_key.resolve(root_dir)?),
+            )))
+        }
+        None => Ok(std::env::var(API_KEY_ENV_VAR)
+            .ok()
+            .zip(std::env::var(API_ISSUER_ENV_VAR).ok())
+            .map(|(api_key, api_issuer)| (api_key, api_issuer, None))),
+    }
+}
+
+/// `altool` only reads private keys from files named after the key ID, so
+/// the key goes in a dir only we can read, which is removed afterwards.
+#[cfg(target_os = "macos")]
+fn write_private_key(api_key: &str, private_key: &str) -> std::io::Result<PathBuf> {
+    use std::{
+        fs::{self, DirBuilder, OpenOptions},
+        io::Write as _,
+        os::unix::fs::{DirBuilderExt as _, OpenOptionsExt as _},
+    };
+
+    let keys_dir = util::temp_dir().join(format!("private-keys-{}", std::process::id()));
+    fs::create_dir_all(util::temp_dir())?;
+    DirBuilder::new().mode(0o700).create(&keys_dir)?;
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(keys_dir.join(format!("AuthKey_{}.p8", api_key)))?
+        .write_all(private_key.as_bytes())?;
+    Ok(keys_dir)
+}
+
+// Entitlements are an XML plist, where a value follows its key on the next
+// line; this is only good for scalar values.
+#[cfg(target_os = "macos")]
+fn plist_value<'a>(plist: &'a str, key: &str) -> Option<&'a str> {
+    plist
+        .split(&format!("<key>{}</key>", key))
+        .nth(1)
+        .and_then(|rest| rest.trim_start().lines().next())
+        .map(str::trim)
+}
+
+/// Checks a signed AAB or IPA for problems that would get it rejected on
+/// upload, and fails if there are any.
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    overrides: &Overrides,
+    platform: Platform,
+    artifact_override: Option<PathBuf>,
+) -> Result<(), Error> {
+    let (config, _origin) = Config::load_or_gen(".", non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigFailed)?;
+    let artifact = match artifact_override {
+        Some(path) if path.is_file() => path,
+        Some(path) => {
+            return Err(Error::ArtifactMissing {
+                path,
+                hint: "Please check the path passed to `--artifact`.".to_owned(),
+            })
+        }
+        None => artifact(&config, platform)?,
+    };
+    println!("Validating {:?}", artifact);
+    let issues = match platform {
+        Platform::Android => validate_aab(&artifact)?,
+        #[cfg(target_os = "macos")]
+        Platform::Apple => validate_ipa(&config, &artifact)?,
+        #[cfg(not(target_os = "macos"))]
+        Platform::Apple => return Err(Error::AppleUnsupported),
+    };
+    if issues.is_empty() {
+        println!("No issues found");
+        Ok(())
+    } else {
+        for issue in &issues {
+            println!("  - {}", issue);
+        }
+        Err(Error::IssuesFound(issues.len()))
+    }
+}
@@ -0,0 +1,181 @@
+//! Rebuilds and redeploys the app whenever the crate's sources change.
+
+use crate::android;
+#[cfg(target_os = "macos")]
+use crate::{
+    apple::{self, simctl, target::Target as AppleTarget},
+    env,
+};
+use crate::{
+    config::{Config, LoadOrGenError, Overrides},
+    device, opts,
+    run::{self, Picked},
+    util::{
+        self,
+        cli::{Report, Reportable, TextWrapper},
+        progress::Step,
+        watch::Watcher,
+    },
+};
+use std::{io, time::Duration};
+
+#[derive(Debug)]
+pub enum Error {
+    PickFailed(run::Error),
+    ConfigFailed(LoadOrGenError),
+    WatchFailed(io::Error),
+    SimulatorUnsupported,
+    RustupFailed(util::rustup::Error),
+    AndroidRunFailed(android::device::RunError),
+    #[cfg(target_os = "macos")]
+    AppleEnvFailed(env::Error),
+    #[cfg(target_os = "macos")]
+    AppleRunFailed(apple::device::RunError),
+    #[cfg(target_os = "macos")]
+    SimulatorBuildFailed(apple::target::BuildError),
+    #[cfg(target_os = "macos")]
+    SimulatorFailed(simctl::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::PickFailed(err) => err.report(),
+            Self::ConfigFailed(err) => err.report(),
+            Self::WatchFailed(err) => Report::error("Failed to watch sources for changes", err),
+            Self::SimulatorUnsupported => Report::error(
+                "Failed to watch",
+                "The iOS simulator is only available on macOS",
+            ),
+            Self::RustupFailed(err) => err.report(),
+            Self::AndroidRunFailed(err) => err.report(),
+            #[cfg(target_os = "macos")]
+            Self::AppleEnvFailed(err) => err.report(),
+            #[cfg(target_os = "macos")]
+            Self::AppleRunFailed(err) => err.report(),
+            #[cfg(target_os = "macos")]
+            Self::SimulatorBuildFailed(err) => err.report(),
+            #[cfg(target_os = "macos")]
+            Self::SimulatorFailed(err) => err.report(),
+        }
+    }
+}
+
+fn watch(
+    config: &Config,
+    wrapper: &TextWrapper,
+    mut rebuild: impl FnMut() -> Result<(), Error>,
+) -> Result<(), Error> {
+    let app = config.app();
+    let mut watcher = Watcher::new(vec![app.crate_dir().join("src"), app.manifest_path()])
+        .map_err(Error::WatchFailed)?;
+    util::cli::status("Watching for changes; press Ctrl+C to stop.");
+    loop {
+        watcher.wait().map_err(Error::WatchFailed)?;
+        // A broken build shouldn't end the session, since fixing it is just
+        // the next change.
+        match rebuild() {
+            Ok(()) => util::cli::status("Redeployed; watching for changes."),
+            Err(err) => err.report().print(wrapper),
+        }
+    }
+}
+
+pub fn exec(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    noise_level: opts::NoiseLevel,
+    profile: opts::Profile,
+    filter: &device::Filter,
+    wait: Option<Duration>,
+    overrides: &Overrides,
+    simulator: Option<String>,
+) -> Result<(), Error> {
+    #[cfg(target_os = "macos")]
+    let simulator = simulator.or_else(simctl::env_simulator);
+    if let Some(simulator) = simulator {
+        #[cfg(target_os = "macos")]
+        return watch_simulator(
+            wrapper,
+            non_interactive,
+            noise_level,
+            profile,
+            overrides,
+            &simulator,
+        );
+        #[cfg(not(target_os = "macos"))]
+        {
+            let _ = simulator;
+            return Err(Error::SimulatorUnsupported);
+        }
+    }
+    let (config, _metadata, picked) =
+        run::pick_device(wrapper, non_interactive, filter, wait, overrides)
+            .map_err(Error::PickFailed)?;
+    match picked {
+        #[cfg(target_os = "macos")]
+        Picked::Apple { device, env } => {
+            let deploy = || {
+                device
+                    .build(
+                        config.apple(),
+                        &env,
+                        noise_level,
+                        profile,
+                        &Default::default(),
+                    )
+                    .and_then(|()| device.deploy(config.apple(), &env, &Default::default()))
+                    .map_err(Error::AppleRunFailed)
+            };
+            deploy()?;
+            watch(&config, wrapper, deploy)
+        }
+        Picked::Android { device, env } => {
+            let android = config.android();
+            let deploy = || {
+                device
+                    .build(android, &env, noise_level, profile, &Default::default())
+                    .and_then(|()| device.deploy(android, &env, profile, &Default::default()))
+                    .map_err(Error::AndroidRunFailed)
+            };
+            deploy()?;
+            watch(&config, wrapper, deploy)
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn watch_simulator(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    noise_level: opts::NoiseLevel,
+    profile: opts::Profile,
+    overrides: &Overrides,
+    simulator: &str,
+) -> Result<(), Error> {
+    let (config, _origin) = Config::load_or_gen(".", non_interactive, overrides, wrapper)
+        .map_err(Error::ConfigFailed)?;
+    let env = env::Env::new().map_err(Error::AppleEnvFailed)?;
+    let apple = config.apple();
+    // The simulator runs x86_64, same as for `cargo apple record`.
+    let target = AppleTarget::for_arch("x86_64").expect("developer error: no x86_64 target");
+    util::rustup::ensure_targets(Some(target.triple), non_interactive)
+        .map_err(Error::RustupFailed)?;
+    simctl::boot(simulator).map_err(Error::SimulatorFailed)?;
+    let deploy = || {
+        let app_path = Step::run("Building for simulator", || {
+            target.build_for_simulator(apple, &env, noise_level, profile)
+        })
+        .map_err(Error::SimulatorBuildFailed)?;
+        simctl::install_changed(
+            simulator,
+            &app_path,
+            &apple.bundle_id(),
+            apple.app().target_dir(),
+        )
+        .map_err(Error::SimulatorFailed)?;
+        simctl::launch(simulator, &apple.bundle_id()).map_err(Error::SimulatorFailed)
+    };
+    deploy()?;
+    watch(&config, wrapper, deploy)
+}
@@ -0,0 +1,32 @@
+//! Renders the bundled app template packs, to catch templates that no longer
+//! render against the current config.
+
+use cargo_mobile::test_support::Project;
+use std::path::{Path, PathBuf};
+
+fn pack_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("templates/apps")
+        .join(name)
+}
+
+fn project(pack: &str) -> Project {
+    Project::new(&format!(
+        "[app]\nname = \"hello-test\"\ndomain = \"example.com\"\ntemplate-pack = {:?}\n",
+        pack_path(pack)
+    ))
+    .expect("failed to create test project")
+}
+
+#[test]
+fn winit_renders() {
+    let rendered = project("winit")
+        .render(pack_path("winit"))
+        .expect("failed to render `winit` pack");
+    rendered.assert_contains("Cargo.toml", "name = \"hello-test\"");
+    rendered.assert_contains("Cargo.toml", "name = \"hello_test\"");
+    rendered.assert_contains("src/lib.rs", ".with_tag(\"hello-test\")");
+    rendered.assert_contains("gen/bin/desktop.rs", "hello_test::start_app();");
+    rendered.assert_exists(".gitignore");
+    rendered.assert_missing("Cargo.toml.hbs");
+}